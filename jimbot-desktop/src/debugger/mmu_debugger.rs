@@ -42,7 +42,7 @@ pub fn run_mmu_debugger(mut jimbot: ResMut<JimbotResource>, mut egui_context: Eg
                         });
                     });
                 }
-                ui.collapsing("VRAM (0x8000 - 0x9FFF)", |ui| {
+                ui.collapsing("VRAM (0x8000 - 0x9FFF, banks 0-1)", |ui| {
                     ui.set_max_height(250.);
                     ScrollArea::vertical().show(ui, |ui| {
                         ui.label(config_hex(
@@ -94,7 +94,7 @@ pub fn run_mmu_debugger(mut jimbot: ResMut<JimbotResource>, mut egui_context: Eg
                 //     });
                 // });
 
-                ui.collapsing("WRAM (0xC000 - 0xDFFF)", |ui| {
+                ui.collapsing("WRAM (0xC000 - 0xDFFF, banks 0-7)", |ui| {
                     ui.set_max_height(250.);
                     ScrollArea::vertical().show(ui, |ui| {
                         ui.label(config_hex(
@@ -42,14 +42,14 @@ pub fn run_ppu_debugger(
                     // ui.set_max_height(250.);
                     // ScrollArea::vertical().show(ui, |ui| {
                     let lcd = jimbot.ppu().lcd();
-                    let mut lcd_flat = [0u8; 144 * 160];
+                    let mut lcd_flat = [0u16; 144 * 160];
                     for y in 0..144usize {
                         for x in 0..160usize {
                             let index = y * 160 + x;
                             lcd_flat[index] = lcd[x][y];
                         }
                     }
-                    let lcd_as_string = lcd_flat.map(|i| i.to_string()).join("");
+                    let lcd_as_string = lcd_flat.map(|i| format!("{:04X} ", i)).join("");
                     ui.label(RichText::new(&lcd_as_string).size(7.))
                     // ui.label(
                     //     RichText::new(config_hex(
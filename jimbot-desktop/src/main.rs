@@ -1,6 +1,10 @@
 mod debugger;
 
 use std::borrow::BorrowMut;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::debugger::cpu_debugger::{run_cpu_debugger, setup_cpu_debugger, CpuDebugger};
 use crate::debugger::lcd_debugger::{run_lcd_debugger, setup_lcd_debugger};
@@ -9,6 +13,7 @@ use crate::debugger::ppu_debugger::run_ppu_debugger;
 use crate::debugger::setup_debugger;
 use bevy::app::App;
 use bevy::asset::{Assets, Handle};
+use bevy::input::gamepad::{GamepadRumbleIntensity, GamepadRumbleRequest};
 use bevy::math::{Quat, Vec3};
 use bevy::prelude::*;
 use bevy::prelude::{Commands, Image, KeyCode, Res, ResMut, Resource};
@@ -17,13 +22,25 @@ use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
 use bevy::sprite::SpriteBundle;
 use bevy::window::WindowMode;
 use bevy::DefaultPlugins;
-use bevy_egui::EguiPlugin;
+use bevy_egui::egui::Window as EguiWindow;
+use bevy_egui::{EguiContexts, EguiPlugin};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use jimbot::boot::BootMode;
+use jimbot::cartridge;
+use jimbot::cartridge::image_source::StaticImage;
+use jimbot::cartridge::metadata::Metadata;
+use jimbot::cartridge::save_storage::FileStorage;
+use jimbot::color::compatibility::ButtonCombo;
 use jimbot::cpu::instruction::Instruction;
 use jimbot::cpu::op::Op;
 use jimbot::cpu::registers::R16;
 use jimbot::jimbot::Jimbot;
 use jimbot::mmu::joypad;
+use jimbot::movie::Movie;
+use jimbot::patch;
+use jimbot::rewind::Rewind;
+use jimbot::serial::link_cable::LinkCable;
+use jimbot::serial::printer::GameBoyPrinter;
 use ringbuf::{Producer, RingBuffer};
 
 #[derive(Resource)]
@@ -32,6 +49,40 @@ pub struct BuffProducer(Producer<f32>);
 #[derive(Resource)]
 pub struct JimbotResource(Jimbot);
 
+/// Movie given on the command line, F5 writes the recorded movie
+#[derive(Resource, Default)]
+pub struct MovieFile {
+    record_path: Option<String>,
+    playing: bool,
+}
+
+/// Printed images are written as `print_<n>.png` in `directory`
+#[derive(Resource)]
+pub struct PrinterOutput {
+    printer: GameBoyPrinter,
+    directory: String,
+    count: usize,
+}
+
+/// Motor of a rumble cartridge, forwarded to the gamepads
+#[derive(Resource, Default)]
+pub struct RumbleMotor {
+    on: Arc<AtomicBool>,
+    rumbling: bool,
+}
+
+/// Shown instead of the emulator when the cartridge cannot be loaded
+#[derive(Resource)]
+pub struct LoadError(String);
+
+/// Size of the frames, 256x224 with the SGB border, and their scale in the window
+#[derive(Resource, Copy, Clone)]
+pub struct ScreenSize {
+    width: u32,
+    height: u32,
+    scale: f32,
+}
+
 fn main() {
     let host = cpal::default_host();
     let output_device = host.default_output_device().unwrap();
@@ -61,8 +112,51 @@ fn main() {
         output_stream
     };
 
-    App::new()
-        .insert_resource(BuffProducer(buff_prod))
+    let mut movie_file = MovieFile::default();
+    let mut printer_output = None;
+    let jimbot = parse_args(std::env::args().skip(1).collect()).and_then(|args| {
+        let bytes = load_rom(&args.cartridge_file_path)?;
+        let title = Metadata::from_rom(&bytes).map(|metadata| metadata.title().to_string()).unwrap_or_default();
+        let storage = FileStorage::next_to_rom(&args.cartridge_file_path, &title);
+        let cartridge = cartridge::new_cartridge_with_storage(bytes, Box::new(storage))
+            .map_err(|err| format!("Cannot load {}: {}", args.cartridge_file_path, err))?;
+        let mut jimbot = Jimbot::new(cartridge, args.boot_mode);
+        if let Some(combo) = args.palette {
+            jimbot.set_dmg_palette(combo.into());
+        }
+        if let Some(movie_file_path) = args.play_movie {
+            let movie = std::fs::read_to_string(&movie_file_path)
+                .map_err(|err| err.to_string())
+                .and_then(|text| Movie::from_text(&text).map_err(|err| err.to_string()))
+                .and_then(|movie| jimbot.play_movie(movie).map_err(|err| err.to_string()));
+            movie.map_err(|err| format!("Cannot play movie {}: {}", movie_file_path, err))?;
+            movie_file.playing = true;
+        }
+        if let Some((listen, address)) = args.link {
+            jimbot.connect_serial(Box::new(open_link_cable(listen, &address)?));
+        }
+        if let Some(directory) = args.printer {
+            let printer = GameBoyPrinter::default();
+            jimbot.connect_serial(Box::new(printer.clone()));
+            printer_output = Some(PrinterOutput { printer, directory, count: 0 });
+        }
+        if let Some(image_file_path) = args.camera {
+            let image = StaticImage::open(&image_file_path)
+                .map_err(|err| format!("Cannot load camera image {}: {}", image_file_path, err))?;
+            jimbot.set_image_source(Box::new(image));
+        }
+        if let Some(movie_file_path) = args.record_movie {
+            jimbot.start_movie_recording().map_err(|err| err.to_string())?;
+            movie_file.record_path = Some(movie_file_path);
+        }
+        Ok(jimbot)
+    });
+
+    let (width, height) = jimbot.as_ref().map_or((160, 144), |jimbot| (jimbot.frame().width(), jimbot.frame().height()));
+    let screen_size = ScreenSize { width: width as u32, height: height as u32, scale: if width > 160 { 3. } else { 5. } };
+    let mut app = App::new();
+    app.insert_resource(BuffProducer(buff_prod))
+        .insert_resource(screen_size)
         .insert_resource(Msaa::Off)
         .add_plugins(
             DefaultPlugins
@@ -70,7 +164,7 @@ fn main() {
                     primary_window: Some(Window {
                         title: "Jimbot".to_string(),
                         present_mode: bevy::window::PresentMode::AutoVsync,
-                        resolution: (160. * 5., 144. * 5.).into(),
+                        resolution: (width as f32 * screen_size.scale, height as f32 * screen_size.scale).into(),
                         position: WindowPosition::Centered(MonitorSelection::Current),
                         resize_constraints: Default::default(),
                         resizable: false,
@@ -85,8 +179,27 @@ fn main() {
                 })
                 .set(ImagePlugin::default_nearest()),
         )
-        .add_plugins(EguiPlugin)
-        .insert_resource(JimbotResource(Jimbot::default()))
+        .add_plugins(EguiPlugin);
+    let mut jimbot = match jimbot {
+        Ok(jimbot) => jimbot,
+        Err(err) => {
+            eprintln!("{}", err);
+            app.insert_resource(LoadError(err))
+                .add_systems(Update, show_load_error)
+                .run();
+            return;
+        }
+    };
+    jimbot.enable_rewind(Rewind::default());
+    let rumble_motor = RumbleMotor::default();
+    let motor_on = rumble_motor.on.clone();
+    jimbot.set_rumble_callback(Box::new(move |on| motor_on.store(on, Ordering::Relaxed)));
+    if let Some(printer_output) = printer_output {
+        app.insert_resource(printer_output);
+    }
+    app.insert_resource(JimbotResource(jimbot))
+        .insert_resource(movie_file)
+        .insert_resource(rumble_motor)
         .add_systems(
             Startup,
             (
@@ -99,12 +212,14 @@ fn main() {
             Update,
             (
                 run_jimbot,
+                rumble_gamepads.after(run_jimbot),
                 // run_mmu_debugger,
                 // run_cpu_debugger,
                 // run_lcd_debugger,
                 // run_ppu_debugger,
             ),
         )
+        .add_systems(Last, flush_save_on_exit)
         // .add_systems(Update, run_jimbot)
         // .add_system(run_mmu_debugger)
         // .add_system(run_cpu_debugger.before("run_jimbot"))
@@ -113,20 +228,121 @@ fn main() {
         .run();
 }
 
+const USAGE: &str = "Usage: jimbot-desktop [--skip-boot | --boot-rom <boot rom file>] \
+    [--record-movie <movie file> | --play-movie <movie file>] \
+    [--link-listen <address> | --link-connect <address> | --printer <output directory>] \
+    [--camera <png or pgm file>] [--palette <up|left|down|right>[+a|+b]] <rom file>
+    address is host:port or unix:<socket path>, the palette colors dmg games like the CGB boot button combos";
+
+struct Args {
+    cartridge_file_path: String,
+    boot_mode: BootMode,
+    record_movie: Option<String>,
+    play_movie: Option<String>,
+    /// listen or connect, address
+    link: Option<(bool, String)>,
+    printer: Option<String>,
+    /// image seen by the Game Boy Camera
+    camera: Option<String>,
+    palette: Option<ButtonCombo>,
+}
+
+fn parse_args(args: Vec<String>) -> Result<Args, String> {
+    let mut boot_mode = BootMode::default();
+    let mut cartridge_file_path = None;
+    let mut record_movie = None;
+    let mut play_movie = None;
+    let mut link = None;
+    let mut printer = None;
+    let mut camera = None;
+    let mut palette = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--skip-boot" => boot_mode = BootMode::Skip,
+            "--record-movie" if play_movie.is_none() => record_movie = Some(args.next().ok_or(USAGE)?),
+            "--play-movie" if record_movie.is_none() => play_movie = Some(args.next().ok_or(USAGE)?),
+            "--link-listen" if link.is_none() && printer.is_none() => link = Some((true, args.next().ok_or(USAGE)?)),
+            "--link-connect" if link.is_none() && printer.is_none() => link = Some((false, args.next().ok_or(USAGE)?)),
+            "--printer" if link.is_none() => printer = Some(args.next().ok_or(USAGE)?),
+            "--camera" => camera = Some(args.next().ok_or(USAGE)?),
+            "--palette" => palette = Some(args.next().ok_or(USAGE)?.parse::<ButtonCombo>()?),
+            "--boot-rom" => {
+                let boot_rom_file_path = args.next().ok_or(USAGE)?;
+                let boot_rom = std::fs::read(&boot_rom_file_path)
+                    .map_err(|err| format!("Cannot load boot rom {}: {}", boot_rom_file_path, err))?;
+                boot_mode = BootMode::Custom(boot_rom);
+            }
+            _ if cartridge_file_path.is_none() && !arg.starts_with("--") => cartridge_file_path = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    Ok(Args {
+        cartridge_file_path: cartridge_file_path.ok_or(USAGE)?,
+        boot_mode,
+        record_movie,
+        play_movie,
+        link,
+        printer,
+        camera,
+        palette,
+    })
+}
+
+/// Rom bytes with the `.ips`, `.ups` and `.bps` patches named after the rom applied, in this order
+fn load_rom(file_path: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = std::fs::read(file_path).map_err(|err| format!("Cannot load {}: {}", file_path, err))?;
+    for extension in ["ips", "ups", "bps"] {
+        let patch_file_path = Path::new(file_path).with_extension(extension);
+        if let Ok(patch_bytes) = std::fs::read(&patch_file_path) {
+            bytes = patch::apply(&bytes, &patch_bytes)
+                .map_err(|err| format!("Cannot apply patch {}: {}", patch_file_path.display(), err))?;
+            println!("Patch applied: {}", patch_file_path.display());
+        }
+    }
+    Ok(bytes)
+}
+
+fn open_link_cable(listen: bool, address: &str) -> Result<LinkCable, String> {
+    if listen {
+        println!("Waiting for link cable on {}", address);
+    }
+    let link_cable = match (address.strip_prefix("unix:"), listen) {
+        #[cfg(unix)]
+        (Some(path), true) => LinkCable::listen_unix(path),
+        #[cfg(unix)]
+        (Some(path), false) => LinkCable::connect_unix(path),
+        #[cfg(not(unix))]
+        (Some(_), _) => return Err("Unix sockets are not supported".to_string()),
+        (None, true) => LinkCable::listen_tcp(address),
+        (None, false) => LinkCable::connect_tcp(address),
+    };
+    link_cable.map_err(|err| format!("Cannot open link cable {}: {}", address, err))
+}
+
+fn show_load_error(error: Res<LoadError>, mut egui_context: EguiContexts) {
+    EguiWindow::new("Cannot load cartridge")
+        .collapsible(false)
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(error.0.as_str());
+        });
+}
+
 #[derive(Resource)]
 pub struct Display {
     pub image: Handle<Image>,
 }
 
-fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>, screen_size: Res<ScreenSize>) {
     let image = Image::new_fill(
         Extent3d {
-            width: 160,
-            height: 144,
+            width: screen_size.width,
+            height: screen_size.height,
             depth_or_array_layers: 1,
         },
         TextureDimension::D2,
-        &[0x0; 160 * 144 * 4],
+        &[0x0; 4],
         TextureFormat::Rgba8Unorm,
         RenderAssetUsages::default(),
     );
@@ -142,7 +358,7 @@ fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
         transform: bevy::prelude::Transform {
             translation: Vec3::ZERO,
             rotation: Quat::IDENTITY,
-            scale: Vec3::new(5.0, 5.0, 1.0),
+            scale: Vec3::new(screen_size.scale, screen_size.scale, 1.0),
         },
         ..Default::default()
     });
@@ -170,6 +386,28 @@ fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
 //     })
 // }
 
+fn rumble_gamepads(mut motor: ResMut<RumbleMotor>, gamepads: Res<Gamepads>, mut requests: EventWriter<GamepadRumbleRequest>) {
+    let on = motor.on.load(Ordering::Relaxed);
+    if on == motor.rumbling {
+        return;
+    }
+    motor.rumbling = on;
+    for gamepad in gamepads.iter() {
+        requests.send(if on {
+            GamepadRumbleRequest::Add { gamepad, intensity: GamepadRumbleIntensity::MAX, duration: Duration::from_secs(10) }
+        } else {
+            GamepadRumbleRequest::Stop { gamepad }
+        });
+    }
+}
+
+/// The battery backed ram is flushed with a delay, write what is left before quitting
+fn flush_save_on_exit(mut jimbot: ResMut<JimbotResource>, mut exit: EventReader<AppExit>) {
+    if exit.read().next().is_some() {
+        jimbot.0.flush_save();
+    }
+}
+
 fn run_jimbot(
     mut jimbot: ResMut<JimbotResource>,
     mut time: Res<Time>,
@@ -177,11 +415,11 @@ fn run_jimbot(
     keys: Res<ButtonInput<KeyCode>>,
     mut images: ResMut<Assets<Image>>,
     mut audio_producer: ResMut<BuffProducer>,
+    mut movie_file: ResMut<MovieFile>,
+    printer_output: Option<ResMut<PrinterOutput>>,
 ) {
     let jimbot = jimbot.0.borrow_mut();
     let audio_producer = audio_producer.0.borrow_mut();
-    const M_CYCLE_PER_FRAME: f32 = (4194304.0 / 4.0) / 60.0;
-    let mut current_cycle = 0.0f32;
 
     if keys.pressed(KeyCode::KeyW) {
         jimbot.joypad_press(joypad::Key::Up)
@@ -223,57 +461,47 @@ fn run_jimbot(
     } else {
         jimbot.joypad_release(joypad::Key::Select)
     }
+    // Arrow keys tilt the console (MBC7 accelerometer)
+    let axis = |negative: KeyCode, positive: KeyCode| keys.pressed(positive) as i8 as f32 - keys.pressed(negative) as i8 as f32;
+    jimbot.set_tilt(axis(KeyCode::ArrowLeft, KeyCode::ArrowRight), axis(KeyCode::ArrowUp, KeyCode::ArrowDown));
 
-    while current_cycle < M_CYCLE_PER_FRAME {
-        // println!("Tima: {}", jimbot.mmu().get(0xFF04));
-        jimbot.run();
-        current_cycle += 1.;
-        // let pc = jimbot.cpu().registers().get16(R16::PC);
-        // if jimbot.error_message().is_none() {// && (pc >= 0x348) && (pc <= 0x38A) {// && pc < 0xCB80) {
-        //     let ly = jimbot.mmu().ly();
-        //     let (op, p1, p2) = jimbot.cpu().instruction().ins();
-        //     let af = jimbot.cpu().registers().get16(R16::AF);
-        //     let bc = jimbot.cpu().registers().get16(R16::BC);
-        //     let de = jimbot.cpu().registers().get16(R16::DE);
-        //     let hl = jimbot.cpu().registers().get16(R16::HL);
-        //     let sp = jimbot.cpu().registers().get16(R16::SP);
-        //     match (op, p1, p2) {
-        //         // (Op::Dcd, _, _) => println!("[{:#06X}] {:?} {:?} {:?} [AF:{:#06X} BC:{:#06X} DE:{:#06X}, HL:{:#06X}, SP:{:#06X}, IME:{}, IF:{:#08b}, IE:{:#08b}] ly:{}", pc, op, p1, p2, af, bc, de, hl, sp, jimbot.cpu().ime(), jimbot.mmu().get(0xFF0F), jimbot.mmu().get(0xFFFF), ly),
-        //         (Op::Dcd, _, _) => println!("[{:#06X}] {:?} {:?} {:?} [AF:{:#06X} BC:{:#06X} DE:{:#06X}, HL:{:#06X}, SP:{:#06X}] ly:{} lyc:{} stat:{:#010b} if:{:#010b} ie:{:#010b} ime:{}", pc, op, p1, p2, af, bc, de, hl, sp, jimbot.mmu().ly(), jimbot.mmu().lyc(), jimbot.mmu().get(0xFF41), jimbot.mmu().get(0xFF0F), jimbot.mmu().get(0xFFFF), jimbot.cpu().ime()),
-        //         // (Op::DcdCB, _, _) => println!("[{:#06X}] {:?} {:?} {:?} [AF:{:#06X} BC:{:#06X} DE:{:#06X}, HL:{:#06X}, SP:{:#06X}]", pc, op, p1, p2, af, bc, de, hl, sp),
-        //         (_, _, _) => {}//println!("\t-----> {:?} {:?} {:?}", op, p1, p2),
-        //     }
-        //     //     cpu_debugger.instructions.push(ins);
-        // }
-    }
+    // Hold R to rewind
+    let frame = if keys.pressed(KeyCode::KeyR) {
+        if let Err(err) = jimbot.rewind_frames(2) {
+            eprintln!("Cannot rewind: {}", err);
+        }
+        jimbot.frame()
+    } else {
+        jimbot.run_frame()
+    };
     let image = images.get_mut(&display.image).unwrap();
-    let lcd = jimbot.ppu().lcd();
-    let byte_per_row = 160 * 4;
-    for lcd_y in 0..144 {
-        for lcd_x in 0..160 {
-            let px: u8 = lcd[lcd_x][lcd_y].into();
-            if px == 0x10 {
-                panic!()
+    image.data.copy_from_slice(&frame.to_rgba());
+    let sound_data = jimbot.get_sound_data();
+    audio_producer.push_slice(sound_data.as_slice());
+
+    if keys.just_pressed(KeyCode::F5) {
+        if let (Some(path), Some(movie)) = (movie_file.record_path.as_ref(), jimbot.recorded_movie()) {
+            match std::fs::write(path, movie.to_text()) {
+                Ok(_) => println!("Movie written to {} ({} frames)", path, movie.frames()),
+                Err(err) => eprintln!("Cannot write movie {}: {}", path, err),
             }
-            let color: u32 = match px {
-                // 0b00 => 0x84d07d,
-                // 0b01 => 0x5e785d,
-                // 0b10 => 0x3e4943,
-                // _ => 0x252b25,
-                0b00 => 0xE0F8D0,
-                0b01 => 0x88C070,
-                0b10 => 0x346856,
-                _ => 0x081820,
-            };
-            let offset_y = (lcd_y * byte_per_row) as usize;
-            let offset_x = (lcd_x as usize * 4); //(x_tile * 8 * 4);
-            let index = (offset_x + offset_y) as usize;
-            image.data[index + 0] = ((color >> 16) & 0xFF) as u8;
-            image.data[index + 1] = ((color >> 8) & 0xFF) as u8;
-            image.data[index + 2] = (color & 0xFF) as u8;
-            image.data[index + 3] = 0xFF;
         }
     }
-    let sound_data = jimbot.get_sound_data();
-    audio_producer.push_slice(sound_data.as_slice());
+    if let Some(mut printer_output) = printer_output {
+        for image in printer_output.printer.take_images() {
+            printer_output.count += 1;
+            let path = Path::new(&printer_output.directory).join(format!("print_{}.png", printer_output.count));
+            match std::fs::write(&path, image.to_png()) {
+                Ok(_) => println!("Printed {}", path.display()),
+                Err(err) => eprintln!("Cannot write {}: {}", path.display(), err),
+            }
+        }
+    }
+    if movie_file.playing && !jimbot.is_movie_playing() {
+        movie_file.playing = false;
+        match jimbot.movie_playback().and_then(|playback| playback.desync()) {
+            Some(desync) => println!("Movie desync at frame {}: hash {:08X}, expected {:08X}", desync.frame, desync.actual, desync.expected),
+            None => println!("Movie finished"),
+        }
+    }
 }
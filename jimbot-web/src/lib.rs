@@ -1,6 +1,12 @@
 use std::sync::{Arc, Mutex};
 use cpal::{traits::{DeviceTrait, HostTrait, StreamTrait}, Device, Stream};
-use jimbot::jimbot::Jimbot;
+use jimbot::boot::BootMode;
+use jimbot::cartridge;
+use jimbot::cartridge::metadata::Metadata;
+use jimbot::cartridge::save_storage::CallbackStorage;
+use jimbot::color::compatibility::ButtonCombo;
+use jimbot::jimbot::{Frame, Jimbot};
+use jimbot::rewind::Rewind;
 use ringbuf::{Producer, RingBuffer};
 use wasm_bindgen::{prelude::wasm_bindgen, JsValue, JsCast};
 use wasm_bindgen::closure::Closure;
@@ -20,8 +26,13 @@ pub fn main_js() -> Result<(), JsValue> {
 
 #[wasm_bindgen]
 impl JimbotWeb {
+    /// `patch` is an optional IPS, UPS or BPS patch applied to the rom
     #[wasm_bindgen(constructor)]
-    pub fn new(cartridge_bytes: Box<[u8]>) -> JimbotWeb {
+    pub fn new(cartridge_bytes: Box<[u8]>, patch: Option<Box<[u8]>>) -> Result<JimbotWeb, JsValue> {
+        let cartridge_bytes = match patch {
+            Some(patch) => jimbot::patch::apply(&cartridge_bytes, &patch).map_err(|err| JsValue::from_str(&err.to_string()))?,
+            None => cartridge_bytes.into_vec(),
+        };
         let host = cpal::default_host();
         let device = host.default_output_device().unwrap();
 
@@ -47,57 +58,77 @@ impl JimbotWeb {
 
         stream.play().expect("Cannot play audio");
         web_sys::console::log_1(&format!("Cart size: {}", cartridge_bytes.len()).into());
-        let mut jimbot = Jimbot::new_with_cartridge_bytes(cartridge_bytes.to_vec());
-        let cart = jimbot.cartridge().as_ref();
-        web_sys::console::log_1(&format!("Cart loaded: {}", cart.is_some()).into());
-        let cart = cart.expect("No Cartridge");
-        web_sys::console::log_1(&format!("{:#?}", cart.metadata()).into());
-        let title = cart.metadata().title().to_string();
-        if let Some(save_data) = jimbot.save_data_mut() {
+        // Battery backed ram is kept in local storage, base64 encoded under the cartridge title
+        let title = Metadata::from_rom(cartridge_bytes.as_ref())
+            .map_err(|err| JsValue::from_str(&err.to_string()))?
+            .title()
+            .to_string();
+        let store = web_sys::window().unwrap().local_storage().unwrap().unwrap();
+        let saved = store.get_item(&title).unwrap().and_then(|base64data| base64::decode(base64data).ok());
+        if saved.is_some() {
+            web_sys::console::log_1(&format!("Saved data loaded: {}", &title).into());
+        }
+        let storage = CallbackStorage::new(saved, move |save_data| {
             let store = web_sys::window().unwrap().local_storage().unwrap().unwrap();
-            if let Some(base64data) = store.get_item(&title).unwrap() {
-                if let Ok(data) = base64::decode(base64data) {
-                    save_data.copy_from_slice(data.as_slice());
-                    web_sys::console::log_1(&format!("Saved data loaded: {}", &title).into());
-                }
+            if store.set_item(&title, &base64::encode(save_data)).is_err() {
+                web_sys::console::log_1(&format!("Cannot write saved data: {}", &title).into());
             }
-        }
+        });
+        let cart = cartridge::new_cartridge_with_storage(cartridge_bytes, Box::new(storage))
+            .map_err(|err| JsValue::from_str(&err.to_string()))?;
+        web_sys::console::log_1(&format!("{}", cart.metadata()).into());
+        let mut jimbot = Jimbot::new(cart, BootMode::default());
+        jimbot.enable_rewind(Rewind::default());
+        jimbot.set_rumble_callback(Box::new(|on| {
+            if let Some(window) = web_sys::window() {
+                window.navigator().vibrate_with_duration(if on { 10_000 } else { 0 });
+            }
+        }));
         let window = web_sys::window().unwrap();
         let jimbot = Arc::new(Mutex::new(jimbot));
         let jimbot_cb = jimbot.clone();
         let cb = Closure::wrap(Box::new(move ||{
-            let store = web_sys::window().unwrap().local_storage().unwrap().unwrap();
-            let jimbot = jimbot_cb.lock().unwrap();
-            if let Some(save_data) = jimbot.save_data() {
-                    store.set_item(jimbot.mmu().cartridge().as_ref().unwrap().metadata().title(), &base64::encode(save_data)).unwrap();
-            }
+            jimbot_cb.lock().unwrap().flush_save();
         }) as Box<dyn FnMut()>);
         window.set_onbeforeunload(Some(cb.as_ref().unchecked_ref()));
         window.set_onpagehide(Some(cb.as_ref().unchecked_ref()));
         cb.forget();
-        Self {
+        Ok(Self {
             jimbot: jimbot.clone(),
             _stream: stream,
             audio_producer,
-        }
+        })
     }
 
     pub fn run(&mut self, lcd_data: &mut [u8]) {
-        const M_CYCLE_PER_FRAME: f32 = (4194304.0 / 4.0) / 60.0;
-        let mut current_cycle = 0.0f32;
         let mut jimbot = self.jimbot.lock().unwrap();
-        while current_cycle < M_CYCLE_PER_FRAME {
-            jimbot.run();
-            current_cycle += 1.;
-        }
+        Self::copy_lcd(&jimbot.run_frame(), lcd_data);
         self.audio_producer.push_slice(jimbot.get_sound_data().as_slice());
-        let pixels = jimbot.ppu().lcd();
-        for y in 0..144 {
-            for x in 0..160 {
-                let pixel = pixels[x][y];
-                lcd_data[(y * 160) + x] = pixel;
-            }
+    }
+
+    /// Goes back `frames` frames and draws the rewound frame, returns the frame number
+    pub fn rewind_frames(&mut self, frames: u32, lcd_data: &mut [u8]) -> u32 {
+        let mut jimbot = self.jimbot.lock().unwrap();
+        if let Err(err) = jimbot.rewind_frames(frames as u64) {
+            web_sys::console::log_1(&format!("Cannot rewind: {}", err).into());
         }
+        let frame = jimbot.frame();
+        Self::copy_lcd(&frame, lcd_data);
+        frame.number() as u32
+    }
+
+    /// Frame size, 256x224 for SGB games (with the border), 160x144 otherwise
+    pub fn width(&self) -> u32 {
+        self.jimbot.lock().unwrap().frame().width() as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.jimbot.lock().unwrap().frame().height() as u32
+    }
+
+    /// `lcd_data` receives the RGBA pixels, `width` * `height` * 4 bytes
+    fn copy_lcd(frame: &Frame, lcd_data: &mut [u8]) {
+        lcd_data.copy_from_slice(&frame.to_rgba());
     }
 
     pub fn joypad_release(&mut self, key: jimbot::mmu::joypad::Key) {
@@ -108,4 +139,24 @@ impl JimbotWeb {
     pub fn joypad_press(&mut self, key: jimbot::mmu::joypad::Key) {
         self.jimbot.lock().unwrap().joypad_press(key);
     }
+
+    /// Tilt for the MBC7 accelerometer, see `Jimbot::set_tilt`
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.jimbot.lock().unwrap().set_tilt(x, y);
+    }
+
+    /// Colors of dmg games, `up`, `up+a`, `up+b`, `left`... `right+b` as the CGB boot button combos
+    pub fn set_palette(&mut self, combo: &str) -> Result<(), JsValue> {
+        let combo = combo.parse::<ButtonCombo>().map_err(|err| JsValue::from_str(&err))?;
+        self.jimbot.lock().unwrap().set_dmg_palette(combo.into());
+        Ok(())
+    }
+
+    /// Cartridge header information and warnings, one per line
+    pub fn rom_info(&self) -> String {
+        match self.jimbot.lock().unwrap().cartridge() {
+            Some(cartridge) => cartridge.metadata().to_string(),
+            None => String::new(),
+        }
+    }
 }
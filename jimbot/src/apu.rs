@@ -3,6 +3,7 @@ use crate::apu::channel1::Channel1;
 use crate::apu::channel2::Channel2;
 use crate::apu::channel3::Channel3;
 use crate::apu::channel4::Channel4;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 mod channel1;
 mod channel2;
@@ -129,4 +130,31 @@ impl APU {
     pub fn nr50(&self) -> u8 {
         self.nr50
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for APU {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.nr50);
+        state.write_u8(self.nr51);
+        state.write_u8(self.nr52);
+        self.channel1.save_state(state);
+        self.channel2.save_state(state);
+        self.channel3.save_state(state);
+        self.channel4.save_state(state);
+        state.write_f32(self.sample_counter);
+    }
+
+    /// pending samples are dropped, they belong to the timeline being replaced
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.nr50 = state.read_u8()?;
+        self.nr51 = state.read_u8()?;
+        self.nr52 = state.read_u8()?;
+        self.channel1.load_state(state)?;
+        self.channel2.load_state(state)?;
+        self.channel3.load_state(state)?;
+        self.channel4.load_state(state)?;
+        self.sample_counter = state.read_f32()?;
+        self.amps.clear();
+        Ok(())
+    }
+}
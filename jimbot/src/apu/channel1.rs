@@ -3,6 +3,7 @@ use crate::apu::frequency::Frequency;
 use crate::apu::frequency_sweep::FrequencySweep;
 use crate::apu::wave_length::WaveLength;
 use crate::cpu::registers::R8::P;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct Channel1 {
     enable: bool,
@@ -136,5 +137,31 @@ impl Channel1 {
     }
 }
 
+impl SaveState for Channel1 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enable);
+        state.write_u8(self.nr10);
+        state.write_u8(self.nr11);
+        state.write_u8(self.nr12);
+        state.write_u8(self.nr13);
+        state.write_u8(self.nr14);
+        self.frequency_sweep.save_state(state);
+        self.frequency.save_state(state);
+        self.wave_length.save_state(state);
+        self.envelope.save_state(state);
+    }
 
-
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enable = state.read_bool()?;
+        self.nr10 = state.read_u8()?;
+        self.nr11 = state.read_u8()?;
+        self.nr12 = state.read_u8()?;
+        self.nr13 = state.read_u8()?;
+        self.nr14 = state.read_u8()?;
+        self.frequency_sweep.load_state(state)?;
+        self.frequency.load_state(state)?;
+        self.wave_length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        Ok(())
+    }
+}
@@ -3,6 +3,7 @@ use crate::apu::frequency::Frequency;
 use crate::apu::frequency_sweep::FrequencySweep;
 use crate::apu::wave_length::WaveLength;
 use crate::cpu::registers::R8::P;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct Channel2 {
     enable: bool,
@@ -116,5 +117,27 @@ impl Channel2 {
     }
 }
 
+impl SaveState for Channel2 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enable);
+        state.write_u8(self.nr21);
+        state.write_u8(self.nr22);
+        state.write_u8(self.nr23);
+        state.write_u8(self.nr24);
+        self.frequency.save_state(state);
+        self.wave_length.save_state(state);
+        self.envelope.save_state(state);
+    }
 
-
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enable = state.read_bool()?;
+        self.nr21 = state.read_u8()?;
+        self.nr22 = state.read_u8()?;
+        self.nr23 = state.read_u8()?;
+        self.nr24 = state.read_u8()?;
+        self.frequency.load_state(state)?;
+        self.wave_length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@ use crate::apu::frequency_sweep::FrequencySweep;
 use crate::apu::length::Length;
 use crate::apu::wave_length::WaveLength;
 use crate::cpu::registers::R8::P;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct Channel3 {
     enable: bool,
@@ -147,5 +148,26 @@ impl Channel3 {
     }
 }
 
+impl SaveState for Channel3 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enable);
+        state.write_bytes(&[self.nr30, self.nr31, self.nr32, self.nr33, self.nr34]);
+        state.write_bytes(&self.wave_pattern_ram);
+        state.write_bytes(&self.wave_pattern);
+        state.write_u8(self.wave_index as u8);
+        self.frequency.save_state(state);
+        self.length.save_state(state);
+    }
 
-
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enable = state.read_bool()?;
+        let mut nr3x = [0; 5];
+        state.read_bytes(&mut nr3x)?;
+        [self.nr30, self.nr31, self.nr32, self.nr33, self.nr34] = nr3x;
+        state.read_bytes(&mut self.wave_pattern_ram)?;
+        state.read_bytes(&mut self.wave_pattern)?;
+        self.wave_index = state.read_u8()? as usize % self.wave_pattern.len();
+        self.frequency.load_state(state)?;
+        self.length.load_state(state)
+    }
+}
@@ -4,6 +4,7 @@ use crate::apu::frequency_sweep::FrequencySweep;
 use crate::apu::noise_frequency::NoiseFrequency;
 use crate::apu::wave_length::WaveLength;
 use crate::cpu::registers::R8::P;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct Channel4 {
     enable: bool,
@@ -124,5 +125,29 @@ impl Channel4 {
     }
 }
 
+impl SaveState for Channel4 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enable);
+        state.write_u16(self.lfsr);
+        state.write_u8(self.nr41);
+        state.write_u8(self.nr42);
+        state.write_u8(self.nr43);
+        state.write_u8(self.nr44);
+        self.frequency.save_state(state);
+        self.wave_length.save_state(state);
+        self.envelope.save_state(state);
+    }
 
-
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enable = state.read_bool()?;
+        self.lfsr = state.read_u16()?;
+        self.nr41 = state.read_u8()?;
+        self.nr42 = state.read_u8()?;
+        self.nr43 = state.read_u8()?;
+        self.nr44 = state.read_u8()?;
+        self.frequency.load_state(state)?;
+        self.wave_length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        Ok(())
+    }
+}
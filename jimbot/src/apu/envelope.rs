@@ -1,3 +1,5 @@
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
 pub struct Envelope {
     nrx2: u8,
     period_timer: u8,
@@ -59,4 +61,19 @@ impl Envelope {
     pub fn sweep(&self) -> u8 {
         self.nrx2 & 0b111
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for Envelope {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.nrx2);
+        state.write_u8(self.period_timer);
+        state.write_u8(self.volume);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.nrx2 = state.read_u8()?;
+        self.period_timer = state.read_u8()?;
+        self.volume = state.read_u8()?;
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use std::process::id;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct Frequency {
     nrx3: u8,
@@ -54,4 +55,21 @@ impl Frequency {
     pub fn set_new_frequency(&mut self, new_frequency: u16) {
         self.initial_frequency = new_frequency;
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for Frequency {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.nrx3);
+        state.write_u8(self.nrx4);
+        state.write_u16(self.initial_frequency);
+        state.write_u16(self.frequency_timer);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.nrx3 = state.read_u8()?;
+        self.nrx4 = state.read_u8()?;
+        self.initial_frequency = state.read_u16()?;
+        self.frequency_timer = state.read_u16()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
 pub struct FrequencySweep {
     nr10: u8,
     shadow_frequency: u16,
@@ -86,4 +88,21 @@ impl FrequencySweep {
     fn shift(&self) -> u8 {
         self.nr10 & 0b111
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for FrequencySweep {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.nr10);
+        state.write_u16(self.shadow_frequency);
+        state.write_u8(self.timer);
+        state.write_bool(self.enabled);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.nr10 = state.read_u8()?;
+        self.shadow_frequency = state.read_u16()?;
+        self.timer = state.read_u8()?;
+        self.enabled = state.read_bool()?;
+        Ok(())
+    }
+}
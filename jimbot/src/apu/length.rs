@@ -1,4 +1,5 @@
 use crate::apu::APU;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct Length {
     nr31: u8,
@@ -42,4 +43,19 @@ impl Length {
     }
 
     pub fn length(&self) -> u8 { self.nr31 }
-}
\ No newline at end of file
+}
+
+impl SaveState for Length {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.nr31);
+        state.write_bool(self.is_length_enable);
+        state.write_u8(self.length_timer);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.nr31 = state.read_u8()?;
+        self.is_length_enable = state.read_bool()?;
+        self.length_timer = state.read_u8()?;
+        Ok(())
+    }
+}
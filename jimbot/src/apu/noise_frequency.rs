@@ -1,4 +1,5 @@
 use std::process::id;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct NoiseFrequency {
     nr43: u8,
@@ -60,4 +61,17 @@ impl NoiseFrequency {
             false
         }
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for NoiseFrequency {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.nr43);
+        state.write_u16(self.frequency_timer);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.nr43 = state.read_u8()?;
+        self.frequency_timer = state.read_u16()?;
+        Ok(())
+    }
+}
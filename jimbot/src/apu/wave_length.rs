@@ -1,4 +1,5 @@
 use crate::apu::APU;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct WaveLength {
     nrx1: u8,
@@ -63,4 +64,21 @@ impl WaveLength {
     pub fn length(&self) -> u8 {
         self.nrx1 & 0b1_1111
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for WaveLength {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.nrx1);
+        state.write_u8(self.current_wave_duty_position);
+        state.write_bool(self.is_length_enable);
+        state.write_u8(self.length_timer);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.nrx1 = state.read_u8()?;
+        self.current_wave_duty_position = state.read_u8()?;
+        self.is_length_enable = state.read_bool()?;
+        self.length_timer = state.read_u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+/// How the machine starts
+///
+/// Cartridges with the CGB flag run in CGB mode unless a dmg boot rom is given with `Custom`.
+pub enum BootMode {
+    /// runs the dmg boot rom built into the crate (feature `embedded-boot-rom`), skipped in CGB mode
+    #[cfg(feature = "embedded-boot-rom")]
+    Embedded,
+    /// starts at $0100 with the cpu and io registers left by the boot rom
+    Skip,
+    /// runs a boot rom loaded by the user, mapped at $0000-$00FF until $FF50 is written.
+    /// A CGB boot rom (0x900 bytes) is also mapped at $0200-$08FF and runs every cartridge in CGB hardware,
+    /// any other size is a dmg boot rom cut or padded with $FF to 0x100 bytes.
+    Custom(Vec<u8>),
+}
+
+impl Default for BootMode {
+    #[cfg(feature = "embedded-boot-rom")]
+    fn default() -> Self {
+        BootMode::Embedded
+    }
+
+    #[cfg(not(feature = "embedded-boot-rom"))]
+    fn default() -> Self {
+        BootMode::Skip
+    }
+}
+
+impl BootMode {
+    /// boot rom to map, `None` when the boot is skipped.
+    /// There is no embedded cgb boot rom, `cgb` cartridges skip the boot
+    #[cfg_attr(not(feature = "embedded-boot-rom"), allow(unused_variables))]
+    pub(crate) fn into_boot_rom(self, cgb: bool) -> Option<Vec<u8>> {
+        match self {
+            #[cfg(feature = "embedded-boot-rom")]
+            BootMode::Embedded if cgb => None,
+            #[cfg(feature = "embedded-boot-rom")]
+            BootMode::Embedded => Some(include_bytes!("../roms/dmg_boot.bin").to_vec()),
+            BootMode::Skip => None,
+            BootMode::Custom(mut boot_rom) => {
+                if boot_rom.len() != CGB_BOOT_ROM_SIZE {
+                    boot_rom.resize(DMG_BOOT_ROM_SIZE, 0xFF);
+                }
+                Some(boot_rom)
+            }
+        }
+    }
+}
+
+/// Size of the dmg boot rom, $0000-$00FF
+pub const DMG_BOOT_ROM_SIZE: usize = 0x100;
+
+/// Size of the cgb boot rom, $0000-$00FF and $0200-$08FF
+pub const CGB_BOOT_ROM_SIZE: usize = 0x900;
+
+/// DMG register values at $0100, after the boot rom ran
+pub(crate) mod post_boot {
+    pub const AF: u16 = 0x01B0;
+    pub const BC: u16 = 0x0013;
+    pub const DE: u16 = 0x00D8;
+    pub const HL: u16 = 0x014D;
+    pub const SP: u16 = 0xFFFE;
+    pub const PC: u16 = 0x0100;
+
+    /// internal 16 bits divider counter, DIV reads $AB
+    pub const DIV_COUNTER: u16 = 0xABCC;
+
+    /// io registers in write order, NR52 first so the apu is powered
+    pub const IO: [(u16, u8); 32] = [
+        (0xFF26, 0xF1),
+        (0xFF00, 0xCF),
+        (0xFF01, 0x00),
+        (0xFF02, 0x7E),
+        (0xFF05, 0x00),
+        (0xFF06, 0x00),
+        (0xFF07, 0xF8),
+        (0xFF0F, 0xE1),
+        (0xFF10, 0x80),
+        (0xFF11, 0xBF),
+        (0xFF12, 0xF3),
+        (0xFF13, 0xFF),
+        (0xFF14, 0xBF),
+        (0xFF16, 0x3F),
+        (0xFF17, 0x00),
+        (0xFF18, 0xFF),
+        (0xFF19, 0xBF),
+        (0xFF1A, 0x7F),
+        (0xFF1B, 0xFF),
+        (0xFF1C, 0x9F),
+        (0xFF1D, 0xFF),
+        (0xFF1E, 0xBF),
+        (0xFF20, 0xFF),
+        (0xFF21, 0x00),
+        (0xFF22, 0x00),
+        (0xFF23, 0xBF),
+        (0xFF24, 0x77),
+        (0xFF25, 0xF3),
+        (0xFF40, 0x91),
+        (0xFF47, 0xFC),
+        (0xFF48, 0xFF),
+        (0xFF49, 0xFF),
+    ];
+
+    /// CGB cpu registers at $0100 for a cgb cartridge, A=$11 tells the game it runs on a CGB
+    pub mod cgb {
+        pub const AF: u16 = 0x1180;
+        pub const BC: u16 = 0x0000;
+        pub const DE: u16 = 0xFF56;
+        pub const HL: u16 = 0x000D;
+    }
+
+    /// SGB cpu registers at $0100, A=$01 like the DMG
+    pub mod sgb {
+        pub const AF: u16 = 0x0100;
+        pub const BC: u16 = 0x0014;
+        pub const DE: u16 = 0x0000;
+        pub const HL: u16 = 0xC060;
+    }
+}
@@ -1,32 +1,58 @@
 use std::str;
+use crate::cartridge::cartridge_error::CartridgeError;
 use crate::cartridge::cartridge_mbc1::CartridgeMBC1;
-use crate::cartridge::cartridge_mbc1_ram::CartridgeMBC1Ram;
-use crate::cartridge::cartridge_mbc1_ram_battery::CartridgeMBC1RamBattery;
-use crate::cartridge::cartridge_mbc2_battery::CartridgeMBC2Battery;
-use crate::cartridge::cartridge_mbc3_ram_battery::CartridgeMBC3RamBattery;
+use crate::cartridge::cartridge_mbc2::CartridgeMBC2;
+use crate::cartridge::cartridge_mbc3::CartridgeMBC3;
+use crate::cartridge::cartridge_mbc7::CartridgeMBC7;
+use crate::cartridge::cartridge_camera::CartridgeCamera;
+use crate::cartridge::cartridge_huc1::CartridgeHuC1;
+use crate::cartridge::cartridge_huc3::CartridgeHuC3;
+use crate::cartridge::cartridge_mmm01::CartridgeMMM01;
+use crate::cartridge::cartridge_tama5::CartridgeTama5;
 use crate::cartridge::cartridge_rom_only::CartridgeRomOnly;
 use crate::cartridge::cartridge_type::CartridgeType;
+use crate::cartridge::image_source::ImageSource;
+use crate::cartridge::infrared::IrTransceiver;
 use crate::cartridge::metadata::Metadata;
 use crate::cartridge::ram_size_type::RamSize;
 use crate::cartridge::rom_size_type::RomSize;
+use crate::cartridge::save_storage::{FileStorage, SaveStorage};
+use crate::save_state::{SaveState, SaveStateError};
 
 use self::cartridge_mbc5::CartridgeMBC5;
-use self::cartridge_mbc5_ram_battery::CartridgeMBC5RamBattery;
 
 pub mod cartridge_type;
+pub mod cartridge_error;
 pub mod ram_size_type;
 pub mod rom_size_type;
 mod cartridge_rom_only;
 mod cartridge_mbc1;
-mod cartridge_mbc1_ram_battery;
-mod metadata;
-mod cartridge_mbc3_ram_battery;
-mod cartridge_mbc5_ram_battery;
+pub mod metadata;
+pub mod licensee;
+pub mod save_storage;
+mod cartridge_mbc3;
 mod cartridge_mbc5;
-mod cartridge_mbc1_ram;
-mod cartridge_mbc2_battery;
+mod cartridge_mbc2;
+mod cartridge_mbc7;
+mod cartridge_camera;
+pub mod image_source;
+mod cartridge_huc1;
+mod cartridge_huc3;
+mod cartridge_mmm01;
+mod cartridge_tama5;
+pub mod infrared;
+mod cartridge_ram;
+mod rtc;
+mod eeprom;
 
-pub trait Cartridge: Sync + Send {
+/// Called with the motor state (true when on) when a rumble cartridge changes it
+pub type RumbleCallback = Box<dyn FnMut(bool) + Send + Sync>;
+
+/// Called with the tone number (0-15) when a HuC3 cartridge plays a tone on its speaker
+pub type ToneCallback = Box<dyn FnMut(u8) + Send + Sync>;
+
+/// `SaveState` saves the mapper registers and cartridge ram, not the rom
+pub trait Cartridge: SaveState + Sync + Send {
     // fn new(file_path: &str) -> Self;
     // fn new_bytes(bytes: Vec<u8>) -> Self;
     fn get(&self, address: usize) -> u8;
@@ -35,6 +61,31 @@ pub trait Cartridge: Sync + Send {
     fn metadata(&self) -> &Metadata;
     fn save_data(&self) -> Option<&Vec<u8>>;
     fn save_data_mut(&mut self) -> Option<&mut Vec<u8>>;
+    /// Called every m-cycle, for mappers with their own clock and to flush the battery backed ram
+    fn cycle(&mut self) {}
+    /// Writes the battery backed ram changed since the last flush to the save storage
+    fn flush_save(&mut self) {}
+    /// Stops writing the battery backed ram to the save storage, the ram is kept
+    fn detach_save_storage(&mut self) {}
+    /// Registers of the real time clock, `None` without clock.
+    /// Movies record them since the clock catches up on the host time when the cartridge is loaded
+    fn clock_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+    /// Restores registers returned by `clock_state`
+    fn load_clock_state(&mut self, _bytes: &[u8]) -> Result<(), SaveStateError> {
+        Err(SaveStateError::InvalidValue("cartridge without clock"))
+    }
+    /// Ignored by cartridges without rumble motor
+    fn set_rumble_callback(&mut self, _callback: RumbleCallback) {}
+    /// Ignored by cartridges without accelerometer, see `Jimbot::set_tilt`
+    fn set_tilt(&mut self, _x: f32, _y: f32) {}
+    /// Ignored by cartridges without camera
+    fn set_image_source(&mut self, _source: Box<dyn ImageSource>) {}
+    /// Ignored by cartridges without infrared port
+    fn set_ir_transceiver(&mut self, _transceiver: Box<dyn IrTransceiver>) {}
+    /// Ignored by cartridges without tone generator
+    fn set_tone_callback(&mut self, _callback: ToneCallback) {}
 
     // fn get_title(&self) -> &str {
     //     str::from_utf8(self.data()[TITLE_ADDRESS_MIN..=Meta TITLE_ADDRESS_MAX]).expect("NO TITLE")
@@ -49,38 +100,57 @@ pub trait Cartridge: Sync + Send {
     // }
 }
 
-pub fn new_cartridge_from_bytes(bytes: Vec<u8>) -> Box<dyn Cartridge> {
-    let metadata = Metadata::from(&bytes);
-    match metadata.cartridge_type() {
-        CartridgeType::RomOnly => Box::new(CartridgeRomOnly::new(bytes)),
-        CartridgeType::RomMbc1 => Box::new(CartridgeMBC1::new(metadata, bytes)),
-        CartridgeType::RomMbc5 => Box::new(CartridgeMBC5::new(bytes)),
-        CartridgeType::RomMbc1Ram => Box::new(CartridgeMBC1Ram::new(metadata, bytes)),
-        CartridgeType::RomMbc1RamBattery => Box::new(CartridgeMBC1RamBattery::new(None, metadata, bytes)),
-        CartridgeType::RomMbc2Battery => Box::new(CartridgeMBC2Battery::new(None, metadata, bytes)),
-        CartridgeType::RomMbc3RamBattery => Box::new(CartridgeMBC3RamBattery::new(None, metadata, bytes)),
-        CartridgeType::RomMbc5RamBattery => Box::new(CartridgeMBC5RamBattery::new(None, metadata, bytes)),
-        _ => panic!("Unsupported cartridge: {:?}", metadata.cartridge_type()),
-    }
+pub fn new_cartridge_from_bytes(bytes: Vec<u8>) -> Result<Box<dyn Cartridge>, CartridgeError> {
+    new_cartridge(None, bytes)
 }
 
-pub fn new_cartridge_from_file_path(file_path: String) -> Box<dyn Cartridge> {
-    let bytes = std::fs::read(&file_path).unwrap();
-    let metadata = Metadata::from(&bytes);
-    println!("{:#?}", metadata);
-    match metadata.cartridge_type() {
-        CartridgeType::RomOnly => Box::new(CartridgeRomOnly::new(bytes)),
-        CartridgeType::RomMbc1 => Box::new(CartridgeMBC1::new(metadata, bytes)),
-        CartridgeType::RomMbc5 => Box::new(CartridgeMBC5::new(bytes)),
-        CartridgeType::RomMbc1Ram => Box::new(CartridgeMBC1Ram::new(metadata, bytes)),
-        CartridgeType::RomMbc1RamBattery => Box::new(CartridgeMBC1RamBattery::new(Some(file_path), metadata, bytes)),
-        CartridgeType::RomMbc2Battery => Box::new(CartridgeMBC2Battery::new(Some(file_path), metadata, bytes)),
-        CartridgeType::RomMbc3RamBattery => Box::new(CartridgeMBC3RamBattery::new(Some(file_path), metadata, bytes)),
-        CartridgeType::RomMbc5RamBattery => Box::new(CartridgeMBC5RamBattery::new(Some(file_path), metadata, bytes)),
-        _ => panic!("Unsupported cartridge: {:?}", metadata.cartridge_type()),
-    }
+/// Battery backed ram is saved as `<title>.sav` next to the rom file
+pub fn new_cartridge_from_file_path(file_path: String) -> Result<Box<dyn Cartridge>, CartridgeError> {
+    let bytes = std::fs::read(&file_path)?;
+    let metadata = Metadata::from_rom(&bytes)?;
+    let storage = FileStorage::next_to_rom(&file_path, metadata.title());
+    new_cartridge(Some(Box::new(storage)), bytes)
+}
+
+/// Battery backed ram is loaded from and saved to `storage`
+pub fn new_cartridge_with_storage(bytes: Vec<u8>, storage: Box<dyn SaveStorage>) -> Result<Box<dyn Cartridge>, CartridgeError> {
+    new_cartridge(Some(storage), bytes)
 }
 
+fn new_cartridge(storage: Option<Box<dyn SaveStorage>>, bytes: Vec<u8>) -> Result<Box<dyn Cartridge>, CartridgeError> {
+    let metadata = Metadata::from_rom(&bytes)?;
+    println!("{}", metadata);
+    Ok(match metadata.cartridge_type() {
+        CartridgeType::RomOnly
+        | CartridgeType::RomRam
+        | CartridgeType::RomRamBattery => Box::new(CartridgeRomOnly::new(storage, metadata, bytes)?),
+        CartridgeType::RomMbc1
+        | CartridgeType::RomMbc1Ram
+        | CartridgeType::RomMbc1RamBattery => Box::new(CartridgeMBC1::new(storage, metadata, bytes)?),
+        CartridgeType::RomMbc2
+        | CartridgeType::RomMbc2Battery => Box::new(CartridgeMBC2::new(storage, metadata, bytes)?),
+        CartridgeType::RomMbc3
+        | CartridgeType::RomMbc3Ram
+        | CartridgeType::RomMbc3RamBattery
+        | CartridgeType::RomMbc3TimerBattery
+        | CartridgeType::RomMbc3TimerRamBattery => Box::new(CartridgeMBC3::new(storage, metadata, bytes)?),
+        CartridgeType::RomMbc5
+        | CartridgeType::RomMbc5Ram
+        | CartridgeType::RomMbc5RamBattery
+        | CartridgeType::RomMbc5Rumble
+        | CartridgeType::RomMbc5RumbleSram
+        | CartridgeType::RomMbc5RumbleSramBattery => Box::new(CartridgeMBC5::new(storage, metadata, bytes)?),
+        CartridgeType::RomMbc7SensorRumbleRamBattery => Box::new(CartridgeMBC7::new(storage, metadata, bytes)?),
+        CartridgeType::PocketCamera => Box::new(CartridgeCamera::new(storage, metadata, bytes)?),
+        CartridgeType::HudsonHuC1 => Box::new(CartridgeHuC1::new(storage, metadata, bytes)?),
+        CartridgeType::HudsonHuC3 => Box::new(CartridgeHuC3::new(storage, metadata, bytes)?),
+        CartridgeType::RomMmm01
+        | CartridgeType::RomMmm01Sram
+        | CartridgeType::RomMmm01SramBattery => Box::new(CartridgeMMM01::new(storage, metadata, bytes)?),
+        CartridgeType::BandaiTama5 => Box::new(CartridgeTama5::new(storage, metadata, bytes)?),
+        cartridge_type => return Err(CartridgeError::UnsupportedMapper(*cartridge_type)),
+    })
+}
 
 // pub fn new_cartridge_from_bytes(bytes: Vec<u8>) -> Box<dyn Cartridge> {
 //     let metadata = Metadata::from(&bytes);
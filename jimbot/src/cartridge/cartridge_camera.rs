@@ -0,0 +1,228 @@
+use crate::cartridge::Cartridge;
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::cartridge::cartridge_ram::CartridgeRam;
+use crate::cartridge::image_source::{ImageSource, TestPattern, HEIGHT, WIDTH};
+use crate::cartridge::metadata::Metadata;
+use crate::cartridge::save_storage::SaveStorage;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Game Boy Camera (Pocket Camera): 64 rom banks, 16 battery backed ram banks and the sensor registers.
+///
+/// $0000-$1FFF enables the ram writes ($0A), the ram can always be read. $2000-$3FFF selects the rom bank
+/// (bank 0 can be mapped at $4000), $4000-$5FFF the ram bank, or the sensor registers at $A000-$A07F
+/// (repeated every $80 bytes) when bit 4 is set:
+/// - $A000: bit 0 starts a capture and reads 1 until it is done, bits 1-2 are kept
+/// - $A001: bit 7 N, bits 5-6 edge enhancement mode (VH), bits 0-4 gain
+/// - $A002-$A003: exposure time, big endian
+/// - $A004: bits 4-6 edge enhancement ratio, bit 3 invert
+/// - $A005: reference voltages
+/// - $A006-$A035: 4×4 dither matrix, 3 thresholds per pixel
+///
+/// The other registers are write only and read $00. A capture takes 32446 m-cycles (+512 without N)
+/// plus 16 per exposure step, then the 128×112 image is written as 2bpp tiles at $A100 of ram bank 0.
+/// The gain and voltages are not emulated, the image is scaled by the exposure ($1000 keeps it unchanged).
+pub struct CartridgeCamera {
+    metadata: Metadata,
+    rom_bank: u8,
+    ram_bank: u8,
+    registers_mapped: bool,
+    ram_write_enabled: bool,
+    registers: [u8; CartridgeCamera::REGISTERS],
+    /// m-cycles before the end of the capture, 0 when idle
+    busy: u32,
+    source: Box<dyn ImageSource>,
+    data: Vec<u8>,
+    ram: CartridgeRam,
+}
+
+impl Cartridge for CartridgeCamera {
+    fn get(&self, address: usize) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.data[address],
+            0x4000..=0x7FFF => {
+                let banks = self.data.len() / 0x4000;
+                self.data[0x4000 * (self.rom_bank as usize % banks) + (address - 0x4000)]
+            }
+            0xA000..=0xBFFF if self.registers_mapped => match address & 0x7F {
+                0x00 => self.registers[0] & 0x06 | (self.busy > 0) as u8,
+                _ => 0x00,
+            },
+            0xA000..=0xBFFF => self.ram.get(self.ram_address(address)),
+            _ => panic!("Cartridge Camera GET {:#06X}", address),
+        }
+    }
+
+    fn set(&mut self, address: usize, val: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_write_enabled = val & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = val & 0x3F,
+            0x4000..=0x5FFF => {
+                self.registers_mapped = val & 0x10 != 0;
+                self.ram_bank = val & 0x0F;
+            }
+            0x6000..=0x7FFF => {}
+            0xA000..=0xBFFF if self.registers_mapped => match address & 0x7F {
+                0x00 => {
+                    if val & 1 == 1 && self.busy == 0 {
+                        self.busy = self.capture_m_cycles();
+                    }
+                    self.registers[0] = val & 0x07;
+                }
+                register @ 0x01..Self::REGISTERS => self.registers[register] = val,
+                _ => {}
+            },
+            0xA000..=0xBFFF => {
+                if self.ram_write_enabled {
+                    self.ram.set(self.ram_address(address), val);
+                }
+            }
+            _ => println!("Cartridge Camera SET {:#06X} {:04X}", address, val),
+        }
+    }
+
+    fn data(&self) -> &Vec<u8> {
+        &self.data
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn save_data(&self) -> Option<&Vec<u8>> {
+        self.ram.save_data()
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut Vec<u8>> {
+        self.ram.save_data_mut()
+    }
+
+    fn cycle(&mut self) {
+        if self.busy > 0 {
+            self.busy -= 1;
+            if self.busy == 0 {
+                self.capture();
+            }
+        }
+        self.ram.cycle_and_flush();
+    }
+
+    fn flush_save(&mut self) {
+        self.ram.flush_save();
+    }
+
+    fn detach_save_storage(&mut self) {
+        self.ram.detach_storage();
+    }
+
+    fn set_image_source(&mut self, source: Box<dyn ImageSource>) {
+        self.source = source;
+    }
+}
+
+impl CartridgeCamera {
+    const REGISTERS: usize = 0x36;
+    const RAM_SIZE: usize = 0x20000;
+    /// offset of the captured image in ram bank 0
+    const IMAGE_ADDRESS: usize = 0x0100;
+    const DITHER_MATRIX: usize = 0x06;
+    const EDGE_RATIOS: [f32; 8] = [0.5, 0.75, 1.0, 1.25, 2.0, 3.0, 4.0, 5.0];
+
+    /// The image source is a `TestPattern` until the frontend gives one
+    pub fn new(storage: Option<Box<dyn SaveStorage>>, metadata: Metadata, bytes: Vec<u8>) -> Result<Self, CartridgeError> {
+        let mut ram = CartridgeRam::new(Self::RAM_SIZE, metadata.cartridge_type().has_battery(), storage);
+        ram.load(&[])?;
+        // reads are always enabled, writes are gated by `ram_write_enabled`
+        ram.set_enabled(true);
+        Ok(Self {
+            metadata,
+            rom_bank: 1,
+            ram_bank: 0,
+            registers_mapped: false,
+            ram_write_enabled: false,
+            registers: [0; Self::REGISTERS],
+            busy: 0,
+            source: Box::<TestPattern>::default(),
+            data: bytes,
+            ram,
+        })
+    }
+
+    fn ram_address(&self, address: usize) -> usize {
+        0x2000 * self.ram_bank as usize + (address - 0xA000)
+    }
+
+    fn exposure(&self) -> u16 {
+        u16::from_be_bytes([self.registers[2], self.registers[3]])
+    }
+
+    fn capture_m_cycles(&self) -> u32 {
+        let n = self.registers[1] & 0x80 != 0;
+        32446 + if n { 0 } else { 512 } + 16 * self.exposure() as u32
+    }
+
+    /// Reads the sensor, then writes the processed image to the ram
+    fn capture(&mut self) {
+        let mut pixels = vec![0; WIDTH * HEIGHT];
+        self.source.capture(&mut pixels);
+        let invert = self.registers[4] & 0x08 != 0;
+        let exposure = self.exposure() as f32 / 0x1000 as f32;
+        let sensor: Vec<f32> = pixels.into_iter()
+            .map(|pixel| if invert { 255 - pixel } else { pixel })
+            .map(|pixel| pixel as f32 * exposure)
+            .collect();
+        let at = |x: isize, y: isize| sensor[y.clamp(0, HEIGHT as isize - 1) as usize * WIDTH + x.clamp(0, WIDTH as isize - 1) as usize];
+        let ratio = Self::EDGE_RATIOS[(self.registers[4] >> 4 & 0x07) as usize];
+        let mode = self.registers[1] >> 5 & 0b11;
+
+        let mut tiles = vec![0u8; WIDTH * HEIGHT / 4];
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let (xi, yi) = (x as isize, y as isize);
+                let mut value = at(xi, yi);
+                if mode & 0b01 != 0 {
+                    value += ratio * (2.0 * at(xi, yi) - at(xi - 1, yi) - at(xi + 1, yi));
+                }
+                if mode & 0b10 != 0 {
+                    value += ratio * (2.0 * at(xi, yi) - at(xi, yi - 1) - at(xi, yi + 1));
+                }
+                let matrix = Self::DITHER_MATRIX + ((y % 4) * 4 + x % 4) * 3;
+                let thresholds = &self.registers[matrix..matrix + 3];
+                let color = thresholds.iter().take_while(|threshold| value >= **threshold as f32).count();
+                // 3 is black
+                let color = 3 - color as u8;
+                let offset = ((y / 8) * (WIDTH / 8) + x / 8) * 16 + (y % 8) * 2;
+                let bit = 7 - x % 8;
+                tiles[offset] |= (color & 1) << bit;
+                tiles[offset + 1] |= (color >> 1) << bit;
+            }
+        }
+        for (i, byte) in tiles.into_iter().enumerate() {
+            self.ram.set(Self::IMAGE_ADDRESS + i, byte);
+        }
+    }
+}
+
+/// The image source is not saved, a capture in progress reads the current source when it ends
+impl SaveState for CartridgeCamera {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.rom_bank);
+        state.write_u8(self.ram_bank);
+        state.write_bool(self.registers_mapped);
+        state.write_bool(self.ram_write_enabled);
+        state.write_bytes(&self.registers);
+        state.write_u32(self.busy);
+        self.ram.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.rom_bank = state.read_u8()?;
+        self.ram_bank = state.read_u8()?;
+        self.registers_mapped = state.read_bool()?;
+        self.ram_write_enabled = state.read_bool()?;
+        state.read_bytes(&mut self.registers)?;
+        self.busy = state.read_u32()?;
+        self.ram.load_state(state)?;
+        if self.rom_bank > 0x3F || self.ram_bank > 0x0F { return Err(SaveStateError::InvalidValue("camera banks")); }
+        Ok(())
+    }
+}
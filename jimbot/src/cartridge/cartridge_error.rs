@@ -0,0 +1,45 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::cartridge::cartridge_type::CartridgeType;
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    /// rom is smaller than its header or than the rom size declared in the header
+    TruncatedRom { size: usize, expected: usize },
+    UnknownType(u8),
+    UnknownRomSize(u8),
+    UnknownRamSize(u8),
+    UnsupportedMapper(CartridgeType),
+    /// existing save file does not match the cartridge ram size
+    BadSaveSize { path: String, size: u64, expected: u64 },
+    Io(std::io::Error),
+}
+
+impl Display for CartridgeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CartridgeError::TruncatedRom { size, expected } => write!(f, "Rom is truncated, size: {} bytes, expected: {} bytes", size, expected),
+            CartridgeError::UnknownType(byte) => write!(f, "Unknown cartridge type: {:#04X}", byte),
+            CartridgeError::UnknownRomSize(code) => write!(f, "Unknown rom size code: {:#04X}", code),
+            CartridgeError::UnknownRamSize(code) => write!(f, "Unknown ram size code: {:#04X}", code),
+            CartridgeError::UnsupportedMapper(cartridge_type) => write!(f, "Unsupported cartridge: {:?}", cartridge_type),
+            CartridgeError::BadSaveSize { path, size, expected } => write!(f, "Corrupted save file: {}, size: {}, ram size: {}", path, size, expected),
+            CartridgeError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for CartridgeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CartridgeError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CartridgeError {
+    fn from(from: std::io::Error) -> Self {
+        CartridgeError::Io(from)
+    }
+}
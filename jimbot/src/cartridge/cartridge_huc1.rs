@@ -0,0 +1,121 @@
+use crate::cartridge::Cartridge;
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::cartridge::cartridge_ram::CartridgeRam;
+use crate::cartridge::infrared::{IrPort, IrTransceiver};
+use crate::cartridge::metadata::Metadata;
+use crate::cartridge::save_storage::SaveStorage;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Hudson HuC1, battery backed ram and an infrared port.
+///
+/// $0000-$1FFF maps the IR port at $A000-$BFFF when $0E is written, the ram otherwise (the ram has no enable).
+/// $2000-$3FFF selects the 6 bits rom bank (bank 0 can be mapped at $4000), $4000-$5FFF the ram bank.
+/// In IR mode reads return $C1 while light is received ($C0 otherwise) and bit 0 of writes drives the LED.
+pub struct CartridgeHuC1 {
+    metadata: Metadata,
+    rom_bank: u8,
+    ram_bank: u8,
+    ir_mode: bool,
+    ir: IrPort,
+    data: Vec<u8>,
+    ram: CartridgeRam,
+}
+
+impl Cartridge for CartridgeHuC1 {
+    fn get(&self, address: usize) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.data[address],
+            0x4000..=0x7FFF => {
+                let banks = self.data.len() / 0x4000;
+                self.data[0x4000 * (self.rom_bank as usize % banks) + (address - 0x4000)]
+            }
+            0xA000..=0xBFFF if self.ir_mode => 0xC0 | self.ir.receiving() as u8,
+            0xA000..=0xBFFF => self.ram.get(0x2000 * self.ram_bank as usize + (address - 0xA000)),
+            _ => panic!("Cartridge HuC1 GET {:#06X}", address),
+        }
+    }
+
+    fn set(&mut self, address: usize, val: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ir_mode = val & 0x0F == 0x0E,
+            0x2000..=0x3FFF => self.rom_bank = val & 0x3F,
+            0x4000..=0x5FFF => self.ram_bank = val & 0x03,
+            0x6000..=0x7FFF => {}
+            0xA000..=0xBFFF if self.ir_mode => self.ir.set_led(val & 1 == 1),
+            0xA000..=0xBFFF => self.ram.set(0x2000 * self.ram_bank as usize + (address - 0xA000), val),
+            _ => println!("Cartridge HuC1 SET {:#06X} {:04X}", address, val),
+        }
+    }
+
+    fn data(&self) -> &Vec<u8> {
+        &self.data
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn save_data(&self) -> Option<&Vec<u8>> {
+        self.ram.save_data()
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut Vec<u8>> {
+        self.ram.save_data_mut()
+    }
+
+    fn cycle(&mut self) {
+        self.ram.cycle_and_flush();
+    }
+
+    fn flush_save(&mut self) {
+        self.ram.flush_save();
+    }
+
+    fn detach_save_storage(&mut self) {
+        self.ram.detach_storage();
+    }
+
+    fn set_ir_transceiver(&mut self, transceiver: Box<dyn IrTransceiver>) {
+        self.ir.set_transceiver(transceiver);
+    }
+}
+
+impl CartridgeHuC1 {
+    pub fn new(storage: Option<Box<dyn SaveStorage>>, metadata: Metadata, bytes: Vec<u8>) -> Result<Self, CartridgeError> {
+        let cartridge_type = metadata.cartridge_type();
+        let ram_size = if cartridge_type.has_ram() { metadata.ram_size().size as usize } else { 0 };
+        let mut ram = CartridgeRam::new(ram_size, cartridge_type.has_battery(), storage);
+        ram.load(&[])?;
+        ram.set_enabled(true);
+        Ok(Self {
+            metadata,
+            rom_bank: 1,
+            ram_bank: 0,
+            ir_mode: false,
+            ir: IrPort::default(),
+            data: bytes,
+            ram,
+        })
+    }
+}
+
+impl SaveState for CartridgeHuC1 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.rom_bank);
+        state.write_u8(self.ram_bank);
+        state.write_bool(self.ir_mode);
+        state.write_bool(self.ir.led());
+        self.ram.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.rom_bank = state.read_u8()?;
+        self.ram_bank = state.read_u8()?;
+        self.ir_mode = state.read_bool()?;
+        let led = state.read_bool()?;
+        self.ram.load_state(state)?;
+        if self.rom_bank > 0x3F || self.ram_bank > 0x03 { return Err(SaveStateError::InvalidValue("huc1 banks")); }
+        self.ir.set_led(led);
+        Ok(())
+    }
+}
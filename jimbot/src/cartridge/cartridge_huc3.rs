@@ -0,0 +1,313 @@
+use crate::cartridge::{Cartridge, ToneCallback};
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::cartridge::cartridge_ram::CartridgeRam;
+use crate::cartridge::infrared::{IrPort, IrTransceiver};
+use crate::cartridge::metadata::Metadata;
+use crate::cartridge::rtc::unix_time;
+use crate::cartridge::save_storage::SaveStorage;
+use crate::save_state;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Hudson HuC3, battery backed ram, a clock counting minutes and days, a tone generator and an infrared port.
+///
+/// $0000-$1FFF selects what is mapped at $A000-$BFFF: $00 ram (read only), $0A ram, $0B clock command (write),
+/// $0C clock response (read), $0D clock semaphore, $0E IR port (reads $C1 while light is received, bit 0 of writes
+/// drives the LED), anything else reads $FF. $2000-$3FFF selects the 7 bits rom bank, $4000-$5FFF the ram bank.
+///
+/// The clock is driven through 256 nibbles of memory: a command is the upper nibble of the value written in mode $0B
+/// with the lower nibble as argument:
+/// - $1: the response is the nibble at the address, the address is incremented
+/// - $3: writes the argument at the address, the address is incremented
+/// - $4/$5: set the low/high nibble of the address
+/// - $6: $60 copies the clock in nibbles $00-$05 (12 bits minute of the day, 12 bits day, low nibble first),
+///   $61 sets the clock from them, $62 answers 1 (clock ok), $6E plays the tone in nibble $27
+///
+/// Reads in mode $0C return the last command in the upper nibble and the response in the lower one,
+/// commands execute at once so the semaphore always reads 1 (ready).
+pub struct CartridgeHuC3 {
+    metadata: Metadata,
+    rom_bank: u8,
+    ram_bank: u8,
+    mode: u8,
+    clock: HuC3Clock,
+    memory: [u8; 256],
+    address: u8,
+    command: u8,
+    response: u8,
+    tone_callback: Option<ToneCallback>,
+    ir: IrPort,
+    data: Vec<u8>,
+    ram: CartridgeRam,
+}
+
+/// Minutes of the day (0-1439) and a 12 bits day counter
+#[derive(Default)]
+struct HuC3Clock {
+    minutes: u16,
+    days: u16,
+    /// m-cycles since the last minute
+    sub_minute: u32,
+}
+
+impl HuC3Clock {
+    const M_CYCLES_PER_SECOND: u32 = 1 << 20;
+    const M_CYCLES_PER_MINUTE: u32 = 60 * Self::M_CYCLES_PER_SECOND;
+    const MINUTES_PER_DAY: u16 = 1440;
+
+    fn cycle(&mut self) {
+        self.sub_minute += 1;
+        if self.sub_minute == Self::M_CYCLES_PER_MINUTE {
+            self.sub_minute = 0;
+            self.advance_minutes(1);
+        }
+    }
+
+    fn advance_minutes(&mut self, minutes: u64) {
+        let total = self.minutes as u64 + minutes;
+        self.minutes = (total % Self::MINUTES_PER_DAY as u64) as u16;
+        self.days = ((self.days as u64 + total / Self::MINUTES_PER_DAY as u64) & 0xFFF) as u16;
+    }
+
+    /// Moves the clock forward by `seconds` of host time
+    fn advance(&mut self, seconds: u64) {
+        let m_cycles = self.sub_minute as u64 + (seconds % 60) * Self::M_CYCLES_PER_SECOND as u64;
+        self.advance_minutes(seconds / 60 + m_cycles / Self::M_CYCLES_PER_MINUTE as u64);
+        self.sub_minute = (m_cycles % Self::M_CYCLES_PER_MINUTE as u64) as u32;
+    }
+}
+
+impl Cartridge for CartridgeHuC3 {
+    fn get(&self, address: usize) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.data[address],
+            0x4000..=0x7FFF => {
+                let banks = self.data.len() / 0x4000;
+                self.data[0x4000 * (self.rom_bank as usize % banks) + (address - 0x4000)]
+            }
+            0xA000..=0xBFFF => match self.mode {
+                0x00 | 0x0A => self.ram.get(self.ram_address(address)),
+                0x0C => (self.command << 4) | self.response,
+                0x0D => 0x01,
+                0x0E => 0xC0 | self.ir.receiving() as u8,
+                _ => 0xFF,
+            },
+            _ => panic!("Cartridge HuC3 GET {:#06X}", address),
+        }
+    }
+
+    fn set(&mut self, address: usize, val: u8) {
+        match address {
+            0x0000..=0x1FFF => self.mode = val & 0x0F,
+            0x2000..=0x3FFF => self.rom_bank = val & 0x7F,
+            0x4000..=0x5FFF => self.ram_bank = val & 0x0F,
+            0x6000..=0x7FFF => {}
+            0xA000..=0xBFFF => match self.mode {
+                0x0A => self.ram.set(self.ram_address(address), val),
+                0x0B => self.execute(val >> 4, val & 0x0F),
+                0x0E => self.ir.set_led(val & 1 == 1),
+                _ => {}
+            },
+            _ => println!("Cartridge HuC3 SET {:#06X} {:04X}", address, val),
+        }
+    }
+
+    fn data(&self) -> &Vec<u8> {
+        &self.data
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn save_data(&self) -> Option<&Vec<u8>> {
+        self.ram.save_data()
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut Vec<u8>> {
+        self.ram.save_data_mut()
+    }
+
+    fn cycle(&mut self) {
+        self.clock.cycle();
+        if self.ram.cycle() {
+            self.flush_save();
+        }
+    }
+
+    /// Always flushed to save the current host time with the clock
+    fn flush_save(&mut self) {
+        self.ram.flush(&self.footer(unix_time().unwrap_or(0)));
+    }
+
+    fn detach_save_storage(&mut self) {
+        self.ram.detach_storage();
+    }
+
+    fn clock_state(&self) -> Option<Vec<u8>> {
+        Some(save_state::to_bytes(&self.clock))
+    }
+
+    fn load_clock_state(&mut self, bytes: &[u8]) -> Result<(), SaveStateError> {
+        let mut clock = HuC3Clock::default();
+        save_state::load_from_bytes(&mut clock, bytes)?;
+        self.clock = clock;
+        Ok(())
+    }
+
+    fn set_ir_transceiver(&mut self, transceiver: Box<dyn IrTransceiver>) {
+        self.ir.set_transceiver(transceiver);
+    }
+
+    fn set_tone_callback(&mut self, callback: ToneCallback) {
+        self.tone_callback = Some(callback);
+    }
+}
+
+impl CartridgeHuC3 {
+    /// Footer appended to the ram in `.sav` files (as SameBoy): unix time of the save as u64 le, minutes and days
+    /// as u16 le, then the alarm minutes, days (u16 le) and enable (u8) which are not emulated
+    const FOOTER_SIZE: usize = 17;
+    const TONE_NIBBLE: usize = 0x27;
+
+    pub fn new(storage: Option<Box<dyn SaveStorage>>, metadata: Metadata, bytes: Vec<u8>) -> Result<Self, CartridgeError> {
+        let cartridge_type = metadata.cartridge_type();
+        let ram_size = if cartridge_type.has_ram() { metadata.ram_size().size as usize } else { 0 };
+        let mut ram = CartridgeRam::new(ram_size, cartridge_type.has_battery(), storage);
+        let footer = ram.load(&[Self::FOOTER_SIZE])?;
+        // the mode register replaces the ram enable
+        ram.set_enabled(true);
+        let mut clock = HuC3Clock::default();
+        if footer.len() == Self::FOOTER_SIZE {
+            let timestamp = u64::from_le_bytes(footer[0..8].try_into().unwrap());
+            clock.minutes = u16::from_le_bytes([footer[8], footer[9]]) % HuC3Clock::MINUTES_PER_DAY;
+            clock.days = u16::from_le_bytes([footer[10], footer[11]]) & 0xFFF;
+            if let Some(now) = unix_time() {
+                clock.advance(now.saturating_sub(timestamp));
+            }
+        }
+        Ok(Self {
+            metadata,
+            rom_bank: 1,
+            ram_bank: 0,
+            mode: 0,
+            clock,
+            memory: [0; 256],
+            address: 0,
+            command: 0,
+            response: 0,
+            tone_callback: None,
+            ir: IrPort::default(),
+            data: bytes,
+            ram,
+        })
+    }
+
+    fn ram_address(&self, address: usize) -> usize {
+        0x2000 * self.ram_bank as usize + (address - 0xA000)
+    }
+
+    fn execute(&mut self, command: u8, argument: u8) {
+        self.command = command;
+        match command {
+            0x1 => {
+                self.response = self.memory[self.address as usize];
+                self.address = self.address.wrapping_add(1);
+            }
+            0x3 => {
+                self.memory[self.address as usize] = argument;
+                self.address = self.address.wrapping_add(1);
+            }
+            0x4 => self.address = (self.address & 0xF0) | argument,
+            0x5 => self.address = (self.address & 0x0F) | (argument << 4),
+            0x6 => match argument {
+                0x0 => {
+                    for i in 0..3 {
+                        self.memory[i] = (self.clock.minutes >> (i * 4)) as u8 & 0x0F;
+                        self.memory[3 + i] = (self.clock.days >> (i * 4)) as u8 & 0x0F;
+                    }
+                }
+                0x1 => {
+                    let nibbles = |start: usize| (0..3).fold(0u16, |val, i| val | (self.memory[start + i] as u16) << (i * 4));
+                    self.clock.minutes = nibbles(0) % HuC3Clock::MINUTES_PER_DAY;
+                    self.clock.days = nibbles(3);
+                    self.clock.sub_minute = 0;
+                    self.ram.mark_dirty();
+                }
+                0x2 => self.response = 0x1,
+                0xE => {
+                    let tone = self.memory[Self::TONE_NIBBLE];
+                    if let Some(callback) = self.tone_callback.as_mut() {
+                        callback(tone);
+                    }
+                }
+                _ => println!("Unknown HuC3 clock command {:#04X}", 0x60 | argument),
+            },
+            _ => println!("Unknown HuC3 clock command {:#04X}", (command << 4) | argument),
+        }
+    }
+
+    fn footer(&self, timestamp: u64) -> [u8; Self::FOOTER_SIZE] {
+        let mut footer = [0; Self::FOOTER_SIZE];
+        footer[0..8].copy_from_slice(&timestamp.to_le_bytes());
+        footer[8..10].copy_from_slice(&self.clock.minutes.to_le_bytes());
+        footer[10..12].copy_from_slice(&self.clock.days.to_le_bytes());
+        footer
+    }
+}
+
+impl Drop for CartridgeHuC3 {
+    fn drop(&mut self) {
+        self.flush_save();
+    }
+}
+
+impl SaveState for CartridgeHuC3 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.rom_bank);
+        state.write_u8(self.ram_bank);
+        state.write_u8(self.mode);
+        self.clock.save_state(state);
+        state.write_bytes(&self.memory);
+        state.write_u8(self.address);
+        state.write_u8(self.command);
+        state.write_u8(self.response);
+        state.write_bool(self.ir.led());
+        self.ram.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.rom_bank = state.read_u8()?;
+        self.ram_bank = state.read_u8()?;
+        self.mode = state.read_u8()?;
+        self.clock.load_state(state)?;
+        state.read_bytes(&mut self.memory)?;
+        self.address = state.read_u8()?;
+        self.command = state.read_u8()?;
+        self.response = state.read_u8()?;
+        let led = state.read_bool()?;
+        self.ram.load_state(state)?;
+        if self.memory.iter().any(|nibble| *nibble > 0x0F) || self.command > 0x0F || self.response > 0x0F {
+            return Err(SaveStateError::InvalidValue("huc3 clock memory"));
+        }
+        self.ir.set_led(led);
+        Ok(())
+    }
+}
+
+impl SaveState for HuC3Clock {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.minutes);
+        state.write_u16(self.days);
+        state.write_u32(self.sub_minute);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.minutes = state.read_u16()?;
+        self.days = state.read_u16()?;
+        self.sub_minute = state.read_u32()?;
+        if self.minutes >= Self::MINUTES_PER_DAY || self.days > 0xFFF || self.sub_minute >= Self::M_CYCLES_PER_MINUTE {
+            return Err(SaveStateError::InvalidValue("huc3 clock"));
+        }
+        Ok(())
+    }
+}
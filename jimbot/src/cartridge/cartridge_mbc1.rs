@@ -1,39 +1,45 @@
 use crate::cartridge::Cartridge;
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::cartridge::cartridge_ram::CartridgeRam;
+use crate::cartridge::save_storage::SaveStorage;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 use crate::cartridge::metadata::Metadata;
 
+/// MBC1, with or without (battery backed) ram.
+///
+/// BANK1 ($2000-$3FFF) holds the 5 low bits of the rom bank, 0 selects 1 (so $20/$40/$60 select $21/$41/$61).
+/// BANK2 ($4000-$5FFF) holds 2 more bits for the rom banks >= $20, in mode 1 ($6000-$7FFF) it also maps
+/// bank $00/$20/$40/$60 at $0000-$3FFF and selects the ram bank. Bank numbers wrap on the rom and ram sizes.
+///
+/// MBC1M multicarts only wire 4 bits of BANK1 (BANK2 selects one of the 256 KiB games),
+/// they are detected by the Nintendo logo of another game header at bank $10, $20 or $30.
 pub struct CartridgeMBC1 {
     metadata: Metadata,
-    rom_hi_bank_number: u16,
-    ram_bank_number: u8,
-    switchable_mode: bool,
+    bank1: u8,
+    bank2: u8,
+    mode: bool,
+    multicart: bool,
     data: Vec<u8>,
+    ram: CartridgeRam,
 }
 
 impl Cartridge for CartridgeMBC1 {
     fn get(&self, address: usize) -> u8 {
         match address {
-            0x0000..=0x3FFF => self.data[address],
-            0x4000..=0x7FFF => self.data[(0x4000 * self.rom_hi_bank_number as usize) + (address - 0x4000)],
-            0xA000..=0xBFFF => {
-                println!("Reading external ram unsupported for type:{:?} address:{:#06x}", self.metadata.cartridge_type(), address);
-                0xFF
-            },
+            0x0000..=0x3FFF => self.data[self.rom_offset(self.low_rom_bank()) + address],
+            0x4000..=0x7FFF => self.data[self.rom_offset(self.high_rom_bank()) + (address - 0x4000)],
+            0xA000..=0xBFFF => self.ram.get(self.ram_address(address)),
             _ => panic!("Cartridge MBC1 GET {:#06X}", address)
         }
     }
 
     fn set(&mut self, address: usize, val: u8) {
         match address {
-            0x0000..=0x1FFF => {
-                // println!("Enable/disable external ram unsupported for type:{:?} address:{:#06x} value:{:#04x}", self.metadata.cartridge_type(), address, val)
-            },
-            0x2000..=0x3FFF => {
-                let new = (val as u16 & self.rom_number_bit_mask() as u16);
-                self.rom_hi_bank_number = if new == 0 { 1 } else { new };
-            }
-            0x4000..=0x5FFF => println!("Switching external ram bank unsupported for type:{:?} address:{:#06x} value:{:#04x}", self.metadata.cartridge_type(), address, val),
-            0x6000..=0x7FFF => self.switchable_mode = val & 1 == 1,
-            0xA000..=0xBFFF => println!("Writing external ram unsupported for type:{:?} address:{:#06x} value:{:#04x}", self.metadata.cartridge_type(), address, val),
+            0x0000..=0x1FFF => self.ram.set_enable(val),
+            0x2000..=0x3FFF => self.bank1 = if val & 0x1F == 0 { 1 } else { val & 0x1F },
+            0x4000..=0x5FFF => self.bank2 = val & 0b11,
+            0x6000..=0x7FFF => self.mode = val & 1 == 1,
+            0xA000..=0xBFFF => self.ram.set(self.ram_address(address), val),
             _ => panic!("Cartridge MBC1 SET {:#06X} {:04X}", address, val)
         }
     }
@@ -46,32 +52,101 @@ impl Cartridge for CartridgeMBC1 {
         &self.metadata
     }
 
-    fn save_data_mut(&mut self) -> Option<&mut Vec<u8>> { None }
-    fn save_data(&self) -> Option<&Vec<u8>> { None }
+    fn save_data_mut(&mut self) -> Option<&mut Vec<u8>> {
+        self.ram.save_data_mut()
+    }
+
+    fn save_data(&self) -> Option<&Vec<u8>> {
+        self.ram.save_data()
+    }
+
+    fn cycle(&mut self) {
+        self.ram.cycle_and_flush();
+    }
+
+    fn flush_save(&mut self) {
+        self.ram.flush_save();
+    }
+
+    fn detach_save_storage(&mut self) {
+        self.ram.detach_storage();
+    }
 }
 
 impl CartridgeMBC1 {
-    pub fn new(metadata: Metadata, bytes: Vec<u8>) -> Self {
-        Self {
+    const MULTICART_SIZE: usize = 1024 * 1024;
+    const LOGO: std::ops::Range<usize> = 0x0104..0x0134;
+
+    /// `storage` is only used by the battery backed type
+    pub fn new(storage: Option<Box<dyn SaveStorage>>, metadata: Metadata, bytes: Vec<u8>) -> Result<Self, CartridgeError> {
+        let cartridge_type = metadata.cartridge_type();
+        let ram_size = if cartridge_type.has_ram() { metadata.ram_size().size as usize } else { 0 };
+        let mut ram = CartridgeRam::new(ram_size, cartridge_type.has_battery(), storage);
+        ram.load(&[])?;
+        let multicart = Self::is_multicart(&bytes);
+        Ok(Self {
             metadata,
-            rom_hi_bank_number: 1,
-            ram_bank_number: 0,
-            switchable_mode: false,
+            bank1: 1,
+            bank2: 0,
+            mode: false,
+            multicart,
             data: bytes,
+            ram,
+        })
+    }
+
+    /// The games of a MBC1M multicart start every 256 KiB (bank $00, $10, $20 and $30) with their own header,
+    /// a logo at one of the banks $10/$20/$30 is enough (the other games may be empty)
+    fn is_multicart(bytes: &[u8]) -> bool {
+        if bytes.len() != Self::MULTICART_SIZE {
+            return false;
         }
+        (1..4).any(|game| {
+            let offset = game * Self::MULTICART_SIZE / 4;
+            bytes[Self::LOGO.start + offset..Self::LOGO.end + offset] == Metadata::NINTENDO_LOGO
+        })
+    }
+
+    /// bits of BANK1 wired to the rom
+    fn bank1_bits(&self) -> u8 {
+        if self.multicart { 4 } else { 5 }
     }
 
+    fn low_rom_bank(&self) -> usize {
+        if self.mode { (self.bank2 as usize) << self.bank1_bits() } else { 0 }
+    }
 
-    fn rom_number_bit_mask(&self) -> u8 {
-        match self.metadata.rom_size().bank_size {
-            002 => 0b0000_0001,
-            004 => 0b0000_0011,
-            008 => 0b0000_0111,
-            016 => 0b0000_1111,
-            032 => 0b0001_1111,
-            064 => 0b0001_1111,
-            128 => 0b0001_1111,
-            _ => panic!("Unknown rom bank size: {}", self.metadata.rom_size().bank_size)
-        }
+    fn high_rom_bank(&self) -> usize {
+        let bank1 = self.bank1 as usize & ((1 << self.bank1_bits()) - 1);
+        ((self.bank2 as usize) << self.bank1_bits()) | bank1
+    }
+
+    fn rom_offset(&self, bank: usize) -> usize {
+        let banks = (self.data.len() / 0x4000).max(1);
+        (bank % banks) * 0x4000
+    }
+
+    fn ram_address(&self, address: usize) -> usize {
+        let bank = if self.mode { self.bank2 as usize } else { 0 };
+        bank * 0x2000 + (address - 0xA000)
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for CartridgeMBC1 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.bank1);
+        state.write_u8(self.bank2);
+        state.write_bool(self.mode);
+        self.ram.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.bank1 = state.read_u8()?;
+        self.bank2 = state.read_u8()?;
+        self.mode = state.read_bool()?;
+        self.ram.load_state(state)?;
+        if self.bank1 & 0x1F == 0 || self.bank1 > 0x1F { return Err(SaveStateError::InvalidValue("mbc1 bank1")); }
+        if self.bank2 > 0b11 { return Err(SaveStateError::InvalidValue("mbc1 bank2")); }
+        Ok(())
+    }
+}
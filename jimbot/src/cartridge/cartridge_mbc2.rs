@@ -0,0 +1,104 @@
+use crate::cartridge::metadata::Metadata;
+use crate::cartridge::Cartridge;
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::cartridge::cartridge_ram::CartridgeRam;
+use crate::cartridge::save_storage::SaveStorage;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// MBC2, with its built-in 512 x 4 bits ram (battery backed for `RomMbc2Battery`).
+/// In $0000-$3FFF bit 8 of the address selects the ram enable (0) or the rom bank (1) register.
+pub struct CartridgeMBC2 {
+    metadata: Metadata,
+    rom_hi_bank_number: u16,
+    data: Vec<u8>,
+    ram: CartridgeRam,
+}
+
+impl Cartridge for CartridgeMBC2 {
+    fn get(&self, address: usize) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.data[address],
+            0x4000..=0x7FFF => {
+                let banks = self.data.len() / 0x4000;
+                self.data[(0x4000 * (self.rom_hi_bank_number as usize % banks)) + (address - 0x4000)]
+            }
+            // only the low nibble is stored, the high nibble reads as 1
+            0xA000..=0xBFFF => self.ram.get(address & 0x1FF) | 0xF0,
+            _ => panic!("Cartridge MBC2 GET {:#06X}", address),
+        }
+    }
+
+    fn set(&mut self, address: usize, val: u8) {
+        match address {
+            0x0000..=0x3FFF => {
+                if address & 0x100 == 0 {
+                    self.ram.set_enable(val);
+                } else {
+                    self.rom_hi_bank_number = if val & 0xF == 0 {
+                        1
+                    } else {
+                        (val & 0xF) as u16
+                    };
+                }
+            }
+            0xA000..=0xBFFF => self.ram.set(address & 0x1FF, val & 0xF),
+            _ => println!("Cartridge MBC2 SET {:#06X} {:04X}", address, val),
+        }
+    }
+
+    fn data(&self) -> &Vec<u8> {
+        &self.data
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut Vec<u8>> {
+        self.ram.save_data_mut()
+    }
+
+    fn save_data(&self) -> Option<&Vec<u8>> {
+        self.ram.save_data()
+    }
+
+    fn cycle(&mut self) {
+        self.ram.cycle_and_flush();
+    }
+
+    fn flush_save(&mut self) {
+        self.ram.flush_save();
+    }
+
+    fn detach_save_storage(&mut self) {
+        self.ram.detach_storage();
+    }
+}
+
+impl CartridgeMBC2 {
+    const RAM_SIZE: usize = 512;
+
+    pub fn new(storage: Option<Box<dyn SaveStorage>>, metadata: Metadata, bytes: Vec<u8>) -> Result<Self, CartridgeError> {
+        let mut ram = CartridgeRam::new(Self::RAM_SIZE, metadata.cartridge_type().has_battery(), storage);
+        ram.load(&[])?;
+
+        Ok(Self {
+            metadata,
+            rom_hi_bank_number: 1,
+            data: bytes,
+            ram,
+        })
+    }
+}
+
+impl SaveState for CartridgeMBC2 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.rom_hi_bank_number);
+        self.ram.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.rom_hi_bank_number = state.read_u16()?;
+        self.ram.load_state(state)
+    }
+}
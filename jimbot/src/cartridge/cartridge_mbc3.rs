@@ -0,0 +1,204 @@
+use crate::cartridge::metadata::Metadata;
+use crate::cartridge::Cartridge;
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::cartridge::cartridge_ram::CartridgeRam;
+use crate::cartridge::save_storage::SaveStorage;
+use crate::cartridge::rtc::{unix_time, Rtc};
+use crate::save_state;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+#[derive(Copy, Clone)]
+enum RamRtcMode {
+    Ram,
+    Rtc,
+}
+
+impl RamRtcMode {
+    /// indexed by `mode as u8`
+    const ALL: [RamRtcMode; 2] = [RamRtcMode::Ram, RamRtcMode::Rtc];
+}
+
+/// MBC3, with a real time clock for the timer cartridge types.
+/// The clock is saved after the ram (see `Rtc::FOOTER_SIZE`) and catches up on the host time when loaded.
+pub struct CartridgeMBC3 {
+    metadata: Metadata,
+    rom_hi_bank_number: u16,
+    ram_bank_number: u8,
+    ram_rtc_mode: RamRtcMode,
+    rtc_data_latch_writes: u8,
+    /// selected rtc register, $08-$0C
+    rtc_register: u8,
+    rtc: Option<Rtc>,
+    data: Vec<u8>,
+    ram: CartridgeRam,
+}
+
+impl Cartridge for CartridgeMBC3 {
+    fn get(&self, address: usize) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.data[address],
+            0x4000..=0x7FFF => {
+                let banks = self.data.len() / 0x4000;
+                self.data[(0x4000 * (self.rom_hi_bank_number as usize % banks)) + (address - 0x4000)]
+            }
+            0xA000..=0xBFFF => match self.ram_rtc_mode {
+                RamRtcMode::Ram => self.ram.get(0x2000 * self.ram_bank_number as usize + (address - 0xA000)),
+                RamRtcMode::Rtc => match self.rtc.as_ref() {
+                    Some(rtc) if self.ram.is_enabled() => rtc.get(self.rtc_register),
+                    _ => 0xFF,
+                },
+            },
+            _ => panic!("Cartridge MBC3 GET {:#06X}", address),
+        }
+    }
+
+    fn set(&mut self, address: usize, val: u8) {
+        match address {
+            // also enables the rtc
+            0x0000..=0x1FFF => self.ram.set_enable(val),
+            0x2000..=0x3FFF => {
+                self.rom_hi_bank_number = if val == 0 { 1 } else { val as u16 };
+            }
+            0x4000..=0x5FFF => match val {
+                0x00..=0x07 => {
+                    self.ram_rtc_mode = RamRtcMode::Ram;
+                    self.ram_bank_number = val;
+                }
+                0x08..=0x0C => {
+                    self.ram_rtc_mode = RamRtcMode::Rtc;
+                    self.rtc_register = val;
+                }
+                _ => println!("Unknown ram bank / rtc select {:#04X}", val),
+            },
+            0x6000..=0x7FFF => {
+                if self.rtc_data_latch_writes == 0x00 && val == 0x01 {
+                    if let Some(rtc) = self.rtc.as_mut() {
+                        rtc.latch();
+                    }
+                }
+                self.rtc_data_latch_writes = val;
+            }
+            0xA000..=0xBFFF => match self.ram_rtc_mode {
+                RamRtcMode::Ram => self.ram.set(0x2000 * self.ram_bank_number as usize + (address - 0xA000), val),
+                RamRtcMode::Rtc => {
+                    if let Some(rtc) = self.rtc.as_mut().filter(|_| self.ram.is_enabled()) {
+                        rtc.set(self.rtc_register, val);
+                        self.ram.mark_dirty();
+                    }
+                }
+            },
+            _ => panic!("Cartridge MBC3 SET {:#06X} {:04X}", address, val),
+        }
+    }
+
+    fn data(&self) -> &Vec<u8> {
+        &self.data
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut Vec<u8>> {
+        self.ram.save_data_mut()
+    }
+
+    fn save_data(&self) -> Option<&Vec<u8>> {
+        self.ram.save_data()
+    }
+
+    fn cycle(&mut self) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.cycle();
+        }
+        if self.ram.cycle() {
+            self.flush_save();
+        }
+    }
+
+    /// Clock cartridges are always flushed to save the current host time with the clock
+    fn flush_save(&mut self) {
+        match self.rtc.as_ref() {
+            Some(rtc) => self.ram.flush(&rtc.to_footer(unix_time().unwrap_or(0))),
+            None => self.ram.flush_save(),
+        }
+    }
+
+    fn detach_save_storage(&mut self) {
+        self.ram.detach_storage();
+    }
+
+    fn clock_state(&self) -> Option<Vec<u8>> {
+        self.rtc.as_ref().map(save_state::to_bytes)
+    }
+
+    fn load_clock_state(&mut self, bytes: &[u8]) -> Result<(), SaveStateError> {
+        if self.rtc.is_none() { return Err(SaveStateError::InvalidValue("mbc3 without clock")); }
+        let mut rtc = Rtc::default();
+        save_state::load_from_bytes(&mut rtc, bytes)?;
+        self.rtc = Some(rtc);
+        Ok(())
+    }
+}
+
+impl CartridgeMBC3 {
+    pub fn new(storage: Option<Box<dyn SaveStorage>>, metadata: Metadata, bytes: Vec<u8>) -> Result<Self, CartridgeError> {
+        let cartridge_type = metadata.cartridge_type();
+        let ram_size = if cartridge_type.has_ram() { metadata.ram_size().size as usize } else { 0 };
+        let has_rtc = cartridge_type.has_timer();
+        let mut ram = CartridgeRam::new(ram_size, cartridge_type.has_battery(), storage);
+        let footer_sizes = if has_rtc { &[Rtc::FOOTER_SIZE, Rtc::FOOTER_SIZE_32][..] } else { &[] };
+        let footer = ram.load(footer_sizes)?;
+        let mut rtc = has_rtc.then(Rtc::default);
+        if let Some((mut saved_rtc, timestamp)) = Rtc::from_footer(&footer) {
+            if let Some(now) = unix_time() {
+                saved_rtc.advance(now.saturating_sub(timestamp));
+            }
+            rtc = Some(saved_rtc);
+        }
+        Ok(Self {
+            metadata,
+            rom_hi_bank_number: 1,
+            ram_bank_number: 0,
+            ram_rtc_mode: RamRtcMode::Ram,
+            rtc_data_latch_writes: 0xFF,
+            rtc_register: 0x08,
+            rtc,
+            data: bytes,
+            ram,
+        })
+    }
+}
+
+impl Drop for CartridgeMBC3 {
+    fn drop(&mut self) {
+        self.flush_save();
+    }
+}
+
+impl SaveState for CartridgeMBC3 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.rom_hi_bank_number);
+        state.write_u8(self.ram_bank_number);
+        state.write_u8(self.ram_rtc_mode as u8);
+        state.write_u8(self.rtc_data_latch_writes);
+        state.write_u8(self.rtc_register);
+        self.ram.save_state(state);
+        if let Some(rtc) = self.rtc.as_ref() {
+            rtc.save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.rom_hi_bank_number = state.read_u16()?;
+        self.ram_bank_number = state.read_u8()?;
+        self.ram_rtc_mode = state.read_enum(&RamRtcMode::ALL, "ram rtc mode")?;
+        self.rtc_data_latch_writes = state.read_u8()?;
+        self.rtc_register = state.read_u8()?;
+        self.ram.load_state(state)?;
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.load_state(state)?;
+        }
+        Ok(())
+    }
+}
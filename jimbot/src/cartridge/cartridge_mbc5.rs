@@ -1,67 +1,64 @@
 use crate::cartridge::metadata::Metadata;
-use crate::cartridge::Cartridge;
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
-#[cfg(target_os = "unix")]
-use std::os::unix::fs::FileExt;
-#[cfg(target_os = "windows")]
-use std::os::windows::fs::FileExt;
-use std::path::{Path, PathBuf};
-
-enum RamRtcMode {
-    Ram,
-    Rtc,
-    None,
-}
+use crate::cartridge::{Cartridge, RumbleCallback};
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::cartridge::cartridge_ram::CartridgeRam;
+use crate::cartridge::save_storage::SaveStorage;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
+/// MBC5, with or without (battery backed) ram and rumble motor.
+/// The rom bank has 9 bits (bank 0 can be mapped at $4000), the ram bank 4 bits.
+/// Rumble cartridges wire bit 3 of the ram bank register to the motor instead.
 pub struct CartridgeMBC5 {
+    metadata: Metadata,
     rom_hi_bank_number: u16,
     ram_bank_number: u8,
-    switchable_mode: bool,
+    rumble: Option<Rumble>,
     data: Vec<u8>,
+    ram: CartridgeRam,
+}
+
+#[derive(Default)]
+struct Rumble {
+    on: bool,
+    callback: Option<RumbleCallback>,
 }
 
 impl Cartridge for CartridgeMBC5 {
     fn get(&self, address: usize) -> u8 {
         match address {
-            0x0000..=0x3FFF => {
-                // println!("GET SWITCH MODE: {} {:#06x}", self.switchable_mode, address);
-                // if !self.switchable_mode {
-                self.data[address]
-                // } else {
-                //     self.data[0x4000 + address]
-                // }
-            }
+            0x0000..=0x3FFF => self.data[address],
             0x4000..=0x7FFF => {
-                self.data[(0x4000 * self.rom_hi_bank_number as usize) + (address - 0x4000)]
+                let banks = self.data.len() / 0x4000;
+                self.data[(0x4000 * (self.rom_hi_bank_number as usize % banks)) + (address - 0x4000)]
             }
+            0xA000..=0xBFFF => self.ram.get(0x2000 * self.ram_bank_number as usize + (address - 0xA000)),
             _ => panic!("Cartridge MBC5 GET {:#06X}", address),
         }
     }
 
     fn set(&mut self, address: usize, val: u8) {
         match address {
+            0x0000..=0x1FFF => self.ram.set_enable(val),
             0x2000..=0x2FFF => {
-                // let new = (val as u16 & self.rom_number_bit_mask() as u16);
-                // println!("NEW ROMBANK: {}", new);
-                self.rom_hi_bank_number = (self.rom_hi_bank_number & 0xFF00) | val as u16;
+                self.rom_hi_bank_number = (self.rom_hi_bank_number & 0x100) | val as u16;
             }
             0x3000..=0x3FFF => {
-                // let new = (val as u16 & self.rom_number_bit_mask() as u16);
-                // println!("NEW ROMBANK: {}", new);
-                self.rom_hi_bank_number = (self.rom_hi_bank_number & 0x00FF) | ((val as u16) << 8);
+                self.rom_hi_bank_number = (self.rom_hi_bank_number & 0x0FF) | ((val as u16 & 1) << 8);
             }
-            0x4000..=0x5FFF => self.ram_bank_number = val,
-            // if self.ram_rtc_enabled {
-            //     let address = match self.metadata.ram_size().bank_size {
-            //         1 | 2 => (address as usize - 0xA000) % self.metadata.ram_size().size as usize,
-            //         _ => panic!("Cartridge MBC1 WRITE UNSUPPORTED RAM:{:?} {:#06x} {:#02x}", self.metadata.ram_size().bank_size, address, val),
-            //     };
-            //     self.ram[address] = val;
-            //     self.save_file.write_at(&self.ram[address..=address], address as u64);
-            // } else {
-            //     println!("Cartridge MBC1 WRITE TO DISABLED RAM {:#06X} {:04X}", address, val)
-            // }
+            0x4000..=0x5FFF => match self.rumble.as_mut() {
+                Some(rumble) => {
+                    self.ram_bank_number = val & 0b0111;
+                    let on = val & 0b1000 != 0;
+                    if on != rumble.on {
+                        rumble.on = on;
+                        if let Some(callback) = rumble.callback.as_mut() {
+                            callback(on);
+                        }
+                    }
+                }
+                None => self.ram_bank_number = val & 0x0F,
+            },
+            0xA000..=0xBFFF => self.ram.set(0x2000 * self.ram_bank_number as usize + (address - 0xA000), val),
             _ => println!("Cartridge MBC5 SET {:#06X} {:04X}", address, val),
         }
     }
@@ -71,25 +68,74 @@ impl Cartridge for CartridgeMBC5 {
     }
 
     fn metadata(&self) -> &Metadata {
-        &self.metadata()
+        &self.metadata
+    }
+
+    fn save_data(&self) -> Option<&Vec<u8>> {
+        self.ram.save_data()
     }
 
     fn save_data_mut(&mut self) -> Option<&mut Vec<u8>> {
-        None
+        self.ram.save_data_mut()
     }
 
-    fn save_data(&self) -> Option<&Vec<u8>> {
-        None
+    fn cycle(&mut self) {
+        self.ram.cycle_and_flush();
+    }
+
+    fn flush_save(&mut self) {
+        self.ram.flush_save();
+    }
+
+    fn detach_save_storage(&mut self) {
+        self.ram.detach_storage();
+    }
+
+    fn set_rumble_callback(&mut self, callback: RumbleCallback) {
+        if let Some(rumble) = self.rumble.as_mut() {
+            rumble.callback = Some(callback);
+        }
     }
 }
 
 impl CartridgeMBC5 {
-    pub fn new(bytes: Vec<u8>) -> Self {
-        Self {
+    pub fn new(storage: Option<Box<dyn SaveStorage>>, metadata: Metadata, bytes: Vec<u8>) -> Result<Self, CartridgeError> {
+        let cartridge_type = metadata.cartridge_type();
+        let ram_size = if cartridge_type.has_ram() { metadata.ram_size().size as usize } else { 0 };
+        let mut ram = CartridgeRam::new(ram_size, cartridge_type.has_battery(), storage);
+        ram.load(&[])?;
+        Ok(Self {
             rom_hi_bank_number: 1,
             ram_bank_number: 0,
-            switchable_mode: false,
+            rumble: cartridge_type.has_rumble().then(Rumble::default),
+            metadata,
             data: bytes,
+            ram,
+        })
+    }
+}
+
+/// The motor state is saved, the callback is told when a loaded state changes it
+impl SaveState for CartridgeMBC5 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.rom_hi_bank_number);
+        state.write_u8(self.ram_bank_number);
+        state.write_bool(self.rumble.as_ref().is_some_and(|rumble| rumble.on));
+        self.ram.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.rom_hi_bank_number = state.read_u16()?;
+        self.ram_bank_number = state.read_u8()?;
+        let on = state.read_bool()?;
+        self.ram.load_state(state)?;
+        if self.rom_hi_bank_number > 0x1FF { return Err(SaveStateError::InvalidValue("mbc5 rom bank")); }
+        if let Some(rumble) = self.rumble.as_mut().filter(|rumble| rumble.on != on) {
+            rumble.on = on;
+            if let Some(callback) = rumble.callback.as_mut() {
+                callback(on);
+            }
         }
+        Ok(())
     }
 }
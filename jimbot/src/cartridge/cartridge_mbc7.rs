@@ -0,0 +1,175 @@
+use crate::cartridge::Cartridge;
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::cartridge::cartridge_ram::CartridgeRam;
+use crate::cartridge::eeprom::Eeprom;
+use crate::cartridge::metadata::Metadata;
+use crate::cartridge::save_storage::SaveStorage;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// MBC7, with a 2 axis accelerometer and a 93LC56 EEPROM instead of ram.
+///
+/// The registers at $A000-$AFFF are enabled by writing $0A in $0000-$1FFF and $40 in $4000-$5FFF.
+/// They repeat every $100 bytes, bits 4-7 of the address select the register:
+/// - Ax0x: writing $55 erases the accelerometer latch (reads $8000)
+/// - Ax1x: writing $AA after an erase latches the accelerometer
+/// - Ax2x-Ax5x: latched x low, x high, y low and y high, $81D0 when flat, about $70 per g
+/// - Ax6x: $00, Ax7x: $FF
+/// - Ax8x: EEPROM port, see `Eeprom`
+pub struct CartridgeMBC7 {
+    metadata: Metadata,
+    rom_bank: u8,
+    ram_enable_1: bool,
+    ram_enable_2: bool,
+    /// the latch was erased and can be latched again
+    latch_erased: bool,
+    x_latch: u16,
+    y_latch: u16,
+    /// in g, set by the frontend
+    tilt: (f32, f32),
+    eeprom: Eeprom,
+    data: Vec<u8>,
+    ram: CartridgeRam,
+}
+
+impl Cartridge for CartridgeMBC7 {
+    fn get(&self, address: usize) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.data[address],
+            0x4000..=0x7FFF => {
+                let banks = self.data.len() / 0x4000;
+                self.data[0x4000 * (self.rom_bank as usize % banks) + (address - 0x4000)]
+            }
+            0xA000..=0xAFFF if self.registers_enabled() => match address & 0xF0 {
+                0x20 => self.x_latch as u8,
+                0x30 => (self.x_latch >> 8) as u8,
+                0x40 => self.y_latch as u8,
+                0x50 => (self.y_latch >> 8) as u8,
+                0x60 => 0x00,
+                0x80 => self.eeprom.get(),
+                _ => 0xFF,
+            },
+            0xA000..=0xBFFF => 0xFF,
+            _ => panic!("Cartridge MBC7 GET {:#06X}", address),
+        }
+    }
+
+    fn set(&mut self, address: usize, val: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enable_1 = val == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = val & 0x7F,
+            0x4000..=0x5FFF => self.ram_enable_2 = val == 0x40,
+            0xA000..=0xAFFF if self.registers_enabled() => match address & 0xF0 {
+                0x00 if val == 0x55 => {
+                    self.latch_erased = true;
+                    self.x_latch = 0x8000;
+                    self.y_latch = 0x8000;
+                }
+                0x10 if val == 0xAA && self.latch_erased => {
+                    self.latch_erased = false;
+                    self.x_latch = Self::axis(self.tilt.0);
+                    self.y_latch = Self::axis(self.tilt.1);
+                }
+                0x80 => self.eeprom.set(val, &mut self.ram),
+                _ => {}
+            },
+            0x6000..=0x7FFF | 0xA000..=0xBFFF => {}
+            _ => println!("Cartridge MBC7 SET {:#06X} {:04X}", address, val),
+        }
+    }
+
+    fn data(&self) -> &Vec<u8> {
+        &self.data
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn save_data(&self) -> Option<&Vec<u8>> {
+        self.ram.save_data()
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut Vec<u8>> {
+        self.ram.save_data_mut()
+    }
+
+    fn cycle(&mut self) {
+        self.ram.cycle_and_flush();
+    }
+
+    fn flush_save(&mut self) {
+        self.ram.flush_save();
+    }
+
+    fn detach_save_storage(&mut self) {
+        self.ram.detach_storage();
+    }
+
+    fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt = (x, y);
+    }
+}
+
+impl CartridgeMBC7 {
+    const CENTER: f32 = 0x81D0 as f32;
+    const PER_G: f32 = 0x70 as f32;
+
+    /// The EEPROM is saved, an EEPROM never written reads $FFFF
+    pub fn new(storage: Option<Box<dyn SaveStorage>>, metadata: Metadata, bytes: Vec<u8>) -> Result<Self, CartridgeError> {
+        let mut ram = CartridgeRam::new(Eeprom::SIZE, true, storage);
+        if let Some(bytes) = ram.save_data_mut() {
+            bytes.fill(0xFF);
+        }
+        ram.load(&[])?;
+        // the EEPROM has no enable, the registers are enabled instead
+        ram.set_enabled(true);
+        Ok(Self {
+            metadata,
+            rom_bank: 1,
+            ram_enable_1: false,
+            ram_enable_2: false,
+            latch_erased: false,
+            x_latch: 0x8000,
+            y_latch: 0x8000,
+            tilt: (0.0, 0.0),
+            eeprom: Eeprom::new(),
+            data: bytes,
+            ram,
+        })
+    }
+
+    fn registers_enabled(&self) -> bool {
+        self.ram_enable_1 && self.ram_enable_2
+    }
+
+    fn axis(tilt: f32) -> u16 {
+        (Self::CENTER + Self::PER_G * tilt) as u16
+    }
+}
+
+/// The tilt is frontend input, it is not saved
+impl SaveState for CartridgeMBC7 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.rom_bank);
+        state.write_bool(self.ram_enable_1);
+        state.write_bool(self.ram_enable_2);
+        state.write_bool(self.latch_erased);
+        state.write_u16(self.x_latch);
+        state.write_u16(self.y_latch);
+        self.eeprom.save_state(state);
+        self.ram.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.rom_bank = state.read_u8()?;
+        self.ram_enable_1 = state.read_bool()?;
+        self.ram_enable_2 = state.read_bool()?;
+        self.latch_erased = state.read_bool()?;
+        self.x_latch = state.read_u16()?;
+        self.y_latch = state.read_u16()?;
+        self.eeprom.load_state(state)?;
+        self.ram.load_state(state)?;
+        if self.rom_bank > 0x7F { return Err(SaveStateError::InvalidValue("mbc7 rom bank")); }
+        Ok(())
+    }
+}
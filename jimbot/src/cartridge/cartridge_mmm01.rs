@@ -0,0 +1,215 @@
+use crate::cartridge::Cartridge;
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::cartridge::cartridge_ram::CartridgeRam;
+use crate::cartridge::metadata::Metadata;
+use crate::cartridge::save_storage::SaveStorage;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// MMM01 multicart, a MBC1 with registers to pick the game.
+///
+/// At power up the menu in the last 32 KiB of the rom is mapped at $0000-$7FFF whatever the registers say.
+/// The menu sets the base and mask of the game it starts, then sets bit 6 of $0000-$1FFF which maps the game
+/// and locks the registers marked (menu) below:
+/// - $0000-$1FFF: bits 0-3 ram enable ($A), bits 4-5 ram bank mask (menu), bit 6 map the game (menu)
+/// - $2000-$3FFF: bits 0-4 rom bank low, bits 5-6 rom bank mid (menu)
+/// - $4000-$5FFF: bits 0-1 ram bank low, bits 2-3 ram bank high (menu), bits 4-5 rom bank high (menu),
+///   bit 6 locks the MBC1 mode (menu)
+/// - $6000-$7FFF: bit 0 MBC1 mode, bits 2-5 rom bank mask (menu), bit 6 multiplex (menu, not emulated)
+///
+/// The bits of rom bank low (1-4) and ram bank low (0-1) set in the masks keep the value given by the menu,
+/// the game only switches the other bits. $0000-$3FFF maps the game first bank (the switchable bits at 0),
+/// $4000-$7FFF the selected bank where 0 selects 1 like the MBC1. The ram bank low is used in MBC1 mode 1 only.
+pub struct CartridgeMMM01 {
+    metadata: Metadata,
+    mapped: bool,
+    rom_bank_low: u8,
+    rom_bank_mid: u8,
+    rom_bank_high: u8,
+    /// bits 1-4 of rom bank low
+    rom_mask: u8,
+    ram_bank_low: u8,
+    ram_bank_high: u8,
+    ram_mask: u8,
+    mode: bool,
+    mode_locked: bool,
+    multiplex: bool,
+    data: Vec<u8>,
+    ram: CartridgeRam,
+}
+
+impl Cartridge for CartridgeMMM01 {
+    fn get(&self, address: usize) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.data[self.rom_offset(self.low_rom_bank()) + address],
+            0x4000..=0x7FFF => self.data[self.rom_offset(self.high_rom_bank()) + (address - 0x4000)],
+            0xA000..=0xBFFF => self.ram.get(self.ram_address(address)),
+            _ => panic!("Cartridge MMM01 GET {:#06X}", address),
+        }
+    }
+
+    fn set(&mut self, address: usize, val: u8) {
+        match address {
+            0x0000..=0x1FFF => {
+                self.ram.set_enable(val);
+                if !self.mapped {
+                    self.ram_mask = (val >> 4) & 0b11;
+                    self.mapped = val & 0x40 != 0;
+                }
+            }
+            0x2000..=0x3FFF => {
+                let writable = self.rom_writable_bits();
+                self.rom_bank_low = (self.rom_bank_low & !writable) | (val & writable);
+                if !self.mapped {
+                    self.rom_bank_mid = (val >> 5) & 0b11;
+                }
+            }
+            0x4000..=0x5FFF => {
+                let writable = if self.mapped { 0b11 & !self.ram_mask } else { 0b11 };
+                self.ram_bank_low = (self.ram_bank_low & !writable) | (val & writable);
+                if !self.mapped {
+                    self.ram_bank_high = (val >> 2) & 0b11;
+                    self.rom_bank_high = (val >> 4) & 0b11;
+                    self.mode_locked = val & 0x40 != 0;
+                }
+            }
+            0x6000..=0x7FFF => {
+                if !self.mapped || !self.mode_locked {
+                    self.mode = val & 1 == 1;
+                }
+                if !self.mapped {
+                    self.rom_mask = (val >> 2) & 0x0F;
+                    self.multiplex = val & 0x40 != 0;
+                }
+            }
+            0xA000..=0xBFFF => self.ram.set(self.ram_address(address), val),
+            _ => panic!("Cartridge MMM01 SET {:#06X} {:04X}", address, val),
+        }
+    }
+
+    fn data(&self) -> &Vec<u8> {
+        &self.data
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn save_data(&self) -> Option<&Vec<u8>> {
+        self.ram.save_data()
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut Vec<u8>> {
+        self.ram.save_data_mut()
+    }
+
+    fn cycle(&mut self) {
+        self.ram.cycle_and_flush();
+    }
+
+    fn flush_save(&mut self) {
+        self.ram.flush_save();
+    }
+
+    fn detach_save_storage(&mut self) {
+        self.ram.detach_storage();
+    }
+}
+
+impl CartridgeMMM01 {
+    /// `metadata` is the menu header, see `Metadata::from_rom`
+    pub fn new(storage: Option<Box<dyn SaveStorage>>, metadata: Metadata, bytes: Vec<u8>) -> Result<Self, CartridgeError> {
+        let cartridge_type = metadata.cartridge_type();
+        let ram_size = if cartridge_type.has_ram() { metadata.ram_size().size as usize } else { 0 };
+        let mut ram = CartridgeRam::new(ram_size, cartridge_type.has_battery(), storage);
+        ram.load(&[])?;
+        Ok(Self {
+            metadata,
+            mapped: false,
+            rom_bank_low: 0,
+            rom_bank_mid: 0,
+            rom_bank_high: 0,
+            rom_mask: 0,
+            ram_bank_low: 0,
+            ram_bank_high: 0,
+            ram_mask: 0,
+            mode: false,
+            mode_locked: false,
+            multiplex: false,
+            data: bytes,
+            ram,
+        })
+    }
+
+    /// bits of rom bank low the game can switch
+    fn rom_writable_bits(&self) -> u8 {
+        if self.mapped { 0x1F & !(self.rom_mask << 1) } else { 0x1F }
+    }
+
+    fn rom_bank(&self, low: u8) -> usize {
+        ((self.rom_bank_high as usize) << 7) | ((self.rom_bank_mid as usize) << 5) | low as usize
+    }
+
+    /// the menu is in the last 2 banks until the game is mapped
+    fn low_rom_bank(&self) -> usize {
+        if !self.mapped {
+            return 0x1FE;
+        }
+        self.rom_bank(self.rom_bank_low & !self.rom_writable_bits())
+    }
+
+    fn high_rom_bank(&self) -> usize {
+        if !self.mapped {
+            return 0x1FF;
+        }
+        let low = if self.rom_bank_low & self.rom_writable_bits() == 0 { self.rom_bank_low | 1 } else { self.rom_bank_low };
+        self.rom_bank(low)
+    }
+
+    fn rom_offset(&self, bank: usize) -> usize {
+        let banks = (self.data.len() / 0x4000).max(1);
+        (bank % banks) * 0x4000
+    }
+
+    fn ram_address(&self, address: usize) -> usize {
+        let low = if self.mode || !self.mapped { self.ram_bank_low } else { self.ram_bank_low & self.ram_mask };
+        let bank = ((self.ram_bank_high as usize) << 2) | low as usize;
+        bank * 0x2000 + (address - 0xA000)
+    }
+}
+
+impl SaveState for CartridgeMMM01 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.mapped);
+        state.write_u8(self.rom_bank_low);
+        state.write_u8(self.rom_bank_mid);
+        state.write_u8(self.rom_bank_high);
+        state.write_u8(self.rom_mask);
+        state.write_u8(self.ram_bank_low);
+        state.write_u8(self.ram_bank_high);
+        state.write_u8(self.ram_mask);
+        state.write_bool(self.mode);
+        state.write_bool(self.mode_locked);
+        state.write_bool(self.multiplex);
+        self.ram.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.mapped = state.read_bool()?;
+        self.rom_bank_low = state.read_u8()?;
+        self.rom_bank_mid = state.read_u8()?;
+        self.rom_bank_high = state.read_u8()?;
+        self.rom_mask = state.read_u8()?;
+        self.ram_bank_low = state.read_u8()?;
+        self.ram_bank_high = state.read_u8()?;
+        self.ram_mask = state.read_u8()?;
+        self.mode = state.read_bool()?;
+        self.mode_locked = state.read_bool()?;
+        self.multiplex = state.read_bool()?;
+        self.ram.load_state(state)?;
+        if self.rom_bank_low > 0x1F || self.rom_bank_mid > 0b11 || self.rom_bank_high > 0b11 || self.rom_mask > 0x0F
+            || self.ram_bank_low > 0b11 || self.ram_bank_high > 0b11 || self.ram_mask > 0b11 {
+            return Err(SaveStateError::InvalidValue("mmm01 registers"));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::cartridge::save_storage::{Battery, SaveStorage};
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// External ram shared by the mappers: enable register, address wrapping on the ram size
+/// and the battery flushing to the save storage when battery backed.
+/// Reads return $FF while the ram is disabled or when the cartridge has no ram.
+/// Changes not flushed yet are written when dropped, mappers saving a footer flush it themselves before.
+pub(crate) struct CartridgeRam {
+    bytes: Vec<u8>,
+    enabled: bool,
+    battery: Battery,
+    battery_backed: bool,
+}
+
+impl CartridgeRam {
+    /// `storage` is only used when `battery_backed`
+    pub fn new(size: usize, battery_backed: bool, storage: Option<Box<dyn SaveStorage>>) -> Self {
+        Self {
+            bytes: vec![0; size],
+            enabled: false,
+            battery: Battery::new(if battery_backed { storage } else { None }),
+            battery_backed,
+        }
+    }
+
+    /// Reads the save, see `Battery::load`
+    pub fn load(&mut self, footer_sizes: &[usize]) -> Result<Vec<u8>, CartridgeError> {
+        self.battery.load(&mut self.bytes, footer_sizes)
+    }
+
+    /// ram enable register, $A in the low nibble enables the ram
+    pub fn set_enable(&mut self, val: u8) {
+        self.enabled = val & 0xF == 0xA;
+    }
+
+    /// for cartridges without enable register
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// `address` is the offset in the whole ram (bank * $2000 + offset in $A000-$BFFF)
+    pub fn get(&self, address: usize) -> u8 {
+        if !self.enabled || self.bytes.is_empty() {
+            return 0xFF;
+        }
+        self.bytes[address % self.bytes.len()]
+    }
+
+    pub fn set(&mut self, address: usize, val: u8) {
+        if !self.enabled || self.bytes.is_empty() {
+            return;
+        }
+        let len = self.bytes.len();
+        self.bytes[address % len] = val;
+        self.battery.write();
+    }
+
+    pub fn save_data(&self) -> Option<&Vec<u8>> {
+        if self.battery_backed { Some(&self.bytes) } else { None }
+    }
+
+    pub fn save_data_mut(&mut self) -> Option<&mut Vec<u8>> {
+        if self.battery_backed { Some(&mut self.bytes) } else { None }
+    }
+
+    /// the ram is no longer saved
+    pub fn detach_storage(&mut self) {
+        self.battery.detach();
+    }
+
+    /// Something else than the ram changed (the rtc), flush it with the ram
+    pub fn mark_dirty(&mut self) {
+        self.battery.write();
+    }
+
+    /// returns true when the save should be flushed with `flush`
+    pub fn cycle(&mut self) -> bool {
+        self.battery.cycle()
+    }
+
+    /// `cycle` for saves without footer, flushes the ram when it is time
+    pub fn cycle_and_flush(&mut self) {
+        if self.battery.cycle() {
+            self.flush(&[]);
+        }
+    }
+
+    /// Writes the ram changed since the last flush, for saves without footer
+    pub fn flush_save(&mut self) {
+        if self.battery.is_dirty() {
+            self.flush(&[]);
+        }
+    }
+
+    /// Writes the ram followed by `footer` to the save storage
+    pub fn flush(&mut self, footer: &[u8]) {
+        if footer.is_empty() {
+            self.battery.flush(&self.bytes);
+        } else {
+            let mut bytes = self.bytes.clone();
+            bytes.extend_from_slice(footer);
+            self.battery.flush(&bytes);
+        }
+    }
+}
+
+impl Drop for CartridgeRam {
+    fn drop(&mut self) {
+        self.flush_save();
+    }
+}
+
+impl SaveState for CartridgeRam {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_vec(&self.bytes);
+    }
+
+    /// the loaded ram is flushed to the save storage
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enabled = state.read_bool()?;
+        state.read_vec_exact(&mut self.bytes)?;
+        self.battery.write();
+        Ok(())
+    }
+}
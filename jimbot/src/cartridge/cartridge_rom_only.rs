@@ -1,18 +1,32 @@
 use crate::cartridge::Cartridge;
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::cartridge::cartridge_ram::CartridgeRam;
+use crate::cartridge::save_storage::SaveStorage;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 use crate::cartridge::metadata::Metadata;
 
+/// 32 KiB rom without mapper, `RomRam` and `RomRamBattery` add up to 8 KiB of ram always enabled
 pub struct CartridgeRomOnly {
     metadata: Metadata,
     data: Vec<u8>,
+    ram: CartridgeRam,
 }
 
 impl Cartridge for CartridgeRomOnly {
 
     fn get(&self, address: usize) -> u8 {
-        self.data[address]
+        match address {
+            0x0000..=0x7FFF => self.data.get(address).copied().unwrap_or(0xFF),
+            0xA000..=0xBFFF => self.ram.get(address - 0xA000),
+            _ => panic!("Cartridge ROM GET {:#06X}", address),
+        }
     }
 
-    fn set(&mut self, address: usize, val: u8) {}
+    fn set(&mut self, address: usize, val: u8) {
+        if let 0xA000..=0xBFFF = address {
+            self.ram.set(address - 0xA000, val);
+        }
+    }
 
     fn data(&self) -> &Vec<u8> {
         &self.data
@@ -23,90 +37,47 @@ impl Cartridge for CartridgeRomOnly {
     }
 
     fn save_data_mut(&mut self) -> Option<&mut Vec<u8>> {
-        None
+        self.ram.save_data_mut()
     }
 
-    fn save_data(&self) -> Option<&Vec<u8>> { None }
+    fn save_data(&self) -> Option<&Vec<u8>> {
+        self.ram.save_data()
+    }
 
-}
+    fn cycle(&mut self) {
+        self.ram.cycle_and_flush();
+    }
 
+    fn flush_save(&mut self) {
+        self.ram.flush_save();
+    }
 
+    fn detach_save_storage(&mut self) {
+        self.ram.detach_storage();
+    }
+}
 
 impl CartridgeRomOnly {
-    // pub fn new(file_path: &str) -> Self {
-    //     let data = std::fs::read(file_path).unwrap();
-    //     // let size = RamSize::from(data[Self::RAM_SIZE_ADDRESS]);
-    //     let cartridge = CartridgeRomOnly {
-    //         data,
-    //         // ram: vec![0; size.size as usize],
-    //     };
-    //     // println!("Cartridge loaded:");
-    //     // println!("\tTitle: {}", cartridge.get_title());
-    //     // println!("\tCartridge Type: {:?}", cartridge.get_type());
-    //     // println!("\tRom Size: {:?}", cartridge.get_rom_size());
-    //     // println!("\tRam Size: {:?}", cartridge.get_ram_size());
-    //     cartridge
-    // }
-
-    pub fn new(bytes: Vec<u8>) -> Self {
-        let metadata = Metadata::from(&bytes);
-        let data = bytes;
-        let cartridge = CartridgeRomOnly {
+    pub fn new(storage: Option<Box<dyn SaveStorage>>, metadata: Metadata, bytes: Vec<u8>) -> Result<Self, CartridgeError> {
+        let cartridge_type = metadata.cartridge_type();
+        let ram_size = if cartridge_type.has_ram() { metadata.ram_size().size.min(0x2000) as usize } else { 0 };
+        let mut ram = CartridgeRam::new(ram_size, cartridge_type.has_battery(), storage);
+        ram.load(&[])?;
+        ram.set_enabled(true);
+        Ok(CartridgeRomOnly {
             metadata,
-            data,
-            // ram: vec![0; size.size as usize],
-        };
-        // println!("Cartridge loaded:");
-        // println!("\tTitle: {}", cartridge.get_title());
-        // println!("\tCartridge Type: {:?}", cartridge.get_type());
-        // println!("\tRom Size: {:?}", cartridge.get_rom_size());
-        // println!("\tRam Size: {:?}", cartridge.get_ram_size());
-        cartridge
+            data: bytes,
+            ram,
+        })
     }
+}
 
+impl SaveState for CartridgeRomOnly {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.ram.save_state(state);
+    }
 
-
-
-
-
-    // pub fn get_title(&self) -> &str {
-    //     str::from_utf8(&self.data[CartridgeRomOnly::TITLE_ADDRESS_MIN..CartridgeRomOnly::TITLE_ADDRESS_MAX]).unwrap_or("N/A")
-    // }
-    //
-    // pub fn get_type(&self) -> CartridgeType {
-    //     self.data[CartridgeRomOnly::TYPE_ADDRESS].into()
-    // }
-    //
-    // pub fn get_rom_size(&self) -> RomSize {
-    //     self.data[CartridgeRomOnly::ROM_SIZE_ADDRESS].into()
-    // }
-    //
-    // pub fn get_ram_size(&self) -> RamSize {
-    //     self.data[CartridgeRomOnly::RAM_SIZE_ADDRESS].into()
-    // }
-    //
-    // pub fn get(&self, address: u16) -> u8 {
-    //     self.data[address as usize]
-    // }
-    //
-    // pub fn set_ram(&mut self, address: u16, to: u8) {
-    //     let ram_size = self.get_ram_size();
-    //     let address = match ram_size.banks_size {
-    //         1 | 2 => (address as usize - 0xA000) % ram_size.size as usize,
-    //         _ => panic!("Writing ext ram unsupported size:{:?} {:#06x} {:#02x}", ram_size, address, to),
-    //     };
-    //     self.ram[address] = to;
-    // }
-    //
-    // pub fn get_ram(&self, address: u16) -> u8 {
-    //     let ram_size = self.get_ram_size();
-    //     let address = match ram_size.banks_size {
-    //         1 | 2 => (address as usize - 0xA000) % ram_size.size as usize,
-    //         _ => panic!("Reading ext ram unsupported size:{:?} {:#06x}", ram_size, address),
-    //     };
-    //     self.ram[address as usize]
-    // }
-    // pub fn data(&self) -> &Vec<u8> {
-    //     &self.data
-    // }
-}
\ No newline at end of file
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram.load_state(state)
+    }
+}
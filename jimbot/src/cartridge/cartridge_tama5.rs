@@ -0,0 +1,383 @@
+use crate::cartridge::Cartridge;
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::cartridge::cartridge_ram::CartridgeRam;
+use crate::cartridge::metadata::Metadata;
+use crate::cartridge::rtc::unix_time;
+use crate::cartridge::save_storage::SaveStorage;
+use crate::save_state;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Bandai TAMA5 (Game de Hakken!! Tamagotchi 3): 32 bytes of battery backed memory and a TC8521 clock
+/// behind a window of 2 registers.
+///
+/// Writing $A001 selects a register (low nibble), writing $A000 sets its low nibble and reading $A000 reads it:
+/// - $0/$1: rom bank bits 0-3 and bit 4 (bank 0 can be mapped at $4000)
+/// - $4/$5: value to write, low and high nibble
+/// - $6: bit 0 address bit 4, bits 1-3 command
+/// - $7: address bits 0-3, writing it runs the command
+/// - $A: reads $F1 (ready), bit 1 is set when the clock alarm went off until another register is selected
+/// - $C/$D: value read by the command, low and high nibble
+///
+/// Commands: 0 writes the value to the memory, 1 reads the memory, 2 writes the low nibble of the value
+/// to a clock register, 3 reads a clock register. Clock registers $00-$0F are the TC8521 page 0 (BCD time),
+/// $10-$1F the page 1 (alarm). Unused registers read $FF.
+pub struct CartridgeTama5 {
+    metadata: Metadata,
+    registers: [u8; 16],
+    selected: u8,
+    output: u8,
+    clock: Tc8521,
+    data: Vec<u8>,
+    ram: CartridgeRam,
+}
+
+/// TC8521 real time clock: BCD time registers ticking every second and an alarm on the minute and hour
+#[derive(Default)]
+struct Tc8521 {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    weekday: u8,
+    /// 1-31
+    day: u8,
+    /// 1-12
+    month: u8,
+    /// 0-99, leap years are multiples of 4
+    year: u8,
+    /// page 0 register $D: bit 2 alarm enable, bit 3 clock running
+    mode: u8,
+    /// page 1 nibbles
+    alarm: [u8; 16],
+    alarm_fired: bool,
+    /// m-cycles since the last second
+    sub_second: u32,
+}
+
+impl Tc8521 {
+    const M_CYCLES_PER_SECOND: u32 = 1 << 20;
+    const RUNNING: u8 = 0x08;
+    const ALARM_ENABLE: u8 = 0x04;
+
+    fn new() -> Self {
+        Self { day: 1, month: 1, mode: Self::RUNNING, ..Self::default() }
+    }
+
+    fn cycle(&mut self) {
+        if self.mode & Self::RUNNING == 0 {
+            return;
+        }
+        self.sub_second += 1;
+        if self.sub_second == Self::M_CYCLES_PER_SECOND {
+            self.sub_second = 0;
+            self.tick();
+        }
+    }
+
+    fn days_in_month(&self) -> u8 {
+        match self.month {
+            2 if self.year.is_multiple_of(4) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
+    fn tick(&mut self) {
+        self.seconds += 1;
+        if self.seconds < 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes += 1;
+        if self.minutes >= 60 {
+            self.minutes = 0;
+            self.hours += 1;
+            if self.hours >= 24 {
+                self.hours = 0;
+                self.next_day();
+            }
+        }
+        if self.mode & Self::ALARM_ENABLE != 0 && (self.minutes, self.hours) == self.alarm_time() {
+            self.alarm_fired = true;
+        }
+    }
+
+    fn next_day(&mut self) {
+        self.weekday = (self.weekday + 1) % 7;
+        self.day += 1;
+        if self.day > self.days_in_month() {
+            self.day = 1;
+            self.month += 1;
+            if self.month > 12 {
+                self.month = 1;
+                self.year = (self.year + 1) % 100;
+            }
+        }
+    }
+
+    /// minute and hour of the alarm, page 1 registers 2-5
+    fn alarm_time(&self) -> (u8, u8) {
+        (self.alarm[2] + self.alarm[3] * 10, self.alarm[4] + self.alarm[5] * 10)
+    }
+
+    /// Moves the clock forward by `seconds` of host time, the alarm does not go off
+    fn advance(&mut self, seconds: u64) {
+        if self.mode & Self::RUNNING == 0 {
+            return;
+        }
+        for _ in 0..seconds % 86400 {
+            self.tick();
+        }
+        for _ in 0..seconds / 86400 {
+            self.next_day();
+        }
+        self.alarm_fired = false;
+    }
+
+    /// `register` $00-$1F, page 0 then page 1
+    fn get(&self, register: u8) -> u8 {
+        let bcd = |val: u8, tens: bool| if tens { val / 10 } else { val % 10 };
+        match register {
+            0x00 | 0x01 => bcd(self.seconds, register & 1 == 1),
+            0x02 | 0x03 => bcd(self.minutes, register & 1 == 1),
+            0x04 | 0x05 => bcd(self.hours, register & 1 == 1),
+            0x06 => self.weekday,
+            0x07 | 0x08 => bcd(self.day, register == 0x08),
+            0x09 | 0x0A => bcd(self.month, register == 0x0A),
+            0x0B | 0x0C => bcd(self.year, register == 0x0C),
+            0x0D => self.mode,
+            0x10..=0x1F => self.alarm[register as usize - 0x10],
+            _ => 0x0F,
+        }
+    }
+
+    /// `val` is a nibble, the other digit of the register is kept. Invalid values (while the game sets the digits
+    /// one by one) are kept as the chip does, they carry on the next tick.
+    fn set(&mut self, register: u8, val: u8) {
+        let bcd = |current: u8, tens: bool| if tens { current % 10 + val * 10 } else { current / 10 * 10 + val };
+        match register {
+            0x00 | 0x01 => {
+                self.seconds = bcd(self.seconds, register & 1 == 1);
+                self.sub_second = 0;
+            }
+            0x02 | 0x03 => self.minutes = bcd(self.minutes, register & 1 == 1),
+            0x04 | 0x05 => self.hours = bcd(self.hours, register & 1 == 1),
+            0x06 => self.weekday = val % 7,
+            0x07 | 0x08 => self.day = bcd(self.day, register == 0x08),
+            0x09 | 0x0A => self.month = bcd(self.month, register == 0x0A),
+            0x0B | 0x0C => self.year = bcd(self.year, register == 0x0C),
+            0x0D => self.mode = val,
+            0x10..=0x1F => self.alarm[register as usize - 0x10] = val,
+            _ => {}
+        }
+    }
+
+    /// page 0 then page 1, one nibble per byte
+    fn registers(&self) -> [u8; 32] {
+        let mut registers = [0; 32];
+        for (register, nibble) in registers.iter_mut().enumerate() {
+            *nibble = self.get(register as u8) & 0x0F;
+        }
+        registers
+    }
+}
+
+impl Cartridge for CartridgeTama5 {
+    fn get(&self, address: usize) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.data[address],
+            0x4000..=0x7FFF => {
+                let banks = self.data.len() / 0x4000;
+                self.data[0x4000 * (self.rom_bank() % banks) + (address - 0x4000)]
+            }
+            0xA000 => match self.selected {
+                0x0A => 0xF1 | ((self.clock.alarm_fired as u8) << 1),
+                0x0C => 0xF0 | (self.output & 0x0F),
+                0x0D => 0xF0 | (self.output >> 4),
+                _ => 0xFF,
+            },
+            0xA001..=0xBFFF => 0xFF,
+            _ => panic!("Cartridge TAMA5 GET {:#06X}", address),
+        }
+    }
+
+    fn set(&mut self, address: usize, val: u8) {
+        match address {
+            0x0000..=0x7FFF => {}
+            0xA000 => {
+                self.registers[self.selected as usize] = val & 0x0F;
+                if self.selected == 0x07 {
+                    self.execute();
+                }
+            }
+            0xA001 => {
+                // the alarm flag is cleared once read
+                if self.selected == 0x0A {
+                    self.clock.alarm_fired = false;
+                }
+                self.selected = val & 0x0F;
+            }
+            0xA002..=0xBFFF => {}
+            _ => println!("Cartridge TAMA5 SET {:#06X} {:04X}", address, val),
+        }
+    }
+
+    fn data(&self) -> &Vec<u8> {
+        &self.data
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn save_data(&self) -> Option<&Vec<u8>> {
+        self.ram.save_data()
+    }
+
+    fn save_data_mut(&mut self) -> Option<&mut Vec<u8>> {
+        self.ram.save_data_mut()
+    }
+
+    fn cycle(&mut self) {
+        self.clock.cycle();
+        if self.ram.cycle() {
+            self.flush_save();
+        }
+    }
+
+    /// Always flushed to save the current host time with the clock
+    fn flush_save(&mut self) {
+        self.ram.flush(&self.footer(unix_time().unwrap_or(0)));
+    }
+
+    fn detach_save_storage(&mut self) {
+        self.ram.detach_storage();
+    }
+
+    fn clock_state(&self) -> Option<Vec<u8>> {
+        Some(save_state::to_bytes(&self.clock))
+    }
+
+    fn load_clock_state(&mut self, bytes: &[u8]) -> Result<(), SaveStateError> {
+        let mut clock = Tc8521::new();
+        save_state::load_from_bytes(&mut clock, bytes)?;
+        self.clock = clock;
+        Ok(())
+    }
+}
+
+impl CartridgeTama5 {
+    const RAM_SIZE: usize = 32;
+    /// Footer appended to the memory in `.sav` files: unix time of the save as u64 le, then the 32 clock registers
+    /// (page 0 and 1), a nibble per byte
+    const FOOTER_SIZE: usize = 40;
+
+    pub fn new(storage: Option<Box<dyn SaveStorage>>, metadata: Metadata, bytes: Vec<u8>) -> Result<Self, CartridgeError> {
+        let mut ram = CartridgeRam::new(Self::RAM_SIZE, true, storage);
+        let footer = ram.load(&[Self::FOOTER_SIZE])?;
+        ram.set_enabled(true);
+        let mut clock = Tc8521::new();
+        if footer.len() == Self::FOOTER_SIZE {
+            let timestamp = u64::from_le_bytes(footer[0..8].try_into().unwrap());
+            // the mode last, it may stop the clock
+            for register in (0..32u8).filter(|register| *register != 0x0D).chain([0x0D]) {
+                clock.set(register, footer[8 + register as usize] & 0x0F);
+            }
+            if let Some(now) = unix_time() {
+                clock.advance(now.saturating_sub(timestamp));
+            }
+        }
+        Ok(Self {
+            metadata,
+            registers: [0; 16],
+            selected: 0,
+            output: 0,
+            clock,
+            data: bytes,
+            ram,
+        })
+    }
+
+    fn rom_bank(&self) -> usize {
+        (self.registers[0] as usize) | ((self.registers[1] as usize & 1) << 4)
+    }
+
+    /// registers $4-$7 were written, $7 last
+    fn execute(&mut self) {
+        let address = ((self.registers[6] & 1) << 4) | self.registers[7];
+        let value = (self.registers[5] << 4) | self.registers[4];
+        match self.registers[6] >> 1 {
+            0 => self.ram.set(address as usize, value),
+            1 => self.output = self.ram.get(address as usize),
+            2 => {
+                self.clock.set(address, value & 0x0F);
+                self.ram.mark_dirty();
+            }
+            3 => self.output = self.clock.get(address),
+            command => println!("Unknown TAMA5 command {} address {:#04X}", command, address),
+        }
+    }
+
+    fn footer(&self, timestamp: u64) -> [u8; Self::FOOTER_SIZE] {
+        let mut footer = [0; Self::FOOTER_SIZE];
+        footer[0..8].copy_from_slice(&timestamp.to_le_bytes());
+        footer[8..].copy_from_slice(&self.clock.registers());
+        footer
+    }
+}
+
+impl Drop for CartridgeTama5 {
+    fn drop(&mut self) {
+        self.flush_save();
+    }
+}
+
+impl SaveState for CartridgeTama5 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.registers);
+        state.write_u8(self.selected);
+        state.write_u8(self.output);
+        self.clock.save_state(state);
+        self.ram.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        state.read_bytes(&mut self.registers)?;
+        self.selected = state.read_u8()?;
+        self.output = state.read_u8()?;
+        self.clock.load_state(state)?;
+        self.ram.load_state(state)?;
+        if self.registers.iter().any(|nibble| *nibble > 0x0F) || self.selected > 0x0F {
+            return Err(SaveStateError::InvalidValue("tama5 registers"));
+        }
+        Ok(())
+    }
+}
+
+impl SaveState for Tc8521 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&[self.seconds, self.minutes, self.hours, self.weekday, self.day, self.month, self.year, self.mode]);
+        state.write_bytes(&self.alarm);
+        state.write_bool(self.alarm_fired);
+        state.write_u32(self.sub_second);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        let mut time = [0; 8];
+        state.read_bytes(&mut time)?;
+        let [seconds, minutes, hours, weekday, day, month, year, mode] = time;
+        state.read_bytes(&mut self.alarm)?;
+        self.alarm_fired = state.read_bool()?;
+        self.sub_second = state.read_u32()?;
+        // 2 BCD digits of 4 bits
+        if time.iter().any(|val| *val > 159) || weekday > 6 || self.sub_second >= Self::M_CYCLES_PER_SECOND {
+            return Err(SaveStateError::InvalidValue("tama5 clock"));
+        }
+        if self.alarm.iter().any(|nibble| *nibble > 0x0F) {
+            return Err(SaveStateError::InvalidValue("tama5 registers"));
+        }
+        (self.seconds, self.minutes, self.hours, self.weekday) = (seconds, minutes, hours, weekday);
+        (self.day, self.month, self.year, self.mode) = (day, month, year, mode);
+        Ok(())
+    }
+}
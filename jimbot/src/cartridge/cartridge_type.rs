@@ -1,4 +1,6 @@
-#[derive(Debug)]
+use crate::cartridge::cartridge_error::CartridgeError;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum CartridgeType {
     RomOnly,
     RomMbc1,
@@ -22,6 +24,7 @@ pub enum CartridgeType {
     RomMbc5Rumble,
     RomMbc5RumbleSram,
     RomMbc5RumbleSramBattery,
+    RomMbc7SensorRumbleRamBattery,
     PocketCamera,
     BandaiTama5,
     HudsonHuC3,
@@ -29,9 +32,46 @@ pub enum CartridgeType {
     Unknown,
 }
 
-impl From<u8> for CartridgeType {
-    fn from(byte: u8) -> Self {
-        match byte {
+impl CartridgeType {
+    /// external ram, the MBC2 built-in ram is not counted
+    pub fn has_ram(&self) -> bool {
+        matches!(self,
+            CartridgeType::RomMbc1Ram | CartridgeType::RomMbc1RamBattery
+            | CartridgeType::RomRam | CartridgeType::RomRamBattery
+            | CartridgeType::RomMmm01Sram | CartridgeType::RomMmm01SramBattery
+            | CartridgeType::RomMbc3TimerRamBattery | CartridgeType::RomMbc3Ram | CartridgeType::RomMbc3RamBattery
+            | CartridgeType::RomMbc5Ram | CartridgeType::RomMbc5RamBattery
+            | CartridgeType::RomMbc5RumbleSram | CartridgeType::RomMbc5RumbleSramBattery
+            | CartridgeType::PocketCamera | CartridgeType::HudsonHuC3 | CartridgeType::HudsonHuC1
+        )
+    }
+
+    pub fn has_battery(&self) -> bool {
+        matches!(self,
+            CartridgeType::RomMbc1RamBattery | CartridgeType::RomMbc2Battery | CartridgeType::RomRamBattery
+            | CartridgeType::RomMmm01SramBattery
+            | CartridgeType::RomMbc3TimerBattery | CartridgeType::RomMbc3TimerRamBattery | CartridgeType::RomMbc3RamBattery
+            | CartridgeType::RomMbc5RamBattery | CartridgeType::RomMbc5RumbleSramBattery
+            | CartridgeType::RomMbc7SensorRumbleRamBattery | CartridgeType::PocketCamera
+            | CartridgeType::HudsonHuC3 | CartridgeType::HudsonHuC1 | CartridgeType::BandaiTama5
+        )
+    }
+
+    /// MBC3 real time clock
+    pub fn has_timer(&self) -> bool {
+        matches!(self, CartridgeType::RomMbc3TimerBattery | CartridgeType::RomMbc3TimerRamBattery)
+    }
+
+    pub fn has_rumble(&self) -> bool {
+        matches!(self, CartridgeType::RomMbc5Rumble | CartridgeType::RomMbc5RumbleSram | CartridgeType::RomMbc5RumbleSramBattery)
+    }
+}
+
+impl TryFrom<u8> for CartridgeType {
+    type Error = CartridgeError;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        Ok(match byte {
             0x00 => CartridgeType::RomOnly,
             0x01 => CartridgeType::RomMbc1,
             0x02 => CartridgeType::RomMbc1Ram,
@@ -54,11 +94,12 @@ impl From<u8> for CartridgeType {
             0x1C => CartridgeType::RomMbc5Rumble,
             0x1D => CartridgeType::RomMbc5RumbleSram,
             0x1E => CartridgeType::RomMbc5RumbleSramBattery,
-            0x1F => CartridgeType::PocketCamera,
+            0x22 => CartridgeType::RomMbc7SensorRumbleRamBattery,
+            0xFC => CartridgeType::PocketCamera,
             0xFD => CartridgeType::BandaiTama5,
             0xFE => CartridgeType::HudsonHuC3,
             0xFF => CartridgeType::HudsonHuC1,
-            _ => panic!("Unknown cartridge type: {:#02x}", byte)
-        }
+            _ => return Err(CartridgeError::UnknownType(byte)),
+        })
     }
 }
@@ -0,0 +1,214 @@
+use crate::cartridge::cartridge_ram::CartridgeRam;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+#[derive(Copy, Clone, PartialEq)]
+enum EepromState {
+    /// waiting for the start bit
+    Idle,
+    /// receiving the 2 bits opcode and 8 bits address
+    Command,
+    /// shifting a word out on DO
+    Read,
+    /// receiving the word of WRITE
+    Write,
+    /// receiving the word of WRAL
+    WriteAll,
+}
+
+impl EepromState {
+    /// indexed by `state as u8`
+    const ALL: [EepromState; 5] = [EepromState::Idle, EepromState::Command, EepromState::Read, EepromState::Write, EepromState::WriteAll];
+}
+
+/// 93LC56 serial EEPROM of the MBC7: 128 words of 16 bits, bit-banged by the game through one register.
+///
+/// A command is a 1 start bit, a 2 bits opcode and 8 address bits (the top one is ignored), shifted in on DI at
+/// the rising edges of CLK while CS is high: READ (10), WRITE (01), ERASE (11), and with opcode 00 the top
+/// address bits select EWEN (11), EWDS (00), ERAL (10) or WRAL (01). READ outputs a 0 bit then the words from the
+/// address on DO, WRITE and WRAL are followed by the 16 bits of data. Writes are ignored until EWEN.
+///
+/// DO is the ready/busy line outside of READ: low while a write is programmed, until the next rising edge of CLK.
+///
+/// The words are stored little endian in the cartridge ram so the save is 256 bytes.
+pub struct Eeprom {
+    state: EepromState,
+    cs: bool,
+    clk: bool,
+    di: bool,
+    /// DO, high when ready, low when busy or for the dummy bit of READ
+    dout: bool,
+    write_enabled: bool,
+    /// bits received for the command or data word
+    shift: u16,
+    bits: u8,
+    /// word address
+    address: u8,
+    /// word being shifted out
+    output: u16,
+}
+
+impl Eeprom {
+    pub const SIZE: usize = 256;
+
+    pub fn new() -> Self {
+        Self {
+            state: EepromState::Idle,
+            cs: false,
+            clk: false,
+            di: false,
+            dout: true,
+            write_enabled: false,
+            shift: 0,
+            bits: 0,
+            address: 0,
+            output: 0,
+        }
+    }
+
+    /// bit 7 CS, bit 6 CLK, bit 1 DI as last written, bit 0 DO
+    pub fn get(&self) -> u8 {
+        ((self.cs as u8) << 7) | ((self.clk as u8) << 6) | ((self.di as u8) << 1) | self.dout as u8
+    }
+
+    pub fn set(&mut self, val: u8, ram: &mut CartridgeRam) {
+        let cs = val & 0x80 != 0;
+        let clk = val & 0x40 != 0;
+        self.di = val & 0x02 != 0;
+        if !cs {
+            // deselecting aborts the command, a write being programmed stays busy
+            if self.state != EepromState::Idle {
+                self.dout = true;
+            }
+            self.state = EepromState::Idle;
+        } else if clk && !self.clk {
+            self.clock(ram);
+        }
+        self.cs = cs;
+        self.clk = clk;
+    }
+
+    /// rising edge of CLK
+    fn clock(&mut self, ram: &mut CartridgeRam) {
+        match self.state {
+            EepromState::Idle => {
+                // the write is programmed
+                self.dout = true;
+                if self.di {
+                    self.state = EepromState::Command;
+                    self.shift = 0;
+                    self.bits = 0;
+                }
+            }
+            EepromState::Command => {
+                self.receive();
+                if self.bits == 10 {
+                    self.command(ram);
+                }
+            }
+            EepromState::Read => {
+                self.dout = self.output & 0x8000 != 0;
+                self.output <<= 1;
+                self.bits += 1;
+                if self.bits == 16 {
+                    // sequential read
+                    self.address = (self.address + 1) & 0x7F;
+                    self.output = Self::word(ram, self.address);
+                    self.bits = 0;
+                }
+            }
+            EepromState::Write | EepromState::WriteAll => {
+                self.receive();
+                if self.bits == 16 {
+                    if self.state == EepromState::Write {
+                        self.write(ram, self.address, self.shift);
+                    } else {
+                        for address in 0..0x80 {
+                            self.write(ram, address, self.shift);
+                        }
+                    }
+                    self.state = EepromState::Idle;
+                }
+            }
+        }
+    }
+
+    fn receive(&mut self) {
+        self.shift = (self.shift << 1) | self.di as u16;
+        self.bits += 1;
+    }
+
+    fn command(&mut self, ram: &mut CartridgeRam) {
+        let opcode = self.shift >> 8 & 0b11;
+        let address = self.shift as u8;
+        self.address = address & 0x7F;
+        self.shift = 0;
+        self.bits = 0;
+        self.state = EepromState::Idle;
+        match opcode {
+            0b10 => {
+                self.state = EepromState::Read;
+                self.output = Self::word(ram, self.address);
+                // dummy bit before the data
+                self.dout = false;
+            }
+            0b01 => self.state = EepromState::Write,
+            0b11 => self.write(ram, self.address, 0xFFFF),
+            _ => match address >> 6 {
+                0b11 => self.write_enabled = true,
+                0b00 => self.write_enabled = false,
+                0b10 => {
+                    for address in 0..0x80 {
+                        self.write(ram, address, 0xFFFF);
+                    }
+                }
+                _ => self.state = EepromState::WriteAll,
+            },
+        }
+    }
+
+    fn word(ram: &CartridgeRam, address: u8) -> u16 {
+        let offset = address as usize * 2;
+        u16::from_le_bytes([ram.get(offset), ram.get(offset + 1)])
+    }
+
+    fn write(&mut self, ram: &mut CartridgeRam, address: u8, word: u16) {
+        if !self.write_enabled {
+            return;
+        }
+        self.dout = false;
+        let offset = address as usize * 2;
+        let [low, high] = word.to_le_bytes();
+        ram.set(offset, low);
+        ram.set(offset + 1, high);
+    }
+}
+
+impl SaveState for Eeprom {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.state as u8);
+        state.write_bool(self.cs);
+        state.write_bool(self.clk);
+        state.write_bool(self.di);
+        state.write_bool(self.dout);
+        state.write_bool(self.write_enabled);
+        state.write_u16(self.shift);
+        state.write_u8(self.bits);
+        state.write_u8(self.address);
+        state.write_u16(self.output);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.state = state.read_enum(&EepromState::ALL, "eeprom state")?;
+        self.cs = state.read_bool()?;
+        self.clk = state.read_bool()?;
+        self.di = state.read_bool()?;
+        self.dout = state.read_bool()?;
+        self.write_enabled = state.read_bool()?;
+        self.shift = state.read_u16()?;
+        self.bits = state.read_u8()?;
+        self.address = state.read_u8()?;
+        self.output = state.read_u16()?;
+        if self.bits > 16 || self.address > 0x7F { return Err(SaveStateError::InvalidValue("eeprom counters")); }
+        Ok(())
+    }
+}
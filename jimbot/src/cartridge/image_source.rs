@@ -0,0 +1,192 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use crate::png::{decode_png, MAX_DECODED_SIZE};
+
+/// Size of the Game Boy Camera sensor image
+pub const WIDTH: usize = 128;
+pub const HEIGHT: usize = 112;
+/// Largest width and height of the images loaded by `StaticImage`
+pub const MAX_IMAGE_SIZE: usize = MAX_DECODED_SIZE as usize;
+
+/// Gives the Game Boy Camera the image seen by its sensor
+pub trait ImageSource: Send + Sync {
+    /// Fills `pixels` (`WIDTH` × `HEIGHT`, row major) with gray levels, 0 is black and 255 white
+    fn capture(&mut self, pixels: &mut [u8]);
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    /// not a PNG or binary/ascii PGM, or a corrupted one
+    Format(String),
+    Io(std::io::Error),
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageError::Format(error) => write!(f, "Bad image: {}", error),
+            ImageError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for ImageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ImageError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ImageError {
+    fn from(from: std::io::Error) -> Self {
+        ImageError::Io(from)
+    }
+}
+
+/// Same image at every capture, loaded from a PNG or PGM file and stretched to the sensor size.
+/// Images are limited to `MAX_IMAGE_SIZE` pixels in width and height.
+pub struct StaticImage {
+    pixels: Vec<u8>,
+}
+
+impl StaticImage {
+    /// The format is detected from the file content
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ImageError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ImageError> {
+        if bytes.starts_with(b"\x89PNG") { Self::from_png(bytes) } else { Self::from_pgm(bytes) }
+    }
+
+    /// Colors are converted to gray with the BT.601 luma, transparent pixels are white
+    pub fn from_png(bytes: &[u8]) -> Result<Self, ImageError> {
+        let (width, height, rgba) = decode_png(bytes).map_err(ImageError::Format)?;
+        let gray: Vec<u8> = rgba.chunks(4).map(|pixel| {
+            let luma = (pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000;
+            let alpha = pixel[3] as u32;
+            ((luma * alpha + 255 * (255 - alpha)) / 255) as u8
+        }).collect();
+        Self::from_gray(width as usize, height as usize, &gray)
+    }
+
+    /// Binary (P5) or ascii (P2) PGM
+    pub fn from_pgm(bytes: &[u8]) -> Result<Self, ImageError> {
+        let error = |message: &str| ImageError::Format(format!("pgm: {}", message));
+        let binary = match bytes.get(..2) {
+            Some(b"P5") => true,
+            Some(b"P2") => false,
+            _ => return Err(error("unknown format")),
+        };
+        // header fields are separated by whitespace, comments start with #
+        let mut position = 2;
+        let mut fields = [0usize; 3];
+        for field in fields.iter_mut() {
+            loop {
+                match bytes.get(position) {
+                    Some(b'#') => while bytes.get(position).is_some_and(|byte| *byte != b'\n') { position += 1 },
+                    Some(byte) if byte.is_ascii_whitespace() => position += 1,
+                    _ => break,
+                }
+            }
+            let start = position;
+            while bytes.get(position).is_some_and(u8::is_ascii_digit) {
+                position += 1;
+            }
+            *field = std::str::from_utf8(&bytes[start..position]).unwrap().parse().map_err(|_| error("bad header"))?;
+        }
+        let [width, height, max] = fields;
+        if max == 0 || max > 0xFFFF {
+            return Err(error("bad maximum value"));
+        }
+        if width > MAX_IMAGE_SIZE || height > MAX_IMAGE_SIZE {
+            return Err(error("image too large"));
+        }
+        let count = width.checked_mul(height).ok_or_else(|| error("image too large"))?;
+        let samples: Vec<usize> = if binary {
+            // a single whitespace before the data
+            let data = bytes.get(position + 1..).unwrap_or(&[]);
+            if max < 256 {
+                data.iter().take(count).map(|byte| *byte as usize).collect()
+            } else {
+                data.chunks_exact(2).take(count).map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as usize).collect()
+            }
+        } else {
+            std::str::from_utf8(&bytes[position..]).map_err(|_| error("bad data"))?
+                .split_ascii_whitespace().take(count)
+                .map(|sample| sample.parse().map_err(|_| error("bad data")))
+                .collect::<Result<_, _>>()?
+        };
+        if samples.len() != count {
+            return Err(error("truncated data"));
+        }
+        let gray: Vec<u8> = samples.into_iter().map(|sample| (sample.min(max) * 255 / max) as u8).collect();
+        Self::from_gray(width, height, &gray)
+    }
+
+    fn from_gray(width: usize, height: usize, gray: &[u8]) -> Result<Self, ImageError> {
+        if width == 0 || height == 0 {
+            return Err(ImageError::Format(String::from("empty image")));
+        }
+        if gray.len() != width * height {
+            return Err(ImageError::Format(String::from("truncated image")));
+        }
+        let mut pixels = vec![0; WIDTH * HEIGHT];
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                pixels[y * WIDTH + x] = gray[(y * height / HEIGHT) * width + x * width / WIDTH];
+            }
+        }
+        Ok(Self { pixels })
+    }
+}
+
+impl ImageSource for StaticImage {
+    fn capture(&mut self, pixels: &mut [u8]) {
+        pixels.copy_from_slice(&self.pixels);
+    }
+}
+
+/// Generated image for testing without a camera: 4 gray bars over a gradient,
+/// the gradient scrolls by one pixel at each capture
+#[derive(Default)]
+pub struct TestPattern {
+    captures: usize,
+}
+
+impl ImageSource for TestPattern {
+    fn capture(&mut self, pixels: &mut [u8]) {
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                pixels[y * WIDTH + x] = if y < HEIGHT / 4 {
+                    (x / (WIDTH / 4) * 255 / 3) as u8
+                } else {
+                    ((x + self.captures) % WIDTH * 255 / (WIDTH - 1)) as u8
+                };
+            }
+        }
+        self.captures += 1;
+    }
+}
+
+type CaptureCallback = dyn FnMut(&mut [u8]) + Send + Sync;
+
+/// Image given by the frontend (a webcam), see `ImageSource::capture`
+pub struct CallbackImageSource {
+    capture: Box<CaptureCallback>,
+}
+
+impl CallbackImageSource {
+    pub fn new<F: FnMut(&mut [u8]) + Send + Sync + 'static>(capture: F) -> Self {
+        Self { capture: Box::new(capture) }
+    }
+}
+
+impl ImageSource for CallbackImageSource {
+    fn capture(&mut self, pixels: &mut [u8]) {
+        (self.capture)(pixels)
+    }
+}
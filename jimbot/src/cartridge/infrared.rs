@@ -0,0 +1,68 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Infrared port of HuC1/HuC3 cartridges: the LED driven by the game and the light sensor it polls
+pub trait IrTransceiver: Send + Sync {
+    /// The cartridge turned its LED on or off
+    fn set_led(&mut self, on: bool);
+    /// true while light from the other side is received, polled by the cartridge reads
+    fn receiving(&self) -> bool;
+}
+
+/// Two IR ports facing each other, for two `Jimbot` running in the same process.
+/// Each side receives the light of the other side LED.
+pub struct IrLoopback {
+    led: Arc<AtomicBool>,
+    other_led: Arc<AtomicBool>,
+}
+
+impl IrLoopback {
+    pub fn pair() -> (IrLoopback, IrLoopback) {
+        let (a, b) = (Arc::new(AtomicBool::new(false)), Arc::new(AtomicBool::new(false)));
+        (
+            IrLoopback { led: a.clone(), other_led: b.clone() },
+            IrLoopback { led: b, other_led: a },
+        )
+    }
+}
+
+impl IrTransceiver for IrLoopback {
+    fn set_led(&mut self, on: bool) {
+        self.led.store(on, Ordering::Relaxed);
+    }
+
+    fn receiving(&self) -> bool {
+        self.other_led.load(Ordering::Relaxed)
+    }
+}
+
+/// LED state and transceiver shared by the IR cartridges, nothing is received without transceiver
+#[derive(Default)]
+pub(crate) struct IrPort {
+    led: bool,
+    transceiver: Option<Box<dyn IrTransceiver>>,
+}
+
+impl IrPort {
+    pub fn set_transceiver(&mut self, mut transceiver: Box<dyn IrTransceiver>) {
+        transceiver.set_led(self.led);
+        self.transceiver = Some(transceiver);
+    }
+
+    pub fn led(&self) -> bool {
+        self.led
+    }
+
+    pub fn set_led(&mut self, on: bool) {
+        if on != self.led {
+            self.led = on;
+            if let Some(transceiver) = self.transceiver.as_mut() {
+                transceiver.set_led(on);
+            }
+        }
+    }
+
+    pub fn receiving(&self) -> bool {
+        self.transceiver.as_ref().is_some_and(|transceiver| transceiver.receiving())
+    }
+}
@@ -1,26 +1,125 @@
-use std::str;
+use std::fmt::{Display, Formatter};
+use crate::cartridge::cartridge_error::CartridgeError;
 use crate::cartridge::cartridge_type::CartridgeType;
+use crate::cartridge::licensee::Licensee;
 use crate::cartridge::ram_size_type::RamSize;
 use crate::cartridge::rom_size_type::RomSize;
 
+/// Cartridge header ($0100-$014F). Values the boot rom or the hardware would reject come back as warnings,
+/// the cartridge is loaded anyway.
 #[derive(Debug)]
 pub struct Metadata {
     title: String,
+    /// $0134-$0143 as is, title, manufacturer code and CGB flag
+    title_bytes: [u8; 16],
+    manufacturer_code: Option<String>,
+    cgb_support: CgbSupport,
+    licensee: Licensee,
+    sgb_support: bool,
     cartridge_type: CartridgeType,
     rom_size: RomSize,
     ram_size: RamSize,
+    destination: Destination,
+    mask_rom_version: u8,
+    header_checksum: u8,
+    global_checksum: u16,
+    warnings: Vec<HeaderWarning>,
+}
+
+/// CGB flag ($0143)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CgbSupport {
+    /// DMG game
+    None,
+    /// $80, works on DMG and CGB with colors
+    Enhanced,
+    /// $C0, CGB only
+    Only,
+}
+
+/// Destination code ($014A)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Destination {
+    Japan,
+    Overseas,
+}
+
+/// Hardware a game is made for
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Hardware {
+    Dmg,
+    Sgb,
+    Cgb,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeaderWarning {
+    /// the boot rom locks up
+    NintendoLogo,
+    /// the boot rom locks up
+    HeaderChecksum { computed: u8, stored: u8 },
+    /// not checked by the hardware, the rom may be modified or corrupted
+    GlobalChecksum { computed: u16, stored: u16 },
+    UnknownDestination(u8),
+    /// rom bigger than the size declared in the header, the extra banks are not reachable
+    RomSize { size: usize, declared: usize },
+}
+
+impl Display for HeaderWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeaderWarning::NintendoLogo => write!(f, "Nintendo logo does not match"),
+            HeaderWarning::HeaderChecksum { computed, stored } => write!(f, "Header checksum is {:#04X}, expected: {:#04X}", stored, computed),
+            HeaderWarning::GlobalChecksum { computed, stored } => write!(f, "Global checksum is {:#06X}, expected: {:#06X}", stored, computed),
+            HeaderWarning::UnknownDestination(code) => write!(f, "Unknown destination code: {:#04X}", code),
+            HeaderWarning::RomSize { size, declared } => write!(f, "Rom is {} bytes, header declares {} bytes", size, declared),
+        }
+    }
 }
 
 impl Metadata {
-    const TITLE_ADDRESS_MIN: usize = 0x0134;
-    const TITLE_ADDRESS_MAX: usize = 0x0142;
+    const LOGO_ADDRESS: usize = 0x0104;
+    const TITLE_ADDRESS: usize = 0x0134;
+    const MANUFACTURER_CODE_ADDRESS: usize = 0x013F;
+    const CGB_FLAG_ADDRESS: usize = 0x0143;
+    const NEW_LICENSEE_ADDRESS: usize = 0x0144;
+    const SGB_FLAG_ADDRESS: usize = 0x0146;
     const TYPE_ADDRESS: usize = 0x147;
     const ROM_SIZE_ADDRESS: usize = 0x148;
     const RAM_SIZE_ADDRESS: usize = 0x149;
+    const DESTINATION_ADDRESS: usize = 0x014A;
+    const OLD_LICENSEE_ADDRESS: usize = 0x014B;
+    const VERSION_ADDRESS: usize = 0x014C;
+    const HEADER_CHECKSUM_ADDRESS: usize = 0x014D;
+    const GLOBAL_CHECKSUM_ADDRESS: usize = 0x014E;
+    const HEADER_END: usize = 0x150;
+    pub const NINTENDO_LOGO: [u8; 48] = [
+        0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+        0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+        0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+    ];
 
     pub fn title(&self) -> &str {
         &self.title
     }
+    /// raw $0134-$0143, the CGB boot rom sums them to pick the palette of dmg games
+    pub fn title_bytes(&self) -> &[u8; 16] {
+        &self.title_bytes
+    }
+    /// 4 characters code of newer CGB games, taken from the end of the title area
+    pub fn manufacturer_code(&self) -> Option<&str> {
+        self.manufacturer_code.as_deref()
+    }
+    pub fn cgb_support(&self) -> CgbSupport {
+        self.cgb_support
+    }
+    pub fn licensee(&self) -> Licensee {
+        self.licensee
+    }
+    /// SGB flag ($0146) set to $03 with the old licensee code $33, otherwise the SGB ignores the game commands
+    pub fn sgb_support(&self) -> bool {
+        self.sgb_support
+    }
     pub fn ram_size(&self) -> &RamSize {
         &self.ram_size
     }
@@ -30,20 +129,165 @@ impl Metadata {
     pub fn cartridge_type(&self) -> &CartridgeType {
         &self.cartridge_type
     }
-}
+    pub fn destination(&self) -> Destination {
+        self.destination
+    }
+    pub fn mask_rom_version(&self) -> u8 {
+        self.mask_rom_version
+    }
+    /// stored header checksum, a mismatch is in `warnings`
+    pub fn header_checksum(&self) -> u8 {
+        self.header_checksum
+    }
+    /// stored global checksum, a mismatch is in `warnings`
+    pub fn global_checksum(&self) -> u16 {
+        self.global_checksum
+    }
+    pub fn warnings(&self) -> &[HeaderWarning] {
+        &self.warnings
+    }
+
+    /// Hardware to run the game on: CGB for CGB games, SGB for games with SGB functions, DMG otherwise
+    pub fn hardware(&self) -> Hardware {
+        match (self.cgb_support, self.sgb_support) {
+            (CgbSupport::Enhanced | CgbSupport::Only, _) => Hardware::Cgb,
+            (CgbSupport::None, true) => Hardware::Sgb,
+            (CgbSupport::None, false) => Hardware::Dmg,
+        }
+    }
 
-impl From<&Vec<u8>> for Metadata {
-    fn from(from: &Vec<u8>) -> Self {
-        let title = str::from_utf8(&from[Self::TITLE_ADDRESS_MIN..=Self::TITLE_ADDRESS_MAX]).expect("NO TITLE").trim_matches(char::from(0)).to_string();
-        let ram_size = RamSize::from(from[Self::RAM_SIZE_ADDRESS]);
-        let rom_size = RomSize::from(from[Self::ROM_SIZE_ADDRESS]);
-        let cartridge_type = CartridgeType::from(from[Self::TYPE_ADDRESS]);
-        Self {
+    /// Header of a whole rom. MMM01 multicarts start with the first game, their header is the one of the menu
+    /// in the last 32 KiB. It is only used when it has the logo and a valid checksum, and bank 0 is not MMM01 itself.
+    pub fn from_rom(rom: &[u8]) -> Result<Self, CartridgeError> {
+        if rom.len() >= 0x10000 && !Self::is_mmm01(rom, 0) {
+            let menu = rom.len() - 0x8000;
+            let header = &rom[menu..menu + Self::HEADER_END];
+            if Self::is_mmm01(rom, menu) && header[Self::LOGO_ADDRESS..Self::TITLE_ADDRESS] == Self::NINTENDO_LOGO
+                && Self::computed_header_checksum(header) == header[Self::HEADER_CHECKSUM_ADDRESS] {
+                return Self::parse(rom, menu);
+            }
+        }
+        Self::try_from(rom)
+    }
+
+    fn is_mmm01(rom: &[u8], header_offset: usize) -> bool {
+        matches!(rom.get(header_offset + Self::TYPE_ADDRESS).map(|code| CartridgeType::try_from(*code)),
+            Some(Ok(CartridgeType::RomMmm01 | CartridgeType::RomMmm01Sram | CartridgeType::RomMmm01SramBattery)))
+    }
+
+    fn computed_header_checksum(header: &[u8]) -> u8 {
+        header[Self::TITLE_ADDRESS..Self::HEADER_CHECKSUM_ADDRESS].iter()
+            .fold(0u8, |checksum, byte| checksum.wrapping_sub(*byte).wrapping_sub(1))
+    }
+
+    /// `header_offset` is the start of the bank holding the header
+    fn parse(rom: &[u8], header_offset: usize) -> Result<Self, CartridgeError> {
+        let header = &rom[header_offset..];
+        if header.len() < Self::HEADER_END {
+            return Err(CartridgeError::TruncatedRom { size: header.len(), expected: Self::HEADER_END });
+        }
+        let ram_size = RamSize::try_from(header[Self::RAM_SIZE_ADDRESS])?;
+        let declared_rom_size = RomSize::try_from(header[Self::ROM_SIZE_ADDRESS])?;
+        let cartridge_type = CartridgeType::try_from(header[Self::TYPE_ADDRESS])?;
+        if rom.len() < declared_rom_size.size as usize {
+            return Err(CartridgeError::TruncatedRom { size: rom.len(), expected: declared_rom_size.size as usize });
+        }
+        let mut warnings = vec![];
+        if header[Self::LOGO_ADDRESS..Self::TITLE_ADDRESS] != Self::NINTENDO_LOGO {
+            warnings.push(HeaderWarning::NintendoLogo);
+        }
+
+        let cgb_flag = header[Self::CGB_FLAG_ADDRESS];
+        let cgb_support = match cgb_flag {
+            0xC0..=0xFF => CgbSupport::Only,
+            0x80..=0xBF => CgbSupport::Enhanced,
+            _ => CgbSupport::None,
+        };
+        let old_licensee = header[Self::OLD_LICENSEE_ADDRESS];
+        let licensee = Licensee::new(old_licensee, [header[Self::NEW_LICENSEE_ADDRESS], header[Self::NEW_LICENSEE_ADDRESS + 1]]);
+        // the title is 16 characters on DMG games, 15 with the CGB flag, 11 when the manufacturer code follows
+        // (CGB games with the new licensee code, the code is 4 upper case letters or digits)
+        let manufacturer_code = &header[Self::MANUFACTURER_CODE_ADDRESS..Self::CGB_FLAG_ADDRESS];
+        let has_manufacturer_code = cgb_support != CgbSupport::None && old_licensee == Licensee::USE_NEW_CODE
+            && manufacturer_code.iter().all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit());
+        let title_end = match (cgb_support, has_manufacturer_code) {
+            (_, true) => Self::MANUFACTURER_CODE_ADDRESS,
+            (CgbSupport::None, false) => Self::CGB_FLAG_ADDRESS + 1,
+            (_, false) => Self::CGB_FLAG_ADDRESS,
+        };
+        let title = header[Self::TITLE_ADDRESS..title_end].split(|byte| *byte == 0).next().unwrap_or_default();
+        let title = String::from_utf8_lossy(title).trim_end().to_string();
+        let manufacturer_code = has_manufacturer_code.then(|| String::from_utf8_lossy(manufacturer_code).to_string());
+
+        let destination = match header[Self::DESTINATION_ADDRESS] {
+            0x00 => Destination::Japan,
+            0x01 => Destination::Overseas,
+            code => {
+                warnings.push(HeaderWarning::UnknownDestination(code));
+                Destination::Overseas
+            }
+        };
+
+        let header_checksum = header[Self::HEADER_CHECKSUM_ADDRESS];
+        let computed = Self::computed_header_checksum(header);
+        if computed != header_checksum {
+            warnings.push(HeaderWarning::HeaderChecksum { computed, stored: header_checksum });
+        }
+        // sum of every rom byte but the checksum itself
+        let checksum_address = header_offset + Self::GLOBAL_CHECKSUM_ADDRESS;
+        let global_checksum = u16::from_be_bytes([rom[checksum_address], rom[checksum_address + 1]]);
+        let computed = rom.iter().fold(0u16, |checksum, byte| checksum.wrapping_add(*byte as u16))
+            .wrapping_sub(rom[checksum_address] as u16)
+            .wrapping_sub(rom[checksum_address + 1] as u16);
+        if computed != global_checksum {
+            warnings.push(HeaderWarning::GlobalChecksum { computed, stored: global_checksum });
+        }
+        if rom.len() > declared_rom_size.size as usize && header_offset == 0 {
+            warnings.push(HeaderWarning::RomSize { size: rom.len(), declared: declared_rom_size.size as usize });
+        }
+
+        Ok(Self {
             title,
+            title_bytes: header[Self::TITLE_ADDRESS..=Self::CGB_FLAG_ADDRESS].try_into().unwrap(),
+            manufacturer_code,
+            cgb_support,
+            licensee,
+            sgb_support: header[Self::SGB_FLAG_ADDRESS] == 0x03 && old_licensee == Licensee::USE_NEW_CODE,
             ram_size,
-            rom_size,
+            rom_size: declared_rom_size,
             cartridge_type,
-        }
+            destination,
+            mask_rom_version: header[Self::VERSION_ADDRESS],
+            header_checksum,
+            global_checksum,
+            warnings,
+        })
     }
 }
 
+impl TryFrom<&[u8]> for Metadata {
+    type Error = CartridgeError;
+
+    fn try_from(from: &[u8]) -> Result<Self, Self::Error> {
+        Self::parse(from, 0)
+    }
+}
+
+/// Rom information, one field per line
+impl Display for Metadata {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Title: {}", self.title)?;
+        if let Some(manufacturer_code) = &self.manufacturer_code {
+            writeln!(f, "Manufacturer code: {}", manufacturer_code)?;
+        }
+        writeln!(f, "Publisher: {}", self.licensee)?;
+        writeln!(f, "Cartridge: {:?}, rom: {} KiB, ram: {} KiB", self.cartridge_type, self.rom_size.size / 1024, self.ram_size.size / 1024)?;
+        writeln!(f, "CGB: {:?}, SGB: {}", self.cgb_support, if self.sgb_support { "yes" } else { "no" })?;
+        writeln!(f, "Destination: {:?}, version: {}", self.destination, self.mask_rom_version)?;
+        write!(f, "Checksums: header {:#04X}, global {:#06X}", self.header_checksum, self.global_checksum)?;
+        for warning in &self.warnings {
+            write!(f, "\nWarning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,16 @@
+use crate::cartridge::cartridge_error::CartridgeError;
+
 #[derive(Debug)]
 pub struct RomSize {
     pub size: u32,
     pub bank_size: u16,
 }
 
-impl From<u8> for RomSize {
-    fn from(code: u8) -> Self {
-        match code {
+impl TryFrom<u8> for RomSize {
+    type Error = CartridgeError;
+
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        Ok(match code {
             0x00 => RomSize { size: (32 * 1024), bank_size: 2 },
             0x01 => RomSize { size: (64 * 1024), bank_size: 4 },
             0x02 => RomSize { size: (128 * 1024), bank_size: 8 },
@@ -19,7 +23,7 @@ impl From<u8> for RomSize {
             0x52 => RomSize { size: (1.1 * 1024.0 * 1024.0) as u32, bank_size: 72 },
             0x53 => RomSize { size: (1.2 * 1024.0 * 1024.0) as u32, bank_size: 80 },
             0x54 => RomSize { size: (3 * 1024 * 1024 / 2), bank_size: 96 },
-            _ => panic!("Unknown rom size code {:02x}", code)
-        }
+            _ => return Err(CartridgeError::UnknownRomSize(code)),
+        })
     }
 }
\ No newline at end of file
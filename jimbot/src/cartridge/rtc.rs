@@ -0,0 +1,193 @@
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// MBC3 real time clock, ticked by the emulated cycles.
+///
+/// Registers selected with $08-$0C in $4000-$5FFF: seconds, minutes, hours, day low
+/// and day high (bit 0 day bit 8, bit 6 halt, bit 7 day counter carry).
+/// The game reads the copy made by the latch sequence (writing $00 then $01 in $6000-$7FFF).
+#[derive(Clone, Default)]
+pub struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    /// 9 bits
+    days: u16,
+    halt: bool,
+    carry: bool,
+    latched: [u8; 5],
+    /// m-cycles since the last second
+    sub_second: u32,
+}
+
+impl Rtc {
+    /// the RTC crystal runs at 32768 Hz, one second is 2^20 m-cycles
+    const M_CYCLES_PER_SECOND: u32 = 1 << 20;
+
+    /// Footer appended to the cartridge ram in `.sav` files (used by BGB, VBA-M, mGBA and SameBoy):
+    /// current then latched registers as u32 le, and the unix time of the save as u64 le.
+    pub const FOOTER_SIZE: usize = 48;
+    /// older footer with a u32 unix time
+    pub const FOOTER_SIZE_32: usize = 44;
+
+    pub fn cycle(&mut self) {
+        if self.halt {
+            return;
+        }
+        self.sub_second += 1;
+        if self.sub_second == Self::M_CYCLES_PER_SECOND {
+            self.sub_second = 0;
+            self.tick();
+        }
+    }
+
+    /// Copies the clock in the registers read by the game
+    pub fn latch(&mut self) {
+        self.latched = self.registers();
+    }
+
+    /// `register` is the $08-$0C value selected by the game, reads the latched registers
+    pub fn get(&self, register: u8) -> u8 {
+        match register {
+            0x08..=0x0C => self.latched[(register - 0x08) as usize],
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes go to the clock itself, writing the seconds resets the sub-second counter
+    pub fn set(&mut self, register: u8, val: u8) {
+        match register {
+            0x08 => {
+                self.seconds = val & 0x3F;
+                self.sub_second = 0;
+            }
+            0x09 => self.minutes = val & 0x3F,
+            0x0A => self.hours = val & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | val as u16,
+            0x0C => {
+                self.days = (self.days & 0xFF) | ((val as u16 & 1) << 8);
+                self.halt = val & 0x40 != 0;
+                self.carry = val & 0x80 != 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn registers(&self) -> [u8; 5] {
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days as u8,
+            ((self.days >> 8) as u8) | ((self.halt as u8) << 6) | ((self.carry as u8) << 7),
+        ]
+    }
+
+    /// One second, out of range values (set by the game) count up to their bit width
+    /// and wrap to 0 without carrying
+    fn tick(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        self.days += 1;
+        if self.days == 0x200 {
+            self.days = 0;
+            self.carry = true;
+        }
+    }
+
+    /// Moves the clock forward by `seconds` of host time, nothing happens while halted
+    pub fn advance(&mut self, mut seconds: u64) {
+        if self.halt {
+            return;
+        }
+        while seconds > 0 && (self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24) {
+            self.tick();
+            seconds -= 1;
+        }
+        let total = self.seconds as u64 + self.minutes as u64 * 60 + self.hours as u64 * 3600
+            + self.days as u64 * 86400 + seconds;
+        let days = total / 86400;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        self.days = (days % 0x200) as u16;
+        self.carry |= days >= 0x200;
+    }
+
+    /// `timestamp` is the unix time of the save
+    pub fn to_footer(&self, timestamp: u64) -> [u8; Self::FOOTER_SIZE] {
+        let mut footer = [0; Self::FOOTER_SIZE];
+        let registers = self.registers().into_iter().chain(self.latched);
+        for (i, register) in registers.enumerate() {
+            footer[i * 4..i * 4 + 4].copy_from_slice(&(register as u32).to_le_bytes());
+        }
+        footer[40..].copy_from_slice(&timestamp.to_le_bytes());
+        footer
+    }
+
+    /// Returns the clock and the unix time of the save, `None` when `footer` has not a footer size
+    pub fn from_footer(footer: &[u8]) -> Option<(Rtc, u64)> {
+        let timestamp = match footer.len() {
+            Self::FOOTER_SIZE => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+            Self::FOOTER_SIZE_32 => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64,
+            _ => return None,
+        };
+        let register = |i: usize| footer[i * 4];
+        let mut rtc = Rtc::default();
+        for i in 0..5 {
+            rtc.set(0x08 + i as u8, register(i));
+            rtc.latched[i] = register(5 + i);
+        }
+        Some((rtc, timestamp))
+    }
+}
+
+/// Seconds since the unix epoch, the browser clock on wasm. `None` without a clock (wasm without the `wasm` feature)
+pub fn unix_time() -> Option<u64> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).ok().map(|duration| duration.as_secs())
+    }
+    #[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+    {
+        Some((js_sys::Date::now() / 1000.0) as u64)
+    }
+    #[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+    {
+        None
+    }
+}
+
+impl SaveState for Rtc {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.registers());
+        state.write_bytes(&self.latched);
+        state.write_u32(self.sub_second);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        let mut registers = [0; 5];
+        state.read_bytes(&mut registers)?;
+        for (i, register) in registers.into_iter().enumerate() {
+            self.set(0x08 + i as u8, register);
+        }
+        state.read_bytes(&mut self.latched)?;
+        self.sub_second = state.read_u32()?;
+        if self.sub_second >= Self::M_CYCLES_PER_SECOND {
+            return Err(SaveStateError::InvalidValue("rtc sub-second counter"));
+        }
+        Ok(())
+    }
+}
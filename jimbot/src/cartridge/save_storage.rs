@@ -0,0 +1,199 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use crate::cartridge::cartridge_error::CartridgeError;
+
+/// Where battery backed cartridge ram is kept between sessions.
+/// `store` receives the whole save (ram, then the rtc footer for clock cartridges).
+pub trait SaveStorage: Send + Sync {
+    /// Returns the saved bytes, `None` when nothing was saved yet
+    fn load(&mut self) -> std::io::Result<Option<Vec<u8>>>;
+    fn store(&mut self, bytes: &[u8]) -> std::io::Result<()>;
+
+    /// used in error messages
+    fn name(&self) -> String {
+        String::from("save storage")
+    }
+}
+
+/// Save file on disk, written to `<path>.tmp` then renamed over `path`.
+/// Every write first copies the previous save to `<path>.bak`.
+pub struct FileStorage {
+    path: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// `<title>.sav` next to the rom file
+    pub fn next_to_rom(rom_file_path: &str, title: &str) -> Self {
+        let mut path = Path::new(rom_file_path).to_owned();
+        path.pop();
+        path.push(format!("{}.sav", title));
+        Self::new(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn with_suffix(&self, suffix: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(suffix);
+        path.into()
+    }
+}
+
+impl SaveStorage for FileStorage {
+    fn load(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => {
+                println!("SAVE FILE FOUND: {:?}", self.path);
+                Ok(Some(bytes))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn store(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        let tmp_path = self.with_suffix(".tmp");
+        let mut tmp_file = File::create(&tmp_path)?;
+        tmp_file.write_all(bytes)?;
+        tmp_file.sync_all()?;
+        if self.path.exists() {
+            std::fs::copy(&self.path, self.with_suffix(".bak"))?;
+        }
+        std::fs::rename(&tmp_path, &self.path)
+    }
+
+    fn name(&self) -> String {
+        self.path.to_string_lossy().to_string()
+    }
+}
+
+/// Keeps the save in memory, clones share the same bytes
+#[derive(Clone, Default)]
+pub struct MemoryStorage {
+    bytes: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl MemoryStorage {
+    pub fn new(bytes: Option<Vec<u8>>) -> Self {
+        Self { bytes: Arc::new(Mutex::new(bytes)) }
+    }
+
+    pub fn bytes(&self) -> Option<Vec<u8>> {
+        self.bytes.lock().unwrap().clone()
+    }
+}
+
+impl SaveStorage for MemoryStorage {
+    fn load(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self.bytes())
+    }
+
+    fn store(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        *self.bytes.lock().unwrap() = Some(bytes.to_vec());
+        Ok(())
+    }
+}
+
+type StoreCallback = dyn FnMut(&[u8]) + Send + Sync;
+
+/// Save loaded by the frontend, every flush is given to `store` (the web build writes it to local storage)
+pub struct CallbackStorage {
+    saved: Option<Vec<u8>>,
+    store: Box<StoreCallback>,
+}
+
+impl CallbackStorage {
+    pub fn new<F: FnMut(&[u8]) + Send + Sync + 'static>(saved: Option<Vec<u8>>, store: F) -> Self {
+        Self { saved, store: Box::new(store) }
+    }
+}
+
+impl SaveStorage for CallbackStorage {
+    fn load(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self.saved.take())
+    }
+
+    fn store(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        (self.store)(bytes);
+        Ok(())
+    }
+}
+
+/// Battery of a cartridge: writes to the ram are batched, the save is flushed once the game
+/// stopped writing for `IDLE_M_CYCLES` or `MAX_DELAY_M_CYCLES` after the first unsaved write.
+pub(crate) struct Battery {
+    storage: Option<Box<dyn SaveStorage>>,
+    dirty: bool,
+    /// m-cycles since the last write
+    idle: u32,
+    /// m-cycles since the first unsaved write
+    pending: u32,
+}
+
+impl Battery {
+    /// about 1 second
+    const IDLE_M_CYCLES: u32 = 1 << 20;
+    /// about 10 seconds
+    const MAX_DELAY_M_CYCLES: u32 = 10 << 20;
+
+    pub fn new(storage: Option<Box<dyn SaveStorage>>) -> Self {
+        Self { storage, dirty: false, idle: 0, pending: 0 }
+    }
+
+    /// Reads the save into `ram`, the save may end with a footer of one of `footer_sizes` bytes.
+    /// Returns the footer, empty when the save has none.
+    pub fn load(&mut self, ram: &mut [u8], footer_sizes: &[usize]) -> Result<Vec<u8>, CartridgeError> {
+        let Some(storage) = self.storage.as_mut() else { return Ok(Vec::new()) };
+        let Some(bytes) = storage.load()? else { return Ok(Vec::new()) };
+        match bytes.len().checked_sub(ram.len()) {
+            Some(footer_size) if footer_size == 0 || footer_sizes.contains(&footer_size) => {
+                ram.copy_from_slice(&bytes[..ram.len()]);
+                Ok(bytes[ram.len()..].to_vec())
+            }
+            _ => Err(CartridgeError::BadSaveSize { path: storage.name(), size: bytes.len() as u64, expected: ram.len() as u64 }),
+        }
+    }
+
+    /// the ram changed
+    pub fn write(&mut self) {
+        self.dirty = true;
+        self.idle = 0;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// returns true when the save should be flushed
+    pub fn cycle(&mut self) -> bool {
+        if !self.dirty {
+            return false;
+        }
+        self.idle += 1;
+        self.pending += 1;
+        self.idle >= Self::IDLE_M_CYCLES || self.pending >= Self::MAX_DELAY_M_CYCLES
+    }
+
+    pub fn detach(&mut self) {
+        self.storage = None;
+    }
+
+    pub fn flush(&mut self, bytes: &[u8]) {
+        self.dirty = false;
+        self.idle = 0;
+        self.pending = 0;
+        if let Some(storage) = self.storage.as_mut() {
+            if let Err(err) = storage.store(bytes) {
+                println!("Cannot write save to {}: {}", storage.name(), err);
+            }
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! Frame buffer colors
+//!
+//! The PPU outputs RGB555 colors as stored in the CGB palette ram: red in bits 0-4, green in bits 5-9
+//! and blue in bits 10-14. Frontends use `rgb888` or `Frame::to_rgba` to display them.
+
+pub mod compatibility;
+
+/// Shades of the DMG palettes without cartridge, dmg games get their `compatibility` palette
+pub const DMG_GRAYSCALE: [u32; 4] = [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000];
+
+/// `0xRRGGBB` to RGB555, the low 3 bits of each component are dropped
+pub const fn rgb555(rgb888: u32) -> u16 {
+    let r = (rgb888 >> 19) & 0x1F;
+    let g = (rgb888 >> 11) & 0x1F;
+    let b = (rgb888 >> 3) & 0x1F;
+    (r | (g << 5) | (b << 10)) as u16
+}
+
+/// RGB555 to `0xRRGGBB`, white stays white
+pub const fn rgb888(rgb555: u16) -> u32 {
+    (expand(rgb555) << 16) | (expand(rgb555 >> 5) << 8) | expand(rgb555 >> 10)
+}
+
+/// 5 bits component to 8 bits
+const fn expand(component: u16) -> u32 {
+    let c = (component & 0x1F) as u32;
+    (c << 3) | (c >> 2)
+}
@@ -0,0 +1,254 @@
+//! Colors given to dmg games by the CGB boot rom
+//!
+//! Games published by Nintendo are looked up by the sum of their title bytes ($0134-$0143), a few sums being
+//! shared by several titles the 4th letter tells them apart. Other games get the default palette, holding a
+//! direction (and A or B) during the boot logo picks one of 12 palettes instead.
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crate::cartridge::metadata::Metadata;
+
+/// RGB555 colors of the bg, OBP0 and OBP1 shades, lightest first
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DmgPalette {
+    pub bg: [u16; 4],
+    pub obj0: [u16; 4],
+    pub obj1: [u16; 4],
+}
+
+impl DmgPalette {
+    /// same `0xRRGGBB` colors for the bg and the sprites
+    pub fn from_rgb888(colors: [u32; 4]) -> Self {
+        let colors = colors.map(super::rgb555);
+        Self { bg: colors, obj0: colors, obj1: colors }
+    }
+
+    /// palette picked by the boot rom for the game of `metadata`
+    pub fn for_game(metadata: &Metadata) -> Self {
+        if !metadata.licensee().is_nintendo() {
+            return Self::combination(0);
+        }
+        let title = metadata.title_bytes();
+        let checksum = title.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        let combination = match TITLE_CHECKSUMS.iter().chain(&DUPLICATE_CHECKSUMS).position(|sum| *sum == checksum) {
+            Some(index) if index < TITLE_CHECKSUMS.len() => PALETTE_PER_CHECKSUM[index],
+            // the same checksum comes back every 14 letters
+            Some(index) => (index - TITLE_CHECKSUMS.len()..FOURTH_LETTERS.len()).step_by(DUPLICATE_CHECKSUMS.len())
+                .find(|i| FOURTH_LETTERS[*i] == title[3])
+                .map_or(0, |i| PALETTE_PER_CHECKSUM[TITLE_CHECKSUMS.len() + i]),
+            None => 0,
+        };
+        Self::combination(combination)
+    }
+
+    fn combination(combination: u8) -> Self {
+        let palette = |offset: u8| {
+            let offset = offset as usize;
+            [COLORS[offset], COLORS[offset + 1], COLORS[offset + 2], COLORS[offset + 3]]
+        };
+        let [obj0, obj1, bg] = COMBINATIONS[combination as usize];
+        Self { bg: palette(bg), obj0: palette(obj0), obj1: palette(obj1) }
+    }
+}
+
+impl From<ButtonCombo> for DmgPalette {
+    fn from(combo: ButtonCombo) -> Self {
+        Self::combination(combo.combination())
+    }
+}
+
+/// Buttons held during the boot logo to choose the palette
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ButtonCombo {
+    Up,
+    UpA,
+    UpB,
+    Left,
+    LeftA,
+    LeftB,
+    Down,
+    DownA,
+    DownB,
+    Right,
+    RightA,
+    RightB,
+}
+
+impl ButtonCombo {
+    pub const ALL: [ButtonCombo; 12] = [
+        ButtonCombo::Up, ButtonCombo::UpA, ButtonCombo::UpB,
+        ButtonCombo::Left, ButtonCombo::LeftA, ButtonCombo::LeftB,
+        ButtonCombo::Down, ButtonCombo::DownA, ButtonCombo::DownB,
+        ButtonCombo::Right, ButtonCombo::RightA, ButtonCombo::RightB,
+    ];
+
+    fn combination(&self) -> u8 {
+        match self {
+            ButtonCombo::Up => 5,
+            ButtonCombo::UpA => 43,
+            ButtonCombo::UpB => 28,
+            ButtonCombo::Left => 48,
+            ButtonCombo::LeftA => 40,
+            ButtonCombo::LeftB => 7,
+            ButtonCombo::Down => 8,
+            ButtonCombo::DownA => 3,
+            ButtonCombo::DownB => 49,
+            ButtonCombo::Right => 1,
+            ButtonCombo::RightA => 0,
+            ButtonCombo::RightB => 6,
+        }
+    }
+}
+
+/// `up`, `up+a`, `left+b`...
+impl Display for ButtonCombo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (direction, button) = match self {
+            ButtonCombo::Up => ("up", ""),
+            ButtonCombo::UpA => ("up", "+a"),
+            ButtonCombo::UpB => ("up", "+b"),
+            ButtonCombo::Left => ("left", ""),
+            ButtonCombo::LeftA => ("left", "+a"),
+            ButtonCombo::LeftB => ("left", "+b"),
+            ButtonCombo::Down => ("down", ""),
+            ButtonCombo::DownA => ("down", "+a"),
+            ButtonCombo::DownB => ("down", "+b"),
+            ButtonCombo::Right => ("right", ""),
+            ButtonCombo::RightA => ("right", "+a"),
+            ButtonCombo::RightB => ("right", "+b"),
+        };
+        write!(f, "{}{}", direction, button)
+    }
+}
+
+impl FromStr for ButtonCombo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+        ButtonCombo::ALL.into_iter()
+            .find(|combo| combo.to_string() == s)
+            .ok_or_else(|| format!("Unknown palette {}, expected one of up, up+a, up+b, left... right+b", s))
+    }
+}
+
+/// boot rom palettes, 4 RGB555 colors each
+const COLORS: [u16; 120] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000,
+    0x639F, 0x4279, 0x15B0, 0x04CB,
+    0x7FFF, 0x6E31, 0x454A, 0x0000,
+    0x7FFF, 0x1BEF, 0x0200, 0x0000,
+    0x7FFF, 0x421F, 0x1CF2, 0x0000,
+    0x7FFF, 0x5294, 0x294A, 0x0000,
+    0x7FFF, 0x03FF, 0x012F, 0x0000,
+    0x7FFF, 0x03EF, 0x01D6, 0x0000,
+    0x7FFF, 0x42B5, 0x3DC8, 0x0000,
+    0x7E74, 0x03FF, 0x0180, 0x0000,
+    0x67FF, 0x77AC, 0x1A13, 0x2D6B,
+    0x7ED6, 0x4BFF, 0x2175, 0x0000,
+    0x53FF, 0x4A5F, 0x7E52, 0x0000,
+    0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0,
+    0x03ED, 0x7FFF, 0x255F, 0x0000,
+    0x036A, 0x021F, 0x03FF, 0x7FFF,
+    0x7FFF, 0x01DF, 0x0112, 0x0000,
+    0x231F, 0x035F, 0x00F2, 0x0009,
+    0x7FFF, 0x03EA, 0x011F, 0x0000,
+    0x299F, 0x001A, 0x000C, 0x0000,
+    0x7FFF, 0x027F, 0x001F, 0x0000,
+    0x7FFF, 0x03E0, 0x0206, 0x0120,
+    0x7FFF, 0x7EEB, 0x001F, 0x7C00,
+    0x7FFF, 0x3FFF, 0x7E00, 0x001F,
+    0x7FFF, 0x03FF, 0x001F, 0x0000,
+    0x03FF, 0x001F, 0x000C, 0x0000,
+    0x7FFF, 0x033F, 0x0193, 0x0000,
+    0x0000, 0x4200, 0x037F, 0x7FFF,
+    0x7FFF, 0x7E8C, 0x7C00, 0x0000,
+    0x7FFF, 0x1BEF, 0x6180, 0x0000,
+];
+
+/// offsets in `COLORS` of the obj0, obj1 and bg palettes of a combination
+const fn palettes(obj0: u8, obj1: u8, bg: u8) -> [u8; 3] {
+    [obj0 * 4, obj1 * 4, bg * 4]
+}
+
+/// a few obj palettes start on the last color of the previous palette, their shades are shifted
+const COMBINATIONS: [[u8; 3]; 51] = [
+    palettes(4, 4, 29),
+    palettes(18, 18, 18),
+    palettes(20, 20, 20),
+    palettes(24, 24, 24),
+    palettes(9, 9, 9),
+    palettes(0, 0, 0),
+    palettes(27, 27, 27),
+    palettes(5, 5, 5),
+    palettes(12, 12, 12),
+    palettes(26, 26, 26),
+    palettes(16, 8, 8),
+    palettes(4, 28, 28),
+    palettes(4, 2, 2),
+    palettes(3, 4, 4),
+    palettes(4, 29, 29),
+    palettes(28, 4, 28),
+    palettes(2, 17, 2),
+    palettes(16, 16, 8),
+    palettes(4, 4, 7),
+    palettes(4, 4, 18),
+    palettes(4, 4, 20),
+    palettes(19, 19, 9),
+    [4 * 4 - 1, 4 * 4 - 1, 11 * 4],
+    palettes(17, 17, 2),
+    palettes(4, 4, 2),
+    palettes(4, 4, 3),
+    palettes(28, 28, 0),
+    palettes(3, 3, 0),
+    palettes(0, 0, 1),
+    palettes(18, 22, 18),
+    palettes(20, 22, 20),
+    palettes(24, 22, 24),
+    palettes(16, 22, 8),
+    palettes(17, 4, 13),
+    [28 * 4 - 1, 0, 14 * 4],
+    [28 * 4 - 1, 4 * 4, 15 * 4],
+    palettes(19, 22, 9),
+    palettes(16, 28, 10),
+    palettes(4, 23, 28),
+    palettes(17, 22, 2),
+    palettes(4, 0, 2),
+    palettes(4, 28, 3),
+    palettes(28, 3, 0),
+    palettes(3, 28, 4),
+    palettes(21, 28, 4),
+    palettes(3, 28, 0),
+    palettes(25, 3, 28),
+    palettes(0, 28, 8),
+    palettes(4, 3, 28),
+    palettes(28, 3, 6),
+    palettes(4, 28, 29),
+];
+
+/// title checksums of known games, the first one is the default palette
+const TITLE_CHECKSUMS: [u8; 65] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B,
+];
+
+/// checksums shared by several titles
+const DUPLICATE_CHECKSUMS: [u8; 14] = [0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4];
+
+/// 4th title letter of each game sharing a checksum, `DUPLICATE_CHECKSUMS` repeated
+const FOURTH_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+/// combination of the `TITLE_CHECKSUMS` games then of the `FOURTH_LETTERS` ones
+const PALETTE_PER_CHECKSUM: [u8; 94] = [
+    0, 4, 5, 35, 34, 3, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44,
+    21, 32, 31, 20, 5, 33, 13, 14, 5, 29, 5, 18, 9, 3, 2, 26,
+    25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34,
+    5, 42, 6, 5, 33, 25, 42, 42, 40, 2, 16, 25, 42, 42, 5, 0,
+    39,
+    36, 22, 25, 6, 32, 12, 36, 11, 39, 18, 39, 24, 31, 50,
+    17, 46, 6, 27, 0, 47, 41, 41, 0, 0, 19, 34, 23, 18,
+    29,
+];
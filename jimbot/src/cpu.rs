@@ -1925,6 +1925,8 @@ impl CPU {
 */
 
 use std::mem;
+use crate::boot::post_boot;
+use crate::cartridge::metadata::Hardware;
 use crate::cpu::hex_u8::HexU8;
 use crate::cpu::instruction::Instruction;
 use crate::cpu::op::Op;
@@ -1935,6 +1937,7 @@ use crate::cpu::registers::R16::{PC, SP};
 use crate::cpu::registers::R8::{PCh, PCl};
 use crate::cpu::registers::{R16, Registers};
 use crate::mmu::MMU;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub mod instruction;
 mod decoder;
@@ -1954,6 +1957,7 @@ pub struct CPU {
     ime: bool,
     registers: Registers,
     instruction: Instruction,
+    breakpoint: bool,
 }
 
 impl Default for CPU {
@@ -1965,6 +1969,7 @@ impl Default for CPU {
             ime: false,
             instruction: Instruction::new((Dcd, FetchU8, Non), None),
             registers,
+            breakpoint: false,
         }
     }
 }
@@ -2071,4 +2076,42 @@ impl CPU {
     pub fn instruction(&self) -> &Instruction {
         &self.instruction
     }
-}
\ No newline at end of file
+    /// true from the decoding of LD B,B ($40) until the next instruction is decoded
+    pub fn is_at_breakpoint(&self) -> bool {
+        self.breakpoint
+    }
+    /// Sets the registers as left by the dmg boot rom, the first fetch reads $0100
+    pub(crate) fn skip_boot(&mut self, hardware: Hardware) {
+        let (af, bc, de, hl) = match hardware {
+            Hardware::Cgb => (post_boot::cgb::AF, post_boot::cgb::BC, post_boot::cgb::DE, post_boot::cgb::HL),
+            Hardware::Sgb => (post_boot::sgb::AF, post_boot::sgb::BC, post_boot::sgb::DE, post_boot::sgb::HL),
+            Hardware::Dmg => (post_boot::AF, post_boot::BC, post_boot::DE, post_boot::HL),
+        };
+        self.registers.set16(R16::AF, af);
+        self.registers.set16(R16::BC, bc);
+        self.registers.set16(R16::DE, de);
+        self.registers.set16(R16::HL, hl);
+        self.registers.set16(SP, post_boot::SP);
+        self.registers.set16(PC, post_boot::PC);
+    }
+}
+
+impl SaveState for CPU {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_tag(b"CPU ");
+        state.write_bool(self.halted);
+        state.write_bool(self.ime_requested);
+        state.write_bool(self.ime);
+        self.registers.save_state(state);
+        self.instruction.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        state.read_tag(b"CPU ")?;
+        self.halted = state.read_bool()?;
+        self.ime_requested = state.read_bool()?;
+        self.ime = state.read_bool()?;
+        self.registers.load_state(state)?;
+        self.instruction.load_state(state)
+    }
+}
@@ -1,2 +1,7 @@
 #[derive(Debug, Copy, Clone)]
-pub enum Condition { Z, NZ, C, NC }
\ No newline at end of file
+pub enum Condition { Z, NZ, C, NC }
+
+impl Condition {
+    /// indexed by `condition as u8`
+    pub(crate) const ALL: [Condition; 4] = [Condition::Z, Condition::NZ, Condition::C, Condition::NC];
+}
@@ -2,7 +2,7 @@ use crate::cpu::condition::Condition;
 use crate::cpu::CPU;
 use crate::cpu::instruction::Instruction;
 use crate::cpu::op::Op;
-use crate::cpu::op::Op::{Adc, Add, And, Bit, Call, Ccf, Cp, Cpl, Daa, DcdCB, Dec, Di, Ei, Halt, Inc, Internal, Jp, Jr, Ld, Nop, Or, Res, Ret, EiImm, Rl, Rla, Rlc, Rlca, Rr, Rra, Rrc, Rrca, Rst, Sbc, Scf, Set, Sla, Sra, Srl, Sub, Swap, Xor};
+use crate::cpu::op::Op::{Adc, Add, And, Bit, Call, Ccf, Cp, Cpl, Daa, DcdCB, Dec, Di, Ei, Halt, Inc, Internal, Jp, Jr, Ld, Nop, Or, Res, Ret, EiImm, Rl, Rla, Rlc, Rlca, Rr, Rra, Rrc, Rrca, Rst, Sbc, Scf, Set, Sla, Sra, Srl, Stop, Sub, Swap, Xor};
 use crate::cpu::op_arg::OpArg::{AddrReg16, AddrReg16d, AddrReg16i, AddrRegd16, CC, FetchAddrU16, FetchI8, FetchInAddrU8, FetchSPI8, FetchU16, FetchU8, InAddrReg8, InAddrU8, Non, Reg16, Reg8, U16, U8};
 use crate::cpu::registers::R16::{BC, DE, HL, SP};
 use crate::cpu::registers::{R16, R8};
@@ -33,6 +33,8 @@ impl CPU {
 
             0x76 => Self::dcd_halt(),
 
+            0x10 => Self::dcd_stop(),
+
             0xC3 => Self::dcd_jp_u16(),
 
             0x2F => Self::dcd_cpl(),
@@ -639,6 +641,13 @@ impl CPU {
         )))
     }
 
+    /// STOP is followed by a byte that is skipped
+    fn dcd_stop() -> (bool, Result<Option<Instruction>, String>) {
+        (false, Ok(Some(
+            Instruction::new((Stop, FetchU8, Non), None)
+        )))
+    }
+
     fn dcd_swap_r8(r8: R8) -> (bool, Result<Option<Instruction>, String>) {
         (true, Ok(Some(Instruction::new(
             (Swap, Reg8(r8), Non), None,
@@ -1,4 +1,4 @@
-use Op::{Adc, Add, And, Bit, Call, Ccf, Cp, Cpl, Daa, Dcd, DcdCB, Dec, Di, Ei, Halt, Inc, Internal, Jp, Jr, Ld, Nop, Or, Res, Ret, EiImm, Rl, Rla, Rlc, Rlca, Rr, Rra, Rrc, Rrca, Rst, Sbc, Scf, Set, Sla, Sra, Srl, Sub, Swap, Write, Xor};
+use Op::{Adc, Add, And, Bit, Call, Ccf, Cp, Cpl, Daa, Dcd, DcdCB, Dec, Di, Ei, Halt, Inc, Internal, Jp, Jr, Ld, Nop, Or, Res, Ret, EiImm, Rl, Rla, Rlc, Rlca, Rr, Rra, Rrc, Rrca, Rst, Sbc, Scf, Set, Sla, Sra, Srl, Stop, Sub, Swap, Write, Xor};
 use OpArg::Non;
 use crate::cpu::condition::Condition;
 use crate::cpu::CPU;
@@ -46,6 +46,7 @@ impl CPU {
             (Ei, Non, Non) => self.exe_ei(),
             (EiImm, Non, Non) => self.exe_ei_imm(),
             (Halt, Non, Non) => self.exe_halt(),
+            (Stop, U8(_), Non) => self.exe_stop(mmu),
             (Inc, AddrReg16(r16), Non) => self.exe_inc_addrr16(r16, mmu),
             (Inc, Reg16(r16), Non) => self.exe_inc_r16(r16),
             (Inc, Reg8(r8), Non) => self.exe_inc_r8(r8),
@@ -231,6 +232,14 @@ impl CPU {
         Ok(None)
     }
 
+    /// switches the CGB speed when KEY1 prepared it, else the low power mode is approximated by HALT
+    fn exe_stop(&mut self, mmu: &mut MMU) -> Result<Option<Instruction>, String> {
+        if !mmu.switch_speed() {
+            self.halted = true;
+        }
+        Ok(None)
+    }
+
     fn exe_ei(&mut self) -> Result<Option<Instruction>, String> {
         self.ime_requested = true;
         Ok(None)
@@ -654,6 +663,8 @@ impl CPU {
     }
 
     fn exe_decode_u8(&mut self, u8: u8, mmu: &mut MMU) -> Result<Option<Instruction>, String> {
+        // LD B,B is used as a software breakpoint by test roms (mooneye)
+        self.breakpoint = u8 == 0x40;
         let (immediate_execute, result) = Self::decode(u8);
         if let (true, Ok(Some(next_instruction))) = (immediate_execute, result.as_ref()) {
             assert!(next_instruction.next().is_none(), "Immediate should only have single instruction");
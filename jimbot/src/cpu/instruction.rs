@@ -3,6 +3,7 @@ use crate::cpu::op::Op::Nop;
 use crate::cpu::op_arg::OpArg;
 use crate::cpu::op_arg::OpArg::Non;
 use crate::cpu::registers::R8;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 #[derive(Debug)]
 pub struct Instruction {
@@ -36,4 +37,43 @@ impl Instruction {
     pub fn next_mut(&mut self) -> &mut Option<Box<Instruction>> {
         &mut self.next
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for Instruction {
+    /// the whole chain is saved, starting with this instruction
+    fn save_state(&self, state: &mut StateWriter) {
+        let mut chain = vec![self.ins];
+        let mut next = &self.next;
+        while let Some(instruction) = next {
+            chain.push(instruction.ins);
+            next = &instruction.next;
+        }
+        state.write_u16(chain.len() as u16);
+        for (op, arg1, arg2) in chain {
+            state.write_u8(op as u8);
+            arg1.save_state(state);
+            arg2.save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        let len = state.read_u16()?;
+        if len == 0 { return Err(SaveStateError::InvalidValue("empty instruction chain")); }
+        let mut chain = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            let op = state.read_enum(&Op::ALL, "op")?;
+            let mut arg1 = Non;
+            let mut arg2 = Non;
+            arg1.load_state(state)?;
+            arg2.load_state(state)?;
+            chain.push((op, arg1, arg2));
+        }
+        let mut next = None;
+        while chain.len() > 1 {
+            let ins = chain.pop().unwrap();
+            next = Some(Box::new(Instruction::new(ins, next)));
+        }
+        *self = Instruction::new(chain[0], next);
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use self::Op::*;
+
 #[derive(Debug, Copy, Clone)]
 pub enum Op {
     Dcd,
@@ -46,4 +48,14 @@ pub enum Op {
     Di,
     Daa,
     Cpl,
-}
\ No newline at end of file
+    Stop,
+}
+
+impl Op {
+    /// every op in declaration order, indexed by `op as u8`
+    pub(crate) const ALL: [Op; 47] = [
+        Dcd, DcdCB, Ei, EiImm, Halt, Bit, Res, Set, Jr, Jp, Inc, Cp, Sub, Sbc, And, Adc, Add,
+        Internal, Rst, Rl, Srl, Rlc, Rrc, Sla, Sra, Rr, Swap, Rla, Rrca, Rlca, Scf, Rra, Ccf, Ret,
+        Call, Dec, Ld, Write, Read, Push, Xor, Or, Nop, Di, Daa, Cpl, Stop,
+    ];
+}
@@ -2,6 +2,7 @@ use crate::cpu::condition::Condition;
 use crate::cpu::hex_u16::HexU16;
 use crate::cpu::hex_u8::HexU8;
 use crate::cpu::registers::{R16, R8};
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 #[derive(Debug, Copy, Clone)]
 pub enum OpArg {
@@ -26,4 +27,123 @@ pub enum OpArg {
     FetchU16(Option<HexU8>, Option<HexU8>),
     U16(HexU16),
     Non,
-}
\ No newline at end of file
+}
+
+impl OpArg {
+    fn save_option(val: &Option<HexU8>, state: &mut StateWriter) {
+        state.write_bool(val.is_some());
+        state.write_u8(val.map(u8::from).unwrap_or(0));
+    }
+
+    fn load_option(state: &mut StateReader) -> Result<Option<HexU8>, SaveStateError> {
+        let is_some = state.read_bool()?;
+        let val = state.read_u8()?;
+        Ok(if is_some { Some(val.into()) } else { None })
+    }
+}
+
+impl SaveState for OpArg {
+    fn save_state(&self, state: &mut StateWriter) {
+        match *self {
+            OpArg::FetchU8 => state.write_u8(0),
+            OpArg::FetchInAddrU8 => state.write_u8(1),
+            OpArg::InAddrU8(val) => {
+                state.write_u8(2);
+                state.write_u8(val.into());
+            }
+            OpArg::Reg8(r8) => {
+                state.write_u8(3);
+                state.write_u8(r8 as u8);
+            }
+            OpArg::Reg16(r16) => {
+                state.write_u8(4);
+                state.write_u8(r16 as u8);
+            }
+            OpArg::AddrReg16(r16) => {
+                state.write_u8(5);
+                state.write_u8(r16 as u8);
+            }
+            OpArg::InAddrReg8(r8) => {
+                state.write_u8(6);
+                state.write_u8(r8 as u8);
+            }
+            OpArg::AddrU16(val) => {
+                state.write_u8(7);
+                state.write_u16(val.into());
+            }
+            OpArg::FetchAddrU16(lo, hi) => {
+                state.write_u8(8);
+                Self::save_option(&lo, state);
+                Self::save_option(&hi, state);
+            }
+            OpArg::FetchI8 => state.write_u8(9),
+            OpArg::FetchSPI8 => state.write_u8(10),
+            OpArg::SPI8(val) => {
+                state.write_u8(11);
+                state.write_u8(val as u8);
+            }
+            OpArg::AddrReg16d(r16) => {
+                state.write_u8(12);
+                state.write_u8(r16 as u8);
+            }
+            OpArg::AddrReg16i(r16) => {
+                state.write_u8(13);
+                state.write_u8(r16 as u8);
+            }
+            OpArg::AddrRegd16(r16) => {
+                state.write_u8(14);
+                state.write_u8(r16 as u8);
+            }
+            OpArg::CC(condition) => {
+                state.write_u8(15);
+                state.write_u8(condition as u8);
+            }
+            OpArg::U8(val) => {
+                state.write_u8(16);
+                state.write_u8(val.into());
+            }
+            OpArg::I8(val) => {
+                state.write_u8(17);
+                state.write_u8(val as u8);
+            }
+            OpArg::FetchU16(lo, hi) => {
+                state.write_u8(18);
+                Self::save_option(&lo, state);
+                Self::save_option(&hi, state);
+            }
+            OpArg::U16(val) => {
+                state.write_u8(19);
+                state.write_u16(val.into());
+            }
+            OpArg::Non => state.write_u8(20),
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        *self = match state.read_u8()? {
+            0 => OpArg::FetchU8,
+            1 => OpArg::FetchInAddrU8,
+            2 => OpArg::InAddrU8(state.read_u8()?.into()),
+            3 => OpArg::Reg8(state.read_enum(&R8::ALL, "r8")?),
+            4 => OpArg::Reg16(state.read_enum(&R16::ALL, "r16")?),
+            5 => OpArg::AddrReg16(state.read_enum(&R16::ALL, "r16")?),
+            6 => OpArg::InAddrReg8(state.read_enum(&R8::ALL, "r8")?),
+            7 => OpArg::AddrU16(state.read_u16()?.into()),
+            8 => OpArg::FetchAddrU16(Self::load_option(state)?, Self::load_option(state)?),
+            9 => OpArg::FetchI8,
+            10 => OpArg::FetchSPI8,
+            11 => OpArg::SPI8(state.read_u8()? as i8),
+            12 => OpArg::AddrReg16d(state.read_enum(&R16::ALL, "r16")?),
+            13 => OpArg::AddrReg16i(state.read_enum(&R16::ALL, "r16")?),
+            14 => OpArg::AddrRegd16(state.read_enum(&R16::ALL, "r16")?),
+            15 => OpArg::CC(state.read_enum(&Condition::ALL, "condition")?),
+            16 => OpArg::U8(state.read_u8()?.into()),
+            17 => OpArg::I8(state.read_u8()? as i8),
+            18 => OpArg::FetchU16(Self::load_option(state)?, Self::load_option(state)?),
+            19 => OpArg::U16(state.read_u16()?.into()),
+            20 => OpArg::Non,
+            _ => return Err(SaveStateError::InvalidValue("op arg")),
+        };
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::cpu::fflag::FFlag;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum R16 {
@@ -26,6 +27,16 @@ pub enum R8 {
     PCh,
 }
 
+impl R16 {
+    /// indexed by `r16 as u8`
+    pub(crate) const ALL: [R16; 6] = [R16::AF, R16::BC, R16::DE, R16::HL, R16::SP, R16::PC];
+}
+
+impl R8 {
+    /// indexed by `r8 as u8`
+    pub(crate) const ALL: [R8; 12] = [R8::A, R8::B, R8::C, R8::D, R8::E, R8::F, R8::H, R8::L, R8::S, R8::P, R8::PCl, R8::PCh];
+}
+
 pub struct Registers {
     a: u8,
     b: u8,
@@ -188,4 +199,18 @@ impl Registers {
             FFlag::C => Self::C_MASK,
         }
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for Registers {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&[self.a, self.b, self.c, self.d, self.e, self.f, self.h, self.l, self.s, self.p, self.pcl, self.pch]);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        let mut bytes = [0; 12];
+        state.read_bytes(&mut bytes)?;
+        let [a, b, c, d, e, f, h, l, s, p, pcl, pch] = bytes;
+        *self = Self { a, b, c, d, e, f: f & 0xF0, h, l, s, p, pcl, pch };
+        Ok(())
+    }
+}
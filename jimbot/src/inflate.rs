@@ -0,0 +1,175 @@
+/// Decompresses a zlib stream (stored, fixed and dynamic Huffman deflate blocks), the adler32 is not checked
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 2 || data[0] & 0x0F != 8 || !u16::from_be_bytes([data[0], data[1]]).is_multiple_of(31) {
+        return Err(String::from("bad zlib header"));
+    }
+    if data[1] & 0x20 != 0 {
+        return Err(String::from("zlib preset dictionary"));
+    }
+    inflate(&data[2..])
+}
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+/// order of the code length code lengths in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, count: u8) -> Result<u32, String> {
+        let mut val = 0;
+        for i in 0..count {
+            let byte = *self.data.get(self.position).ok_or("truncated deflate stream")?;
+            val |= ((byte >> self.bit) as u32 & 1) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.position += 1;
+            }
+        }
+        Ok(val)
+    }
+
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+}
+
+/// Canonical Huffman code: number of codes of each length and the symbols sorted by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for length in 1..16 {
+            for (symbol, _) in lengths.iter().enumerate().filter(|(_, l)| **l == length) {
+                symbols.push(symbol as u16);
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        // first code and index of each length, codes are read msb first
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(String::from("bad huffman code"))
+    }
+}
+
+fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader { data, position: 0, bit: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let header = data.get(reader.position..reader.position + 4).ok_or("truncated stored block")?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                reader.position += 4;
+                let block = data.get(reader.position..reader.position + len).ok_or("truncated stored block")?;
+                out.extend_from_slice(block);
+                reader.position += len;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                inflate_block(&mut reader, &mut out, &Huffman::new(&lengths), &Huffman::new(&[5; 30]))?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &literals, &distances)?;
+            }
+            _ => return Err(String::from("bad deflate block type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn read_dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+    for i in 0..code_length_count {
+        code_lengths[CODE_LENGTH_ORDER[i]] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_length_code.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or("repeat without previous length")?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(String::from("too many code lengths"));
+    }
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+fn inflate_block(reader: &mut BitReader, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = symbol - 257;
+                if i >= LENGTH_BASE.len() {
+                    return Err(String::from("bad length symbol"));
+                }
+                let length = LENGTH_BASE[i] as usize + reader.bits(LENGTH_EXTRA[i])? as usize;
+                let d = distances.decode(reader)? as usize;
+                if d >= DISTANCE_BASE.len() {
+                    return Err(String::from("bad distance symbol"));
+                }
+                let distance = DISTANCE_BASE[d] as usize + reader.bits(DISTANCE_EXTRA[d])? as usize;
+                if distance > out.len() {
+                    return Err(String::from("distance before the start of the stream"));
+                }
+                // the copy may overlap the bytes it writes
+                let start = out.len() - distance;
+                for k in 0..length {
+                    out.push(out[start + k]);
+                }
+            }
+        }
+    }
+}
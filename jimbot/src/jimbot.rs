@@ -1,10 +1,22 @@
 use crate::apu::APU;
+use crate::boot::{BootMode, CGB_BOOT_ROM_SIZE};
 use crate::cartridge;
-use crate::cartridge::Cartridge;
+use crate::cartridge::{Cartridge, RumbleCallback, ToneCallback};
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::cartridge::image_source::ImageSource;
+use crate::cartridge::infrared::IrTransceiver;
+use crate::cartridge::metadata::Hardware;
+use crate::color::compatibility::DmgPalette;
+use crate::color::rgb888;
+use crate::sgb::{SCREEN_HEIGHT, SCREEN_WIDTH};
 use crate::cpu::CPU;
 use crate::mmu::{joypad, MMU};
+use crate::movie::{Movie, MovieError, MovieState, Playback};
 use crate::ppu::PPU;
-use std::env;
+use crate::rewind::Rewind;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+use crate::save_state;
+use crate::serial::SerialDevice;
 
 pub struct Jimbot {
     mmu: MMU,
@@ -12,48 +24,347 @@ pub struct Jimbot {
     ppu: PPU,
     error_message: Option<String>,
     i: u8,
+    frame_count: u64,
+    cycle_count: u64,
+    frame_cycle: u32,
+    rewind: Option<Rewind>,
+    movie: Option<MovieState>,
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-impl Default for Jimbot {
-    fn default() -> Self {
-        let boot_rom = include_bytes!("../roms/dmg_boot.bin").to_owned();
-        let args: Vec<String> = std::env::args().collect();
-        let cartridge_file_path = &args[1];
-        let cartridge = cartridge::new_cartridge_from_file_path(cartridge_file_path.to_owned());
-        Self {
-            mmu: MMU::new(boot_rom, Some(cartridge)),
-            cpu: CPU::default(),
-            ppu: PPU::default(),
-            error_message: None,
-            i: 0,
+/// A finished frame, borrowed from the PPU front buffer
+pub struct Frame<'a> {
+    lcd: &'a [[u16; 144]; 160],
+    sgb_screen: Option<&'a [[u16; SCREEN_HEIGHT]; SCREEN_WIDTH]>,
+    number: u64,
+    cycles: u64,
+}
+
+impl<'a> Frame<'a> {
+    /// RGB555 colors, indexed by x then y
+    pub fn lcd(&self) -> &'a [[u16; 144]; 160] {
+        self.lcd
+    }
+    /// SGB border with the lcd at (`LCD_X`, `LCD_Y`), RGB555 indexed by x then y. `None` out of SGB mode
+    pub fn sgb_screen(&self) -> Option<&'a [[u16; SCREEN_HEIGHT]; SCREEN_WIDTH]> {
+        self.sgb_screen
+    }
+    /// 256x224 in SGB mode, 160x144 otherwise
+    pub fn width(&self) -> usize {
+        if self.sgb_screen.is_some() { SCREEN_WIDTH } else { 160 }
+    }
+    pub fn height(&self) -> usize {
+        if self.sgb_screen.is_some() { SCREEN_HEIGHT } else { 144 }
+    }
+    /// RGBA8888 pixels, row by row, `width` by `height`: the whole SGB screen in SGB mode
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(self.width() * self.height() * 4);
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                let color = self.sgb_screen.map_or_else(|| self.lcd[x][y], |screen| screen[x][y]);
+                let [_, r, g, b] = rgb888(color).to_be_bytes();
+                rgba.extend_from_slice(&[r, g, b, 0xFF]);
+            }
         }
+        rgba
+    }
+    /// number of frames finished since power on
+    pub fn number(&self) -> u64 {
+        self.number
+    }
+    /// number of m-cycles run since power on
+    pub fn cycles(&self) -> u64 {
+        self.cycles
     }
 }
 
 impl Jimbot {
-    pub fn new_with_cartridge_bytes(bytes: Vec<u8>) -> Self {
-        let boot_rom = include_bytes!("../roms/dmg_boot.bin").to_owned();
-        let cartridge = cartridge::new_cartridge_from_bytes(bytes);
+    /// 70224 dots per frame, 4 dots per m-cycle (twice as many m-cycles in CGB double speed)
+    pub const M_CYCLES_PER_FRAME: u32 = 70224 / 4;
+
+    pub fn new_with_cartridge_bytes(bytes: Vec<u8>) -> Result<Self, CartridgeError> {
+        let cartridge = cartridge::new_cartridge_from_bytes(bytes)?;
+        Ok(Self::new(cartridge, BootMode::default()))
+    }
+
+    /// Battery backed ram is saved next to the rom file
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_with_cartridge_file_path(file_path: &str) -> Result<Self, CartridgeError> {
+        let cartridge = cartridge::new_cartridge_from_file_path(file_path.to_owned())?;
+        Ok(Self::new(cartridge, BootMode::default()))
+    }
+
+    /// Cgb cartridges run in CGB mode, see `BootMode`, SGB cartridges in SGB mode.
+    /// Dmg games are colored as on a CGB
+    pub fn new(cartridge: Box<dyn Cartridge>, boot_mode: BootMode) -> Self {
+        let hardware = cartridge.metadata().hardware();
+        let cgb_cartridge = hardware == Hardware::Cgb;
+        let dmg_palette = DmgPalette::for_game(cartridge.metadata());
+        let boot_rom = boot_mode.into_boot_rom(cgb_cartridge);
+        let skip_boot = boot_rom.is_none();
+        let cgb = match boot_rom.as_ref() {
+            Some(boot_rom) => boot_rom.len() == CGB_BOOT_ROM_SIZE,
+            None => cgb_cartridge,
+        };
+        let mut mmu = MMU::new(boot_rom, Some(cartridge), cgb);
+        let hardware = match hardware {
+            _ if cgb => Hardware::Cgb,
+            Hardware::Cgb => Hardware::Dmg,
+            hardware => hardware,
+        };
+        match hardware {
+            Hardware::Sgb => mmu.enable_sgb(),
+            Hardware::Dmg => mmu.set_dmg_palette(&dmg_palette),
+            Hardware::Cgb => {}
+        }
+        let mut cpu = CPU::default();
+        if skip_boot {
+            mmu.skip_boot();
+            cpu.skip_boot(hardware);
+        }
         Self {
-            mmu: MMU::new(boot_rom, Some(cartridge)),
-            cpu: CPU::default(),
+            mmu,
+            cpu,
             ppu: PPU::default(),
             error_message: None,
             i: 0,
+            frame_count: 0,
+            cycle_count: 0,
+            frame_cycle: 0,
+            rewind: None,
+            movie: None,
         }
     }
 
-    pub fn run(&mut self) {
+    /// Runs a single m-cycle, returns true if a frame is finished during this cycle
+    pub fn run(&mut self) -> bool {
         if self.error_message.is_some() {
-            return;
+            return false;
+        }
+        if let Some(MovieState::Playing(playback)) = self.movie.as_mut() {
+            while let Some(input) = playback.next_input(self.cycle_count) {
+                if input.pressed {
+                    self.mmu.joypad_press(input.key);
+                } else {
+                    self.mmu.joypad_release(input.key);
+                }
+            }
         }
-        self.error_message = self.cpu.cycle(&mut self.mmu).err();
-        for _ in 0..4 {
+        let double_speed = self.mmu.is_double_speed();
+        // the cpu waits for the vram dma and the speed switch
+        if !self.mmu.cpu_stall() {
+            self.error_message = self.cpu.cycle(&mut self.mmu).err();
+        }
+        self.mmu.cycle_serial();
+        // the cartridge clocks (rtc, camera) run in real time
+        if !double_speed || self.cycle_count.is_multiple_of(2) {
+            self.mmu.cycle_cartridge();
+        }
+        // the timer runs with the cpu, the ppu and the apu get 2 dots per m-cycle in double speed
+        let dots = if double_speed { 2 } else { 4 };
+        let mut frame_done = false;
+        for dot in 0..4 {
             self.mmu.cycle_timer();
-            self.mmu.cycle_apu();
-            self.ppu.cycle(&mut self.mmu);
+            if dot < dots {
+                self.mmu.cycle_apu();
+                frame_done |= self.ppu.cycle(&mut self.mmu);
+            }
+        }
+        self.cycle_count += 1;
+        self.frame_cycle += 1;
+        // The PPU produces no frame while the lcd is off, keep the frame rate anyway
+        let frame_cycles = if double_speed { 2 * Self::M_CYCLES_PER_FRAME } else { Self::M_CYCLES_PER_FRAME };
+        if !frame_done && !self.ppu.is_enable() && self.frame_cycle >= frame_cycles {
+            frame_done = true;
         }
+        if frame_done {
+            self.mmu.sgb_frame(self.ppu.lcd(), true);
+            self.frame_count += 1;
+            self.frame_cycle = 0;
+            self.hash_movie_frame();
+            self.record_rewind();
+        }
+        frame_done
+    }
+
+    /// Runs until the PPU finishes the current frame (at the end of line 153, when the next frame starts at line 0)
+    pub fn run_frame(&mut self) -> Frame<'_> {
+        while self.error_message.is_none() {
+            if self.run() {
+                break;
+            }
+        }
+        self.frame()
+    }
+
+    /// Runs `n` m-cycles
+    pub fn run_cycles(&mut self, n: u64) -> Frame<'_> {
+        for _ in 0..n {
+            if self.error_message.is_some() {
+                break;
+            }
+            self.run();
+        }
+        self.frame()
+    }
+
+    /// Runs until `predicate` returns true, it is checked after every m-cycle
+    pub fn run_until<P: FnMut(&Jimbot) -> bool>(&mut self, mut predicate: P) -> Frame<'_> {
+        while self.error_message.is_none() {
+            self.run();
+            if predicate(self) {
+                break;
+            }
+        }
+        self.frame()
+    }
+
+    /// Goes back `n` frames, to the oldest recorded frame when the history is too short.
+    /// Does nothing when rewind is not enabled. The machine is left untouched when the snapshot is invalid.
+    pub fn rewind_frames(&mut self, n: u64) -> Result<Frame<'_>, SaveStateError> {
+        let target = self.frame_count.saturating_sub(n);
+        if let Some(state) = self.rewind.as_mut().and_then(|rewind| rewind.rewind_to(target)) {
+            self.restore_state(&state)?;
+            // snapshots are `interval` frames apart, run the remaining frames
+            while self.frame_count < target && self.error_message.is_none() {
+                self.run_frame();
+            }
+            self.mmu.apu.get_data();
+        }
+        Ok(self.frame())
+    }
+
+    /// Starts recording snapshots for `rewind_frames`
+    pub fn enable_rewind(&mut self, rewind: Rewind) {
+        self.rewind = Some(rewind);
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    pub fn rewind(&self) -> Option<&Rewind> {
+        self.rewind.as_ref()
+    }
+
+    fn record_rewind(&mut self) {
+        if !self.rewind.as_ref().is_some_and(|rewind| rewind.should_record(self.frame_count)) { return; }
+        let state = self.save_state();
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.push(self.frame_count, state);
+        }
+    }
+
+    /// Plugs `device` in the link port, replacing the previous one
+    pub fn connect_serial(&mut self, device: Box<dyn SerialDevice>) {
+        self.mmu.serial_mut().connect(device);
+    }
+
+    pub fn disconnect_serial(&mut self) -> Option<Box<dyn SerialDevice>> {
+        self.mmu.serial_mut().disconnect()
+    }
+
+    /// Starts recording the joypad inputs, the machine must be at power on
+    pub fn start_movie_recording(&mut self) -> Result<(), MovieError> {
+        if self.cycle_count != 0 { return Err(MovieError::NotAtPowerOn); }
+        let rom = self.mmu.cartridge().as_ref().map(|cartridge| cartridge.data().as_slice()).unwrap_or_default();
+        let clock = self.mmu.cartridge().as_ref().and_then(|cartridge| cartridge.clock_state());
+        let movie = Movie::new(rom, self.mmu.boot_rom(), self.save_data().cloned(), clock);
+        self.movie = Some(MovieState::Recording(movie));
+        Ok(())
+    }
+
+    /// The movie recorded so far, `None` when not recording
+    pub fn recorded_movie(&mut self) -> Option<&Movie> {
+        let (cycle_count, frame_count) = (self.cycle_count, self.frame_count);
+        match self.movie.as_mut() {
+            Some(MovieState::Recording(movie)) => {
+                movie.set_length(cycle_count, frame_count);
+                Some(movie)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn stop_movie_recording(&mut self) -> Option<Movie> {
+        self.recorded_movie()?;
+        match self.movie.take() {
+            Some(MovieState::Recording(movie)) => Some(movie),
+            _ => None,
+        }
+    }
+
+    /// Replays `movie` from power on, live joypad inputs are ignored until the movie ends.
+    /// The cartridge ram and clock start from the movie ones and the ram is no longer written to the save storage,
+    /// the player's save is left untouched
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), MovieError> {
+        if self.cycle_count != 0 { return Err(MovieError::NotAtPowerOn); }
+        let rom = self.mmu.cartridge().as_ref().map(|cartridge| cartridge.data().as_slice()).unwrap_or_default();
+        movie.check(rom, self.mmu.boot_rom())?;
+        let save_ram_fits = match (movie.save_ram(), self.save_data()) {
+            (Some(save_ram), Some(save_data)) => save_ram.len() == save_data.len(),
+            (None, None) => true,
+            _ => false,
+        };
+        if !save_ram_fits { return Err(MovieError::SaveRamMismatch); }
+        if let Some(cartridge) = self.mmu.cartridge_mut() {
+            match movie.clock() {
+                Some(clock) => cartridge.load_clock_state(clock).map_err(|_| MovieError::ClockMismatch)?,
+                None if cartridge.clock_state().is_some() => return Err(MovieError::ClockMismatch),
+                None => {}
+            }
+            cartridge.detach_save_storage();
+        }
+        if let (Some(save_ram), Some(save_data)) = (movie.save_ram(), self.save_data_mut()) {
+            save_data.copy_from_slice(save_ram);
+        }
+        self.movie = Some(MovieState::Playing(Playback::new(movie)));
+        Ok(())
+    }
+
+    /// The movie being played, kept after it ends to check for desync
+    pub fn movie_playback(&self) -> Option<&Playback> {
+        match self.movie.as_ref() {
+            Some(MovieState::Playing(playback)) => Some(playback),
+            _ => None,
+        }
+    }
+
+    pub fn is_movie_playing(&self) -> bool {
+        self.movie_playback().is_some_and(|playback| !playback.is_finished_at(self.cycle_count))
+    }
+
+    fn hash_movie_frame(&mut self) {
+        let wants_hash = match self.movie.as_ref() {
+            Some(MovieState::Recording(movie)) => movie.wants_hash(self.frame_count),
+            Some(MovieState::Playing(playback)) => playback.wants_hash(self.frame_count),
+            None => false,
+        };
+        if !wants_hash { return; }
+        let state = self.save_state();
+        match self.movie.as_mut() {
+            Some(MovieState::Recording(movie)) => movie.record_hash(self.cycle_count, self.frame_count, &state),
+            Some(MovieState::Playing(playback)) => playback.check_hash(self.frame_count, &state),
+            None => {}
+        }
+    }
+
+    /// The last finished frame
+    pub fn frame(&self) -> Frame<'_> {
+        let sgb = self.mmu.sgb();
+        Frame {
+            lcd: sgb.map_or_else(|| self.ppu.lcd(), |sgb| sgb.lcd()),
+            sgb_screen: sgb.map(|sgb| sgb.screen()),
+            number: self.frame_count,
+            cycles: self.cycle_count,
+        }
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn cycle_count(&self) -> u64 {
+        self.cycle_count
     }
     pub fn mmu(&self) -> &MMU {
         &self.mmu
@@ -79,11 +390,25 @@ impl Jimbot {
         self.mmu.apu.get_data()
     }
     pub fn joypad_press(&mut self, key: joypad::Key) {
-        self.mmu.joypad_press(key);
+        self.joypad_input(key, true);
     }
 
     pub fn joypad_release(&mut self, key: joypad::Key) {
-        self.mmu.joypad_release(key);
+        self.joypad_input(key, false);
+    }
+
+    fn joypad_input(&mut self, key: joypad::Key, pressed: bool) {
+        if self.is_movie_playing() { return; }
+        if let Some(MovieState::Recording(movie)) = self.movie.as_mut() {
+            if self.mmu.joypad().is_pressed(key) != pressed {
+                movie.record_input(self.cycle_count, self.frame_count, key, pressed);
+            }
+        }
+        if pressed {
+            self.mmu.joypad_press(key);
+        } else {
+            self.mmu.joypad_release(key);
+        }
     }
 
     pub fn save_data(&self) -> Option<&Vec<u8>> {
@@ -104,7 +429,137 @@ impl Jimbot {
         None
     }
 
+    /// Overrides the colors picked for dmg games by `DmgPalette::for_game`, like the button combos of the CGB boot
+    /// logo. CGB and SGB games have their own palettes
+    pub fn set_dmg_palette(&mut self, palette: DmgPalette) {
+        if !self.mmu.is_cgb() && self.mmu.sgb().is_none() {
+            self.mmu.set_dmg_palette(&palette);
+        }
+    }
+
+    /// `callback` is told when the rumble motor of the cartridge starts or stops
+    pub fn set_rumble_callback(&mut self, callback: RumbleCallback) {
+        if let Some(cartridge) = self.mmu.cartridge_mut() {
+            cartridge.set_rumble_callback(callback);
+        }
+    }
+
+    /// Tilt of the console for the MBC7 accelerometer, in g (-1.0 to 1.0 for a 90° tilt).
+    /// `x` is positive when the right side is lowered, `y` when the bottom side is lowered.
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        if let Some(cartridge) = self.mmu.cartridge_mut() {
+            cartridge.set_tilt(x, y);
+        }
+    }
+
+    /// Image seen by the Game Boy Camera, a test pattern by default
+    pub fn set_image_source(&mut self, source: Box<dyn ImageSource>) {
+        if let Some(cartridge) = self.mmu.cartridge_mut() {
+            cartridge.set_image_source(source);
+        }
+    }
+
+    /// Infrared port of HuC1/HuC3 cartridges, see `IrLoopback` to link two `Jimbot`
+    pub fn set_ir_transceiver(&mut self, transceiver: Box<dyn IrTransceiver>) {
+        if let Some(cartridge) = self.mmu.cartridge_mut() {
+            cartridge.set_ir_transceiver(transceiver);
+        }
+    }
+
+    /// `callback` is told when a HuC3 cartridge plays a tone, the tone is not mixed in the sound output
+    pub fn set_tone_callback(&mut self, callback: ToneCallback) {
+        if let Some(cartridge) = self.mmu.cartridge_mut() {
+            cartridge.set_tone_callback(callback);
+        }
+    }
+
+    /// Writes the battery backed ram to the save storage now instead of waiting for the debounce
+    pub fn flush_save(&mut self) {
+        if let Some(cartridge) = self.mmu.cartridge_mut() {
+            cartridge.flush_save();
+        }
+    }
+
     pub fn cartridge(&self) -> &Option<Box<dyn Cartridge>> {
         self.mmu.cartridge()
     }
+
+    /// Snapshot of the whole machine, see `save_state` module for the format
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::default();
+        state.write_bytes(save_state::MAGIC);
+        state.write_u16(save_state::VERSION);
+        let (title, checksums) = self.rom_identity();
+        state.write_vec(title);
+        state.write_bytes(&checksums);
+        state.write_tag(b"JBOT");
+        state.write_u64(self.frame_count);
+        state.write_u64(self.cycle_count);
+        state.write_u32(self.frame_cycle);
+        self.cpu.save_state(&mut state);
+        self.mmu.save_state(&mut state);
+        self.ppu.save_state(&mut state);
+        state.into_bytes()
+    }
+
+    /// Restores a snapshot made by `save_state`, the machine is left untouched on error.
+    /// The rewind history is cleared, a recorded movie loses the inputs after the snapshot and a played movie stops.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), SaveStateError> {
+        self.restore_state(bytes)?;
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
+        Ok(())
+    }
+
+    fn restore_state(&mut self, bytes: &[u8]) -> Result<(), SaveStateError> {
+        let backup = self.save_state();
+        let res = self.load_state_unchecked(bytes);
+        if res.is_err() {
+            self.load_state_unchecked(&backup).expect("Cannot restore state backup");
+            return res;
+        }
+        match self.movie.as_mut() {
+            Some(MovieState::Recording(movie)) => movie.truncate(self.cycle_count, self.frame_count),
+            Some(MovieState::Playing(_)) => self.movie = None,
+            None => {}
+        }
+        res
+    }
+
+    fn load_state_unchecked(&mut self, bytes: &[u8]) -> Result<(), SaveStateError> {
+        let mut state = StateReader::new(bytes);
+        let mut magic = [0; 4];
+        state.read_bytes(&mut magic).map_err(|_| SaveStateError::BadMagic)?;
+        if &magic != save_state::MAGIC { return Err(SaveStateError::BadMagic); }
+        let version = state.read_u16()?;
+        if version != save_state::VERSION { return Err(SaveStateError::UnsupportedVersion(version)); }
+        let (title, checksums) = self.rom_identity();
+        let mut state_checksums = [0; 3];
+        let state_title = state.read_vec()?;
+        state.read_bytes(&mut state_checksums)?;
+        if state_title != title || state_checksums != checksums { return Err(SaveStateError::RomMismatch); }
+        state.read_tag(b"JBOT")?;
+        self.frame_count = state.read_u64()?;
+        self.cycle_count = state.read_u64()?;
+        self.frame_cycle = state.read_u32()?;
+        self.cpu.load_state(&mut state)?;
+        self.mmu.load_state(&mut state)?;
+        self.ppu.load_state(&mut state)?;
+        if !state.is_empty() { return Err(SaveStateError::InvalidValue("trailing data")); }
+        self.mmu.sgb_frame(self.ppu.lcd(), false);
+        self.error_message = None;
+        Ok(())
+    }
+
+    /// title and header/global checksums of the inserted rom
+    fn rom_identity(&self) -> (&[u8], [u8; 3]) {
+        match self.mmu.cartridge() {
+            Some(cartridge) => {
+                let data = cartridge.data();
+                (cartridge.metadata().title().as_bytes(), [data[0x14D], data[0x14E], data[0x14F]])
+            }
+            None => (&[], [0; 3]),
+        }
+    }
 }
@@ -4,6 +4,17 @@ pub mod cpu;
 mod ppu;
 mod apu;
 mod wram;
-mod cartridge;
+pub mod cartridge;
 mod timer;
+pub mod serial;
+pub mod save_state;
+pub mod rewind;
+pub mod boot;
+pub mod movie;
+pub mod patch;
+pub mod color;
+pub mod sgb;
+mod crc32;
+mod png;
+mod inflate;
 
@@ -5,24 +5,40 @@ pub mod bgp;
 pub mod tac;
 pub mod interrupt_flag;
 pub mod joypad;
+pub mod hdma;
 
 use std::ptr::addr_of;
 use crate::apu::APU;
+use crate::boot::post_boot;
 use crate::cartridge::{Cartridge};
+use crate::color::compatibility::DmgPalette;
+use crate::color::DMG_GRAYSCALE;
+use crate::sgb::Sgb;
 use crate::mmu::bgp::{BGP, OBP};
+use crate::mmu::hdma::Hdma;
 use crate::mmu::interrupt_flag::{InterruptRequest, Interrupts};
 use crate::mmu::joypad::JoyPad;
 use crate::mmu::lcdc::LCDC;
 use crate::mmu::lcdstat::{LCDSTAT, Mode};
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+use crate::serial::Serial;
 use crate::timer::Timer;
 
+/// m-cycles the cpu stays stopped after a speed switch
+const SPEED_SWITCH_DELAY: u16 = 2050;
+
 pub struct MMU {
     interrupt_flags: u8,
     interrupt_enables: u8,
     boot_mode: bool,
-    boot_rom: [u8; 0x100],
+    boot_rom: Vec<u8>,
     cart: Option<Box<dyn Cartridge>>,
-    vram: [u8; 0x2000],
+    /// CGB mode: vram and wram banks, color palettes and bg attributes
+    cgb: bool,
+    /// SGB mode: command packets, the lcd holds shades colored by the SGB
+    sgb: Option<Box<Sgb>>,
+    vram: [u8; 0x4000],
+    vram_bank: u8,
     bgp: u8,
     lcdc: u8,
     lcdstat: u8,
@@ -30,7 +46,18 @@ pub struct MMU {
     scx: u8,
     ly: u8,
     pub(crate) apu: APU,
-    wram: [u8; 0x2000],
+    wram: [u8; 0x8000],
+    wram_bank: u8,
+    bcps: u8,
+    ocps: u8,
+    bg_palettes: [u8; 0x40],
+    obj_palettes: [u8; 0x40],
+    hdma: Hdma,
+    /// m-cycles the cpu is stopped for by the vram dma or a speed switch
+    cpu_stall: u16,
+    double_speed: bool,
+    /// KEY1 bit 0, the next STOP switches the speed
+    speed_switch: bool,
     oam: [u8; 0xA0],
     hram: [u8; 0x7F],
     timer: Timer,
@@ -39,8 +66,7 @@ pub struct MMU {
     obp0: u8,
     obp1: u8,
     lyc: u8,
-    serial_transfer_data: u8,
-    serial_transfer_control: u8,
+    serial: Serial,
     joypad: JoyPad,
     test: i8,
 }
@@ -52,15 +78,19 @@ impl MMU {
     pub fn test(&self) -> i8 {
         self.test
     }
-    pub fn new(boot_rom: [u8; 0x100], cartridge: Option<Box<dyn Cartridge>>) -> Self {
-        Self {
+    /// Without boot rom the mmu starts with the cartridge mapped, call `skip_boot` to set the post boot io registers
+    pub fn new(boot_rom: Option<Vec<u8>>, cartridge: Option<Box<dyn Cartridge>>, cgb: bool) -> Self {
+        let mut mmu = Self {
             test: 0,
             interrupt_flags: 0,
             interrupt_enables: 0,
-            boot_mode: true,
-            boot_rom,
+            boot_mode: boot_rom.is_some(),
+            boot_rom: boot_rom.unwrap_or_default(),
             cart: cartridge,
-            vram: [0x00; 0x2000],
+            cgb,
+            sgb: None,
+            vram: [0x00; 0x4000],
+            vram_bank: 0,
             bgp: 0,
             lcdc: 0,
             lcdstat:0x84,
@@ -68,7 +98,16 @@ impl MMU {
             scx: 0,
             ly: 0,
             apu: APU::default(),
-            wram: [0; 0x2000],
+            wram: [0; 0x8000],
+            wram_bank: 1,
+            bcps: 0,
+            ocps: 0,
+            bg_palettes: [0; 0x40],
+            obj_palettes: [0; 0x40],
+            hdma: Hdma::default(),
+            cpu_stall: 0,
+            double_speed: false,
+            speed_switch: false,
             oam: [0; 0xA0],
             hram: [0; 0x7F],
             timer: Default::default(),
@@ -78,24 +117,27 @@ impl MMU {
             obp1: 0,
             lyc: 0,
             joypad: JoyPad::default(),
-            serial_transfer_control: 0,
-            serial_transfer_data: 0,
-        }
+            serial: Serial::default(),
+        };
+        mmu.set_dmg_palette(&DmgPalette::from_rgb888(DMG_GRAYSCALE));
+        mmu
     }
     pub fn get(&self, address: u16) -> u8 {
         let address_usize = address as usize;
         let val = match address {
             0x0000..=0x00FF => {
                 if self.boot_mode {
-                    self.boot_rom[address_usize]
+                    self.boot_rom.get(address_usize).copied().unwrap_or(0xFF)
                 } else {
                     self.cart.as_ref().expect(&format!("No cartridge {:#06X}", address_usize)).get(address as usize)
                 }
             }
+            // the cgb boot rom is 0x900 bytes long, the cartridge header stays visible
+            0x0200..=0x08FF if self.boot_mode && self.boot_rom.len() > 0x100 => self.boot_rom.get(address_usize).copied().unwrap_or(0xFF),
             0x0100..=0x7FFF => self.cart.as_ref().expect(&format!("No cartridge {:#06X}", address_usize)).get(address as usize),
             0x8000..=0x9FFF => {
                 // println!("GET VRAM {:#06X}={:#06X}", address_usize, address_usize - 0x8000);
-                self.vram[address_usize - 0x8000]
+                self.vram_get(self.vram_bank, address)
             }
             0xA000..=0xBFFF => {
                 if let Some(cart) = self.cart.as_ref() {
@@ -104,12 +146,12 @@ impl MMU {
                     panic!("Get EXT RAM with no cartridge {:#06X}", address_usize)
                 }
             }
-            0xC000..=0xDFFF => self.wram[address_usize - 0xC000],
+            0xC000..=0xDFFF => self.wram[self.wram_index(address)],
             0xE000..=0xFDFF => self.get(address - 0x2000),
             0xFE00..=0xFE9F => self.oam[address_usize - 0xFE00],
             0xFF00 => self.joypad.bytes(),
-            0xFF01 => self.serial_transfer_data,
-            0xFF02 => self.serial_transfer_control,
+            0xFF01 => self.serial.get(address_usize),
+            0xFF02 => self.serial.get(address_usize),
             0xFF04 => self.timer.get(address_usize),
             0xFF05 => self.timer.get(address_usize),
             0xFF06 => self.timer.get(address_usize),
@@ -134,6 +176,14 @@ impl MMU {
             0xFF49 => self.obp1,
             0xFF4A => self.wy,
             0xFF4B => self.wx,
+            0xFF4D if self.cgb => 0x7E | ((self.double_speed as u8) << 7) | self.speed_switch as u8,
+            0xFF4F if self.cgb => 0xFE | self.vram_bank,
+            0xFF51..=0xFF55 if self.cgb => self.hdma.get(address_usize),
+            0xFF68 if self.cgb => 0x40 | self.bcps,
+            0xFF69 if self.cgb => self.bg_palettes[(self.bcps & 0x3F) as usize],
+            0xFF6A if self.cgb => 0x40 | self.ocps,
+            0xFF6B if self.cgb => self.obj_palettes[(self.ocps & 0x3F) as usize],
+            0xFF70 if self.cgb => 0xF8 | self.wram_bank,
             0xFF4C..=0xFF7F => {
                 println!("Get to unusable io: {:#06X}", address_usize);
                 0xFF
@@ -159,20 +209,26 @@ impl MMU {
             }
             0x8000..=0x9FFF => {
                 // println!("SET VRAM {:#06X}={:#06X} => {:#04X}", address_usize, address_usize - 0x8000, val);
-                self.vram[address_usize - 0x8000] = val
+                self.vram[self.vram_bank as usize * 0x2000 + address_usize - 0x8000] = val
             }
             0xA000..=0xBFFF => {
                 if let Some(cart) = self.cart.as_mut() {
                     cart.set(address_usize, val);
                 }
             }
-            0xC000..=0xDFFF => self.wram[address_usize - 0xC000] = val,
+            0xC000..=0xDFFF => self.wram[self.wram_index(address)] = val,
             0xE000..=0xFDFF => self.set(address - 0x2000, val),
             0xFE00..=0xFE9F => self.oam[address_usize - 0xFE00] = val,
             0xFEA0..=0xFEFF => println!("Write to unusable io: {:#06X} {}", address_usize, val),
-            0xFF00 => self.joypad.write(val),
-            0xFF01 => self.serial_transfer_data = val,
-            0xFF02 => self.serial_transfer_control = val,
+            0xFF00 => {
+                if let (Some(packet), Some(sgb)) = (self.joypad.write(val), self.sgb.as_mut()) {
+                    if let Some(players) = sgb.receive(packet) {
+                        self.joypad.set_players(players);
+                    }
+                }
+            }
+            0xFF01 => self.serial.set(address_usize, val),
+            0xFF02 => self.serial.set(address_usize, val),
             0xFF04 => self.timer.set(address_usize, val),
             0xFF05 => self.timer.set(address_usize, val),
             0xFF06 => self.timer.set(address_usize, val),
@@ -214,6 +270,24 @@ impl MMU {
             0xFF4A => self.wy = val,
             0xFF4B => self.wx = if val < 7 { 7 } else { val },
             0xFF50 => self.boot_mode = false,
+            // KEY0, the cgb boot rom switches to dmg compatibility mode for dmg cartridges
+            0xFF4C if self.cgb && self.boot_mode && val & 0x04 != 0 => {
+                self.cgb = false;
+                self.vram_bank = 0;
+                self.wram_bank = 1;
+            }
+            0xFF4D if self.cgb => self.speed_switch = val & 1 == 1,
+            0xFF4F if self.cgb => self.vram_bank = val & 1,
+            0xFF51..=0xFF55 if self.cgb => {
+                for _ in 0..self.hdma.set(address_usize, val) {
+                    self.hdma_block();
+                }
+            }
+            0xFF68 if self.cgb => self.bcps = val & 0xBF,
+            0xFF69 if self.cgb => Self::write_palette(&mut self.bg_palettes, &mut self.bcps, val),
+            0xFF6A if self.cgb => self.ocps = val & 0xBF,
+            0xFF6B if self.cgb => Self::write_palette(&mut self.obj_palettes, &mut self.ocps, val),
+            0xFF70 if self.cgb => self.wram_bank = (val & 0x07).max(1),
             0xFF4C..=0xFF79 => println!("Write to unusable io: {:#06X} {}", address_usize, val),
             0xFF80..=0xFFFE => self.hram[address_usize - 0xFF80] = val,
             0xFFFF => self.interrupt_enables = val,
@@ -229,24 +303,139 @@ impl MMU {
             self.oam[i_to] = self.get(i_from);
         }
     }
-    pub fn boot_rom(&self) -> &[u8; 0x100] {
+    /// copies the next 16 bytes of the vram dma, the cpu waits 8 m-cycles (16 in double speed)
+    fn hdma_block(&mut self) {
+        let (source, destination) = self.hdma.next_block();
+        for i in 0..0x10 {
+            let val = self.get(source.wrapping_add(i));
+            self.vram[self.vram_bank as usize * 0x2000 + (destination + i) as usize] = val;
+        }
+        self.cpu_stall += if self.double_speed { 16 } else { 8 };
+    }
+    /// called by the ppu when it enters HBlank on a visible line
+    pub(crate) fn hblank_dma(&mut self) {
+        if self.hdma.is_hblank_transfer() {
+            self.hdma_block();
+        }
+    }
+    /// true while the cpu is stopped by the vram dma or a speed switch, counts down one m-cycle
+    pub(crate) fn cpu_stall(&mut self) -> bool {
+        if self.cpu_stall == 0 {
+            return false;
+        }
+        self.cpu_stall -= 1;
+        true
+    }
+    /// STOP with the KEY1 switch prepared in CGB mode: toggles the speed and resets DIV, returns false without switch
+    pub(crate) fn switch_speed(&mut self) -> bool {
+        if !self.cgb || !self.speed_switch {
+            return false;
+        }
+        self.double_speed = !self.double_speed;
+        self.speed_switch = false;
+        self.timer.set(0xFF04, 0);
+        self.cpu_stall += SPEED_SWITCH_DELAY;
+        true
+    }
+    /// CGB double speed, the cpu and the timer run twice as fast
+    pub fn is_double_speed(&self) -> bool {
+        self.double_speed
+    }
+    /// BCPD/OCPD write at the BCPS/OCPS index, incremented when bit 7 is set
+    fn write_palette(palettes: &mut [u8; 0x40], specification: &mut u8, val: u8) {
+        palettes[(*specification & 0x3F) as usize] = val;
+        if *specification & 0x80 != 0 {
+            *specification = 0x80 | ((*specification + 1) & 0x3F);
+        }
+    }
+    /// $C000-$CFFF is bank 0, $D000-$DFFF the bank selected by SVBK (always 1 in dmg mode)
+    fn wram_index(&self, address: u16) -> usize {
+        match address {
+            0xC000..=0xCFFF => address as usize - 0xC000,
+            _ => self.wram_bank as usize * 0x1000 + address as usize - 0xD000,
+        }
+    }
+    pub fn boot_rom(&self) -> &[u8] {
         &self.boot_rom
     }
+    /// Sets the io registers as left by the boot rom and unmaps it
+    pub(crate) fn skip_boot(&mut self) {
+        for (address, val) in post_boot::IO {
+            self.set(address, val);
+        }
+        if self.cgb {
+            // the cgb boot rom leaves every color white
+            self.bg_palettes = [0xFF; 0x40];
+            self.obj_palettes = [0xFF; 0x40];
+        }
+        self.timer.set_div_counter(post_boot::DIV_COUNTER);
+        self.boot_mode = false;
+    }
     pub fn cartridge(&self) -> &Option<Box<dyn Cartridge>> {
         &self.cart
     }
     pub fn cartridge_mut(&mut self) -> &mut Option<Box<dyn Cartridge>> {
         &mut self.cart
     }
-    pub fn vram(&self) -> &[u8; 0x2000] {
+    /// both banks, bank 1 starts at 0x2000
+    pub fn vram(&self) -> &[u8; 0x4000] {
         &self.vram
     }
+    /// vram byte at `address` ($8000-$9FFF) of `bank`, whatever VBK selects
+    pub(crate) fn vram_get(&self, bank: u8, address: u16) -> u8 {
+        self.vram[bank as usize * 0x2000 + (address as usize & 0x1FFF)]
+    }
     pub fn hram(&self) -> &[u8; 0x7F] {
         &self.hram
     }
-    pub fn wram(&self) -> &[u8; 0x2000] {
+    /// the 8 banks of 4 KiB, only banks 0 and 1 are used in dmg mode
+    pub fn wram(&self) -> &[u8; 0x8000] {
         &self.wram
     }
+    pub fn is_cgb(&self) -> bool {
+        self.cgb
+    }
+    pub fn sgb(&self) -> Option<&Sgb> {
+        self.sgb.as_deref()
+    }
+    pub(crate) fn enable_sgb(&mut self) {
+        self.sgb = Some(Box::default());
+        self.joypad.enable_sgb();
+        self.set_dmg_palette(&DmgPalette { bg: [0, 1, 2, 3], obj0: [0, 1, 2, 3], obj1: [0, 1, 2, 3] });
+    }
+    /// Runs the pending SGB transfer and colors the finished frame `lcd`, `vblank` is false after a state load
+    pub(crate) fn sgb_frame(&mut self, lcd: &[[u16; 144]; 160], vblank: bool) {
+        if let Some(sgb) = self.sgb.as_mut() {
+            if vblank {
+                sgb.vblank(&self.vram[..0x2000], self.lcdc);
+            }
+            sgb.compose(lcd);
+        }
+    }
+    /// RGB555 `color` (0-3) of the bg palette `palette` (0-7)
+    pub fn bg_color(&self, palette: u8, color: u8) -> u16 {
+        Self::palette_color(&self.bg_palettes, palette, color)
+    }
+    /// RGB555 `color` (0-3) of the obj palette `palette` (0-7)
+    pub fn obj_color(&self, palette: u8, color: u8) -> u16 {
+        Self::palette_color(&self.obj_palettes, palette, color)
+    }
+    fn palette_color(palettes: &[u8; 0x40], palette: u8, color: u8) -> u16 {
+        let index = (palette as usize & 7) * 8 + (color as usize & 3) * 2;
+        u16::from_le_bytes([palettes[index], palettes[index + 1]]) & 0x7FFF
+    }
+    /// Colors of the 4 shades in dmg mode, BGP uses bg palette 0, OBP0 and OBP1 obj palettes 0 and 1
+    pub(crate) fn set_dmg_palette(&mut self, palette: &DmgPalette) {
+        let write = |palettes: &mut [u8; 0x40], index: usize, colors: [u16; 4]| {
+            for (shade, color) in colors.iter().enumerate() {
+                let i = index * 8 + shade * 2;
+                palettes[i..i + 2].copy_from_slice(&color.to_le_bytes());
+            }
+        };
+        write(&mut self.bg_palettes, 0, palette.bg);
+        write(&mut self.obj_palettes, 0, palette.obj0);
+        write(&mut self.obj_palettes, 1, palette.obj1);
+    }
     pub fn apu(&self) -> &APU {
         &self.apu
     }
@@ -271,11 +460,27 @@ impl MMU {
     }
 
     pub fn cycle_timer(&mut self) {
-        if self.timer.cycle(&mut self.apu) {
+        if self.timer.cycle(&mut self.apu, self.double_speed) {
             self.request_interrupt(InterruptRequest::Timer);
         }
     }
 
+    pub fn cycle_serial(&mut self) {
+        if self.serial.cycle() {
+            self.request_interrupt(InterruptRequest::Serial);
+        }
+    }
+
+    pub fn cycle_cartridge(&mut self) {
+        if let Some(cart) = self.cart.as_mut() {
+            cart.cycle();
+        }
+    }
+
+    pub fn serial_mut(&mut self) -> &mut Serial {
+        &mut self.serial
+    }
+
     pub fn cycle_apu(&mut self) {
         self.apu.cycle();
     }
@@ -306,9 +511,14 @@ impl MMU {
         self.interrupt_flags = iflag.into();
     }
 
+    /// the joypad interrupt is only requested when the key was not already pressed
     pub fn joypad_press(&mut self, key: joypad::Key) {
-        self.joypad.press(key);
-        self.request_interrupt(InterruptRequest::Joypad);
+        if self.joypad.press(key) {
+            self.request_interrupt(InterruptRequest::Joypad);
+        }
+    }
+    pub fn joypad(&self) -> &JoyPad {
+        &self.joypad
     }
 
     pub fn joypad_release(&mut self, key: joypad::Key) {
@@ -320,4 +530,91 @@ impl MMU {
     pub fn wy(&self) -> u8 {
         self.wy
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for MMU {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_tag(b"MMU ");
+        state.write_u8(self.interrupt_flags);
+        state.write_u8(self.interrupt_enables);
+        state.write_bool(self.boot_mode);
+        state.write_bool(self.cgb);
+        state.write_bytes(&self.vram);
+        state.write_u8(self.vram_bank);
+        state.write_bytes(&self.wram);
+        state.write_u8(self.wram_bank);
+        state.write_u8(self.bcps);
+        state.write_u8(self.ocps);
+        state.write_bytes(&self.bg_palettes);
+        state.write_bytes(&self.obj_palettes);
+        self.hdma.save_state(state);
+        state.write_u16(self.cpu_stall);
+        state.write_bool(self.double_speed);
+        state.write_bool(self.speed_switch);
+        state.write_bytes(&self.oam);
+        state.write_bytes(&self.hram);
+        state.write_bytes(&[
+            self.bgp, self.lcdc, self.lcdstat, self.scy, self.scx, self.ly, self.wx, self.wy,
+            self.obp0, self.obp1, self.lyc,
+        ]);
+        state.write_u8(self.test as u8);
+        self.timer.save_state(state);
+        self.apu.save_state(state);
+        self.joypad.save_state(state);
+        self.serial.save_state(state);
+        if let Some(sgb) = self.sgb.as_ref() {
+            sgb.save_state(state);
+        }
+        state.write_bool(self.cart.is_some());
+        if let Some(cart) = self.cart.as_ref() {
+            state.write_tag(b"CART");
+            cart.save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        state.read_tag(b"MMU ")?;
+        self.interrupt_flags = state.read_u8()?;
+        self.interrupt_enables = state.read_u8()?;
+        self.boot_mode = state.read_bool()?;
+        self.cgb = state.read_bool()?;
+        state.read_bytes(&mut self.vram)?;
+        self.vram_bank = state.read_u8()?;
+        state.read_bytes(&mut self.wram)?;
+        self.wram_bank = state.read_u8()?;
+        self.bcps = state.read_u8()?;
+        self.ocps = state.read_u8()?;
+        state.read_bytes(&mut self.bg_palettes)?;
+        state.read_bytes(&mut self.obj_palettes)?;
+        self.hdma.load_state(state)?;
+        self.cpu_stall = state.read_u16()?;
+        self.double_speed = state.read_bool()?;
+        self.speed_switch = state.read_bool()?;
+        if self.vram_bank > 1 || self.wram_bank == 0 || self.wram_bank > 7 { return Err(SaveStateError::InvalidValue("vram or wram bank")); }
+        state.read_bytes(&mut self.oam)?;
+        state.read_bytes(&mut self.hram)?;
+        let mut io = [0; 11];
+        state.read_bytes(&mut io)?;
+        [
+            self.bgp, self.lcdc, self.lcdstat, self.scy, self.scx, self.ly, self.wx, self.wy,
+            self.obp0, self.obp1, self.lyc,
+        ] = io;
+        self.test = state.read_u8()? as i8;
+        self.timer.load_state(state)?;
+        self.apu.load_state(state)?;
+        self.joypad.load_state(state)?;
+        self.serial.load_state(state)?;
+        if let Some(sgb) = self.sgb.as_mut() {
+            sgb.load_state(state)?;
+        }
+        let has_cart = state.read_bool()?;
+        match self.cart.as_mut() {
+            Some(cart) if has_cart => {
+                state.read_tag(b"CART")?;
+                cart.load_state(state)
+            }
+            None if !has_cart => Ok(()),
+            _ => Err(SaveStateError::RomMismatch),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// CGB vram dma, HDMA1-HDMA5 ($FF51-$FF55)
+///
+/// Copies blocks of 16 bytes to the selected vram bank, all at once (general purpose) or one block per HBlank.
+/// The `MMU` does the copy of the blocks given by `next_block`.
+pub struct Hdma {
+    source: u16,
+    /// offset in vram
+    destination: u16,
+    /// blocks left minus 1, as read in HDMA5 bits 0-6
+    remaining: u8,
+    hblank: bool,
+}
+
+impl Default for Hdma {
+    fn default() -> Self {
+        Self {
+            source: 0,
+            destination: 0,
+            remaining: 0x7F,
+            hblank: false,
+        }
+    }
+}
+
+impl Hdma {
+    /// HDMA5 bit 7 is clear while a HBlank transfer runs, it reads $FF once done
+    pub fn get(&self, address: usize) -> u8 {
+        match address {
+            0xFF55 => if self.hblank { self.remaining } else { 0x80 | self.remaining },
+            _ => 0xFF,
+        }
+    }
+
+    /// returns the number of blocks to copy right away, all of them for a general purpose transfer
+    pub fn set(&mut self, address: usize, val: u8) -> u8 {
+        match address {
+            0xFF51 => self.source = (self.source & 0x00FF) | ((val as u16) << 8),
+            0xFF52 => self.source = (self.source & 0xFF00) | (val & 0xF0) as u16,
+            0xFF53 => self.destination = (self.destination & 0x00FF) | (((val & 0x1F) as u16) << 8),
+            0xFF54 => self.destination = (self.destination & 0x1F00) | (val & 0xF0) as u16,
+            0xFF55 => {
+                // writing bit 7 clear during a HBlank transfer cancels it
+                if self.hblank && val & 0x80 == 0 {
+                    self.hblank = false;
+                    return 0;
+                }
+                self.remaining = val & 0x7F;
+                if val & 0x80 == 0 {
+                    return self.remaining + 1;
+                }
+                self.hblank = true;
+            }
+            _ => {}
+        }
+        0
+    }
+
+    pub fn is_hblank_transfer(&self) -> bool {
+        self.hblank
+    }
+
+    /// source address and vram offset of the next block, the transfer ends after the last one
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let block = (self.source, self.destination);
+        self.source = self.source.wrapping_add(0x10);
+        self.destination = (self.destination + 0x10) & 0x1FF0;
+        self.remaining = self.remaining.wrapping_sub(1) & 0x7F;
+        if self.remaining == 0x7F {
+            self.hblank = false;
+        }
+        block
+    }
+}
+
+impl SaveState for Hdma {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.source);
+        state.write_u16(self.destination);
+        state.write_u8(self.remaining);
+        state.write_bool(self.hblank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.source = state.read_u16()?;
+        self.destination = state.read_u16()?;
+        self.remaining = state.read_u8()?;
+        self.hblank = state.read_bool()?;
+        if self.source & 0x0F != 0 || self.destination & !0x1FF0 != 0 || self.remaining > 0x7F {
+            return Err(SaveStateError::InvalidValue("hdma registers"));
+        }
+        Ok(())
+    }
+}
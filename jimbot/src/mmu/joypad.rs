@@ -1,6 +1,10 @@
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::wasm_bindgen;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+use crate::sgb::packet::PacketReceiver;
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Key {
     Start,
     Select,
@@ -12,12 +16,23 @@ pub enum Key {
     Right,
 }
 
+impl Key {
+    /// indexed by `key as u8`
+    pub const ALL: [Key; 8] = [Key::Start, Key::Select, Key::B, Key::A, Key::Down, Key::Up, Key::Left, Key::Right];
+}
+
+#[derive(Copy, Clone)]
 pub enum Mode {
     Direction,
     Action,
     None,
 }
 
+impl Mode {
+    /// indexed by `mode as u8`
+    const ALL: [Mode; 3] = [Mode::Direction, Mode::Action, Mode::None];
+}
+
 pub struct JoyPad {
     mode: Mode,
     start: bool,
@@ -28,6 +43,13 @@ pub struct JoyPad {
     up: bool,
     left: bool,
     right: bool,
+    /// P14 and P15 as last written
+    lines: u8,
+    /// SGB command packets, `None` on other hardware
+    packets: Option<PacketReceiver>,
+    /// SGB multiplayer (MLT_REQ): 1, 2 or 4 joypads, the keys are the ones of the first joypad
+    players: u8,
+    player: u8,
 }
 
 impl Default for JoyPad {
@@ -41,32 +63,73 @@ impl Default for JoyPad {
             down: false,
             up: false,
             left: false,
-            right: false
+            right: false,
+            lines: 0x30,
+            packets: None,
+            players: 1,
+            player: 0,
         }
     }
 }
 
 impl JoyPad {
-    pub fn write(&mut self, u8: u8) {
+    /// returns the SGB command packet completed by this write
+    pub fn write(&mut self, u8: u8) -> Option<[u8; 16]> {
         self.mode = if (u8 >> 5) & 1 == 0 {
             Mode::Action
-        } else if (u8 >> 4) & 0 == 0 {
+        } else if (u8 >> 4) & 1 == 0 {
             Mode::Direction
         } else {
             Mode::None
         };
+        // the next joypad is selected when P15 goes back high
+        if self.lines == 0x10 && u8 & 0x30 == 0x30 {
+            self.player = (self.player + 1) % self.players;
+        }
+        self.lines = u8 & 0x30;
+        self.packets.as_mut()?.write(u8)
+    }
+
+    pub(crate) fn enable_sgb(&mut self) {
+        self.packets = Some(PacketReceiver::default());
+    }
+
+    /// MLT_REQ, the first joypad is selected
+    pub(crate) fn set_players(&mut self, players: u8) {
+        self.players = players;
+        self.player = 0;
+    }
+
+    /// joypad read when P14 and P15 are high, 0 to 3
+    pub fn player(&self) -> u8 {
+        self.player
+    }
+
+    /// returns true if the key was not already pressed
+    pub fn press(&mut self, key: Key) -> bool {
+        let pressed = match key {
+            Key::Start => &mut self.start,
+            Key::Select => &mut self.select,
+            Key::B => &mut self.b,
+            Key::A => &mut self.a,
+            Key::Down => &mut self.down,
+            Key::Up => &mut self.up,
+            Key::Left => &mut self.left,
+            Key::Right => &mut self.right,
+        };
+        !std::mem::replace(pressed, true)
     }
 
-    pub fn press(&mut self, key: Key) {
+    pub fn is_pressed(&self, key: Key) -> bool {
         match key {
-            Key::Start => { self.start = true }
-            Key::Select => { self.select = true }
-            Key::B => { self.b = true }
-            Key::A => { self.a = true }
-            Key::Down => { self.down = true }
-            Key::Up => { self.up = true }
-            Key::Left => { self.left = true }
-            Key::Right => { self.right = true }
+            Key::Start => self.start,
+            Key::Select => self.select,
+            Key::B => self.b,
+            Key::A => self.a,
+            Key::Down => self.down,
+            Key::Up => self.up,
+            Key::Left => self.left,
+            Key::Right => self.right,
         }
     }
 
@@ -85,7 +148,11 @@ impl JoyPad {
 
     pub fn bytes(&self) -> u8 {
         match self.mode {
-            Mode::None => { return 0xFF },
+            // the id of the selected joypad, $F for the first one
+            Mode::None => { return 0xFF - self.player },
+            // only the first joypad has keys pressed
+            Mode::Direction if self.player != 0 => { return 0b1101_1111 },
+            Mode::Action if self.player != 0 => { return 0b1110_1111 },
             Mode::Direction => {
                 let mut byte = 0b1101_1111;
 
@@ -108,4 +175,41 @@ impl JoyPad {
     }
 
 
-}
\ No newline at end of file
+}
+
+impl SaveState for JoyPad {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.mode as u8);
+        for pressed in [self.start, self.select, self.b, self.a, self.down, self.up, self.left, self.right] {
+            state.write_bool(pressed);
+        }
+        state.write_u8(self.lines);
+        state.write_u8(self.players);
+        state.write_u8(self.player);
+        if let Some(packets) = self.packets.as_ref() {
+            packets.save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.mode = state.read_enum(&Mode::ALL, "joypad mode")?;
+        self.start = state.read_bool()?;
+        self.select = state.read_bool()?;
+        self.b = state.read_bool()?;
+        self.a = state.read_bool()?;
+        self.down = state.read_bool()?;
+        self.up = state.read_bool()?;
+        self.left = state.read_bool()?;
+        self.right = state.read_bool()?;
+        self.lines = state.read_u8()?;
+        self.players = state.read_u8()?;
+        self.player = state.read_u8()?;
+        if !matches!(self.players, 1 | 2 | 4) || self.player >= self.players {
+            return Err(SaveStateError::InvalidValue("sgb joypads"));
+        }
+        if let Some(packets) = self.packets.as_mut() {
+            packets.load_state(state)?;
+        }
+        Ok(())
+    }
+}
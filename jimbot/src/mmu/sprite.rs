@@ -1,3 +1,5 @@
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
 #[derive(Debug, Copy, Clone)]
 pub struct Flag(u8);
 
@@ -10,6 +12,10 @@ impl Flag {
     pub fn is_y_flipped(&self) -> bool { (self.0 >> 6) & 1 == 1 }
     pub fn bg_prior(&self) -> bool { (self.0 >> 7) & 1 == 1 }
     pub fn palette_1(&self) -> bool { (self.0 >> 4) & 1 == 1 }
+    /// obj palette in cgb mode
+    pub fn cgb_palette(&self) -> u8 { self.0 & 0b111 }
+    /// tile data bank in cgb mode
+    pub fn vram_bank(&self) -> u8 { (self.0 >> 3) & 1 }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -18,6 +24,8 @@ pub struct Sprite {
     x: u8,
     tiledata_index: u8,
     flags: Flag,
+    /// position in the oam, the lowest index is drawn on top in cgb mode
+    oam_index: u8,
 }
 
 impl From<[u8; 4]> for Sprite {
@@ -26,7 +34,8 @@ impl From<[u8; 4]> for Sprite {
             y: from[0],
             x: from[1],
             tiledata_index: from[2],
-            flags: from[3].into()
+            flags: from[3].into(),
+            oam_index: 0,
         }
     }
 }
@@ -38,9 +47,14 @@ impl Sprite {
             x,
             tiledata_index,
             flags: flags.into(),
+            oam_index: 0,
         }
     }
 
+    pub fn with_oam_index(self, oam_index: u8) -> Self {
+        Self { oam_index, ..self }
+    }
+
     pub fn is_empty(&self) -> bool {
         let flags: u8 = self.flags.into();
         flags + self.y + self.x + self.tiledata_index == 0
@@ -50,10 +64,26 @@ impl Sprite {
     pub fn x(&self) -> u8 { self.x }
     pub fn tiledata_index(&self) -> u8 { self.tiledata_index }
     pub fn flags(&self) -> &Flag { &self.flags }
+    pub fn oam_index(&self) -> u8 { self.oam_index }
 }
 
 impl Default for Sprite {
     fn default() -> Self {
         Self::new(0,0,0,0)
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for Sprite {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&[self.y, self.x, self.tiledata_index, self.flags.into(), self.oam_index]);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        let mut bytes = [0; 5];
+        state.read_bytes(&mut bytes)?;
+        let [y, x, tiledata_index, flags, oam_index] = bytes;
+        if oam_index >= 40 { return Err(SaveStateError::InvalidValue("sprite oam index")); }
+        *self = Sprite::new(y, x, tiledata_index, flags).with_oam_index(oam_index);
+        Ok(())
+    }
+}
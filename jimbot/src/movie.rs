@@ -0,0 +1,355 @@
+//! Input movies
+//!
+//! A movie starts at power on and stores every joypad change with the m-cycle it happened at,
+//! so the playback is exact whatever the frontend frame pacing is.
+//! A hash of the save state is recorded every `hash_interval` frames to detect desyncs.
+//!
+//! Text format, one item per line, numbers are decimal unless noted, `#` starts a comment:
+//!
+//! ```text
+//! JBMOVIE 2
+//! rom <crc32 hex>                 crc32 of the whole rom
+//! boot <crc32 hex> | skip         crc32 of the boot rom, skip for `BootMode::Skip`
+//! save-ram <hex bytes> | none     cartridge ram at power on
+//! clock <hex bytes> | none        cartridge clock at power on, see `Cartridge::clock_state`
+//! hash-interval <frames>
+//! <cycle> <frame> press <key>     key is Start, Select, B, A, Down, Up, Left or Right
+//! <cycle> <frame> release <key>
+//! <cycle> <frame> hash <crc32 hex> crc32 of the save state at the end of the frame
+//! <cycle> <frame> end             last line, length of the movie
+//! ```
+//!
+//! `cycle` and `frame` are `Jimbot::cycle_count` and `Jimbot::frame_count` when the event happened,
+//! an input is applied before running the m-cycle `cycle`.
+//!
+//! Versions:
+//! - 1: initial format
+//! - 2: cartridge clock
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Write};
+use crate::crc32::crc32;
+use crate::mmu::joypad::Key;
+
+pub const VERSION: u32 = 2;
+pub const DEFAULT_HASH_INTERVAL: u64 = 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MovieError {
+    BadHeader,
+    UnsupportedVersion(u32),
+    InvalidLine(usize),
+    /// movies can only be recorded or played from power on
+    NotAtPowerOn,
+    RomMismatch,
+    BootMismatch,
+    SaveRamMismatch,
+    ClockMismatch,
+}
+
+impl Display for MovieError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MovieError::BadHeader => write!(f, "Not a jimbot movie"),
+            MovieError::UnsupportedVersion(version) => write!(f, "Unsupported movie version: {} (supported: {})", version, VERSION),
+            MovieError::InvalidLine(line) => write!(f, "Invalid movie line: {}", line),
+            MovieError::NotAtPowerOn => write!(f, "Movies start at power on"),
+            MovieError::RomMismatch => write!(f, "Movie was recorded with another rom"),
+            MovieError::BootMismatch => write!(f, "Movie was recorded with another boot mode"),
+            MovieError::SaveRamMismatch => write!(f, "Movie save ram does not fit the cartridge"),
+            MovieError::ClockMismatch => write!(f, "Movie clock does not fit the cartridge"),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Input {
+    pub cycle: u64,
+    pub frame: u64,
+    pub key: Key,
+    pub pressed: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Hash {
+    pub cycle: u64,
+    pub frame: u64,
+    pub crc32: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    rom_crc32: u32,
+    boot_rom_crc32: Option<u32>,
+    save_ram: Option<Vec<u8>>,
+    clock: Option<Vec<u8>>,
+    hash_interval: u64,
+    inputs: Vec<Input>,
+    hashes: Vec<Hash>,
+    length: (u64, u64),
+}
+
+impl Movie {
+    pub(crate) fn new(rom: &[u8], boot_rom: &[u8], save_ram: Option<Vec<u8>>, clock: Option<Vec<u8>>) -> Self {
+        Self {
+            rom_crc32: crc32(rom),
+            boot_rom_crc32: boot_rom_crc32(boot_rom),
+            save_ram,
+            clock,
+            hash_interval: DEFAULT_HASH_INTERVAL,
+            inputs: Vec::new(),
+            hashes: Vec::new(),
+            length: (0, 0),
+        }
+    }
+
+    pub fn rom_crc32(&self) -> u32 {
+        self.rom_crc32
+    }
+    /// `None` when the boot rom is skipped
+    pub fn boot_rom_crc32(&self) -> Option<u32> {
+        self.boot_rom_crc32
+    }
+    pub fn save_ram(&self) -> Option<&Vec<u8>> {
+        self.save_ram.as_ref()
+    }
+    pub fn clock(&self) -> Option<&Vec<u8>> {
+        self.clock.as_ref()
+    }
+    pub fn inputs(&self) -> &[Input] {
+        &self.inputs
+    }
+    pub fn hashes(&self) -> &[Hash] {
+        &self.hashes
+    }
+    /// number of m-cycles
+    pub fn cycles(&self) -> u64 {
+        self.length.0
+    }
+    pub fn frames(&self) -> u64 {
+        self.length.1
+    }
+
+    pub(crate) fn check(&self, rom: &[u8], boot_rom: &[u8]) -> Result<(), MovieError> {
+        if crc32(rom) != self.rom_crc32 { return Err(MovieError::RomMismatch); }
+        if boot_rom_crc32(boot_rom) != self.boot_rom_crc32 { return Err(MovieError::BootMismatch); }
+        Ok(())
+    }
+
+    /// drops everything recorded after `cycle`, used when the recording machine goes back in time
+    pub(crate) fn truncate(&mut self, cycle: u64, frame: u64) {
+        self.inputs.retain(|input| input.cycle < cycle);
+        self.hashes.retain(|hash| hash.frame <= frame);
+        self.length = (cycle, frame);
+    }
+
+    pub(crate) fn record_input(&mut self, cycle: u64, frame: u64, key: Key, pressed: bool) {
+        self.inputs.push(Input { cycle, frame, key, pressed });
+    }
+
+    pub(crate) fn wants_hash(&self, frame: u64) -> bool {
+        frame.is_multiple_of(self.hash_interval)
+    }
+
+    pub(crate) fn record_hash(&mut self, cycle: u64, frame: u64, state: &[u8]) {
+        self.hashes.push(Hash { cycle, frame, crc32: crc32(state) });
+    }
+
+    pub(crate) fn set_length(&mut self, cycle: u64, frame: u64) {
+        self.length = (cycle, frame);
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        writeln!(text, "JBMOVIE {}", VERSION).unwrap();
+        writeln!(text, "rom {:08X}", self.rom_crc32).unwrap();
+        match self.boot_rom_crc32 {
+            Some(boot_rom_crc32) => writeln!(text, "boot {:08X}", boot_rom_crc32).unwrap(),
+            None => writeln!(text, "boot skip").unwrap(),
+        }
+        write_hex_line(&mut text, "save-ram", self.save_ram.as_deref());
+        write_hex_line(&mut text, "clock", self.clock.as_deref());
+        writeln!(text, "hash-interval {}", self.hash_interval).unwrap();
+        let mut hashes = self.hashes.iter().peekable();
+        for input in &self.inputs {
+            while let Some(hash) = hashes.next_if(|hash| hash.cycle <= input.cycle) {
+                writeln!(text, "{} {} hash {:08X}", hash.cycle, hash.frame, hash.crc32).unwrap();
+            }
+            let event = if input.pressed { "press" } else { "release" };
+            writeln!(text, "{} {} {} {:?}", input.cycle, input.frame, event, input.key).unwrap();
+        }
+        for hash in hashes {
+            writeln!(text, "{} {} hash {:08X}", hash.cycle, hash.frame, hash.crc32).unwrap();
+        }
+        writeln!(text, "{} {} end", self.length.0, self.length.1).unwrap();
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, MovieError> {
+        let mut lines = text.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.split('#').next().unwrap_or("").trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        let mut header = |name: &str| -> Result<(usize, &str), MovieError> {
+            let (i, line) = lines.next().ok_or(MovieError::BadHeader)?;
+            match line.split_once(' ') {
+                Some((key, val)) if key == name => Ok((i, val.trim())),
+                _ => Err(MovieError::BadHeader),
+            }
+        };
+        let (i, version) = header("JBMOVIE")?;
+        let version = version.parse().map_err(|_| MovieError::InvalidLine(i))?;
+        if version != VERSION { return Err(MovieError::UnsupportedVersion(version)); }
+        let (i, rom) = header("rom")?;
+        let rom_crc32 = u32::from_str_radix(rom, 16).map_err(|_| MovieError::InvalidLine(i))?;
+        let (i, boot) = header("boot")?;
+        let boot_rom_crc32 = match boot {
+            "skip" => None,
+            boot => Some(u32::from_str_radix(boot, 16).map_err(|_| MovieError::InvalidLine(i))?),
+        };
+        let (i, save_ram) = header("save-ram")?;
+        let save_ram = match save_ram {
+            "none" => None,
+            save_ram => Some(parse_hex_bytes(save_ram).ok_or(MovieError::InvalidLine(i))?),
+        };
+        let (i, clock) = header("clock")?;
+        let clock = match clock {
+            "none" => None,
+            clock => Some(parse_hex_bytes(clock).ok_or(MovieError::InvalidLine(i))?),
+        };
+        let (i, hash_interval) = header("hash-interval")?;
+        let hash_interval = hash_interval.parse().ok().filter(|interval| *interval > 0).ok_or(MovieError::InvalidLine(i))?;
+
+        let mut movie = Self {
+            rom_crc32,
+            boot_rom_crc32,
+            save_ram,
+            clock,
+            hash_interval,
+            inputs: Vec::new(),
+            hashes: Vec::new(),
+            length: (0, 0),
+        };
+        let mut ended = false;
+        for (i, line) in lines {
+            if ended { return Err(MovieError::InvalidLine(i)); }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let (cycle, frame) = match (words.first().map(|w| w.parse()), words.get(1).map(|w| w.parse())) {
+                (Some(Ok(cycle)), Some(Ok(frame))) => (cycle, frame),
+                _ => return Err(MovieError::InvalidLine(i)),
+            };
+            match words[2..] {
+                ["press", key] | ["release", key] => movie.inputs.push(Input {
+                    cycle,
+                    frame,
+                    key: *Key::ALL.iter().find(|k| format!("{:?}", k) == key).ok_or(MovieError::InvalidLine(i))?,
+                    pressed: words[2] == "press",
+                }),
+                ["hash", crc] => movie.hashes.push(Hash {
+                    cycle,
+                    frame,
+                    crc32: u32::from_str_radix(crc, 16).map_err(|_| MovieError::InvalidLine(i))?,
+                }),
+                ["end"] => {
+                    movie.length = (cycle, frame);
+                    ended = true;
+                }
+                _ => return Err(MovieError::InvalidLine(i)),
+            }
+        }
+        if !ended { return Err(MovieError::InvalidLine(text.lines().count())); }
+        Ok(movie)
+    }
+}
+
+fn boot_rom_crc32(boot_rom: &[u8]) -> Option<u32> {
+    if boot_rom.is_empty() { None } else { Some(crc32(boot_rom)) }
+}
+
+/// `<name> <hex bytes>` or `<name> none`
+fn write_hex_line(text: &mut String, name: &str, bytes: Option<&[u8]>) {
+    text.push_str(name);
+    match bytes {
+        Some(bytes) => {
+            text.push(' ');
+            for byte in bytes {
+                write!(text, "{:02X}", byte).unwrap();
+            }
+        }
+        None => text.push_str(" none"),
+    }
+    text.push('\n');
+}
+
+fn parse_hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) { return None; }
+    (0..hex.len()).step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+/// First frame whose state hash differs from the recorded one
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Desync {
+    pub frame: u64,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+pub(crate) enum MovieState {
+    Recording(Movie),
+    Playing(Playback),
+}
+
+pub struct Playback {
+    movie: Movie,
+    next_input: usize,
+    next_hash: usize,
+    desync: Option<Desync>,
+}
+
+impl Playback {
+    pub(crate) fn new(movie: Movie) -> Self {
+        Self {
+            movie,
+            next_input: 0,
+            next_hash: 0,
+            desync: None,
+        }
+    }
+
+    /// next input to apply before running m-cycle `cycle`
+    pub(crate) fn next_input(&mut self, cycle: u64) -> Option<Input> {
+        let input = *self.movie.inputs.get(self.next_input)?;
+        if input.cycle > cycle { return None; }
+        self.next_input += 1;
+        Some(input)
+    }
+
+    /// true if the state at the end of `frame` must be hashed
+    pub(crate) fn wants_hash(&self, frame: u64) -> bool {
+        self.movie.hashes.get(self.next_hash).is_some_and(|hash| hash.frame == frame)
+    }
+
+    pub(crate) fn check_hash(&mut self, frame: u64, state: &[u8]) {
+        let expected = self.movie.hashes[self.next_hash].crc32;
+        self.next_hash += 1;
+        let actual = crc32(state);
+        if actual != expected && self.desync.is_none() {
+            self.desync = Some(Desync { frame, expected, actual });
+        }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+    pub fn desync(&self) -> Option<Desync> {
+        self.desync
+    }
+    pub(crate) fn is_finished_at(&self, cycle: u64) -> bool {
+        cycle >= self.movie.length.0
+    }
+}
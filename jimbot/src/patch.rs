@@ -0,0 +1,273 @@
+//! Rom soft patching
+//!
+//! Applies IPS, UPS and BPS patches to the rom bytes before they are given to `new_cartridge_from_bytes`,
+//! the format is detected from the patch header. `apply` returns a new rom so patches can be chained.
+//!
+//! - IPS: records of offset (u24 be), size (u16 be) and data, a size of 0 is a RLE record (u16 be count, value).
+//!   Ends with `EOF`, optionally followed by the size (u24 be) to truncate the rom to. No checksum.
+//! - UPS: source and target sizes then hunks of skipped bytes and bytes xored with the source, ended by a 0.
+//!   The patch also reverts the patched rom to the source.
+//! - BPS: source, target and metadata sizes then actions copying bytes from the source, the patch or the target.
+//!
+//! UPS and BPS end with the CRC-32 of the source, the target and the patch, all of them are checked.
+//! Patched roms are limited to `MAX_ROM_SIZE`, the size is checked before allocating.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::crc32::crc32;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PatchFormat {
+    Ips,
+    Ups,
+    Bps,
+}
+
+impl PatchFormat {
+    /// format of `patch` from its header, `None` if it is not a patch
+    pub fn detect(patch: &[u8]) -> Option<Self> {
+        match patch.get(..5)? {
+            b"PATCH" => Some(PatchFormat::Ips),
+            [b'U', b'P', b'S', b'1', _] => Some(PatchFormat::Ups),
+            [b'B', b'P', b'S', b'1', _] => Some(PatchFormat::Bps),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PatchError {
+    UnknownFormat,
+    /// patch ends in the middle of a record
+    Truncated,
+    /// a record reads or writes out of the rom
+    OutOfBounds,
+    /// patch is corrupted
+    PatchChecksum { expected: u32, actual: u32 },
+    /// patch is made for another rom
+    SourceChecksum { expected: u32, actual: u32 },
+    TargetChecksum { expected: u32, actual: u32 },
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "Not an IPS, UPS or BPS patch"),
+            PatchError::Truncated => write!(f, "Patch is truncated"),
+            PatchError::OutOfBounds => write!(f, "Patch goes out of the rom"),
+            PatchError::PatchChecksum { expected, actual } => write!(f, "Patch is corrupted, crc32: {:08X}, expected: {:08X}", actual, expected),
+            PatchError::SourceChecksum { expected, actual } => write!(f, "Patch is made for another rom, rom crc32: {:08X}, expected: {:08X}", actual, expected),
+            PatchError::TargetChecksum { expected, actual } => write!(f, "Patched rom is wrong, crc32: {:08X}, expected: {:08X}", actual, expected),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+/// Largest Game Boy rom, 8 MiB
+pub const MAX_ROM_SIZE: usize = 0x800000;
+
+/// Patched copy of `rom`
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    match PatchFormat::detect(patch) {
+        Some(PatchFormat::Ips) => apply_ips(rom, patch),
+        Some(PatchFormat::Ups) => apply_ups(rom, patch),
+        Some(PatchFormat::Bps) => apply_bps(rom, patch),
+        None => Err(PatchError::UnknownFormat),
+    }
+}
+
+/// Applies `patches` one after the other
+pub fn apply_all<P: AsRef<[u8]>>(rom: Vec<u8>, patches: &[P]) -> Result<Vec<u8>, PatchError> {
+    patches.iter().try_fold(rom, |rom, patch| apply(&rom, patch.as_ref()))
+}
+
+/// Reads the patch bytes in order
+struct PatchReader<'a> {
+    patch: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(patch: &'a [u8], position: usize) -> Self {
+        Self { patch, position }
+    }
+
+    fn read_bytes(&mut self, n: usize) -> Result<&'a [u8], PatchError> {
+        let bytes = self.patch.get(self.position..self.position + n).ok_or(PatchError::Truncated)?;
+        self.position += n;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, PatchError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    /// big endian, `n` bytes
+    fn read_be(&mut self, n: usize) -> Result<usize, PatchError> {
+        Ok(self.read_bytes(n)?.iter().fold(0, |val, byte| (val << 8) | *byte as usize))
+    }
+
+    /// UPS/BPS variable length number: 7 bits per byte, low bits first, bit 7 set on the last byte
+    fn read_number(&mut self) -> Result<usize, PatchError> {
+        let mut val = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.read_u8()?;
+            val = (byte as usize & 0x7F).checked_mul(shift).and_then(|bits| val.checked_add(bits)).ok_or(PatchError::OutOfBounds)?;
+            if byte & 0x80 != 0 {
+                return Ok(val);
+            }
+            shift = shift.checked_shl(7).filter(|shift| *shift != 0).ok_or(PatchError::OutOfBounds)?;
+            val = val.checked_add(shift).ok_or(PatchError::OutOfBounds)?;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    const EOF: usize = 0x454F46;
+    let mut target = rom.to_vec();
+    let mut reader = PatchReader::new(patch, 5);
+    loop {
+        let offset = reader.read_be(3)?;
+        if offset == EOF {
+            break;
+        }
+        let (size, data) = match reader.read_be(2)? {
+            0 => {
+                let count = reader.read_be(2)?;
+                (count, vec![reader.read_u8()?; count])
+            }
+            size => (size, reader.read_bytes(size)?.to_vec()),
+        };
+        if target.len() < offset + size {
+            if offset + size > MAX_ROM_SIZE {
+                return Err(PatchError::OutOfBounds);
+            }
+            target.resize(offset + size, 0);
+        }
+        target[offset..offset + size].copy_from_slice(&data);
+    }
+    // truncation extension
+    if patch.len() - reader.position >= 3 {
+        target.truncate(reader.read_be(3)?);
+    }
+    Ok(target)
+}
+
+/// source, target and patch crc32 at the end of UPS and BPS patches
+fn read_checksums(patch: &[u8], header_size: usize) -> Result<[u32; 3], PatchError> {
+    if patch.len() < header_size + 12 {
+        return Err(PatchError::Truncated);
+    }
+    let footer = &patch[patch.len() - 12..];
+    let checksum = |i: usize| u32::from_le_bytes(footer[i * 4..i * 4 + 4].try_into().unwrap());
+    let checksums = [checksum(0), checksum(1), checksum(2)];
+    let actual = crc32(&patch[..patch.len() - 4]);
+    if actual != checksums[2] {
+        return Err(PatchError::PatchChecksum { expected: checksums[2], actual });
+    }
+    Ok(checksums)
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let [source_crc32, target_crc32, _] = read_checksums(patch, 4)?;
+    let mut reader = PatchReader::new(patch, 4);
+    let source_size = reader.read_number()?;
+    let target_size = reader.read_number()?;
+    let rom_crc32 = crc32(rom);
+    // xor is its own inverse, the patch also turns the target back into the source
+    let (target_size, expected_crc32) = if rom_crc32 == source_crc32 && rom.len() == source_size {
+        (target_size, target_crc32)
+    } else if rom_crc32 == target_crc32 && rom.len() == target_size {
+        (source_size, source_crc32)
+    } else {
+        return Err(PatchError::SourceChecksum { expected: source_crc32, actual: rom_crc32 });
+    };
+    if target_size > MAX_ROM_SIZE {
+        return Err(PatchError::OutOfBounds);
+    }
+    let mut target = rom.to_vec();
+    target.resize(target_size, 0);
+    let mut position = 0usize;
+    while reader.position < patch.len() - 12 {
+        position = position.checked_add(reader.read_number()?).filter(|position| *position <= target.len()).ok_or(PatchError::OutOfBounds)?;
+        loop {
+            let xor = reader.read_u8()?;
+            if xor == 0 {
+                position += 1;
+                break;
+            }
+            if let Some(byte) = target.get_mut(position) {
+                *byte ^= xor;
+            }
+            position += 1;
+        }
+    }
+    let actual = crc32(&target);
+    if actual != expected_crc32 {
+        return Err(PatchError::TargetChecksum { expected: expected_crc32, actual });
+    }
+    Ok(target)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let [source_crc32, target_crc32, _] = read_checksums(patch, 4)?;
+    let rom_crc32 = crc32(rom);
+    if rom_crc32 != source_crc32 {
+        return Err(PatchError::SourceChecksum { expected: source_crc32, actual: rom_crc32 });
+    }
+    let mut reader = PatchReader::new(patch, 4);
+    let source_size = reader.read_number()?;
+    let target_size = reader.read_number()?;
+    let metadata_size = reader.read_number()?;
+    reader.read_bytes(metadata_size)?;
+    if rom.len() != source_size {
+        return Err(PatchError::SourceChecksum { expected: source_crc32, actual: rom_crc32 });
+    }
+    if target_size > MAX_ROM_SIZE {
+        return Err(PatchError::OutOfBounds);
+    }
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_offset = 0usize;
+    let mut target_offset = 0usize;
+    // signed relative offset: bit 0 is the sign
+    let relative = |offset: usize, data: usize| {
+        if data & 1 == 1 { offset.checked_sub(data >> 1) } else { offset.checked_add(data >> 1) }
+    };
+    while reader.position < patch.len() - 12 {
+        let data = reader.read_number()?;
+        let length = (data >> 2) + 1;
+        let position = target.len();
+        if position + length > target_size {
+            return Err(PatchError::OutOfBounds);
+        }
+        match data & 3 {
+            // source read
+            0 => target.extend_from_slice(rom.get(position..position + length).ok_or(PatchError::OutOfBounds)?),
+            // target read
+            1 => target.extend_from_slice(reader.read_bytes(length)?),
+            // source copy
+            2 => {
+                source_offset = relative(source_offset, reader.read_number()?).ok_or(PatchError::OutOfBounds)?;
+                let end = source_offset.checked_add(length).ok_or(PatchError::OutOfBounds)?;
+                target.extend_from_slice(rom.get(source_offset..end).ok_or(PatchError::OutOfBounds)?);
+                source_offset += length;
+            }
+            // target copy, byte by byte as it may read the bytes being written
+            _ => {
+                target_offset = relative(target_offset, reader.read_number()?).ok_or(PatchError::OutOfBounds)?;
+                for _ in 0..length {
+                    let byte = *target.get(target_offset).ok_or(PatchError::OutOfBounds)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    let actual = crc32(&target);
+    if target.len() != target_size || actual != target_crc32 {
+        return Err(PatchError::TargetChecksum { expected: target_crc32, actual });
+    }
+    Ok(target)
+}
@@ -0,0 +1,193 @@
+use crate::crc32::crc32;
+
+/// Encodes a 8 bits RGBA image, the image data is stored without compression
+pub fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let size = (width as usize).checked_mul(height as usize).and_then(|pixels| pixels.checked_mul(4));
+    assert_eq!(Some(rgba.len()), size, "RGBA buffer does not match the image size");
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // bit depth 8, color type RGBA, deflate, no filter, no interlace
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // every scanline starts with its filter type (none)
+    let mut raw = Vec::with_capacity(rgba.len() + height as usize);
+    for row in rgba.chunks(width as usize * 4) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// zlib stream made of deflate stored blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        zlib.push(last as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(data).to_be_bytes());
+    zlib
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// Largest width and height `decode_png` accepts
+pub const MAX_DECODED_SIZE: u32 = 0x4000;
+
+/// Decodes a non interlaced PNG (any color type, 1 to 16 bits) to 8 bits RGBA, returns the width, height and pixels
+pub fn decode_png(png: &[u8]) -> Result<(u32, u32, Vec<u8>), String> {
+    if !png.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Err(String::from("not a png"));
+    }
+    let mut chunks = Vec::new();
+    let mut position = 8;
+    while position + 12 <= png.len() {
+        let len = u32::from_be_bytes(png[position..position + 4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = png[position + 4..position + 8].try_into().unwrap();
+        let data = png.get(position + 8..position + 8 + len).ok_or("truncated png chunk")?;
+        chunks.push((kind, data));
+        position += 12 + len;
+    }
+    let header = chunks.iter().find(|(kind, _)| kind == b"IHDR").map(|(_, data)| *data).ok_or("missing IHDR")?;
+    if header.len() != 13 {
+        return Err(String::from("bad IHDR"));
+    }
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap());
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap());
+    let (depth, color_type) = (header[8], header[9]);
+    if width > MAX_DECODED_SIZE || height > MAX_DECODED_SIZE {
+        return Err(format!("png of {}x{} is too large", width, height));
+    }
+    if header[12] != 0 {
+        return Err(String::from("interlaced png"));
+    }
+    let channels = match color_type {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return Err(format!("bad png color type {}", color_type)),
+    };
+    if !matches!(depth, 1 | 2 | 4 | 8 | 16) {
+        return Err(format!("bad png bit depth {}", depth));
+    }
+    let palette = chunks.iter().find(|(kind, _)| kind == b"PLTE").map(|(_, data)| *data).unwrap_or(&[]);
+    let transparency = chunks.iter().find(|(kind, _)| kind == b"tRNS").map(|(_, data)| *data).unwrap_or(&[]);
+    let compressed: Vec<u8> = chunks.iter().filter(|(kind, _)| kind == b"IDAT").flat_map(|(_, data)| data.iter().copied()).collect();
+    let raw = crate::inflate::zlib_decompress(&compressed)?;
+
+    let bits_per_pixel = channels * depth as usize;
+    let stride = (width as usize * bits_per_pixel).div_ceil(8);
+    // distance to the same byte of the previous pixel for the filters
+    let bpp = bits_per_pixel.div_ceil(8);
+    let image_size = (stride + 1).checked_mul(height as usize);
+    let rgba_size = (width as usize).checked_mul(height as usize).and_then(|pixels| pixels.checked_mul(4));
+    let (Some(image_size), Some(rgba_size)) = (image_size, rgba_size) else {
+        return Err(String::from("png is too large"));
+    };
+    if raw.len() < image_size {
+        return Err(String::from("truncated png image data"));
+    }
+    let mut rgba = Vec::with_capacity(rgba_size);
+    let mut previous = vec![0u8; stride];
+    let mut line = vec![0u8; stride];
+    for row in raw.chunks(stride + 1).take(height as usize) {
+        let filter = row[0];
+        for i in 0..stride {
+            let a = if i >= bpp { line[i - bpp] } else { 0 };
+            let b = previous[i];
+            let c = if i >= bpp { previous[i - bpp] } else { 0 };
+            line[i] = row[1 + i].wrapping_add(match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(format!("bad png filter {}", filter)),
+            });
+        }
+        let sample = |index: usize| -> u16 {
+            match depth {
+                8 => line[index] as u16,
+                16 => u16::from_be_bytes([line[index * 2], line[index * 2 + 1]]),
+                _ => {
+                    let bit = index * depth as usize;
+                    (line[bit / 8] >> (8 - depth as usize - bit % 8)) as u16 & ((1 << depth) - 1)
+                }
+            }
+        };
+        // scales a sample to 8 bits
+        let scale = |val: u16| -> u8 {
+            match depth {
+                16 => (val >> 8) as u8,
+                _ => (val as u32 * 255 / ((1 << depth) - 1)) as u8,
+            }
+        };
+        for x in 0..width as usize {
+            let samples: Vec<u16> = (0..channels).map(|channel| sample(x * channels + channel)).collect();
+            let pixel = match color_type {
+                0 => {
+                    let alpha = if transparency.len() >= 2 && u16::from_be_bytes([transparency[0], transparency[1]]) == samples[0] { 0 } else { 0xFF };
+                    let gray = scale(samples[0]);
+                    [gray, gray, gray, alpha]
+                }
+                2 => [scale(samples[0]), scale(samples[1]), scale(samples[2]), 0xFF],
+                3 => {
+                    let index = samples[0] as usize;
+                    let color = palette.get(index * 3..index * 3 + 3).ok_or("png palette index out of range")?;
+                    [color[0], color[1], color[2], *transparency.get(index).unwrap_or(&0xFF)]
+                }
+                4 => {
+                    let gray = scale(samples[0]);
+                    [gray, gray, gray, scale(samples[1])]
+                }
+                _ => [scale(samples[0]), scale(samples[1]), scale(samples[2]), scale(samples[3])],
+            };
+            rgba.extend_from_slice(&pixel);
+        }
+        std::mem::swap(&mut previous, &mut line);
+    }
+    Ok((width, height, rgba))
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
@@ -11,6 +11,7 @@ use crate::mmu::MMU;
 use crate::mmu::sprite::Sprite;
 use crate::ppu::lcd_transfer::LCDTransfer;
 use crate::ppu::oam_search::OAMSearch;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct PPU {
     enable: bool,
@@ -20,7 +21,8 @@ pub struct PPU {
     oam_search: OAMSearch,
     lcd_transfer: LCDTransfer,
     scanline_cycle: u16,
-    lcd: [[[u8; 144]; 160]; 2],
+    /// RGB555 colors
+    lcd: [[[u16; 144]; 160]; 2],
     current_buffer: usize,
     stat_interrupt_line: bool,
 }
@@ -35,7 +37,7 @@ impl Default for PPU {
             sprite_buffer: Vec::with_capacity(10),
             oam_search: OAMSearch::default(),
             lcd_transfer: LCDTransfer::default(),
-            lcd: [[[0; 144]; 160]; 2],
+            lcd: [[[0x7FFF; 144]; 160]; 2],
             current_buffer: 0,
             stat_interrupt_line: false,
         }
@@ -45,7 +47,8 @@ impl Default for PPU {
 // 43 to 44 cycle after turn on to change ly to 1
 // wait 5 cycle after lcd enable from oamsearch mode (lcdstat:80) to drawing mode (lcdstat:0x83) 0x1CF statcount breakpoint interest
 impl PPU {
-    pub fn cycle(&mut self, mmu: &mut MMU) {
+    /// returns true when a frame is finished and the front buffer is swapped
+    pub fn cycle(&mut self, mmu: &mut MMU) -> bool {
         let lcdc = mmu.lcdc();
         let mut stat = mmu.lcdstat();
         if self.enable && !lcdc.is_display_enable() {
@@ -54,24 +57,26 @@ impl PPU {
             self.scanline = 0;
             mmu.set_ly(0);
             self.enable = false;
+            // the lightest dmg shade, white in cgb mode
+            let blank = if mmu.is_cgb() { 0x7FFF } else { mmu.bg_color(0, 0) };
             for lcd in self.lcd.iter_mut() {
                 for pixel_rows in lcd.iter_mut() {
                     for pixel in pixel_rows {
-                        *pixel = 0;
+                        *pixel = blank;
                     }
                 }
             }
             stat.set_mode(Mode::HBlank);
             self.sprite_buffer.clear();
             mmu.set_lcdstat(stat);
-            return;
+            return false;
         } else if !self.enable && lcdc.is_display_enable() {
             self.enable = true;
             self.init_enable = true;
             self.scanline = 0;
             self.scanline_cycle = 8;
         }
-        if !self.enable { return; }
+        if !self.enable { return false; }
         let mut new_stat_interrupt = false;
 
         match self.scanline {
@@ -160,6 +165,7 @@ impl PPU {
                 if self.lcd_transfer.cycle(mmu, &mut self.sprite_buffer, &mut self.lcd[self.current_buffer]) {
                     self.sprite_buffer.clear();
                     stat.set_mode(Mode::HBlank);
+                    mmu.hblank_dma();
                     new_stat_interrupt = new_stat_interrupt || stat.hblank_interrupt();
                 }
             }
@@ -176,11 +182,66 @@ impl PPU {
             self.scanline += 1;
             if self.scanline >= 154 {
                 self.current_buffer = (self.current_buffer + 1) % 2;
-                self.scanline = 0
+                self.scanline = 0;
+                return true;
             }
         }
+        false
     }
-    pub fn lcd(&self) -> [[u8; 144]; 160] {
-        self.lcd[(self.current_buffer + 1) % 2]
+    pub fn lcd(&self) -> &[[u16; 144]; 160] {
+        &self.lcd[(self.current_buffer + 1) % 2]
     }
-}
\ No newline at end of file
+    pub fn is_enable(&self) -> bool {
+        self.enable
+    }
+}
+
+impl SaveState for PPU {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_tag(b"PPU ");
+        state.write_bool(self.enable);
+        state.write_u8(self.scanline);
+        state.write_bool(self.init_enable);
+        state.write_u8(self.sprite_buffer.len() as u8);
+        for sprite in self.sprite_buffer.iter() {
+            sprite.save_state(state);
+        }
+        self.oam_search.save_state(state);
+        self.lcd_transfer.save_state(state);
+        state.write_u16(self.scanline_cycle);
+        for lcd in self.lcd.iter() {
+            for pixel in lcd.iter().flatten() {
+                state.write_u16(*pixel);
+            }
+        }
+        state.write_u8(self.current_buffer as u8);
+        state.write_bool(self.stat_interrupt_line);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        state.read_tag(b"PPU ")?;
+        self.enable = state.read_bool()?;
+        self.scanline = state.read_u8()?;
+        self.init_enable = state.read_bool()?;
+        let sprites = state.read_u8()?;
+        if sprites > 10 { return Err(SaveStateError::InvalidValue("sprite buffer length")); }
+        self.sprite_buffer.clear();
+        for _ in 0..sprites {
+            let mut sprite = Sprite::default();
+            sprite.load_state(state)?;
+            self.sprite_buffer.push(sprite);
+        }
+        self.oam_search.load_state(state)?;
+        self.lcd_transfer.load_state(state)?;
+        self.scanline_cycle = state.read_u16()?;
+        for lcd in self.lcd.iter_mut() {
+            for pixel in lcd.iter_mut().flatten() {
+                *pixel = state.read_u16()?;
+            }
+        }
+        self.current_buffer = state.read_u8()? as usize % 2;
+        self.stat_interrupt_line = state.read_bool()?;
+        if self.scanline >= 154 || self.scanline_cycle >= 456 { return Err(SaveStateError::InvalidValue("ppu scanline")); }
+        Ok(())
+    }
+}
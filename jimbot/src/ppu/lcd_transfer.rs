@@ -1,8 +1,9 @@
 use crate::mmu::MMU;
-use crate::mmu::sprite::Sprite;
+use crate::mmu::sprite::{Flag, Sprite};
 use crate::ppu::pixel_fetcher::PixelFetcher;
 use crate::ppu::pixel_fifo::PixelFifo;
 use crate::ppu::sprite_pixel_fifo::SpritePixelFifo;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct LCDTransfer {
     is_initial_scanline: bool,
@@ -29,7 +30,7 @@ impl Default for LCDTransfer {
 }
 
 impl LCDTransfer {
-    pub fn cycle(&mut self, mmu: &MMU, sprite_buffer: &mut Vec<Sprite>, lcd: &mut [[u8; 144]; 160]) -> bool {
+    pub fn cycle(&mut self, mmu: &MMU, sprite_buffer: &mut Vec<Sprite>, lcd: &mut [[u16; 144]; 160]) -> bool {
         if self.is_initial_scanline {
             let wy = mmu.wy();
             let ly = mmu.ly();
@@ -57,16 +58,8 @@ impl LCDTransfer {
                             self.pixel_fetcher.fetch_sprite(sprite, mmu, &mut self.sprite_pixel_fifo);
                         } else {
                             let bg = self.pixel_fifo.pop();
-                            let pixel = if let Some((sprite_px, flag)) = self.sprite_pixel_fifo.pop() {
-                                if !flag.bg_prior() && sprite_px > 0 || bg == 0 && sprite_px > 0 {
-                                    if !flag.palette_1() { mmu.obp0().get_color(sprite_px) } else { mmu.obp1().get_color(sprite_px) }
-                                } else {
-                                    mmu.bgp().get_color(bg)
-                                }
-                            } else {
-                                mmu.bgp().get_color(bg)
-                            };
-                            lcd[self.x as usize][mmu.ly() as usize] = pixel;
+                            let sprite = self.sprite_pixel_fifo.pop().map(|(sprite_px, flag, _)| (sprite_px, flag));
+                            lcd[self.x as usize][mmu.ly() as usize] = Self::mix(mmu, bg, sprite);
                             self.x += 1;
                             self.pixel_fetcher.step(mmu, &mut self.pixel_fifo, &mut self.sprite_pixel_fifo);
                         }
@@ -87,6 +80,27 @@ impl LCDTransfer {
         }
     }
 
+    /// RGB555 color of the bg pixel or the sprite pixel drawn over it
+    fn mix(mmu: &MMU, bg: u8, sprite: Option<(u8, Flag)>) -> u16 {
+        let bg_px = bg & 0b11;
+        if let Some((sprite_px, flag)) = sprite.filter(|(sprite_px, _)| *sprite_px > 0) {
+            if mmu.is_cgb() {
+                // LCDC bit 0 off puts the sprites over everything, else the bg attribute or the sprite flag can keep the bg on top
+                if !mmu.lcdc().is_bg_window_enable() || bg_px == 0 || !flag.bg_prior() && bg & 0x80 == 0 {
+                    return mmu.obj_color(flag.cgb_palette(), sprite_px);
+                }
+            } else if !flag.bg_prior() || bg_px == 0 {
+                let shade = if !flag.palette_1() { mmu.obp0().get_color(sprite_px) } else { mmu.obp1().get_color(sprite_px) };
+                return mmu.obj_color(flag.palette_1() as u8, shade);
+            }
+        }
+        if mmu.is_cgb() {
+            mmu.bg_color((bg >> 2) & 0b111, bg_px)
+        } else {
+            mmu.bg_color(0, mmu.bgp().get_color(bg_px))
+        }
+    }
+
     fn get_sprite(&self, sprite_buffer: &mut Vec<Sprite>) -> Option<Sprite> {
         if sprite_buffer.is_empty() { return None; };
 
@@ -107,4 +121,28 @@ impl LCDTransfer {
         if all { self.window_line = false; }
         self.pixel_fetcher.reset(all);
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for LCDTransfer {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.is_initial_scanline);
+        self.pixel_fifo.save_state(state);
+        self.sprite_pixel_fifo.save_state(state);
+        self.pixel_fetcher.save_state(state);
+        state.write_u8(self.x as u8);
+        state.write_u8(self.pixel_to_discard);
+        state.write_bool(self.window_line);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.is_initial_scanline = state.read_bool()?;
+        self.pixel_fifo.load_state(state)?;
+        self.sprite_pixel_fifo.load_state(state)?;
+        self.pixel_fetcher.load_state(state)?;
+        self.x = state.read_u8()? as usize;
+        self.pixel_to_discard = state.read_u8()?;
+        self.window_line = state.read_bool()?;
+        if self.x >= 160 { return Err(SaveStateError::InvalidValue("lcd transfer x")); }
+        Ok(())
+    }
+}
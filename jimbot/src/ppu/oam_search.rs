@@ -1,5 +1,6 @@
 use crate::mmu::MMU;
 use crate::mmu::sprite::Sprite;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct OAMSearch {
     current_entry: usize,
@@ -31,6 +32,7 @@ impl OAMSearch {
                 mmu.oam()[oam_index + 2],
                 mmu.oam()[oam_index + 3],
             ].into();
+            let sprite = sprite.with_oam_index(self.current_entry as u8);
             if sprite.x() > 0 &&
                 ly + 16 >= sprite.y() &&
                 ly + 16 < sprite.y() + sprite_height &&
@@ -53,4 +55,18 @@ impl OAMSearch {
         self.cycle_available = 0;
         self.current_entry = 0;
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for OAMSearch {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.current_entry as u8);
+        state.write_u8(self.cycle_available);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.current_entry = state.read_u8()? as usize;
+        self.cycle_available = state.read_u8()?;
+        if self.current_entry >= 40 { return Err(SaveStateError::InvalidValue("oam search entry")); }
+        Ok(())
+    }
+}
@@ -4,14 +4,17 @@ use crate::mmu::sprite::Sprite;
 use crate::ppu::pixel_fifo::{PixelFifo};
 use crate::ppu::sprite_pixel_fetcher::SpritePixelFetcher;
 use crate::ppu::sprite_pixel_fifo::SpritePixelFifo;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
+/// Bg pixels pushed to the fifo: color in bits 0-1, cgb palette in bits 2-4 and the cgb bg priority in bit 7
 #[derive(Debug)]
 pub enum Step {
     WaitFifo { tile_pixel_row: [u8; 8] },
     FetchTileDataIndex,
-    FetchTileDataLow { tile_data_index: u8 },
-    FetchTileDataHi { tile_data_row_address_low: u16, tile_data_row_low: u8 },
-    PushToFifo { tile_data_row_low: u8, tile_data_row_hi: u8 },
+    /// `attributes` is the bg map attribute byte from vram bank 1, 0 in dmg mode
+    FetchTileDataLow { tile_data_index: u8, attributes: u8 },
+    FetchTileDataHi { tile_data_row_address_low: u16, tile_data_row_low: u8, attributes: u8 },
+    PushToFifo { tile_data_row_low: u8, tile_data_row_hi: u8, attributes: u8 },
 }
 
 pub struct PixelFetcher {
@@ -64,9 +67,9 @@ impl PixelFetcher {
             self.cycle_available += 1;
             match self.current_step {
                 Step::FetchTileDataIndex => self.fetch_tile_data_index(mmu),
-                Step::FetchTileDataLow { tile_data_index } => self.fetch_tile_data_low(tile_data_index, mmu),
-                Step::FetchTileDataHi { tile_data_row_address_low, tile_data_row_low } => self.fetch_tile_data_hi(tile_data_row_address_low, tile_data_row_low, mmu),
-                Step::PushToFifo { tile_data_row_low, tile_data_row_hi } => self.push_to_fifo(tile_data_row_low, tile_data_row_hi, mmu, pixel_fifo),
+                Step::FetchTileDataLow { tile_data_index, attributes } => self.fetch_tile_data_low(tile_data_index, attributes, mmu),
+                Step::FetchTileDataHi { tile_data_row_address_low, tile_data_row_low, attributes } => self.fetch_tile_data_hi(tile_data_row_address_low, tile_data_row_low, attributes, mmu),
+                Step::PushToFifo { tile_data_row_low, tile_data_row_hi, attributes } => self.push_to_fifo(tile_data_row_low, tile_data_row_hi, attributes, mmu, pixel_fifo),
                 Step::WaitFifo { tile_pixel_row } => self.wait_fifo(tile_pixel_row, pixel_fifo),
             }
         }
@@ -85,17 +88,18 @@ impl PixelFetcher {
         let y_offset = if self.is_window_mode { 32 * (self.window_line_counter as u16 / 8) } else { 32 * (((ly as u16 + scy as u16) & 0xFF) / 8) };
         let offset = (x_offset + y_offset) & 0x3FF;
         let tile_data_address = tile_map_area.address(offset);
-        let tile_data_index = mmu.get(tile_data_address);
+        let tile_data_index = mmu.vram_get(0, tile_data_address);
+        let attributes = if mmu.is_cgb() { mmu.vram_get(1, tile_data_address) } else { 0 };
         // if self.is_window_mode {
         //     println!("ly: {}, address: {:#06X}, index: {:#04X}", ly, tile_data_address, tile_data_index);
         // }
         // if mmu.lcdc().is_window_enable() {
         //     println!("[w:{}]({},{}) s({},{}) off({},{}) add:{:#06X} idx:{:#04X}", self.is_window_mode, self.x_position_counter, ly, scx, scy, x_offset, y_offset, tile_data_address, tile_data_index);
         // }
-        self.current_step = Step::FetchTileDataLow { tile_data_index };
+        self.current_step = Step::FetchTileDataLow { tile_data_index, attributes };
     }
 
-    fn fetch_tile_data_low(&mut self, tile_data_index: u8, mmu: &MMU) {
+    fn fetch_tile_data_low(&mut self, tile_data_index: u8, attributes: u8, mmu: &MMU) {
         if self.cycle_available < 2 { return; }
         self.cycle_available -= 2;
         let lcdc = mmu.lcdc();
@@ -103,35 +107,40 @@ impl PixelFetcher {
         let scy = mmu.scy();
         let tile_data_area = lcdc.bg_window_tiledata_area();
         let tile_data_address = tile_data_area.address(tile_data_index as u16); // 16 bytes per tile
-        let tile_row_offset = if self.is_window_mode { 2 * (self.window_line_counter as u16 % 8) } else { 2 * ((ly as u16 + scy as u16) % 8) };
-        let tile_data_row_address_low = tile_data_address + tile_row_offset;
-        let tile_data_row_low = mmu.get(tile_data_row_address_low);
+        let tile_row = if self.is_window_mode { self.window_line_counter as u16 % 8 } else { (ly as u16 + scy as u16) % 8 };
+        let tile_row = if Self::is_y_flipped(attributes) { 7 - tile_row } else { tile_row };
+        let tile_data_row_address_low = tile_data_address + 2 * tile_row;
+        let tile_data_row_low = mmu.vram_get(Self::vram_bank(attributes), tile_data_row_address_low);
         // if mmu.lcdc().is_window_enable() {
         //     println!("FTL [w:{}]({},{}) wline:{}, scy:{} off:{} add:{:#06X} data:{:08b}", self.is_window_mode, self.x_position_counter, ly, self.window_line_counter, scy, tile_row_offset, tile_data_row_address_low, tile_data_row_low);
         // }
-        self.current_step = Step::FetchTileDataHi { tile_data_row_address_low, tile_data_row_low }
+        self.current_step = Step::FetchTileDataHi { tile_data_row_address_low, tile_data_row_low, attributes }
     }
 
-    fn fetch_tile_data_hi(&mut self, tile_data_row_address_low: u16, tile_data_row_low: u8, mmu: &MMU) {
+    fn fetch_tile_data_hi(&mut self, tile_data_row_address_low: u16, tile_data_row_low: u8, attributes: u8, mmu: &MMU) {
         if self.cycle_available < 2 { return; }
         self.cycle_available -= 2;
-        let tile_data_row_hi = mmu.get(tile_data_row_address_low + 1);
+        let tile_data_row_hi = mmu.vram_get(Self::vram_bank(attributes), tile_data_row_address_low + 1);
         // if mmu.lcdc().is_window_enable() {
         //     println!("FTH addr:{:#06X} data:{:08b}", tile_data_row_address_low + 1, tile_data_row_hi);
         // }
         self.current_step = Step::PushToFifo {
             tile_data_row_low,
             tile_data_row_hi,
+            attributes,
         }
     }
 
-    fn push_to_fifo(&mut self, tile_data_row_low: u8, tile_data_row_hi: u8, mmu: &MMU, pixel_fifo: &mut PixelFifo) {
+    fn push_to_fifo(&mut self, tile_data_row_low: u8, tile_data_row_hi: u8, attributes: u8, mmu: &MMU, pixel_fifo: &mut PixelFifo) {
         // println!("PF: {}", self.x_position_counter);
         if self.cycle_available < 2 { return; }
         self.cycle_available -= 2;
-        let tile_pixel_row = if mmu.lcdc().is_bg_window_enable() {
+        // in cgb mode LCDC bit 0 only drops the bg priority, the bg is always drawn
+        let tile_pixel_row = if mmu.is_cgb() || mmu.lcdc().is_bg_window_enable() {
             // if mmu.ly()>=8 && mmu.ly()<=15 { println!("Draw bg enabled: ly: {}", mmu.ly()) }
-            Self::pixels_from_bg_tile_data(tile_data_row_low, tile_data_row_hi)
+            let mut pixels = Self::pixels_from_bg_tile_data(tile_data_row_low, tile_data_row_hi);
+            if attributes & 0x20 != 0 { pixels.reverse(); }
+            pixels.map(|pixel| pixel | ((attributes & 0b111) << 2) | (attributes & 0x80))
         } else {
             // println!("remove bg: ly:{}", mmu.ly());
             [0; 8]
@@ -176,6 +185,10 @@ impl PixelFetcher {
         ]
     }
 
+    fn is_y_flipped(attributes: u8) -> bool { attributes & 0x40 != 0 }
+
+    fn vram_bank(attributes: u8) -> u8 { (attributes >> 3) & 1 }
+
     pub fn reset(&mut self, is_vblank: bool) {
         // assert_eq!(self.current_step, Step::Idle, "Step should be idle but {:?}", self.current_step);
         self.current_step = Step::FetchTileDataIndex;
@@ -188,4 +201,59 @@ impl PixelFetcher {
     pub fn is_window_mode(&self) -> bool {
         self.is_window_mode
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for PixelFetcher {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.sprite_pixel_fetcher.save_state(state);
+        state.write_u8(self.cycle_available);
+        match self.current_step {
+            Step::WaitFifo { tile_pixel_row } => {
+                state.write_u8(0);
+                state.write_bytes(&tile_pixel_row);
+            }
+            Step::FetchTileDataIndex => state.write_u8(1),
+            Step::FetchTileDataLow { tile_data_index, attributes } => {
+                state.write_u8(2);
+                state.write_u8(tile_data_index);
+                state.write_u8(attributes);
+            }
+            Step::FetchTileDataHi { tile_data_row_address_low, tile_data_row_low, attributes } => {
+                state.write_u8(3);
+                state.write_u16(tile_data_row_address_low);
+                state.write_u8(tile_data_row_low);
+                state.write_u8(attributes);
+            }
+            Step::PushToFifo { tile_data_row_low, tile_data_row_hi, attributes } => {
+                state.write_u8(4);
+                state.write_u8(tile_data_row_low);
+                state.write_u8(tile_data_row_hi);
+                state.write_u8(attributes);
+            }
+        }
+        state.write_bool(self.is_window_mode);
+        state.write_u8(self.x_position_counter);
+        state.write_u8(self.window_line_counter);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.sprite_pixel_fetcher.load_state(state)?;
+        self.cycle_available = state.read_u8()?;
+        self.current_step = match state.read_u8()? {
+            0 => {
+                let mut tile_pixel_row = [0; 8];
+                state.read_bytes(&mut tile_pixel_row)?;
+                Step::WaitFifo { tile_pixel_row }
+            }
+            1 => Step::FetchTileDataIndex,
+            2 => Step::FetchTileDataLow { tile_data_index: state.read_u8()?, attributes: state.read_u8()? },
+            3 => Step::FetchTileDataHi { tile_data_row_address_low: state.read_u16()?, tile_data_row_low: state.read_u8()?, attributes: state.read_u8()? },
+            4 => Step::PushToFifo { tile_data_row_low: state.read_u8()?, tile_data_row_hi: state.read_u8()?, attributes: state.read_u8()? },
+            _ => return Err(SaveStateError::InvalidValue("pixel fetcher step")),
+        };
+        self.is_window_mode = state.read_bool()?;
+        self.x_position_counter = state.read_u8()?;
+        self.window_line_counter = state.read_u8()?;
+        Ok(())
+    }
+}
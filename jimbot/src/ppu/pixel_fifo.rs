@@ -1,4 +1,5 @@
 use std::collections::VecDeque;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct PixelFifo {
     pixels: VecDeque<u8>,
@@ -46,4 +47,23 @@ impl PixelFifo {
         }
         res
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for PixelFifo {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.pixels.len() as u8);
+        for pixel in self.pixels.iter() {
+            state.write_u8(*pixel);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        let len = state.read_u8()?;
+        if len > 16 { return Err(SaveStateError::InvalidValue("pixel fifo length")); }
+        self.pixels.clear();
+        for _ in 0..len {
+            self.pixels.push_back(state.read_u8()?);
+        }
+        Ok(())
+    }
+}
@@ -3,6 +3,7 @@ use crate::mmu::MMU;
 use crate::mmu::sprite::{Flag, Sprite};
 use crate::ppu::pixel_fifo::{PixelFifo};
 use crate::ppu::sprite_pixel_fifo::SpritePixelFifo;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 #[derive(Debug)]
 pub enum Step {
@@ -61,7 +62,7 @@ impl SpritePixelFetcher {
             0x8000 + (sprite.tiledata_index() & !1) as u16 * 16
         };
         let tile_data_row_address_low = tile_data_address + tile_row_offset as u16;
-        let mut tile_data_row_low = mmu.get(tile_data_row_address_low);
+        let mut tile_data_row_low = mmu.vram_get(Self::vram_bank(&sprite, mmu), tile_data_row_address_low);
         if sprite.x() < 8 {
             if sprite.flags().is_x_flipped() {
                 tile_data_row_low = tile_data_row_low >> (8 - sprite.x());
@@ -75,7 +76,7 @@ impl SpritePixelFetcher {
     fn fetch_tile_data_hi(&mut self, sprite: Sprite, tile_data_row_address_low: u16, tile_data_row_low: u8, mmu: &MMU) {
         if self.cycle_available < 2 { return; }
         self.cycle_available -= 2;
-        let mut tile_data_row_hi = mmu.get(tile_data_row_address_low + 1);
+        let mut tile_data_row_hi = mmu.vram_get(Self::vram_bank(&sprite, mmu), tile_data_row_address_low + 1);
         if sprite.x() < 8 {
             if sprite.flags().is_x_flipped() {
                 tile_data_row_hi = tile_data_row_hi >> (8 - sprite.x());
@@ -94,31 +95,76 @@ impl SpritePixelFetcher {
         if self.cycle_available < 2 { return; }
         self.cycle_available -= 2;
         let mut sprite_tile_pixels = if mmu.lcdc().is_sprite_enable() {
-            Self::pixels_from_sprite_tile_data(tile_data_row_low, tile_data_row_hi, sprite.flags().clone())
+            Self::pixels_from_sprite_tile_data(tile_data_row_low, tile_data_row_hi, sprite.flags().clone(), sprite.oam_index())
         } else {
-            [(0, sprite.flags().clone()); 8]
+            [(0, sprite.flags().clone(), sprite.oam_index()); 8]
         };
         if sprite.flags().is_x_flipped() { sprite_tile_pixels.reverse() };
-        pixel_fifo.push_tile_pixel_row(sprite_tile_pixels);
+        pixel_fifo.push_tile_pixel_row(sprite_tile_pixels, mmu.is_cgb());
         assert_eq!(self.cycle_available, 0, "Cycle available should 0 but {}", self.cycle_available);
         self.current_step = Step::Idle;
     }
 
-    fn pixels_from_sprite_tile_data(lo: u8, hi: u8, flag: Flag) -> [(u8, Flag); 8] {
+    fn pixels_from_sprite_tile_data(lo: u8, hi: u8, flag: Flag, oam_index: u8) -> [(u8, Flag, u8); 8] {
         [
-            (((lo >> 7) & 1) | (((hi >> 7) & 1) << 1), flag),
-            (((lo >> 6) & 1) | (((hi >> 6) & 1) << 1), flag),
-            (((lo >> 5) & 1) | (((hi >> 5) & 1) << 1), flag),
-            (((lo >> 4) & 1) | (((hi >> 4) & 1) << 1), flag),
-            (((lo >> 3) & 1) | (((hi >> 3) & 1) << 1), flag),
-            (((lo >> 2) & 1) | (((hi >> 2) & 1) << 1), flag),
-            (((lo >> 1) & 1) | (((hi >> 1) & 1) << 1), flag),
-            (((lo >> 0) & 1) | (((hi >> 0) & 1) << 1), flag),
+            (((lo >> 7) & 1) | (((hi >> 7) & 1) << 1), flag, oam_index),
+            (((lo >> 6) & 1) | (((hi >> 6) & 1) << 1), flag, oam_index),
+            (((lo >> 5) & 1) | (((hi >> 5) & 1) << 1), flag, oam_index),
+            (((lo >> 4) & 1) | (((hi >> 4) & 1) << 1), flag, oam_index),
+            (((lo >> 3) & 1) | (((hi >> 3) & 1) << 1), flag, oam_index),
+            (((lo >> 2) & 1) | (((hi >> 2) & 1) << 1), flag, oam_index),
+            (((lo >> 1) & 1) | (((hi >> 1) & 1) << 1), flag, oam_index),
+            (((lo >> 0) & 1) | (((hi >> 0) & 1) << 1), flag, oam_index),
         ]
     }
 
+    /// tile data bank, always 0 in dmg mode
+    fn vram_bank(sprite: &Sprite, mmu: &MMU) -> u8 {
+        if mmu.is_cgb() { sprite.flags().vram_bank() } else { 0 }
+    }
+
     pub fn reset(&mut self, is_vblank: bool) {
         assert_eq!(self.cycle_available, 0, "Cycle should be 0 but {}", self.cycle_available);
         // assert_eq!(self.current_step, Step::Idle, "Step should be idle but {:?}", self.current_step);
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for SpritePixelFetcher {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.cycle_available);
+        match self.current_step {
+            Step::Idle => state.write_u8(0),
+            Step::FetchTileDataLow { sprite } => {
+                state.write_u8(1);
+                sprite.save_state(state);
+            }
+            Step::FetchTileDataHi { sprite, tile_data_row_address_low, tile_data_row_low } => {
+                state.write_u8(2);
+                sprite.save_state(state);
+                state.write_u16(tile_data_row_address_low);
+                state.write_u8(tile_data_row_low);
+            }
+            Step::PushToFifo { sprite, tile_data_row_low, tile_data_row_hi } => {
+                state.write_u8(3);
+                sprite.save_state(state);
+                state.write_u8(tile_data_row_low);
+                state.write_u8(tile_data_row_hi);
+            }
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.cycle_available = state.read_u8()?;
+        let step = state.read_u8()?;
+        let mut sprite = Sprite::default();
+        if step != 0 { sprite.load_state(state)?; }
+        self.current_step = match step {
+            0 => Step::Idle,
+            1 => Step::FetchTileDataLow { sprite },
+            2 => Step::FetchTileDataHi { sprite, tile_data_row_address_low: state.read_u16()?, tile_data_row_low: state.read_u8()? },
+            3 => Step::PushToFifo { sprite, tile_data_row_low: state.read_u8()?, tile_data_row_hi: state.read_u8()? },
+            _ => return Err(SaveStateError::InvalidValue("sprite fetcher step")),
+        };
+        Ok(())
+    }
+}
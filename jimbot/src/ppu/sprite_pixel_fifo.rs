@@ -1,8 +1,10 @@
 use std::collections::VecDeque;
 use crate::mmu::sprite::Flag;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
+/// color, flags and oam index of the sprite pixels
 pub struct SpritePixelFifo {
-    pixels: VecDeque<(u8, Flag)>,
+    pixels: VecDeque<(u8, Flag, u8)>,
 }
 
 impl Default for SpritePixelFifo {
@@ -16,13 +18,16 @@ impl Default for SpritePixelFifo {
 impl SpritePixelFifo {
     pub(crate) fn can_pop(&self) -> bool { self.pixels.len() >= 8 }
     pub(crate) fn can_push(&self) -> bool { self.pixels.len() <= 8 }
-    pub(crate) fn pop(&mut self) -> Option<(u8, Flag)> {
+    pub(crate) fn pop(&mut self) -> Option<(u8, Flag, u8)> {
         self.pixels.pop_front()
     }
-    pub(crate) fn push_tile_pixel_row(&mut self, tile_pixel_row: [(u8, Flag); 8]) {
+    /// Overlapping pixels are kept unless transparent, in cgb mode the sprite first in oam wins
+    pub(crate) fn push_tile_pixel_row(&mut self, tile_pixel_row: [(u8, Flag, u8); 8], cgb: bool) {
         for i in 0..8 {
             if i < self.pixels.len() {
-                if self.pixels[i].0 == 0 {
+                let (pixel, _, oam_index) = self.pixels[i];
+                let (new_pixel, _, new_oam_index) = tile_pixel_row[i];
+                if pixel == 0 || cgb && new_pixel != 0 && new_oam_index < oam_index {
                     self.pixels[i] = tile_pixel_row[i];
                 }
                 continue
@@ -33,4 +38,28 @@ impl SpritePixelFifo {
     pub fn reset(&mut self) {
         self.pixels.clear();
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for SpritePixelFifo {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.pixels.len() as u8);
+        for (pixel, flag, oam_index) in self.pixels.iter() {
+            state.write_u8(*pixel);
+            state.write_u8((*flag).into());
+            state.write_u8(*oam_index);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        let len = state.read_u8()?;
+        if len > 16 { return Err(SaveStateError::InvalidValue("sprite pixel fifo length")); }
+        self.pixels.clear();
+        for _ in 0..len {
+            let pixel = state.read_u8()?;
+            let flag = state.read_u8()?.into();
+            let oam_index = state.read_u8()?;
+            self.pixels.push_back((pixel, flag, oam_index));
+        }
+        Ok(())
+    }
+}
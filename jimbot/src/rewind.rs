@@ -0,0 +1,162 @@
+use std::collections::VecDeque;
+
+/// Ring buffer of save states used to rewind the emulation.
+///
+/// Only the newest snapshot is kept in full, every older snapshot is stored as a delta
+/// (XOR against the next snapshot, run length encoded) so a mostly unchanged machine costs a few bytes per snapshot.
+/// The oldest deltas are dropped once `capacity` bytes are used.
+pub struct Rewind {
+    interval: u64,
+    capacity: usize,
+    latest: Option<(u64, Vec<u8>)>,
+    deltas: VecDeque<(u64, Vec<u8>)>,
+    size: usize,
+}
+
+impl Default for Rewind {
+    /// a snapshot every 2 frames, up to 32 MiB
+    fn default() -> Self {
+        Self::new(2, 32 * 1024 * 1024)
+    }
+}
+
+impl Rewind {
+    /// records a snapshot every `interval` frames and uses up to `capacity` bytes
+    pub fn new(interval: u32, capacity: usize) -> Self {
+        Self {
+            interval: interval.max(1) as u64,
+            capacity,
+            latest: None,
+            deltas: VecDeque::new(),
+            size: 0,
+        }
+    }
+
+    pub(crate) fn should_record(&self, frame: u64) -> bool {
+        match &self.latest {
+            Some((latest_frame, _)) => frame >= latest_frame + self.interval || frame < *latest_frame,
+            None => true,
+        }
+    }
+
+    /// Stores the snapshot of `frame`, a frame older than the newest one clears the history
+    pub fn push(&mut self, frame: u64, state: Vec<u8>) {
+        if let Some((latest_frame, latest)) = self.latest.take() {
+            if frame > latest_frame {
+                let delta = encode_delta(&state, &latest);
+                self.size += delta.len();
+                self.deltas.push_back((latest_frame, delta));
+                self.size -= latest.len();
+            } else {
+                // timeline went backward, history is no longer valid
+                self.clear();
+            }
+        }
+        self.size += state.len();
+        self.latest = Some((frame, state));
+        while self.size > self.capacity {
+            match self.deltas.pop_front() {
+                Some((_, delta)) => self.size -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Drops snapshots newer than `frame` and returns the newest remaining one (or the oldest one).
+    pub fn rewind_to(&mut self, frame: u64) -> Option<Vec<u8>> {
+        let (mut latest_frame, mut latest) = self.latest.take()?;
+        while latest_frame > frame {
+            let Some((older_frame, delta)) = self.deltas.pop_back() else { break };
+            let older = apply_delta(&latest, &delta);
+            self.size = self.size + older.len() - latest.len() - delta.len();
+            latest_frame = older_frame;
+            latest = older;
+        }
+        self.latest = Some((latest_frame, latest.clone()));
+        Some(latest)
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.size = 0;
+    }
+
+    /// number of snapshots stored
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.latest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// bytes used by the stored snapshots
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// frame number of the oldest snapshot
+    pub fn oldest_frame(&self) -> Option<u64> {
+        self.deltas.front().map(|(frame, _)| *frame).or(self.latest.as_ref().map(|(frame, _)| *frame))
+    }
+}
+
+/// Delta turning `from` into `to`: length of `to` followed by runs of
+/// (unchanged byte count, changed byte count, `from ^ to` of the changed bytes).
+/// Counts are LEB128, bytes past the end of `from` are read as 0.
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    write_varint(&mut delta, to.len());
+    let xor = |i: usize| from.get(i).copied().unwrap_or(0) ^ to[i];
+    let mut i = 0;
+    while i < to.len() {
+        let unchanged_start = i;
+        while i < to.len() && xor(i) == 0 { i += 1; }
+        if i == to.len() { break; }
+        let changed_start = i;
+        // short unchanged gaps are cheaper inside the changed run than as a new run
+        while i < to.len() && (xor(i) != 0 || (i + 2 < to.len() && (xor(i + 1) != 0 || xor(i + 2) != 0))) { i += 1; }
+        write_varint(&mut delta, changed_start - unchanged_start);
+        write_varint(&mut delta, i - changed_start);
+        delta.extend((changed_start..i).map(xor));
+    }
+    delta
+}
+
+fn apply_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut position = 0;
+    let len = read_varint(delta, &mut position);
+    let mut to: Vec<u8> = (0..len).map(|i| from.get(i).copied().unwrap_or(0)).collect();
+    let mut i = 0;
+    while position < delta.len() {
+        i += read_varint(delta, &mut position);
+        let changed = read_varint(delta, &mut position);
+        for xor in &delta[position..position + changed] {
+            to[i] ^= xor;
+            i += 1;
+        }
+        position += changed;
+    }
+    to
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut val: usize) {
+    while val >= 0x80 {
+        bytes.push((val as u8) | 0x80);
+        val >>= 7;
+    }
+    bytes.push(val as u8);
+}
+
+fn read_varint(bytes: &[u8], position: &mut usize) -> usize {
+    let mut val = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*position];
+        *position += 1;
+        val |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 { return val; }
+        shift += 7;
+    }
+}
@@ -0,0 +1,203 @@
+//! Save state binary format
+//!
+//! All numbers are little endian, `bool` is a single byte (0 or 1).
+//!
+//! ```text
+//! magic       4 bytes  "JBST"
+//! version     u16      see `VERSION`
+//! rom title   u32 length + bytes, the cartridge header title
+//! rom check   3 bytes  header checksum ($014D) and global checksum ($014E-$014F)
+//! sections    each section starts with a 4 bytes tag, in this order:
+//!             "JBOT" frame and cycle counters
+//!             "CPU " registers, ime, halt and the in-flight instruction chain
+//!             "MMU " io registers, vram, wram, oam, hram, timer, apu, joypad and serial
+//!             "SGB " palettes, attributes, border and pending command, only in SGB mode
+//!             "CART" mapper registers and cartridge ram, only when a cartridge is inserted
+//!             "PPU " scanline state, fetchers, fifos and both lcd buffers
+//! ```
+//!
+//! A state can only be loaded into a `Jimbot` running the same rom (same title and checksums).
+//! Variable length data (cartridge ram, fifos, sprite buffer, instruction chain) is prefixed with its length.
+//!
+//! Versions:
+//! - 1: initial format
+//! - 2: serial transfer state
+//! - 3: MBC3 real time clock
+//! - 4: unified MBC1 registers
+//! - 5: ram enable and rumble motor of every mapper
+//! - 6: CGB mode, vram and wram banks, color palettes, bg attributes and RGB555 lcd buffers
+//! - 7: CGB vram dma (HDMA)
+//! - 8: CGB double speed and STOP instruction
+//! - 9: SGB mode, joypad lines and multiplayer
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+pub const MAGIC: &[u8; 4] = b"JBST";
+pub const VERSION: u16 = 9;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SaveStateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnexpectedEof,
+    BadSection([u8; 4]),
+    InvalidValue(&'static str),
+    RomMismatch,
+}
+
+impl Display for SaveStateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveStateError::BadMagic => write!(f, "Not a jimbot save state"),
+            SaveStateError::UnsupportedVersion(version) => write!(f, "Unsupported save state version: {} (supported: {})", version, VERSION),
+            SaveStateError::UnexpectedEof => write!(f, "Save state is truncated"),
+            SaveStateError::BadSection(tag) => write!(f, "Unexpected save state section: {:?}", String::from_utf8_lossy(tag)),
+            SaveStateError::InvalidValue(what) => write!(f, "Invalid value in save state: {}", what),
+            SaveStateError::RomMismatch => write!(f, "Save state was made with another rom"),
+        }
+    }
+}
+
+impl Error for SaveStateError {}
+
+/// Implemented by every subsystem that is part of a save state
+pub trait SaveState {
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError>;
+}
+
+/// State of `value` alone, without header nor tag
+pub fn to_bytes<T: SaveState>(value: &T) -> Vec<u8> {
+    let mut state = StateWriter::default();
+    value.save_state(&mut state);
+    state.into_bytes()
+}
+
+/// Loads `value` from bytes made by `to_bytes`, every byte must be used
+pub fn load_from_bytes<T: SaveState>(value: &mut T, bytes: &[u8]) -> Result<(), SaveStateError> {
+    let mut state = StateReader::new(bytes);
+    value.load_state(&mut state)?;
+    if !state.is_empty() { return Err(SaveStateError::InvalidValue("trailing data")); }
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn write_tag(&mut self, tag: &[u8; 4]) {
+        self.bytes.extend_from_slice(tag);
+    }
+    pub fn write_u8(&mut self, val: u8) {
+        self.bytes.push(val);
+    }
+    pub fn write_bool(&mut self, val: bool) {
+        self.bytes.push(val as u8);
+    }
+    pub fn write_u16(&mut self, val: u16) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+    pub fn write_u32(&mut self, val: u32) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+    pub fn write_u64(&mut self, val: u64) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+    pub fn write_f32(&mut self, val: f32) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+    /// fixed size data, the reader must know the length
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+    /// variable size data, prefixed with its length
+    pub fn write_vec(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.bytes.extend_from_slice(bytes);
+    }
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.bytes.len() - self.position < n { return Err(SaveStateError::UnexpectedEof); }
+        let res = &self.bytes[self.position..self.position + n];
+        self.position += n;
+        Ok(res)
+    }
+
+    pub fn read_tag(&mut self, expected: &[u8; 4]) -> Result<(), SaveStateError> {
+        let tag = self.take(4)?;
+        if tag != expected {
+            return Err(SaveStateError::BadSection([tag[0], tag[1], tag[2], tag[3]]));
+        }
+        Ok(())
+    }
+    pub fn read_u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.take(1)?[0])
+    }
+    pub fn read_bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::InvalidValue("bool")),
+        }
+    }
+    pub fn read_u16(&mut self) -> Result<u16, SaveStateError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+    pub fn read_u32(&mut self) -> Result<u32, SaveStateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+    pub fn read_u64(&mut self) -> Result<u64, SaveStateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+    pub fn read_f32(&mut self) -> Result<f32, SaveStateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(f32::from_le_bytes(bytes))
+    }
+    /// fills `bytes` with data written by `StateWriter::write_bytes`
+    pub fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<(), SaveStateError> {
+        bytes.copy_from_slice(self.take(bytes.len())?);
+        Ok(())
+    }
+    /// reads data written by `StateWriter::write_vec`
+    pub fn read_vec(&mut self) -> Result<Vec<u8>, SaveStateError> {
+        let len = self.read_u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+    /// reads data written by `StateWriter::write_vec` into `bytes`, the length must match
+    pub fn read_vec_exact(&mut self, bytes: &mut [u8]) -> Result<(), SaveStateError> {
+        let len = self.read_u32()? as usize;
+        if len != bytes.len() { return Err(SaveStateError::InvalidValue("data length")); }
+        self.read_bytes(bytes)
+    }
+    /// reads a u8 index into `table`, used for fieldless enums saved with `as u8`
+    pub fn read_enum<T: Copy>(&mut self, table: &[T], what: &'static str) -> Result<T, SaveStateError> {
+        let index = self.read_u8()? as usize;
+        table.get(index).copied().ok_or(SaveStateError::InvalidValue(what))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+}
@@ -0,0 +1,189 @@
+use std::sync::{Arc, Mutex};
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+pub mod link_cable;
+pub mod printer;
+
+/// Something plugged in the link port
+pub trait SerialDevice: Send + Sync {
+    /// Called when a transfer clocked by the Game Boy starts, `byte` is shifted out
+    /// and the returned byte is shifted in (0xFF when nothing answers).
+    fn exchange(&mut self, byte: u8) -> u8;
+
+    /// Called when the Game Boy waits for an external clock with `byte` in SB
+    fn ready(&mut self, _byte: u8) {}
+
+    /// Polled every m-cycle while the Game Boy waits for an external clock,
+    /// returns the byte shifted in once the other side clocked the transfer
+    fn poll(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+/// Records every byte sent by the Game Boy, clones share the same buffer.
+/// Test roms (blargg) print their results this way.
+#[derive(Clone, Default)]
+pub struct SerialCapture {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl SerialCapture {
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.lock().unwrap().clone()
+    }
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.bytes.lock().unwrap()).to_string()
+    }
+    pub fn clear(&self) {
+        self.bytes.lock().unwrap().clear();
+    }
+}
+
+impl SerialDevice for SerialCapture {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        self.bytes.lock().unwrap().push(byte);
+        0xFF
+    }
+}
+
+/// SB ($FF01) and SC ($FF02), a byte is shifted out msb first while the other side shifts in.
+/// With the internal clock a bit is shifted every 128 m-cycles (8192 Hz),
+/// with the external clock the whole byte is exchanged when the device says the other side clocked it.
+pub struct Serial {
+    data: u8,
+    control: u8,
+    /// bits left to shift in the current transfer
+    bits: u8,
+    clock: u8,
+    incoming: u8,
+    device: Option<Box<dyn SerialDevice>>,
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self {
+            data: 0,
+            control: 0,
+            bits: 0,
+            clock: 0,
+            incoming: 0xFF,
+            device: None,
+        }
+    }
+}
+
+impl Serial {
+    const M_CYCLES_PER_BIT: u8 = 128;
+
+    pub fn set(&mut self, address: usize, val: u8) {
+        match address {
+            0xFF01 => self.data = val,
+            0xFF02 => {
+                self.control = val;
+                if self.is_transfer_requested() {
+                    if self.is_internal_clock() {
+                        self.start_transfer();
+                    } else if let Some(device) = self.device.as_mut() {
+                        device.ready(self.data);
+                    }
+                }
+            }
+            _ => panic!("SET SERIAL: {:#06x}->{:#04x}", address, val)
+        }
+    }
+
+    pub fn get(&self, address: usize) -> u8 {
+        match address {
+            0xFF01 => self.data,
+            0xFF02 => self.control | 0b0111_1110,
+            _ => panic!("GET SERIAL: {:#06x}", address)
+        }
+    }
+
+    /// returns true when a transfer is finished (set serial interrupt flag)
+    pub fn cycle(&mut self) -> bool {
+        if !self.is_internal_clock() {
+            return self.cycle_external();
+        }
+        if self.bits == 0 {
+            return false;
+        }
+        self.clock += 1;
+        if self.clock < Self::M_CYCLES_PER_BIT {
+            return false;
+        }
+        self.clock = 0;
+        self.shift_bit()
+    }
+
+    fn cycle_external(&mut self) -> bool {
+        if !self.is_transfer_requested() {
+            return false;
+        }
+        match self.device.as_mut().and_then(|device| device.poll()) {
+            Some(byte) => {
+                self.data = byte;
+                self.control &= 0b0111_1111;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn start_transfer(&mut self) {
+        self.bits = 8;
+        self.clock = 0;
+        self.incoming = match self.device.as_mut() {
+            Some(device) => device.exchange(self.data),
+            None => 0xFF,
+        };
+    }
+
+    fn shift_bit(&mut self) -> bool {
+        self.data = (self.data << 1) | (self.incoming >> 7);
+        self.incoming <<= 1;
+        self.bits -= 1;
+        if self.bits == 0 {
+            self.control &= 0b0111_1111;
+            return true;
+        }
+        false
+    }
+
+    fn is_transfer_requested(&self) -> bool {
+        (self.control >> 7) & 1 == 1
+    }
+
+    fn is_internal_clock(&self) -> bool {
+        self.control & 1 == 1
+    }
+
+    pub fn connect(&mut self, device: Box<dyn SerialDevice>) {
+        self.device = Some(device);
+    }
+
+    pub fn disconnect(&mut self) -> Option<Box<dyn SerialDevice>> {
+        self.device.take()
+    }
+}
+
+/// The connected device is not part of the state
+impl SaveState for Serial {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.data);
+        state.write_u8(self.control);
+        state.write_u8(self.bits);
+        state.write_u8(self.clock);
+        state.write_u8(self.incoming);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.data = state.read_u8()?;
+        self.control = state.read_u8()?;
+        self.bits = state.read_u8()?;
+        self.clock = state.read_u8()?;
+        self.incoming = state.read_u8()?;
+        if self.bits > 8 { return Err(SaveStateError::InvalidValue("serial bits")); }
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use crate::serial::SerialDevice;
+
+/// Connects the link ports of two Game Boys.
+///
+/// The side using the internal clock (SC = $81) is the master: when it starts a transfer it takes the byte
+/// the slave (SC = $80) made ready and sends its own byte, the slave then finishes its transfer on its next m-cycle.
+/// A master transfer while the other side is not waiting for a clock receives $FF, like an unplugged cable.
+///
+/// `pair` links two `Jimbot` in the same process, `connect_tcp`/`listen_tcp` (and the unix socket variants)
+/// link two processes. Over a socket the slave state is sent ahead of time so the master never waits for the network.
+pub struct LinkCable {
+    /// what the other side sent
+    inbox: Arc<Mutex<Inbox>>,
+    transport: Transport,
+}
+
+#[derive(Default)]
+struct Inbox {
+    /// byte in the other side SB while it waits for an external clock
+    ready: Option<u8>,
+    /// byte clocked in by the other side, not polled yet
+    incoming: Option<u8>,
+}
+
+enum Transport {
+    /// inbox of the other `LinkCable` of the pair
+    Local(Arc<Mutex<Inbox>>),
+    Stream(Box<dyn Write + Send + Sync>),
+}
+
+/// Messages sent over a stream, a tag followed by a byte
+const MESSAGE_READY: u8 = 0x01;
+const MESSAGE_TRANSFER: u8 = 0x02;
+
+impl LinkCable {
+    /// Both ends of a cable for two `Jimbot` running in the same process
+    pub fn pair() -> (LinkCable, LinkCable) {
+        let a = Arc::new(Mutex::new(Inbox::default()));
+        let b = Arc::new(Mutex::new(Inbox::default()));
+        (
+            LinkCable { inbox: a.clone(), transport: Transport::Local(b.clone()) },
+            LinkCable { inbox: b, transport: Transport::Local(a) },
+        )
+    }
+
+    /// Cable over a byte stream, `reader` and `writer` are usually the two halves of a socket.
+    /// A thread reads the messages of the other side until the stream is closed.
+    pub fn from_stream<R, W>(mut reader: R, writer: W) -> LinkCable
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + Sync + 'static,
+    {
+        let inbox = Arc::new(Mutex::new(Inbox::default()));
+        let thread_inbox = inbox.clone();
+        std::thread::spawn(move || {
+            let mut message = [0; 2];
+            while reader.read_exact(&mut message).is_ok() {
+                let mut inbox = thread_inbox.lock().unwrap();
+                match message {
+                    [MESSAGE_READY, byte] => inbox.ready = Some(byte),
+                    [MESSAGE_TRANSFER, byte] => inbox.incoming = Some(byte),
+                    _ => println!("Unknown link cable message: {:?}", message),
+                }
+            }
+            println!("Link cable disconnected");
+        });
+        LinkCable { inbox, transport: Transport::Stream(Box::new(writer)) }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn connect_tcp(address: &str) -> std::io::Result<LinkCable> {
+        let stream = std::net::TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        Ok(Self::from_stream(stream.try_clone()?, stream))
+    }
+
+    /// Waits for the other side to connect
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn listen_tcp(address: &str) -> std::io::Result<LinkCable> {
+        let (stream, _) = std::net::TcpListener::bind(address)?.accept()?;
+        stream.set_nodelay(true)?;
+        Ok(Self::from_stream(stream.try_clone()?, stream))
+    }
+
+    #[cfg(unix)]
+    pub fn connect_unix(path: &str) -> std::io::Result<LinkCable> {
+        let stream = std::os::unix::net::UnixStream::connect(path)?;
+        Ok(Self::from_stream(stream.try_clone()?, stream))
+    }
+
+    /// Waits for the other side to connect
+    #[cfg(unix)]
+    pub fn listen_unix(path: &str) -> std::io::Result<LinkCable> {
+        let (stream, _) = std::os::unix::net::UnixListener::bind(path)?.accept()?;
+        Ok(Self::from_stream(stream.try_clone()?, stream))
+    }
+
+    /// sends `message` to the other side
+    fn send(&mut self, message: [u8; 2]) {
+        match &mut self.transport {
+            Transport::Local(other_inbox) => {
+                let mut other_inbox = other_inbox.lock().unwrap();
+                match message {
+                    [MESSAGE_READY, byte] => other_inbox.ready = Some(byte),
+                    [_, byte] => other_inbox.incoming = Some(byte),
+                }
+            }
+            Transport::Stream(writer) => {
+                if writer.write_all(&message).and_then(|_| writer.flush()).is_err() {
+                    println!("Cannot write to link cable");
+                }
+            }
+        }
+    }
+}
+
+impl SerialDevice for LinkCable {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let slave_byte = self.inbox.lock().unwrap().ready.take();
+        match slave_byte {
+            Some(slave_byte) => {
+                self.send([MESSAGE_TRANSFER, byte]);
+                slave_byte
+            }
+            None => 0xFF,
+        }
+    }
+
+    fn ready(&mut self, byte: u8) {
+        self.send([MESSAGE_READY, byte]);
+    }
+
+    fn poll(&mut self) -> Option<u8> {
+        self.inbox.lock().unwrap().incoming.take()
+    }
+}
@@ -0,0 +1,246 @@
+use std::sync::{Arc, Mutex};
+use crate::png::encode_png;
+use crate::serial::SerialDevice;
+
+/// Game Boy Printer plugged in the link port, clones share the same printer.
+///
+/// A packet is `$88 $33`, command, compression, length (u16 le), data, checksum (u16 le, sum of command to data),
+/// then two `$00` bytes answered by `$81` (printer connected) and the status.
+/// Printed bands are joined until a print command with a margin after, the image is then available in `take_images`.
+/// The exposure (darkness) is ignored.
+#[derive(Clone, Default)]
+pub struct GameBoyPrinter {
+    printer: Arc<Mutex<Printer>>,
+}
+
+impl GameBoyPrinter {
+    /// finished print jobs, oldest first
+    pub fn take_images(&self) -> Vec<PrintedImage> {
+        std::mem::take(&mut self.printer.lock().unwrap().images)
+    }
+}
+
+impl SerialDevice for GameBoyPrinter {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        self.printer.lock().unwrap().receive(byte)
+    }
+}
+
+/// A print job in shades, 0 is white and 3 is black
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrintedImage {
+    height: usize,
+    shades: Vec<u8>,
+}
+
+impl PrintedImage {
+    pub const WIDTH: usize = 160;
+
+    pub fn width(&self) -> usize {
+        Self::WIDTH
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    /// one byte per pixel, row major
+    pub fn shades(&self) -> &[u8] {
+        &self.shades
+    }
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.shades.iter()
+            .flat_map(|shade| {
+                let gray = 0xFF - shade * 0x55;
+                [gray, gray, gray, 0xFF]
+            })
+            .collect()
+    }
+    pub fn to_png(&self) -> Vec<u8> {
+        encode_png(Self::WIDTH as u32, self.height as u32, &self.to_rgba())
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+enum Stage {
+    #[default]
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    KeepAlive,
+    Status,
+}
+
+#[derive(Default)]
+struct Printer {
+    stage: Stage,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    sum: u16,
+    status: u8,
+    /// status inquiries answered busy after a print
+    busy: u8,
+    /// decompressed tile data waiting for a print command
+    tiles: Vec<u8>,
+    /// bands printed without margin after, waiting for the end of the job
+    job: Vec<u8>,
+    images: Vec<PrintedImage>,
+}
+
+impl Printer {
+    const COMMAND_INIT: u8 = 0x01;
+    const COMMAND_PRINT: u8 = 0x02;
+    const COMMAND_DATA: u8 = 0x04;
+    const COMMAND_STATUS: u8 = 0x0F;
+
+    const STATUS_CHECKSUM_ERROR: u8 = 0b0000_0001;
+    const STATUS_BUSY: u8 = 0b0000_0010;
+    const STATUS_FULL: u8 = 0b0000_0100;
+    const STATUS_UNPROCESSED: u8 = 0b0000_1000;
+
+    /// 9 packets of 640 bytes (2 rows of 20 tiles)
+    const MAX_TILES: usize = 640 * 9;
+    const BUSY_INQUIRIES: u8 = 4;
+
+    /// returns the byte shifted to the Game Boy
+    fn receive(&mut self, byte: u8) -> u8 {
+        let mut response = 0x00;
+        self.stage = match self.stage {
+            Stage::Magic1 => if byte == 0x88 { Stage::Magic2 } else { Stage::Magic1 },
+            Stage::Magic2 => if byte == 0x33 { Stage::Command } else { Stage::Magic1 },
+            Stage::Command => {
+                self.command = byte;
+                self.sum = byte as u16;
+                Stage::Compression
+            }
+            Stage::Compression => {
+                self.compressed = byte & 1 == 1;
+                self.sum = self.sum.wrapping_add(byte as u16);
+                Stage::LengthLow
+            }
+            Stage::LengthLow => {
+                self.length = byte as u16;
+                self.sum = self.sum.wrapping_add(byte as u16);
+                Stage::LengthHigh
+            }
+            Stage::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.sum = self.sum.wrapping_add(byte as u16);
+                self.data.clear();
+                if self.length == 0 { Stage::ChecksumLow } else { Stage::Data }
+            }
+            Stage::Data => {
+                self.data.push(byte);
+                self.sum = self.sum.wrapping_add(byte as u16);
+                if self.data.len() == self.length as usize { Stage::ChecksumLow } else { Stage::Data }
+            }
+            Stage::ChecksumLow => {
+                self.checksum = byte as u16;
+                Stage::ChecksumHigh
+            }
+            Stage::ChecksumHigh => {
+                self.checksum |= (byte as u16) << 8;
+                self.run_command();
+                Stage::KeepAlive
+            }
+            Stage::KeepAlive => {
+                response = 0x81;
+                Stage::Status
+            }
+            Stage::Status => {
+                response = self.status;
+                Stage::Magic1
+            }
+        };
+        response
+    }
+
+    fn run_command(&mut self) {
+        if self.checksum != self.sum {
+            self.status |= Self::STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !Self::STATUS_CHECKSUM_ERROR;
+        match self.command {
+            Self::COMMAND_INIT => {
+                self.tiles.clear();
+                self.busy = 0;
+                self.status = 0;
+            }
+            Self::COMMAND_DATA => {
+                let data = if self.compressed { decompress(&self.data) } else { self.data.clone() };
+                let free = Self::MAX_TILES - self.tiles.len();
+                self.tiles.extend_from_slice(&data[..data.len().min(free)]);
+                if !self.tiles.is_empty() { self.status |= Self::STATUS_UNPROCESSED; }
+                if self.tiles.len() == Self::MAX_TILES || self.length == 0 { self.status |= Self::STATUS_FULL; }
+            }
+            Self::COMMAND_PRINT => {
+                if let [_sheets, margins, palette, _exposure] = self.data[..] {
+                    self.print(margins, palette);
+                }
+                self.status = Self::STATUS_BUSY | Self::STATUS_FULL;
+                self.busy = Self::BUSY_INQUIRIES;
+            }
+            Self::COMMAND_STATUS => {
+                if self.busy > 0 {
+                    self.busy -= 1;
+                    if self.busy == 0 { self.status &= !(Self::STATUS_BUSY | Self::STATUS_FULL | Self::STATUS_UNPROCESSED); }
+                }
+            }
+            command => println!("Unknown printer command: {:#04X}", command),
+        }
+    }
+
+    /// decodes the tile rows with `palette`, margins are in the high (before) and low (after) nibbles
+    fn print(&mut self, margins: u8, palette: u8) {
+        // palette 0 is used by some games as the default one
+        let palette = if palette == 0 { 0xE4 } else { palette };
+        let tile_rows = self.tiles.len() / (20 * 16);
+        for tile_row in 0..tile_rows {
+            for y in 0..8 {
+                for x in 0..PrintedImage::WIDTH {
+                    let tile = tile_row * 20 + x / 8;
+                    let low = self.tiles[tile * 16 + y * 2];
+                    let high = self.tiles[tile * 16 + y * 2 + 1];
+                    let bit = 7 - (x % 8);
+                    let color = (((high >> bit) & 1) << 1) | ((low >> bit) & 1);
+                    self.job.push((palette >> (color * 2)) & 0b11);
+                }
+            }
+        }
+        self.tiles.clear();
+        if margins & 0x0F != 0 && !self.job.is_empty() {
+            let shades = std::mem::take(&mut self.job);
+            self.images.push(PrintedImage { height: shades.len() / PrintedImage::WIDTH, shades });
+        }
+    }
+}
+
+/// RLE used by the printer: a byte with bit 7 set repeats the next byte (n & $7F) + 2 times,
+/// otherwise the next n + 1 bytes are copied
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let n = data[i];
+        i += 1;
+        if n & 0x80 != 0 {
+            if let Some(byte) = data.get(i) {
+                res.extend(std::iter::repeat_n(*byte, (n & 0x7F) as usize + 2));
+            }
+            i += 1;
+        } else {
+            let end = (i + n as usize + 1).min(data.len());
+            res.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+    res
+}
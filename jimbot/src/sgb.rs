@@ -0,0 +1,408 @@
+//! Super Game Boy
+//!
+//! Games send commands to the SNES with packets written to the joypad register (see `packet`). The SGB colors
+//! the Game Boy screen with 4 palettes picked per 8x8 cell and draws a 256x224 border around it.
+//! Bigger data (system palettes, attribute files, border tiles and map) is drawn on the Game Boy screen and
+//! captured by the SNES at the next frame.
+
+pub mod packet;
+
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// SNES screen size
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 224;
+/// position of the Game Boy screen in the SNES screen
+pub const LCD_X: usize = 48;
+pub const LCD_Y: usize = 40;
+
+/// palette 1-A, used until the game sends its own
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+/// 8x8 cells of the Game Boy screen
+const CELLS_WIDTH: usize = 20;
+const CELLS_HEIGHT: usize = 18;
+/// 45 attribute files of 90 bytes, 4 cells per byte
+const ATTRIBUTE_FILE_SIZE: usize = 90;
+const ATTRIBUTE_FILES: usize = 45;
+/// PCT_TRN data, 32x32 map entries then 4 palettes of 16 colors
+const BORDER_MAP_SIZE: usize = 0x880;
+
+/// Game Boy screen output while a MASK_EN command is active
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mask {
+    None,
+    /// the last frame stays on screen
+    Freeze,
+    Black,
+    /// color 0 of the palettes
+    Color0,
+}
+
+impl Mask {
+    /// indexed by `mask as u8`, the MASK_EN parameter
+    const ALL: [Mask; 4] = [Mask::None, Mask::Freeze, Mask::Black, Mask::Color0];
+}
+
+/// Screen data the SNES captures at the next frame
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Transfer {
+    None,
+    /// PAL_TRN
+    SystemPalettes,
+    /// ATTR_TRN
+    AttributeFiles,
+    /// CHR_TRN, tiles $00-$7F
+    BorderTilesLow,
+    /// CHR_TRN, tiles $80-$FF
+    BorderTilesHigh,
+    /// PCT_TRN
+    BorderMap,
+}
+
+impl Transfer {
+    /// indexed by `transfer as u8`
+    const ALL: [Transfer; 6] = [
+        Transfer::None, Transfer::SystemPalettes, Transfer::AttributeFiles, Transfer::BorderTilesLow,
+        Transfer::BorderTilesHigh, Transfer::BorderMap,
+    ];
+}
+
+pub struct Sgb {
+    /// packets of the command being received
+    packets: Vec<[u8; 16]>,
+    /// RGB555 colors, color 0 is shared by the 4 palettes
+    palettes: [[u16; 4]; 4],
+    /// 512 palettes sent with PAL_TRN
+    system_palettes: Vec<u8>,
+    attribute_files: Vec<u8>,
+    /// palette of each 8x8 cell of the Game Boy screen, row by row
+    attributes: [u8; CELLS_WIDTH * CELLS_HEIGHT],
+    mask: Mask,
+    transfer: Transfer,
+    /// 256 SNES 4bpp tiles
+    border_tiles: Vec<u8>,
+    /// 32x32 map entries then the colors of the border palettes 4-7
+    border_map: Vec<u8>,
+    /// border colors, row by row, `None` where transparent. Rendered from the tiles and the map
+    border: Vec<Option<u16>>,
+    lcd: Box<[[u16; 144]; 160]>,
+    screen: Box<[[u16; SCREEN_HEIGHT]; SCREEN_WIDTH]>,
+}
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Self {
+            packets: vec![],
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: vec![0; 0x1000],
+            attribute_files: vec![0; ATTRIBUTE_FILES * ATTRIBUTE_FILE_SIZE],
+            attributes: [0; CELLS_WIDTH * CELLS_HEIGHT],
+            mask: Mask::None,
+            transfer: Transfer::None,
+            border_tiles: vec![0; 0x2000],
+            border_map: vec![0; BORDER_MAP_SIZE],
+            border: vec![None; SCREEN_WIDTH * SCREEN_HEIGHT],
+            lcd: vec![[DEFAULT_PALETTE[0]; 144]; 160].into_boxed_slice().try_into().unwrap(),
+            screen: vec![[DEFAULT_PALETTE[0]; SCREEN_HEIGHT]; SCREEN_WIDTH].into_boxed_slice().try_into().unwrap(),
+        }
+    }
+}
+
+impl Sgb {
+    /// colored Game Boy screen, RGB555 indexed by x then y
+    pub fn lcd(&self) -> &[[u16; 144]; 160] {
+        &self.lcd
+    }
+    /// border with the Game Boy screen at (`LCD_X`, `LCD_Y`), RGB555 indexed by x then y
+    pub fn screen(&self) -> &[[u16; SCREEN_HEIGHT]; SCREEN_WIDTH] {
+        &self.screen
+    }
+    pub fn palettes(&self) -> &[[u16; 4]; 4] {
+        &self.palettes
+    }
+    /// palette (0-3) of the 8x8 cell at (`x`, `y`)
+    pub fn attribute(&self, x: usize, y: usize) -> u8 {
+        self.attributes[y * CELLS_WIDTH + x]
+    }
+    pub fn mask(&self) -> Mask {
+        self.mask
+    }
+
+    /// Adds a packet to the command being received, the first packet gives the number of packets (1-7).
+    /// Returns the number of joypads (1, 2 or 4) when the command is MLT_REQ
+    pub fn receive(&mut self, packet: [u8; 16]) -> Option<u8> {
+        if self.packets.is_empty() && packet[0] & 7 == 0 {
+            return None;
+        }
+        self.packets.push(packet);
+        if self.packets.len() < (self.packets[0][0] & 7) as usize {
+            return None;
+        }
+        let data = self.packets.concat();
+        self.packets.clear();
+        self.command(&data)
+    }
+
+    fn command(&mut self, data: &[u8]) -> Option<u8> {
+        match data[0] >> 3 {
+            0x00 => self.set_palettes(0, 1, data),
+            0x01 => self.set_palettes(2, 3, data),
+            0x02 => self.set_palettes(0, 3, data),
+            0x03 => self.set_palettes(1, 2, data),
+            0x04 => self.attribute_blocks(data),
+            0x05 => self.attribute_lines(data),
+            0x06 => self.attribute_division(data),
+            0x07 => self.attribute_cells(data),
+            0x0A => {
+                for i in 0..4 {
+                    let palette = u16::from_le_bytes([data[1 + i * 2], data[2 + i * 2]]) as usize & 0x1FF;
+                    let colors = &self.system_palettes[palette * 8..palette * 8 + 8];
+                    for (color, bytes) in self.palettes[i].iter_mut().zip(colors.chunks(2)) {
+                        *color = u16::from_le_bytes([bytes[0], bytes[1]]) & 0x7FFF;
+                    }
+                }
+                let color0 = self.palettes[0][0];
+                self.palettes.iter_mut().for_each(|palette| palette[0] = color0);
+                if data[9] & 0x80 != 0 {
+                    self.set_attribute_file(data[9]);
+                } else if data[9] & 0x40 != 0 {
+                    self.mask = Mask::None;
+                }
+            }
+            0x0B => self.transfer = Transfer::SystemPalettes,
+            0x11 => return Some([1, 2, 1, 4][data[1] as usize & 3]),
+            0x13 => self.transfer = if data[1] & 1 == 0 { Transfer::BorderTilesLow } else { Transfer::BorderTilesHigh },
+            0x14 => self.transfer = Transfer::BorderMap,
+            0x15 => self.transfer = Transfer::AttributeFiles,
+            0x16 => self.set_attribute_file(data[1]),
+            0x17 => self.mask = Mask::ALL[data[1] as usize & 3],
+            // sound, SNES code and data, icons
+            _ => {}
+        }
+        None
+    }
+
+    /// PAL01, PAL23, PAL03, PAL12: the shared color 0 then colors 1-3 of both palettes
+    fn set_palettes(&mut self, first: usize, second: usize, data: &[u8]) {
+        let color = |i: usize| u16::from_le_bytes([data[1 + i * 2], data[2 + i * 2]]) & 0x7FFF;
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color(0);
+        }
+        for shade in 1..4 {
+            self.palettes[first][shade] = color(shade);
+            self.palettes[second][shade] = color(shade + 3);
+        }
+    }
+
+    /// ATTR_SET and the file of PAL_SET: bits 0-5 file number, bit 6 cancels the mask
+    fn set_attribute_file(&mut self, val: u8) {
+        let file = (val & 0x3F) as usize;
+        if file < ATTRIBUTE_FILES {
+            let file = &self.attribute_files[file * ATTRIBUTE_FILE_SIZE..(file + 1) * ATTRIBUTE_FILE_SIZE];
+            for (i, attribute) in self.attributes.iter_mut().enumerate() {
+                *attribute = (file[i / 4] >> (6 - 2 * (i % 4))) & 3;
+            }
+        }
+        if val & 0x40 != 0 {
+            self.mask = Mask::None;
+        }
+    }
+
+    /// ATTR_BLK: rectangles with a palette for the inside, the border line and the outside.
+    /// A line without its own palette takes the one of the inside or the outside when only one of them is set
+    fn attribute_blocks(&mut self, data: &[u8]) {
+        let count = (data[1] & 0x1F) as usize;
+        for block in data[2..].chunks_exact(6).take(count) {
+            let control = block[0] & 7;
+            let [inside, line, outside] = [block[1] & 3, (block[1] >> 2) & 3, (block[1] >> 4) & 3];
+            let line = match control {
+                _ if control & 2 != 0 => Some(line),
+                1 => Some(inside),
+                4 => Some(outside),
+                _ => None,
+            };
+            let [x1, y1, x2, y2] = [block[2], block[3], block[4], block[5]].map(|val| (val & 0x1F) as usize);
+            for y in 0..CELLS_HEIGHT {
+                for x in 0..CELLS_WIDTH {
+                    let palette = if !(x1..=x2).contains(&x) || !(y1..=y2).contains(&y) {
+                        (control & 4 != 0).then_some(outside)
+                    } else if x == x1 || x == x2 || y == y1 || y == y2 {
+                        line
+                    } else {
+                        (control & 1 != 0).then_some(inside)
+                    };
+                    if let Some(palette) = palette {
+                        self.attributes[y * CELLS_WIDTH + x] = palette;
+                    }
+                }
+            }
+        }
+    }
+
+    /// ATTR_LIN: whole rows (bit 7 set) or columns (bits 0-4) in a palette (bits 5-6)
+    fn attribute_lines(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+        for line in data[2..].iter().take(count) {
+            let (position, palette) = ((line & 0x1F) as usize, (line >> 5) & 3);
+            for y in 0..CELLS_HEIGHT {
+                for x in 0..CELLS_WIDTH {
+                    if (line & 0x80 != 0 && y == position) || (line & 0x80 == 0 && x == position) {
+                        self.attributes[y * CELLS_WIDTH + x] = palette;
+                    }
+                }
+            }
+        }
+    }
+
+    /// ATTR_DIV: the screen split by a row (bit 6 set) or a column, with palettes for both sides and the line
+    fn attribute_division(&mut self, data: &[u8]) {
+        let [after, before, on] = [data[1] & 3, (data[1] >> 2) & 3, (data[1] >> 4) & 3];
+        let position = (data[2] & 0x1F) as usize;
+        for y in 0..CELLS_HEIGHT {
+            for x in 0..CELLS_WIDTH {
+                let coordinate = if data[1] & 0x40 != 0 { y } else { x };
+                self.attributes[y * CELLS_WIDTH + x] = match coordinate.cmp(&position) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on,
+                    std::cmp::Ordering::Greater => after,
+                };
+            }
+        }
+    }
+
+    /// ATTR_CHR: palettes of consecutive cells from (x, y), left to right or top to bottom, 4 per byte
+    fn attribute_cells(&mut self, data: &[u8]) {
+        let (mut x, mut y) = ((data[1] & 0x1F) as usize, (data[2] & 0x1F) as usize);
+        let count = u16::from_le_bytes([data[3], data[4]]) as usize;
+        let vertical = data[5] & 1 != 0;
+        for i in 0..count {
+            let Some(byte) = data.get(6 + i / 4) else { break };
+            if x >= CELLS_WIDTH || y >= CELLS_HEIGHT {
+                break;
+            }
+            self.attributes[y * CELLS_WIDTH + x] = (byte >> (6 - 2 * (i % 4))) & 3;
+            if vertical {
+                y += 1;
+                if y == CELLS_HEIGHT { y = 0; x += 1; }
+            } else {
+                x += 1;
+                if x == CELLS_WIDTH { x = 0; y += 1; }
+            }
+        }
+    }
+
+    /// Captures the screen data of a pending transfer: 256 tiles taken in the order of the bg map,
+    /// 20 per row. `vram` is vram bank 0
+    pub(crate) fn vblank(&mut self, vram: &[u8], lcdc: u8) {
+        if self.transfer == Transfer::None {
+            return;
+        }
+        let map = if lcdc & 0x08 != 0 { 0x1C00 } else { 0x1800 };
+        let mut data = Vec::with_capacity(0x1000);
+        for i in 0..256 {
+            let tile = vram[map + (i / CELLS_WIDTH) * 32 + i % CELLS_WIDTH];
+            let address = if lcdc & 0x10 != 0 { tile as usize * 16 } else { (0x1000 + tile as i8 as isize * 16) as usize };
+            data.extend_from_slice(&vram[address..address + 16]);
+        }
+        match self.transfer {
+            Transfer::SystemPalettes => self.system_palettes.copy_from_slice(&data),
+            Transfer::AttributeFiles => self.attribute_files.copy_from_slice(&data[..ATTRIBUTE_FILES * ATTRIBUTE_FILE_SIZE]),
+            Transfer::BorderTilesLow => self.border_tiles[..0x1000].copy_from_slice(&data),
+            Transfer::BorderTilesHigh => self.border_tiles[0x1000..].copy_from_slice(&data),
+            Transfer::BorderMap => self.border_map.copy_from_slice(&data[..BORDER_MAP_SIZE]),
+            Transfer::None => {}
+        }
+        if matches!(self.transfer, Transfer::BorderTilesLow | Transfer::BorderTilesHigh | Transfer::BorderMap) {
+            self.render_border();
+        }
+        self.transfer = Transfer::None;
+    }
+
+    /// 32x28 tiles of the map, entries are the tile (bits 0-7), the palette (bits 10-12) and the x and y flips
+    /// (bits 14 and 15). Color 0 is transparent
+    fn render_border(&mut self) {
+        for ty in 0..SCREEN_HEIGHT / 8 {
+            for tx in 0..SCREEN_WIDTH / 8 {
+                let entry = (ty * 32 + tx) * 2;
+                let entry = u16::from_le_bytes([self.border_map[entry], self.border_map[entry + 1]]);
+                let tile = &self.border_tiles[(entry & 0xFF) as usize * 32..][..32];
+                let palette = 0x800 + ((entry >> 10) & 3) as usize * 32;
+                for py in 0..8 {
+                    let row = if entry & 0x8000 != 0 { 7 - py } else { py };
+                    let planes = [tile[row * 2], tile[row * 2 + 1], tile[16 + row * 2], tile[17 + row * 2]];
+                    for px in 0..8 {
+                        let bit = if entry & 0x4000 != 0 { px } else { 7 - px };
+                        let color = planes.iter().enumerate()
+                            .fold(0, |color, (plane, bits)| color | (((bits >> bit) & 1) as usize) << plane);
+                        let address = palette + color * 2;
+                        self.border[(ty * 8 + py) * SCREEN_WIDTH + tx * 8 + px] = (color != 0)
+                            .then(|| u16::from_le_bytes([self.border_map[address], self.border_map[address + 1]]) & 0x7FFF);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Colors the shades (0-3) of the Game Boy `lcd` and draws it inside the border, where the border is
+    /// transparent. The backdrop is color 0
+    pub(crate) fn compose(&mut self, lcd: &[[u16; 144]; 160]) {
+        for (x, column) in self.lcd.iter_mut().enumerate() {
+            for (y, pixel) in column.iter_mut().enumerate() {
+                *pixel = match self.mask {
+                    Mask::None => self.palettes[self.attributes[(y / 8) * CELLS_WIDTH + x / 8] as usize][(lcd[x][y] & 3) as usize],
+                    Mask::Freeze => *pixel,
+                    Mask::Black => 0x0000,
+                    Mask::Color0 => self.palettes[0][0],
+                };
+            }
+        }
+        for (x, column) in self.screen.iter_mut().enumerate() {
+            for (y, pixel) in column.iter_mut().enumerate() {
+                let lcd_pixel = x.checked_sub(LCD_X).zip(y.checked_sub(LCD_Y))
+                    .and_then(|(x, y)| self.lcd.get(x).and_then(|column| column.get(y)));
+                *pixel = self.border[y * SCREEN_WIDTH + x].or(lcd_pixel.copied()).unwrap_or(self.palettes[0][0]);
+            }
+        }
+    }
+}
+
+impl SaveState for Sgb {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_tag(b"SGB ");
+        state.write_vec(&self.packets.concat());
+        for color in self.palettes.as_flattened() {
+            state.write_u16(*color);
+        }
+        state.write_bytes(&self.system_palettes);
+        state.write_bytes(&self.attribute_files);
+        state.write_bytes(&self.attributes);
+        state.write_u8(self.mask as u8);
+        state.write_u8(self.transfer as u8);
+        state.write_bytes(&self.border_tiles);
+        state.write_bytes(&self.border_map);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        state.read_tag(b"SGB ")?;
+        let packets = state.read_vec()?;
+        if packets.len() % 16 != 0 || packets.len() >= 16 * 7 {
+            return Err(SaveStateError::InvalidValue("sgb packets"));
+        }
+        self.packets = packets.chunks(16).map(|packet| packet.try_into().unwrap()).collect();
+        for color in self.palettes.as_flattened_mut() {
+            *color = state.read_u16()?;
+        }
+        state.read_bytes(&mut self.system_palettes)?;
+        state.read_bytes(&mut self.attribute_files)?;
+        state.read_bytes(&mut self.attributes)?;
+        self.mask = state.read_enum(&Mask::ALL, "sgb mask")?;
+        self.transfer = state.read_enum(&Transfer::ALL, "sgb transfer")?;
+        state.read_bytes(&mut self.border_tiles)?;
+        state.read_bytes(&mut self.border_map)?;
+        if self.attributes.iter().any(|palette| *palette > 3) {
+            return Err(SaveStateError::InvalidValue("sgb attributes"));
+        }
+        self.render_border();
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Command packets sent through the joypad register
+///
+/// A packet is 16 bytes sent least significant bit first. Writing $00 to $FF00 (P14 and P15 low) starts it,
+/// then every bit is a pulse, P14 low for a 0 or P15 low for a 1, followed by $30. A 0 stop bit ends it.
+#[derive(Default)]
+pub struct PacketReceiver {
+    packet: [u8; 16],
+    /// bits received, the stop bit comes after the 128th
+    bit: u8,
+    receiving: bool,
+    /// P14 and P15 went back high since the last pulse
+    ready: bool,
+}
+
+impl PacketReceiver {
+    /// `p1` is the value written to $FF00, returns the packet once its stop bit is received
+    pub fn write(&mut self, p1: u8) -> Option<[u8; 16]> {
+        match p1 & 0x30 {
+            0x00 => *self = Self { receiving: true, ..Self::default() },
+            0x30 => self.ready = true,
+            pulse if self.receiving && self.ready => {
+                self.ready = false;
+                let one = pulse == 0x10;
+                if self.bit == 128 {
+                    self.receiving = false;
+                    return (!one).then_some(self.packet);
+                }
+                self.packet[self.bit as usize / 8] |= (one as u8) << (self.bit % 8);
+                self.bit += 1;
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+impl SaveState for PacketReceiver {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.packet);
+        state.write_u8(self.bit);
+        state.write_bool(self.receiving);
+        state.write_bool(self.ready);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        state.read_bytes(&mut self.packet)?;
+        self.bit = state.read_u8()?;
+        self.receiving = state.read_bool()?;
+        self.ready = state.read_bool()?;
+        if self.bit > 128 {
+            return Err(SaveStateError::InvalidValue("sgb packet bit"));
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use crate::apu::APU;
 use crate::mmu::tac::TAC;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct Timer {
     div: u16,
@@ -22,8 +23,9 @@ impl Default for Timer {
 }
 
 impl Timer {
-    /// returns true if TIMA exceeds $FF (set timer interrupt flag)
-    pub fn cycle(&mut self, apu: &mut APU) -> bool {
+    /// returns true if TIMA exceeds $FF (set timer interrupt flag).
+    /// In double speed the apu is clocked by the next div bit so it keeps its rate
+    pub fn cycle(&mut self, apu: &mut APU, double_speed: bool) -> bool {
         let prev_div = self.div;
         self.div = self.div.wrapping_add(1);
 
@@ -42,8 +44,9 @@ impl Timer {
             }
         }
 
-        let prev_div_sound_clock_bit = (prev_div >> 13) & 1;
-        let curr_div_sound_clock_bit = (self.div >> 13) & 1;
+        let sound_clock_bit = if double_speed { 14 } else { 13 };
+        let prev_div_sound_clock_bit = (prev_div >> sound_clock_bit) & 1;
+        let curr_div_sound_clock_bit = (self.div >> sound_clock_bit) & 1;
         if prev_div_sound_clock_bit == 1 && curr_div_sound_clock_bit == 0 {
             apu.clock(self.apu_clock_step);
             self.apu_clock_step = (self.apu_clock_step + 1) % 8;
@@ -61,6 +64,10 @@ impl Timer {
         }
     }
 
+    pub(crate) fn set_div_counter(&mut self, div: u16) {
+        self.div = div;
+    }
+
     pub fn get(&self, address: usize) -> u8 {
         match address {
             0xFF04 => (self.div >> 8) as u8,
@@ -71,3 +78,22 @@ impl Timer {
         }
     }
 }
+
+impl SaveState for Timer {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.div);
+        state.write_u8(self.tima);
+        state.write_u8(self.tma);
+        state.write_u8(self.tac);
+        state.write_u8(self.apu_clock_step);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.div = state.read_u16()?;
+        self.tima = state.read_u8()?;
+        self.tma = state.read_u8()?;
+        self.tac = state.read_u8()?;
+        self.apu_clock_step = state.read_u8()? % 8;
+        Ok(())
+    }
+}
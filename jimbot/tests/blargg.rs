@@ -0,0 +1,51 @@
+//! Blargg's test roms print their results on the serial port.
+//! Ignored without the test roms, run with `JIMBOT_TEST_ROMS=<dir> cargo test --release --test blargg -- --ignored`.
+
+use jimbot::jimbot::Jimbot;
+use jimbot::serial::SerialCapture;
+
+mod common;
+
+/// Runs `path` until it prints "Passed" or "Failed", returns the printed text
+fn run_blargg(path: &str, max_frames: u64) -> String {
+    let mut jimbot = common::load_test_rom(path);
+    let capture = SerialCapture::default();
+    jimbot.connect_serial(Box::new(capture.clone()));
+    while jimbot.frame_count() < max_frames {
+        jimbot.run_frame();
+        if let Some(error) = jimbot.error_message() {
+            panic!("{}: {}\n{}", path, error, capture.text());
+        }
+        let text = capture.text();
+        if text.contains("Passed") || text.contains("Failed") {
+            // let the rom finish its line
+            jimbot.run_cycles(Jimbot::M_CYCLES_PER_FRAME as u64 * 10);
+            return capture.text();
+        }
+    }
+    panic!("{}: timeout after {} frames\n{}", path, max_frames, capture.text());
+}
+
+fn assert_passed(path: &str, max_frames: u64) {
+    let text = run_blargg(path, max_frames);
+    println!("{}:\n{}", path, text);
+    assert!(text.contains("Passed") && !text.contains("Failed"), "{}:\n{}", path, text);
+}
+
+#[test]
+#[ignore = "needs the test roms"]
+fn cpu_instrs() {
+    assert_passed("blargg/cpu_instrs/cpu_instrs.gb", 60 * 120);
+}
+
+#[test]
+#[ignore = "needs the test roms"]
+fn instr_timing() {
+    assert_passed("blargg/instr_timing/instr_timing.gb", 60 * 10);
+}
+
+#[test]
+#[ignore = "needs the test roms"]
+fn mem_timing() {
+    assert_passed("blargg/mem_timing/mem_timing.gb", 60 * 10);
+}
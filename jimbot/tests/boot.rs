@@ -0,0 +1,119 @@
+//! Registers left by `BootMode::Skip` and user boot roms run with `BootMode::Custom`
+
+use jimbot::boot::BootMode;
+use jimbot::cartridge::new_cartridge_from_bytes;
+use jimbot::cpu::registers::R16;
+use jimbot::jimbot::Jimbot;
+
+/// rom only cartridge jumping to an endless loop, `patch` sets the header flags before the checksum
+fn jimbot(patch: impl FnOnce(&mut [u8]), boot_mode: BootMode) -> Jimbot {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0000] = 0x42;
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x134..0x138].copy_from_slice(b"BOOT");
+    rom[0x150..0x152].copy_from_slice(&[0x18, 0xFE]);
+    rom[0x200] = 0x24;
+    patch(&mut rom);
+    rom[0x14D] = rom[0x134..0x14D].iter().fold(0u8, |checksum, byte| checksum.wrapping_sub(*byte).wrapping_sub(1));
+    Jimbot::new(new_cartridge_from_bytes(rom).unwrap(), boot_mode)
+}
+
+/// AF, BC, DE, HL, SP and PC
+fn registers(jimbot: &Jimbot) -> [u16; 6] {
+    [R16::AF, R16::BC, R16::DE, R16::HL, R16::SP, R16::PC].map(|r16| jimbot.cpu().registers().get16(r16))
+}
+
+/// io registers after the dmg boot rom, as read by the cpu.
+/// P1 ($CF) is left out, the joypad only models one key group selected at a time
+const DMG_IO: [(u16, u8); 32] = [
+    (0xFF01, 0x00), (0xFF02, 0x7E), (0xFF04, 0xAB), (0xFF05, 0x00), (0xFF06, 0x00), (0xFF07, 0xF8),
+    (0xFF0F, 0xE1), (0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF14, 0xBF), (0xFF16, 0x3F), (0xFF17, 0x00),
+    (0xFF19, 0xBF), (0xFF1A, 0x7F), (0xFF1C, 0x9F), (0xFF1E, 0xBF), (0xFF21, 0x00), (0xFF22, 0x00), (0xFF23, 0xBF),
+    (0xFF24, 0x77), (0xFF25, 0xF3), (0xFF26, 0xF1), (0xFF40, 0x91), (0xFF42, 0x00), (0xFF43, 0x00), (0xFF45, 0x00),
+    (0xFF47, 0xFC), (0xFF4A, 0x00), (0xFF4B, 0x00), (0xFF50, 0xFF), (0xFFFF, 0x00),
+];
+
+#[test]
+fn dmg_post_boot() {
+    let jimbot = jimbot(|_| {}, BootMode::Skip);
+    assert_eq!(registers(&jimbot), [0x01B0, 0x0013, 0x00D8, 0x014D, 0xFFFE, 0x0100]);
+    for (address, val) in DMG_IO {
+        assert_eq!(jimbot.mmu().get(address), val, "{:#06X}", address);
+    }
+    // the cartridge is mapped
+    assert!(jimbot.mmu().boot_rom().is_empty());
+    assert_eq!(jimbot.mmu().get(0x0000), 0x42);
+}
+
+#[test]
+fn div_phase() {
+    let mut jimbot = jimbot(|_| {}, BootMode::Skip);
+    // DIV increments every 64 m-cycles, the boot rom leaves it 13 m-cycles before the next increment
+    for _ in 0..12 {
+        jimbot.run();
+    }
+    assert_eq!(jimbot.mmu().get(0xFF04), 0xAB);
+    jimbot.run();
+    assert_eq!(jimbot.mmu().get(0xFF04), 0xAC);
+}
+
+#[test]
+fn cgb_post_boot() {
+    let jimbot = jimbot(|rom| rom[0x143] = 0x80, BootMode::Skip);
+    assert_eq!(registers(&jimbot), [0x1180, 0x0000, 0xFF56, 0x000D, 0xFFFE, 0x0100]);
+    assert_eq!(jimbot.mmu().get(0xFF40), 0x91);
+    assert_eq!(jimbot.mmu().get(0xFF04), 0xAB);
+}
+
+#[test]
+fn sgb_post_boot() {
+    let jimbot = jimbot(|rom| {
+        rom[0x146] = 0x03;
+        rom[0x14B] = 0x33;
+    }, BootMode::Skip);
+    assert_eq!(registers(&jimbot), [0x0100, 0x0014, 0x0000, 0xC060, 0xFFFE, 0x0100]);
+    assert_eq!(jimbot.mmu().get(0xFF40), 0x91);
+}
+
+/// LD A,$01 / LDH ($50),A then the cartridge from $0004
+fn boot_rom(size: usize) -> Vec<u8> {
+    let mut boot_rom = vec![0x00; size];
+    boot_rom[..4].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+    boot_rom
+}
+
+#[test]
+fn custom_boot_rom() {
+    let mut jimbot = jimbot(|_| {}, BootMode::Custom(boot_rom(0x100)));
+    assert_eq!(registers(&jimbot)[5], 0x0000);
+    assert_eq!(jimbot.mmu().get(0x0000), 0x3E);
+    assert_eq!(jimbot.mmu().get(0x0100), 0x00);
+    // LD A,$01 (2 m-cycles) and LDH ($50),A (3 m-cycles) unmap the boot rom
+    for _ in 0..4 {
+        jimbot.run();
+    }
+    assert_eq!(jimbot.mmu().get(0x0000), 0x3E);
+    jimbot.run();
+    assert_eq!(jimbot.mmu().get(0x0000), 0x42);
+    // the cartridge runs on from $0004 to the loop at $0150
+    for _ in 0..1000 {
+        jimbot.run();
+    }
+    assert_eq!(registers(&jimbot)[0] >> 8, 0x01);
+    assert!((0x0150..=0x0152).contains(&registers(&jimbot)[5]), "{:#06X}", registers(&jimbot)[5]);
+}
+
+#[test]
+fn wrong_size_custom_boot_rom() {
+    // padded with $FF
+    let jimbot = jimbot(|_| {}, BootMode::Custom(boot_rom(0x50)));
+    assert_eq!(jimbot.mmu().boot_rom().len(), 0x100);
+    assert_eq!(jimbot.mmu().get(0x0004), 0x00);
+    assert_eq!(jimbot.mmu().get(0x0050), 0xFF);
+
+    // cut, the cartridge stays visible after $0100
+    let jimbot = self::jimbot(|_| {}, BootMode::Custom(boot_rom(0x200)));
+    assert_eq!(jimbot.mmu().boot_rom().len(), 0x100);
+    assert_eq!(jimbot.mmu().get(0x0200), 0x24);
+    assert_eq!(jimbot.mmu().get(0xFF40) & 0x80, 0x00);
+}
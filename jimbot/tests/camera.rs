@@ -0,0 +1,116 @@
+//! Captures images with the Game Boy Camera mapper as the camera rom would, without a camera rom
+
+use jimbot::cartridge::image_source::{CallbackImageSource, ImageError, ImageSource, StaticImage, TestPattern, HEIGHT, WIDTH};
+use jimbot::cartridge::{new_cartridge_from_bytes, Cartridge};
+
+/// 1 MiB Pocket Camera rom with 128 KiB of ram and an empty program
+fn camera_cartridge() -> Box<dyn Cartridge> {
+    let mut rom = vec![0; 1024 * 1024];
+    rom[0x147] = 0xFC;
+    rom[0x148] = 0x05;
+    rom[0x149] = 0x04;
+    new_cartridge_from_bytes(rom).unwrap()
+}
+
+/// takes a picture with the given exposure and thresholds 64/128/192, returns the 2bpp tiles
+fn capture(cartridge: &mut Box<dyn Cartridge>, exposure: u16, edge_mode: u8) -> Vec<u8> {
+    cartridge.set(0x4000, 0x10);
+    cartridge.set(0xA001, 0x80 | edge_mode << 5);
+    cartridge.set(0xA002, (exposure >> 8) as u8);
+    cartridge.set(0xA003, exposure as u8);
+    cartridge.set(0xA004, 0x20);
+    for i in 0..16 {
+        cartridge.set(0xA006 + i * 3, 64);
+        cartridge.set(0xA007 + i * 3, 128);
+        cartridge.set(0xA008 + i * 3, 192);
+    }
+    cartridge.set(0xA000, 0x01);
+    assert_eq!(cartridge.get(0xA000), 0x01);
+    for _ in 1..32446 + 16 * exposure as u32 {
+        cartridge.cycle();
+    }
+    assert_eq!(cartridge.get(0xA000), 0x01, "capture ended early");
+    cartridge.cycle();
+    assert_eq!(cartridge.get(0xA000), 0x00);
+    cartridge.set(0x4000, 0x00);
+    (0..WIDTH * HEIGHT / 4).map(|i| cartridge.get(0xA100 + i)).collect()
+}
+
+/// color (3 is black) of a pixel in the tiles
+fn color(tiles: &[u8], x: usize, y: usize) -> u8 {
+    let offset = ((y / 8) * (WIDTH / 8) + x / 8) * 16 + (y % 8) * 2;
+    let bit = 7 - x % 8;
+    (tiles[offset] >> bit & 1) | (tiles[offset + 1] >> bit & 1) << 1
+}
+
+#[test]
+fn capture_callback_image() {
+    let mut cartridge = camera_cartridge();
+    // 4 vertical bars, black to white
+    cartridge.set_image_source(Box::new(CallbackImageSource::new(|pixels: &mut [u8]| {
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            *pixel = [0, 100, 150, 255][i % WIDTH / 32];
+        }
+    })));
+    let tiles = capture(&mut cartridge, 0x1000, 0);
+    assert_eq!([0, 32, 64, 96].map(|x| color(&tiles, x, 50)), [3, 2, 1, 0]);
+    // half the exposure darkens the image
+    let tiles = capture(&mut cartridge, 0x0800, 0);
+    assert_eq!([0, 32, 64, 96].map(|x| color(&tiles, x, 50)), [3, 3, 2, 2]);
+    // horizontal edge enhancement brightens the edge on the bright side
+    let tiles = capture(&mut cartridge, 0x1000, 1);
+    assert_eq!([31, 32, 33].map(|x| color(&tiles, x, 50)), [3, 0, 2]);
+}
+
+#[test]
+fn capture_is_saved_in_ram() {
+    let mut cartridge = camera_cartridge();
+    let tiles = capture(&mut cartridge, 0x1000, 0);
+    assert_eq!(&cartridge.save_data().unwrap()[0x100..0x100 + tiles.len()], tiles.as_slice());
+    // ram writes need the enable register
+    cartridge.set(0xA000, 0x12);
+    assert_eq!(cartridge.get(0xA000), 0x00);
+    cartridge.set(0x0000, 0x0A);
+    cartridge.set(0xA000, 0x12);
+    assert_eq!(cartridge.get(0xA000), 0x12);
+}
+
+#[test]
+fn static_pgm_image() {
+    let mut image = StaticImage::from_pgm(b"P2\n# 2x2\n2 2\n15\n0 15\n15 0\n").unwrap();
+    let mut pixels = vec![0; WIDTH * HEIGHT];
+    image.capture(&mut pixels);
+    assert_eq!([pixels[0], pixels[WIDTH - 1], pixels[(HEIGHT - 1) * WIDTH]], [0, 255, 255]);
+    let mut binary = b"P5 2 1 255\n".to_vec();
+    binary.extend_from_slice(&[10, 20]);
+    StaticImage::from_bytes(&binary).unwrap().capture(&mut pixels);
+    assert_eq!([pixels[0], pixels[WIDTH - 1]], [10, 20]);
+    assert!(StaticImage::from_bytes(b"P5 2 2 255\n\x00").is_err());
+}
+
+#[test]
+fn oversized_images_are_rejected() {
+    // the pixel count overflows
+    let error = StaticImage::from_bytes(b"P5 4294967296 4294967297 255\n\x00").err().unwrap();
+    assert!(matches!(error, ImageError::Format(_)), "{}", error);
+    assert!(matches!(StaticImage::from_bytes(b"P2 100000 1 255\n0"), Err(ImageError::Format(_))));
+
+    // 16 bits RGBA header of 2^32 - 1 pixels square, no image data
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend_from_slice(&13u32.to_be_bytes());
+    png.extend_from_slice(b"IHDR");
+    png.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 16, 6, 0, 0, 0]);
+    png.extend_from_slice(&[0; 4]);
+    let error = StaticImage::from_bytes(&png).err().unwrap();
+    assert!(matches!(error, ImageError::Format(_)), "{}", error);
+}
+
+#[test]
+fn test_pattern_scrolls() {
+    let mut pattern = TestPattern::default();
+    let (mut first, mut second) = (vec![0; WIDTH * HEIGHT], vec![0; WIDTH * HEIGHT]);
+    pattern.capture(&mut first);
+    pattern.capture(&mut second);
+    assert_eq!([first[0], first[WIDTH - 1]], [0, 255]);
+    assert_eq!(&first[WIDTH * HEIGHT / 2 + 1..WIDTH * (HEIGHT / 2 + 1)], &second[WIDTH * HEIGHT / 2..WIDTH * (HEIGHT / 2 + 1) - 1]);
+}
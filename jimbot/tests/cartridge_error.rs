@@ -0,0 +1,58 @@
+//! Bad roms and saves are reported as `CartridgeError` instead of panicking
+
+use jimbot::cartridge::{new_cartridge_from_bytes, new_cartridge_with_storage};
+use jimbot::cartridge::cartridge_error::CartridgeError;
+use jimbot::cartridge::save_storage::{FileStorage, MemoryStorage};
+
+/// 32 KiB MBC1+RAM+BATTERY with 8 KiB of ram
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x147] = 0x03;
+    rom[0x149] = 0x02;
+    rom
+}
+
+fn error(rom: Vec<u8>) -> CartridgeError {
+    new_cartridge_from_bytes(rom).err().expect("the rom is loaded")
+}
+
+#[test]
+fn truncated_rom() {
+    assert!(matches!(error(vec![]), CartridgeError::TruncatedRom { size: 0, expected: 0x150 }));
+    assert!(matches!(error(rom()[..0x14F].to_vec()), CartridgeError::TruncatedRom { size: 0x14F, expected: 0x150 }));
+    // 64 KiB declared
+    let mut rom = rom();
+    rom[0x148] = 0x01;
+    assert!(matches!(error(rom), CartridgeError::TruncatedRom { size: 0x8000, expected: 0x10000 }));
+}
+
+#[test]
+fn unknown_header_codes() {
+    let mut rom = rom();
+    rom[0x147] = 0x04;
+    assert!(matches!(error(rom), CartridgeError::UnknownType(0x04)));
+    let mut rom = self::rom();
+    rom[0x148] = 0x09;
+    assert!(matches!(error(rom), CartridgeError::UnknownRomSize(0x09)));
+    let mut rom = self::rom();
+    rom[0x149] = 0x06;
+    let error = error(rom);
+    assert!(matches!(error, CartridgeError::UnknownRamSize(0x06)));
+    assert_eq!(error.to_string(), "Unknown ram size code: 0x06");
+}
+
+#[test]
+fn bad_save_size() {
+    let storage = MemoryStorage::new(Some(vec![0; 0x2001]));
+    let error = new_cartridge_with_storage(rom(), Box::new(storage)).err().unwrap();
+    assert!(matches!(error, CartridgeError::BadSaveSize { size: 0x2001, expected: 0x2000, .. }), "{}", error);
+
+    let path = std::env::temp_dir().join(format!("jimbot-bad-save-{}.sav", std::process::id()));
+    std::fs::write(&path, [0; 0x10]).unwrap();
+    let error = new_cartridge_with_storage(rom(), Box::new(FileStorage::new(&path))).err().unwrap();
+    let CartridgeError::BadSaveSize { path: error_path, size: 0x10, expected: 0x2000 } = error else { panic!("{}", error) };
+    assert_eq!(error_path, path.to_string_lossy());
+    // the save is left as it was
+    assert_eq!(std::fs::read(&path).unwrap(), [0; 0x10]);
+    std::fs::remove_file(&path).unwrap();
+}
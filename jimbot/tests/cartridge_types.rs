@@ -0,0 +1,140 @@
+//! Loads every MBC1/2/3/5 and rom only cartridge type, switches a rom bank and checks the ram
+
+use std::sync::{Arc, Mutex};
+use jimbot::cartridge::{new_cartridge_with_storage, Cartridge};
+use jimbot::cartridge::cartridge_type::CartridgeType;
+use jimbot::cartridge::save_storage::MemoryStorage;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Ram {
+    None,
+    Volatile,
+    Battery,
+}
+
+struct Case {
+    code: u8,
+    cartridge_type: CartridgeType,
+    /// register selecting rom bank 3, `None` without banking
+    bank_register: Option<usize>,
+    ram: Ram,
+    /// size of the save (ram then clock footer)
+    save_size: Option<usize>,
+}
+
+const fn case(code: u8, cartridge_type: CartridgeType, bank_register: Option<usize>, ram: Ram, save_size: Option<usize>) -> Case {
+    Case { code, cartridge_type, bank_register, ram, save_size }
+}
+
+const CASES: [Case; 19] = [
+    case(0x00, CartridgeType::RomOnly, None, Ram::None, None),
+    case(0x08, CartridgeType::RomRam, None, Ram::Volatile, None),
+    case(0x09, CartridgeType::RomRamBattery, None, Ram::Battery, Some(0x2000)),
+    case(0x01, CartridgeType::RomMbc1, Some(0x2000), Ram::None, None),
+    case(0x02, CartridgeType::RomMbc1Ram, Some(0x2000), Ram::Volatile, None),
+    case(0x03, CartridgeType::RomMbc1RamBattery, Some(0x2000), Ram::Battery, Some(0x2000)),
+    // the built-in ram, bit 8 of the address selects the rom bank register
+    case(0x05, CartridgeType::RomMbc2, Some(0x2100), Ram::Volatile, None),
+    case(0x06, CartridgeType::RomMbc2Battery, Some(0x2100), Ram::Battery, Some(0x200)),
+    // the clock is saved without ram
+    case(0x0F, CartridgeType::RomMbc3TimerBattery, Some(0x2000), Ram::None, Some(48)),
+    case(0x10, CartridgeType::RomMbc3TimerRamBattery, Some(0x2000), Ram::Battery, Some(0x2000 + 48)),
+    case(0x11, CartridgeType::RomMbc3, Some(0x2000), Ram::None, None),
+    case(0x12, CartridgeType::RomMbc3Ram, Some(0x2000), Ram::Volatile, None),
+    case(0x13, CartridgeType::RomMbc3RamBattery, Some(0x2000), Ram::Battery, Some(0x2000)),
+    case(0x19, CartridgeType::RomMbc5, Some(0x2000), Ram::None, None),
+    case(0x1A, CartridgeType::RomMbc5Ram, Some(0x2000), Ram::Volatile, None),
+    case(0x1B, CartridgeType::RomMbc5RamBattery, Some(0x2000), Ram::Battery, Some(0x2000)),
+    case(0x1C, CartridgeType::RomMbc5Rumble, Some(0x2000), Ram::None, None),
+    case(0x1D, CartridgeType::RomMbc5RumbleSram, Some(0x2000), Ram::Volatile, None),
+    case(0x1E, CartridgeType::RomMbc5RumbleSramBattery, Some(0x2000), Ram::Battery, Some(0x2000)),
+];
+
+/// 128 KiB (32 KiB without banking) holding the bank number at the start of every bank
+fn rom(case: &Case) -> Vec<u8> {
+    let rom_size = if case.bank_register.is_some() { 0x02 } else { 0x00 };
+    let mut rom = vec![0; 0x8000 << rom_size];
+    for (bank, data) in rom.chunks_mut(0x4000).enumerate() {
+        data[0] = bank as u8;
+    }
+    rom[0x147] = case.code;
+    rom[0x148] = rom_size;
+    // MBC2 has no external ram
+    rom[0x149] = if case.ram != Ram::None && case.bank_register != Some(0x2100) { 0x02 } else { 0x00 };
+    rom
+}
+
+fn load(case: &Case, storage: &MemoryStorage) -> Box<dyn Cartridge> {
+    load_rom(case, rom(case), storage)
+}
+
+fn load_rom(case: &Case, rom: Vec<u8>, storage: &MemoryStorage) -> Box<dyn Cartridge> {
+    let mut cartridge = new_cartridge_with_storage(rom, Box::new(storage.clone()))
+        .unwrap_or_else(|err| panic!("{:?}: {}", case.cartridge_type, err));
+    cartridge.set(0x0000, 0x0A);
+    cartridge
+}
+
+#[test]
+fn every_type_loads() {
+    for case in &CASES {
+        let name = format!("{:?}", case.cartridge_type);
+        let storage = MemoryStorage::default();
+        let mut cartridge = load(case, &storage);
+        assert_eq!(*cartridge.metadata().cartridge_type(), case.cartridge_type, "{}", name);
+
+        assert_eq!(cartridge.get(0x4000), 1, "{}", name);
+        if let Some(register) = case.bank_register {
+            cartridge.set(register, 3);
+            assert_eq!(cartridge.get(0x4000), 3, "{}", name);
+        }
+
+        cartridge.set(0xA000, 0x05);
+        // MBC2 stores nibbles, the high one reads 1s
+        let expected = match case.ram {
+            Ram::None => 0xFF,
+            _ if case.bank_register == Some(0x2100) => 0xF5,
+            _ => 0x05,
+        };
+        assert_eq!(cartridge.get(0xA000), expected, "{}", name);
+        assert_eq!(cartridge.save_data().is_some(), case.save_size.is_some(), "{}", name);
+
+        drop(cartridge);
+        let save = storage.bytes();
+        assert_eq!(save.as_ref().map(|save| save.len()), case.save_size, "{}", name);
+        if case.ram == Ram::Battery {
+            assert_eq!(save.unwrap()[0], 0x05, "{}", name);
+            assert_eq!(load(case, &storage).get(0xA000), expected, "{}", name);
+        }
+    }
+}
+
+#[test]
+fn mbc5_rumble() {
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let callback_changes = changes.clone();
+    // 128 KiB of ram, 16 banks
+    let rom = |case: &Case| {
+        let mut rom = rom(case);
+        rom[0x149] = 0x04;
+        rom
+    };
+    let rumble = &CASES[17];
+    let mut cartridge = load_rom(rumble, rom(rumble), &MemoryStorage::default());
+    cartridge.set_rumble_callback(Box::new(move |on| callback_changes.lock().unwrap().push(on)));
+    cartridge.set(0xA000, 0x42);
+    cartridge.set(0x4000, 0x08);
+    // bit 3 drives the motor, it does not select a ram bank
+    assert_eq!(cartridge.get(0xA000), 0x42);
+    cartridge.set(0x4000, 0x09);
+    cartridge.set(0x4000, 0x01);
+    cartridge.set(0x4000, 0x00);
+    assert_eq!(*changes.lock().unwrap(), [true, false]);
+
+    // without motor bit 3 selects the ram bank
+    let mut cartridge = load_rom(&CASES[14], rom(&CASES[14]), &MemoryStorage::default());
+    cartridge.set_rumble_callback(Box::new(|_| panic!("no motor")));
+    cartridge.set(0xA000, 0x42);
+    cartridge.set(0x4000, 0x08);
+    assert_eq!(cartridge.get(0xA000), 0x00);
+}
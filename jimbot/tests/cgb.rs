@@ -0,0 +1,168 @@
+//! Runs a small program using the CGB banks and color palettes
+
+use jimbot::boot::BootMode;
+use jimbot::cartridge::new_cartridge_from_bytes;
+use jimbot::cartridge::metadata::Metadata;
+use jimbot::color::compatibility::{ButtonCombo, DmgPalette};
+use jimbot::color::rgb555;
+use jimbot::jimbot::Jimbot;
+
+/// 32 KiB rom running `program` at $0150, bytes from $0300 count up from 0
+fn rom(cgb_flag: u8, program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x143] = cgb_flag;
+    rom[0x150..0x150 + program.len()].copy_from_slice(program);
+    for (i, byte) in rom[0x300..0x400].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    rom
+}
+
+/// Loads bg palettes 0 (red) and 1 (green), gives the first map tile palette 1 through the vram bank 1
+/// attributes and writes $42 at $D000 in wram bank 2
+fn palettes_rom(cgb_flag: u8) -> Vec<u8> {
+    let program = [
+        0x21, 0x00, 0x02, // ld hl, $0200
+        0x06, 0x10, // ld b, 16
+        0x3E, 0x80, 0xE0, 0x68, // BCPS: index 0, auto-increment
+        0x2A, 0xE0, 0x69, 0x05, 0x20, 0xFA, // BCPD <- (hl+) 16 times
+        0x3E, 0x01, 0xE0, 0x4F, // VBK 1
+        0x3E, 0x01, 0xEA, 0x00, 0x98, // attributes of the first tile: palette 1
+        0x3E, 0x00, 0xE0, 0x4F, // VBK 0
+        0x3E, 0x02, 0xE0, 0x70, // SVBK 2
+        0x3E, 0x42, 0xEA, 0x00, 0xD0,
+        0x3E, 0x03, 0xE0, 0x70, // SVBK 3
+        0x18, 0xFE,
+    ];
+    let mut rom = rom(cgb_flag, &program);
+    rom[0x200..0x210].copy_from_slice(&[0x1F, 0x00, 0, 0, 0, 0, 0, 0, 0xE0, 0x03, 0, 0, 0, 0, 0, 0]);
+    rom
+}
+
+fn run(rom: Vec<u8>, dmg_palette: Option<DmgPalette>) -> Jimbot {
+    let mut jimbot = Jimbot::new(new_cartridge_from_bytes(rom).unwrap(), BootMode::Skip);
+    if let Some(palette) = dmg_palette {
+        jimbot.set_dmg_palette(palette);
+    }
+    for _ in 0..3 {
+        jimbot.run_frame();
+    }
+    jimbot
+}
+
+#[test]
+fn cgb_palettes_and_banks() {
+    let jimbot = run(palettes_rom(0xC0), None);
+    assert!(jimbot.mmu().is_cgb());
+    let lcd = jimbot.frame().lcd();
+    assert_eq!(lcd[0][0], 0x03E0);
+    assert_eq!(lcd[7][7], 0x03E0);
+    assert_eq!(lcd[8][0], 0x001F);
+    assert_eq!(jimbot.mmu().wram()[0x2000], 0x42);
+    assert_eq!(jimbot.mmu().get(0xD000), 0x00);
+    assert_eq!(jimbot.mmu().get(0xFF70), 0xFB);
+    assert_eq!(jimbot.frame().to_rgba()[0..4], [0x00, 0xFF, 0x00, 0xFF]);
+
+    let state = jimbot.save_state();
+    let mut other = run(palettes_rom(0xC0), None);
+    other.load_state(&state).unwrap();
+    assert_eq!(other.save_state(), state);
+}
+
+#[test]
+fn dmg_cartridge_ignores_cgb_registers() {
+    let jimbot = run(palettes_rom(0x00), Some(DmgPalette::from_rgb888([0xE0F8D0, 0x88C070, 0x346856, 0x081820])));
+    assert!(!jimbot.mmu().is_cgb());
+    assert_eq!(jimbot.frame().lcd()[0][0], rgb555(0xE0F8D0));
+    assert_eq!(jimbot.mmu().get(0xD000), 0x42);
+    assert_eq!(jimbot.mmu().get(0xFF70), 0xFF);
+}
+
+/// dmg rom titled `title` from the old licensee `licensee`, $33 is followed by the new licensee "01"
+fn titled_rom(title: &str, licensee: u8) -> Vec<u8> {
+    let mut rom = rom(0x00, &[0x18, 0xFE]);
+    rom[0x134..0x134 + title.len()].copy_from_slice(title.as_bytes());
+    rom[0x144..0x146].copy_from_slice(b"01");
+    rom[0x14B] = licensee;
+    rom
+}
+
+fn palette(title: &str, licensee: u8) -> DmgPalette {
+    DmgPalette::for_game(&Metadata::from_rom(&titled_rom(title, licensee)).unwrap())
+}
+
+#[test]
+fn dmg_compatibility_palettes() {
+    let tetris = palette("TETRIS", 0x01);
+    assert_eq!(tetris.bg, [0x7FFF, 0x03FF, 0x001F, 0x0000]);
+    assert_eq!(tetris, ButtonCombo::DownA.into());
+    assert_eq!(palette("TETRIS", 0x33), tetris);
+    // not published by Nintendo
+    assert_eq!(palette("TETRIS", 0x08), ButtonCombo::RightA.into());
+    assert_eq!(palette("UNKNOWN GAME", 0x01), ButtonCombo::RightA.into());
+    // same title checksum, told apart by the 4th letter
+    let blue = palette("POKEMON BLUE", 0x01);
+    assert_eq!(blue.bg, [0x7FFF, 0x7E8C, 0x7C00, 0x0000]);
+    assert_eq!(blue.obj0, [0x7FFF, 0x421F, 0x1CF2, 0x0000]);
+    let vegas = palette("VEGAS STAKES", 0x01);
+    assert_eq!(vegas.bg, [0x7FFF, 0x1BEF, 0x0200, 0x0000]);
+    assert_eq!(vegas.obj1, blue.bg);
+    // obj palettes shifted by one color
+    assert_eq!(palette("SUPER MARIOLAND", 0x01).obj0, [0x0000, 0x7FFF, 0x421F, 0x1CF2]);
+
+    let mut jimbot = Jimbot::new(new_cartridge_from_bytes(titled_rom("TETRIS", 0x01)).unwrap(), BootMode::Skip);
+    assert_eq!(jimbot.mmu().bg_color(0, 1), 0x03FF);
+    jimbot.set_dmg_palette(ButtonCombo::RightB.into());
+    assert_eq!(jimbot.mmu().bg_color(0, 0), 0x0000);
+    assert_eq!(jimbot.mmu().obj_color(1, 3), 0x7FFF);
+    assert_eq!("Left+B".parse(), Ok(ButtonCombo::LeftB));
+    assert!("down+c".parse::<ButtonCombo>().is_err());
+}
+
+#[test]
+fn vram_dma() {
+    let program = [
+        0x3E, 0x03, 0xE0, 0x51, 0xAF, 0xE0, 0x52, // source $0300
+        0xE0, 0x53, 0xE0, 0x54, // destination $8000
+        0x3E, 0x01, 0xE0, 0x4F, // VBK 1
+        0x3E, 0x01, 0xE0, 0x55, // general purpose, 2 blocks
+        0xF0, 0x55, 0xEA, 0x00, 0xC0,
+        0x3E, 0x82, 0xE0, 0x55, // HBlank, 3 blocks
+        0xF0, 0x55, 0xFE, 0xFF, 0x20, 0xFA, // wait for the end
+        0x3E, 0x8F, 0xE0, 0x55, // HBlank, 16 blocks
+        0xAF, 0xE0, 0x55, // cancelled
+        0xF0, 0x55, 0xEA, 0x02, 0xC0,
+        0x18, 0xFE,
+    ];
+    let jimbot = run(rom(0xC0, &program), None);
+    let vram = jimbot.mmu().vram();
+    assert_eq!(vram[0x2000..0x2050], (0..0x50).collect::<Vec<u8>>()[..]);
+    assert_eq!(vram[0x2050], 0);
+    assert_eq!(vram[0..0x50], [0; 0x50]);
+    assert_eq!(jimbot.mmu().wram()[0], 0xFF);
+    // no block copied before the cancel
+    assert_eq!(jimbot.mmu().wram()[2], 0x8F);
+}
+
+#[test]
+fn double_speed() {
+    let program = [
+        0x3E, 0x01, 0xE0, 0x4D, // KEY1: prepare the switch
+        0x10, 0x00, // STOP
+        0xF0, 0x4D, 0xEA, 0x00, 0xC0,
+        0x18, 0xFE,
+    ];
+    let mut jimbot = run(rom(0xC0, &program), None);
+    assert!(jimbot.mmu().is_double_speed());
+    assert_eq!(jimbot.mmu().wram()[0], 0xFE);
+    // the ppu keeps its rate, a frame lasts twice as many m-cycles
+    let start = jimbot.frame().cycles();
+    let end = jimbot.run_frame().cycles();
+    assert_eq!(end - start, 2 * Jimbot::M_CYCLES_PER_FRAME as u64);
+
+    let mut dmg = run(rom(0x00, &program), None);
+    assert!(!dmg.mmu().is_double_speed());
+    let start = dmg.frame().cycles();
+    assert_eq!(dmg.run_frame().cycles() - start, Jimbot::M_CYCLES_PER_FRAME as u64);
+}
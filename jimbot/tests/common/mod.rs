@@ -0,0 +1,48 @@
+// every test crate builds its own copy and uses a part of it
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use jimbot::boot::BootMode;
+use jimbot::cartridge::{self, Cartridge};
+use jimbot::cartridge::save_storage::MemoryStorage;
+use jimbot::jimbot::Jimbot;
+
+/// Why the tests running external roms are ignored by default
+pub const NEEDS_TEST_ROMS: &str = "needs the test roms, run with `JIMBOT_TEST_ROMS=<dir> cargo test --release -- --ignored`";
+
+/// Test roms are not part of the repository, they are looked up in `$JIMBOT_TEST_ROMS`
+/// (default `jimbot/tests/roms`). The tests using them are ignored unless asked for, a missing rom then fails.
+pub fn test_roms_dir() -> PathBuf {
+    std::env::var_os("JIMBOT_TEST_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("roms"))
+}
+
+/// Boots `path` (relative to `test_roms_dir`) without boot rom
+pub fn load_test_rom(path: &str) -> Jimbot {
+    let file_path = test_roms_dir().join(path);
+    let bytes = std::fs::read(&file_path)
+        .unwrap_or_else(|err| panic!("Cannot read test rom {}: {} ({})", file_path.display(), err, NEEDS_TEST_ROMS));
+    let cartridge = cartridge::new_cartridge_from_bytes(bytes)
+        .unwrap_or_else(|err| panic!("Cannot load {}: {}", file_path.display(), err));
+    Jimbot::new(cartridge, BootMode::Skip)
+}
+
+/// Loads `rom` with its save in memory, the storage is returned to look at what the cartridge saves
+pub fn cartridge_with_save(rom: Vec<u8>, save: Option<Vec<u8>>) -> (Box<dyn Cartridge>, MemoryStorage) {
+    let storage = MemoryStorage::new(save);
+    let cartridge = cartridge::new_cartridge_with_storage(rom, Box::new(storage.clone())).unwrap();
+    (cartridge, storage)
+}
+
+/// Cycles the cartridge alone, a second is 2^20 m-cycles
+pub fn run_seconds(cartridge: &mut Box<dyn Cartridge>, seconds: u32) {
+    for _ in 0..seconds << 20 {
+        cartridge.cycle();
+    }
+}
+
+/// Host time in seconds since the unix epoch
+pub fn now() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
@@ -0,0 +1,60 @@
+//! Drives the HuC1/HuC3 infrared port and HuC3 clock as the games do
+
+use jimbot::cartridge::infrared::IrLoopback;
+use jimbot::cartridge::{new_cartridge_from_bytes, Cartridge};
+
+/// 1 MiB rom of the given HuC type with 32 KiB of ram
+fn cartridge(cartridge_type: u8) -> Box<dyn Cartridge> {
+    let mut rom = vec![0; 1024 * 1024];
+    rom[0x147] = cartridge_type;
+    rom[0x148] = 0x05;
+    rom[0x149] = 0x03;
+    new_cartridge_from_bytes(rom).unwrap()
+}
+
+/// sends a HuC3 clock command, returns the response
+fn command(cartridge: &mut Box<dyn Cartridge>, val: u8) -> u8 {
+    cartridge.set(0x0000, 0x0B);
+    cartridge.set(0xA000, val);
+    cartridge.set(0x0000, 0x0C);
+    cartridge.get(0xA000)
+}
+
+#[test]
+fn ir_loopback() {
+    let (a, b) = IrLoopback::pair();
+    let mut huc1 = cartridge(0xFF);
+    let mut huc3 = cartridge(0xFE);
+    huc1.set_ir_transceiver(Box::new(a));
+    huc3.set_ir_transceiver(Box::new(b));
+    huc1.set(0x0000, 0x0E);
+    huc3.set(0x0000, 0x0E);
+    assert_eq!(huc3.get(0xA000), 0xC0);
+    huc1.set(0xA000, 0x01);
+    assert_eq!(huc3.get(0xA000), 0xC1);
+    assert_eq!(huc1.get(0xA000), 0xC0);
+    huc3.set(0xA000, 0x01);
+    assert_eq!(huc1.get(0xA000), 0xC1);
+    huc1.set(0xA000, 0x00);
+    assert_eq!(huc3.get(0xA000), 0xC0);
+}
+
+#[test]
+fn huc3_clock_rolls_over() {
+    let mut huc3 = cartridge(0xFE);
+    // 23:59 of day $FFF
+    command(&mut huc3, 0x40);
+    command(&mut huc3, 0x50);
+    for nibble in [0xF, 0x9, 0x5, 0xF, 0xF, 0xF] {
+        command(&mut huc3, 0x30 | nibble);
+    }
+    command(&mut huc3, 0x61);
+    for _ in 0..60 << 20 {
+        huc3.cycle();
+    }
+    command(&mut huc3, 0x60);
+    command(&mut huc3, 0x40);
+    let nibbles: Vec<u8> = (0..6).map(|_| command(&mut huc3, 0x10)).collect();
+    assert_eq!(nibbles, [0x10; 6]);
+    assert_eq!(command(&mut huc3, 0x62), 0x61);
+}
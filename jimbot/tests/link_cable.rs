@@ -0,0 +1,72 @@
+//! Links two `Jimbot` with `LinkCable::pair`
+
+use jimbot::boot::BootMode;
+use jimbot::cartridge::new_cartridge_from_bytes;
+use jimbot::jimbot::Jimbot;
+use jimbot::mmu::joypad::Key;
+use jimbot::serial::link_cable::LinkCable;
+
+fn new_jimbot(program: &[u8]) -> Jimbot {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x150..0x150 + program.len()].copy_from_slice(program);
+    Jimbot::new(new_cartridge_from_bytes(rom).unwrap(), BootMode::Skip)
+}
+
+/// runs both sides m-cycle by m-cycle
+fn run(master: &mut Jimbot, slave: &mut Jimbot, m_cycles: u32) {
+    for _ in 0..m_cycles {
+        master.run();
+        slave.run();
+    }
+}
+
+fn serial_interrupt(jimbot: &Jimbot) -> bool {
+    jimbot.mmu().get(0xFF0F) & 0x08 != 0
+}
+
+#[test]
+fn master_clocks_the_transfer() {
+    // sends $99 with the internal clock once A is pressed
+    let mut master = new_jimbot(&[
+        0x3E, 0x99, 0xE0, 0x01,
+        0x3E, 0x10, 0xE0, 0x00,
+        0xF0, 0x00, 0xE6, 0x01, 0x20, 0xFA, // wait for A
+        0x3E, 0x81, 0xE0, 0x02,
+        0x18, 0xFE,
+    ]);
+    // waits for the master clock with $42 in SB
+    let mut slave = new_jimbot(&[
+        0x3E, 0x42, 0xE0, 0x01,
+        0x3E, 0x80, 0xE0, 0x02,
+        0x18, 0xFE,
+    ]);
+    let (cable, other_cable) = LinkCable::pair();
+    master.connect_serial(Box::new(cable));
+    slave.connect_serial(Box::new(other_cable));
+
+    run(&mut master, &mut slave, Jimbot::M_CYCLES_PER_FRAME);
+    // nothing is shifted until the master clocks
+    assert_eq!((slave.mmu().get(0xFF01), slave.mmu().get(0xFF02)), (0x42, 0xFE));
+    assert!(!serial_interrupt(&master) && !serial_interrupt(&slave));
+
+    master.joypad_press(Key::A);
+    // 8 bits, 128 m-cycles each
+    run(&mut master, &mut slave, 8 * 128 + 100);
+    assert_eq!((master.mmu().get(0xFF01), master.mmu().get(0xFF02)), (0x42, 0x7F));
+    assert_eq!((slave.mmu().get(0xFF01), slave.mmu().get(0xFF02)), (0x99, 0x7E));
+    assert!(serial_interrupt(&master) && serial_interrupt(&slave));
+}
+
+#[test]
+fn unplugged_cable_reads_ff() {
+    let mut master = new_jimbot(&[0x3E, 0x99, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0x18, 0xFE]);
+    // the other side is not waiting for a clock
+    let mut idle = new_jimbot(&[0x18, 0xFE]);
+    let (cable, other_cable) = LinkCable::pair();
+    master.connect_serial(Box::new(cable));
+    idle.connect_serial(Box::new(other_cable));
+    run(&mut master, &mut idle, 8 * 128 + 100);
+    assert_eq!(master.mmu().get(0xFF01), 0xFF);
+    assert!(serial_interrupt(&master) && !serial_interrupt(&idle));
+}
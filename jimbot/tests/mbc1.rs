@@ -0,0 +1,125 @@
+//! MBC1 banking on roms holding their bank number at the start of every bank
+
+use jimbot::cartridge::{new_cartridge_from_bytes, Cartridge};
+use jimbot::cartridge::metadata::Metadata;
+
+/// MBC1+RAM+BATTERY with 32 KiB of ram, `rom_size` is the header code (2^code * 32 KiB)
+fn rom(rom_size: u8) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000 << rom_size];
+    for (bank, data) in rom.chunks_mut(0x4000).enumerate() {
+        data[0] = bank as u8;
+    }
+    rom[0x104..0x134].copy_from_slice(&Metadata::NINTENDO_LOGO);
+    rom[0x147] = 0x03;
+    rom[0x148] = rom_size;
+    rom[0x149] = 0x03;
+    rom
+}
+
+fn cartridge(rom: Vec<u8>) -> Box<dyn Cartridge> {
+    new_cartridge_from_bytes(rom).unwrap()
+}
+
+/// banks mapped at $0000 and $4000
+fn banks(cartridge: &dyn Cartridge) -> (u8, u8) {
+    (cartridge.get(0x0000), cartridge.get(0x4000))
+}
+
+#[test]
+fn bank_0_selects_bank_1() {
+    // 2 MiB
+    let mut cartridge = cartridge(rom(0x06));
+    assert_eq!(banks(&*cartridge), (0x00, 0x01));
+    cartridge.set(0x2000, 0x05);
+    assert_eq!(banks(&*cartridge), (0x00, 0x05));
+    cartridge.set(0x2000, 0x00);
+    assert_eq!(banks(&*cartridge), (0x00, 0x01));
+    // only the 5 low bits count
+    cartridge.set(0x2000, 0xE0);
+    assert_eq!(banks(&*cartridge), (0x00, 0x01));
+    for (bank2, bank) in [(1, 0x21), (2, 0x41), (3, 0x61)] {
+        cartridge.set(0x4000, bank2);
+        assert_eq!(banks(&*cartridge), (0x00, bank));
+    }
+    cartridge.set(0x2000, 0x1F);
+    assert_eq!(banks(&*cartridge), (0x00, 0x7F));
+
+    // bank numbers wrap on the rom size (512 KiB)
+    let mut cartridge = self::cartridge(rom(0x04));
+    cartridge.set(0x2000, 0x25);
+    assert_eq!(banks(&*cartridge), (0x00, 0x05));
+}
+
+#[test]
+fn mode_1_maps_bank2_at_0000() {
+    let mut cartridge = cartridge(rom(0x06));
+    cartridge.set(0x4000, 2);
+    cartridge.set(0x2000, 3);
+    assert_eq!(banks(&*cartridge), (0x00, 0x43));
+    cartridge.set(0x6000, 1);
+    assert_eq!(banks(&*cartridge), (0x40, 0x43));
+    cartridge.set(0x4000, 3);
+    assert_eq!(banks(&*cartridge), (0x60, 0x63));
+    cartridge.set(0x6000, 0);
+    assert_eq!(banks(&*cartridge), (0x00, 0x63));
+}
+
+#[test]
+fn ram_banks_need_mode_1() {
+    let mut cartridge = cartridge(rom(0x06));
+    cartridge.set(0xA000, 0x11);
+    // disabled
+    assert_eq!(cartridge.get(0xA000), 0xFF);
+    cartridge.set(0x0000, 0x0A);
+    // mode 0: always ram bank 0
+    cartridge.set(0x4000, 2);
+    cartridge.set(0xA000, 0x22);
+    // mode 1: bank2 selects the ram bank
+    cartridge.set(0x6000, 1);
+    assert_eq!(cartridge.get(0xA000), 0x00);
+    cartridge.set(0xA000, 0x33);
+    cartridge.set(0x4000, 3);
+    cartridge.set(0xBFFF, 0x44);
+    cartridge.set(0x6000, 0);
+    assert_eq!(cartridge.get(0xA000), 0x22);
+
+    let ram = cartridge.save_data().unwrap();
+    assert_eq!(ram.len(), 0x8000);
+    assert_eq!((ram[0x0000], ram[0x2000], ram[0x4000], ram[0x7FFF]), (0x22, 0x00, 0x33, 0x44));
+    cartridge.set(0x0000, 0x00);
+    assert_eq!(cartridge.get(0xA000), 0xFF);
+}
+
+#[test]
+fn mbc1m_multicart() {
+    // 1 MiB, a second game header at bank $10
+    let mut multicart = rom(0x05);
+    multicart[0x40104..0x40134].copy_from_slice(&Metadata::NINTENDO_LOGO);
+    let mut cartridge = cartridge(multicart);
+    // BANK1 has 4 bits, BANK2 selects the game
+    cartridge.set(0x4000, 1);
+    cartridge.set(0x2000, 0x02);
+    assert_eq!(banks(&*cartridge), (0x00, 0x12));
+    cartridge.set(0x2000, 0x12);
+    assert_eq!(banks(&*cartridge), (0x00, 0x12));
+    // $10 is not 0, bank $00 of the game is mapped
+    cartridge.set(0x2000, 0x10);
+    assert_eq!(banks(&*cartridge), (0x00, 0x10));
+    cartridge.set(0x6000, 1);
+    cartridge.set(0x4000, 3);
+    assert_eq!(banks(&*cartridge), (0x30, 0x30));
+
+    // same rom without the second logo
+    let mut cartridge = self::cartridge(rom(0x05));
+    cartridge.set(0x4000, 1);
+    cartridge.set(0x2000, 0x02);
+    assert_eq!(banks(&*cartridge), (0x00, 0x22));
+
+    // zeroed logos everywhere are not repeated Nintendo logos
+    let mut zeroed = rom(0x05);
+    zeroed[0x104..0x134].fill(0);
+    let mut cartridge = self::cartridge(zeroed);
+    cartridge.set(0x4000, 1);
+    cartridge.set(0x2000, 0x02);
+    assert_eq!(banks(&*cartridge), (0x00, 0x22));
+}
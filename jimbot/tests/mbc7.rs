@@ -0,0 +1,189 @@
+//! MBC7 93LC56 EEPROM bit-banged by hand through $Ax8x, and the accelerometer latch
+
+use jimbot::cartridge::{new_cartridge_from_bytes, Cartridge};
+
+const PORT: usize = 0xA080;
+const READ: u16 = 0b10 << 8;
+const WRITE: u16 = 0b01 << 8;
+const ERASE: u16 = 0b11 << 8;
+const EWEN: u16 = 0b1100_0000;
+const EWDS: u16 = 0b0000_0000;
+const ERAL: u16 = 0b1000_0000;
+const WRAL: u16 = 0b0100_0000;
+
+/// MBC7+SENSOR+RUMBLE+RAM+BATTERY with the registers enabled
+fn mbc7() -> Box<dyn Cartridge> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x147] = 0x22;
+    let mut cartridge = new_cartridge_from_bytes(rom).unwrap();
+    cartridge.set(0x0000, 0x0A);
+    cartridge.set(0x4000, 0x40);
+    cartridge
+}
+
+fn dout(cartridge: &dyn Cartridge) -> bool {
+    cartridge.get(PORT) & 0x01 != 0
+}
+
+/// CS high, `di` set with CLK low then latched on the rising edge
+fn clock(cartridge: &mut dyn Cartridge, di: bool) {
+    let di = (di as u8) << 1;
+    cartridge.set(PORT, 0x80 | di);
+    cartridge.set(PORT, 0xC0 | di);
+}
+
+fn shift_in(cartridge: &mut dyn Cartridge, bits: u16, count: u8) {
+    for bit in (0..count).rev() {
+        clock(cartridge, bits >> bit & 1 != 0);
+    }
+}
+
+fn deselect(cartridge: &mut dyn Cartridge) {
+    cartridge.set(PORT, 0x00);
+}
+
+/// start bit, opcode and address
+fn command(cartridge: &mut dyn Cartridge, command: u16) {
+    clock(cartridge, false);
+    assert!(dout(cartridge), "busy before the command");
+    shift_in(cartridge, 0b100_0000_0000 | command, 11);
+}
+
+fn simple(cartridge: &mut dyn Cartridge, bits: u16) {
+    command(cartridge, bits);
+    deselect(cartridge);
+}
+
+/// the command then the data word, DO tells if the write is programmed
+fn write(cartridge: &mut dyn Cartridge, bits: u16, word: u16) -> bool {
+    command(cartridge, bits);
+    shift_in(cartridge, word, 16);
+    busy(cartridge)
+}
+
+/// DO low until the next rising edge of CLK, even when deselected
+fn busy(cartridge: &mut dyn Cartridge) -> bool {
+    deselect(cartridge);
+    let busy = !dout(cartridge);
+    clock(cartridge, false);
+    assert!(dout(cartridge), "ready after a clock");
+    deselect(cartridge);
+    busy
+}
+
+fn read(cartridge: &mut dyn Cartridge, address: u8) -> u16 {
+    command(cartridge, READ | address as u16);
+    assert!(!dout(cartridge), "dummy 0 bit");
+    let mut word = 0;
+    for _ in 0..16 {
+        clock(cartridge, false);
+        word = word << 1 | dout(cartridge) as u16;
+    }
+    deselect(cartridge);
+    assert!(dout(cartridge), "ready after a read");
+    word
+}
+
+#[test]
+fn write_then_read() {
+    let mut cartridge = mbc7();
+    assert_eq!(read(&mut *cartridge, 0x05), 0xFFFF);
+    // writes start disabled
+    assert!(!write(&mut *cartridge, WRITE | 0x05, 0x1234));
+    assert_eq!(read(&mut *cartridge, 0x05), 0xFFFF);
+
+    simple(&mut *cartridge, EWEN);
+    assert!(write(&mut *cartridge, WRITE | 0x05, 0x1234));
+    assert_eq!(read(&mut *cartridge, 0x05), 0x1234);
+    // the top address bit is ignored
+    assert_eq!(read(&mut *cartridge, 0x85), 0x1234);
+    assert_eq!(read(&mut *cartridge, 0x04), 0xFFFF);
+    assert_eq!(&cartridge.save_data().unwrap()[0x0A..0x0C], [0x34, 0x12]);
+}
+
+#[test]
+fn ewds_blocks_writes() {
+    let mut cartridge = mbc7();
+    simple(&mut *cartridge, EWEN);
+    assert!(write(&mut *cartridge, WRITE | 0x10, 0xBEEF));
+    simple(&mut *cartridge, EWDS);
+    assert!(!write(&mut *cartridge, WRITE | 0x10, 0x0000));
+    command(&mut *cartridge, ERASE | 0x10);
+    assert!(!busy(&mut *cartridge));
+    assert_eq!(read(&mut *cartridge, 0x10), 0xBEEF);
+}
+
+#[test]
+fn erase() {
+    let mut cartridge = mbc7();
+    simple(&mut *cartridge, EWEN);
+    assert!(write(&mut *cartridge, WRITE | 0x20, 0x0000));
+    assert!(write(&mut *cartridge, WRITE | 0x21, 0x0000));
+    command(&mut *cartridge, ERASE | 0x20);
+    assert!(busy(&mut *cartridge));
+    assert_eq!(read(&mut *cartridge, 0x20), 0xFFFF);
+    assert_eq!(read(&mut *cartridge, 0x21), 0x0000);
+}
+
+#[test]
+fn eral_and_wral() {
+    let mut cartridge = mbc7();
+    simple(&mut *cartridge, EWEN);
+    assert!(write(&mut *cartridge, WRAL, 0xA55A));
+    assert!(cartridge.save_data().unwrap().chunks(2).all(|word| word == [0x5A, 0xA5]));
+    assert_eq!(read(&mut *cartridge, 0x7F), 0xA55A);
+    command(&mut *cartridge, ERAL);
+    assert!(busy(&mut *cartridge));
+    assert!(cartridge.save_data().unwrap().iter().all(|&byte| byte == 0xFF));
+}
+
+#[test]
+fn sequential_read() {
+    let mut cartridge = mbc7();
+    simple(&mut *cartridge, EWEN);
+    assert!(write(&mut *cartridge, WRITE | 0x7F, 0x1111));
+    assert!(write(&mut *cartridge, WRITE, 0x2222));
+    command(&mut *cartridge, READ | 0x7F);
+    let mut words = [0; 2];
+    for word in &mut words {
+        for _ in 0..16 {
+            clock(&mut *cartridge, false);
+            *word = *word << 1 | dout(&*cartridge) as u16;
+        }
+    }
+    deselect(&mut *cartridge);
+    // the address wraps
+    assert_eq!(words, [0x1111, 0x2222]);
+}
+
+fn latched(cartridge: &dyn Cartridge) -> (u16, u16) {
+    let byte = |address| cartridge.get(address) as u16;
+    (byte(0xA020) | byte(0xA030) << 8, byte(0xA040) | byte(0xA050) << 8)
+}
+
+#[test]
+fn accelerometer_latch() {
+    let mut cartridge = mbc7();
+    cartridge.set_tilt(1.0, -0.5);
+    assert_eq!(latched(&*cartridge), (0x8000, 0x8000));
+    // latching needs an erase first
+    cartridge.set(0xA010, 0xAA);
+    assert_eq!(latched(&*cartridge), (0x8000, 0x8000));
+
+    cartridge.set(0xA000, 0x55);
+    assert_eq!(latched(&*cartridge), (0x8000, 0x8000));
+    cartridge.set(0xA010, 0xAA);
+    assert_eq!(latched(&*cartridge), (0x81D0 + 0x70, 0x81D0 - 0x38));
+
+    // kept until erased and latched again
+    cartridge.set_tilt(0.0, 0.0);
+    cartridge.set(0xA010, 0xAA);
+    assert_eq!(latched(&*cartridge), (0x81D0 + 0x70, 0x81D0 - 0x38));
+    cartridge.set(0xA000, 0x55);
+    cartridge.set(0xA010, 0xAA);
+    assert_eq!(latched(&*cartridge), (0x81D0, 0x81D0));
+    // registers repeat every $100 bytes
+    assert_eq!(cartridge.get(0xA720), 0xD0);
+    assert_eq!(cartridge.get(0xA760), 0x00);
+    assert_eq!(cartridge.get(0xA770), 0xFF);
+}
@@ -0,0 +1,51 @@
+//! Decodes synthetic cartridge headers
+
+use jimbot::cartridge::licensee::Licensee;
+use jimbot::cartridge::metadata::{CgbSupport, Destination, Hardware, HeaderWarning, Metadata};
+
+/// 32 KiB rom with a valid logo and checksums, CGB only, new licensee "01" and SGB functions
+fn cgb_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x104..0x134].copy_from_slice(&Metadata::NINTENDO_LOGO);
+    rom[0x134..0x143].copy_from_slice(b"PM_CRYSTAL\0BYTE");
+    rom[0x143] = 0xC0;
+    rom[0x144..0x146].copy_from_slice(b"01");
+    rom[0x146] = 0x03;
+    rom[0x14A] = 0x01;
+    rom[0x14B] = 0x33;
+    rom[0x14C] = 0x02;
+    rom[0x14D] = rom[0x134..0x14D].iter().fold(0u8, |checksum, byte| checksum.wrapping_sub(*byte).wrapping_sub(1));
+    let global = rom.iter().fold(0u16, |checksum, byte| checksum.wrapping_add(*byte as u16));
+    rom[0x14E..0x150].copy_from_slice(&global.to_be_bytes());
+    rom
+}
+
+#[test]
+fn full_header() {
+    let metadata = Metadata::from_rom(&cgb_rom()).unwrap();
+    assert_eq!(metadata.title(), "PM_CRYSTAL");
+    assert_eq!(metadata.manufacturer_code(), Some("BYTE"));
+    assert_eq!(metadata.cgb_support(), CgbSupport::Only);
+    assert_eq!(metadata.licensee(), Licensee::New(*b"01"));
+    assert_eq!(metadata.licensee().name(), Some("Nintendo Research & Development 1"));
+    assert!(metadata.sgb_support());
+    assert_eq!(metadata.destination(), Destination::Overseas);
+    assert_eq!(metadata.mask_rom_version(), 2);
+    assert_eq!(metadata.hardware(), Hardware::Cgb);
+    assert_eq!(metadata.warnings(), []);
+}
+
+#[test]
+fn corrupted_header_warnings() {
+    let mut rom = cgb_rom();
+    rom[0x104] = 0;
+    rom[0x14C] = 0x03;
+    rom[0x4000] = 0x01;
+    let metadata = Metadata::from_rom(&rom).unwrap();
+    let stored = metadata.global_checksum();
+    assert_eq!(metadata.warnings(), [
+        HeaderWarning::NintendoLogo,
+        HeaderWarning::HeaderChecksum { computed: metadata.header_checksum().wrapping_sub(1), stored: metadata.header_checksum() },
+        HeaderWarning::GlobalChecksum { computed: stored.wrapping_sub(0xCE).wrapping_add(2), stored },
+    ]);
+}
@@ -0,0 +1,93 @@
+//! MMM01 header detection and game mapping on a 256 KiB multicart holding the bank number at the start of every bank
+
+use jimbot::cartridge::{new_cartridge_from_bytes, Cartridge};
+use jimbot::cartridge::cartridge_type::CartridgeType;
+use jimbot::cartridge::metadata::Metadata;
+
+const MENU: usize = 0x40000 - 0x8000;
+
+/// `code` type and `title` in the header at `offset`, with the logo and a valid checksum
+fn header(rom: &mut [u8], offset: usize, code: u8, title: &[u8]) {
+    let header = &mut rom[offset..offset + 0x150];
+    header[0x104..0x134].copy_from_slice(&Metadata::NINTENDO_LOGO);
+    header[0x134..0x134 + title.len()].copy_from_slice(title);
+    header[0x147] = code;
+    header[0x148] = 0x03;
+    header[0x14D] = header[0x134..0x14D].iter().fold(0u8, |checksum, byte| checksum.wrapping_sub(*byte).wrapping_sub(1));
+}
+
+/// the first game (MBC1) in bank 0, the menu (MMM01) in the last 32 KiB
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x40000];
+    for (bank, data) in rom.chunks_mut(0x4000).enumerate() {
+        data[0] = bank as u8;
+    }
+    header(&mut rom, 0, 0x01, b"GAME");
+    header(&mut rom, MENU, 0x0B, b"MENU");
+    rom
+}
+
+fn banks(cartridge: &dyn Cartridge) -> (u8, u8) {
+    (cartridge.get(0x0000), cartridge.get(0x4000))
+}
+
+#[test]
+fn menu_header() {
+    let metadata = Metadata::from_rom(&rom()).unwrap();
+    assert_eq!(metadata.title(), "MENU");
+    assert_eq!(*metadata.cartridge_type(), CartridgeType::RomMmm01);
+}
+
+#[test]
+fn menu_header_needs_logo_and_checksum() {
+    // a MBC1 rom whose last bank happens to hold a MMM01 type
+    let mut rom = rom();
+    rom[MENU + 0x104] = 0;
+    let metadata = Metadata::from_rom(&rom).unwrap();
+    assert_eq!(metadata.title(), "GAME");
+    assert_eq!(*metadata.cartridge_type(), CartridgeType::RomMbc1);
+
+    let mut rom = self::rom();
+    rom[MENU + 0x14D] ^= 0xFF;
+    assert_eq!(Metadata::from_rom(&rom).unwrap().title(), "GAME");
+}
+
+#[test]
+fn bank_0_mmm01_header_is_preferred() {
+    let mut rom = rom();
+    header(&mut rom, 0, 0x0D, b"FIRST");
+    let metadata = Metadata::from_rom(&rom).unwrap();
+    assert_eq!(metadata.title(), "FIRST");
+    assert_eq!(*metadata.cartridge_type(), CartridgeType::RomMmm01SramBattery);
+}
+
+#[test]
+fn menu_then_locked_game() {
+    let mut cartridge = new_cartridge_from_bytes(rom()).unwrap();
+    // the menu whatever the registers say
+    assert_eq!(banks(&*cartridge), (0x0E, 0x0F));
+    // game of 4 banks from bank 4: rom bank low bits 2-4 masked
+    cartridge.set(0x6000, 0b1110 << 2);
+    cartridge.set(0x2000, 0x04);
+    cartridge.set(0x4000, 0x00);
+    assert_eq!(banks(&*cartridge), (0x0E, 0x0F));
+
+    cartridge.set(0x0000, 0x40);
+    assert_eq!(banks(&*cartridge), (0x04, 0x05));
+    cartridge.set(0x2000, 0x02);
+    assert_eq!(banks(&*cartridge), (0x04, 0x06));
+    // the masked bits keep the base
+    cartridge.set(0x2000, 0x1F);
+    assert_eq!(banks(&*cartridge), (0x04, 0x07));
+    cartridge.set(0x2000, 0x00);
+    assert_eq!(banks(&*cartridge), (0x04, 0x05));
+
+    // the menu registers are locked
+    cartridge.set(0x6000, 0x00);
+    cartridge.set(0x2000, 0x61);
+    cartridge.set(0x4000, 0x30);
+    cartridge.set(0x0000, 0x00);
+    assert_eq!(banks(&*cartridge), (0x04, 0x05));
+    cartridge.set(0x2000, 0x1A);
+    assert_eq!(banks(&*cartridge), (0x04, 0x06));
+}
@@ -0,0 +1,117 @@
+//! Runs the mooneye-gb acceptance roms found in `$JIMBOT_TEST_ROMS/mooneye/acceptance`
+//! and the mapper roms of `$JIMBOT_TEST_ROMS/mooneye/emulator-only/mbc1`.
+//!
+//! A rom ends with `LD B,B`, it passed if the registers hold the fibonacci sequence (B=3, C=5, D=8, E=13, H=21, L=34).
+//! Acceptance roms listed in `tests/mooneye_passing.txt` must pass, the others are only reported.
+//! Every mbc1 rom must pass.
+//! Ignored without the test roms, run with `JIMBOT_TEST_ROMS=<dir> cargo test --release --test mooneye -- --ignored --nocapture`
+//! to see the table.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use jimbot::cpu::registers::R8;
+
+mod common;
+
+const MAX_FRAMES: u64 = 60 * 10;
+const FIBONACCI: [(R8, u8); 6] = [(R8::B, 3), (R8::C, 5), (R8::D, 8), (R8::E, 13), (R8::H, 21), (R8::L, 34)];
+
+enum Outcome {
+    Pass,
+    Fail,
+    Timeout,
+    Error(String),
+}
+
+fn run_mooneye(path: &str) -> Outcome {
+    let mut jimbot = common::load_test_rom(path);
+    jimbot.run_until(|jimbot| jimbot.cpu().is_at_breakpoint() || jimbot.frame_count() >= MAX_FRAMES);
+    if let Some(error) = jimbot.error_message() {
+        return Outcome::Error(error.clone());
+    }
+    if !jimbot.cpu().is_at_breakpoint() {
+        return Outcome::Timeout;
+    }
+    let registers = jimbot.cpu().registers();
+    if FIBONACCI.iter().all(|(r8, val)| registers.get8(*r8) == *val) { Outcome::Pass } else { Outcome::Fail }
+}
+
+/// Model specific roms have a suffix after the last `-`, keep the ones running on a DMG
+fn runs_on_dmg(file_name: &str) -> bool {
+    let Some((_, models)) = file_name.trim_end_matches(".gb").rsplit_once('-') else { return true };
+    if models.contains("dmgABC") { return true; }
+    models.chars().all(|c| c.is_ascii_uppercase()) && (models.contains('G') || models.contains('A'))
+}
+
+fn find_roms(dir: &Path, root: &Path, roms: &mut BTreeSet<String>) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            find_roms(&path, root, roms);
+        } else if path.extension().is_some_and(|extension| extension == "gb") {
+            let file_name = path.file_name().unwrap().to_string_lossy();
+            if runs_on_dmg(&file_name) {
+                let relative = path.strip_prefix(root).unwrap();
+                roms.insert(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs the test roms"]
+fn mooneye_acceptance() {
+    let expected: BTreeSet<&str> = include_str!("mooneye_passing.txt")
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+    let passed = run_suite(&["mooneye", "acceptance"], |rom| expected.contains(rom));
+    // an empty list would let every rom regress
+    assert!(!expected.is_empty(), "mooneye_passing.txt is empty, add the passing roms:\n{}", passed.join("\n"));
+}
+
+#[test]
+#[ignore = "needs the test roms"]
+fn mooneye_mbc1() {
+    run_suite(&["mooneye", "emulator-only", "mbc1"], |_| true);
+}
+
+/// Runs the roms found in `dirs` (relative to the test roms directory), fails if a rom `must_pass` does not
+/// or if there is no rom. Returns the roms that passed
+fn run_suite<F: Fn(&str) -> bool>(dirs: &[&str], must_pass: F) -> Vec<String> {
+    let root = common::test_roms_dir();
+    let suite_dir = dirs.iter().fold(root.clone(), |dir, name| dir.join(name));
+    let mut roms = BTreeSet::new();
+    find_roms(&suite_dir, &root, &mut roms);
+    assert!(!roms.is_empty(), "No mooneye roms in {} ({})", suite_dir.display(), common::NEEDS_TEST_ROMS);
+
+    let mut regressions = Vec::new();
+    let mut passed = Vec::new();
+    for rom in &roms {
+        let outcome = run_mooneye(rom);
+        let status = match &outcome {
+            Outcome::Pass => "pass".to_string(),
+            Outcome::Fail => "FAIL".to_string(),
+            Outcome::Timeout => "TIMEOUT".to_string(),
+            Outcome::Error(error) => format!("ERROR {}", error),
+        };
+        let expected_pass = must_pass(rom);
+        let note = match (&outcome, expected_pass) {
+            (Outcome::Pass, false) => "  (new, add to mooneye_passing.txt)",
+            (Outcome::Pass, true) => "",
+            (_, true) => "  (regression)",
+            (_, false) => "",
+        };
+        println!("{:<60} {}{}", rom, status, note);
+        match outcome {
+            Outcome::Pass => passed.push(rom.clone()),
+            _ if expected_pass => regressions.push(rom.clone()),
+            _ => {}
+        }
+    }
+    println!("{}/{} passed", passed.len(), roms.len());
+    assert!(regressions.is_empty(), "Mooneye regressions: {:?}", regressions);
+    passed
+}
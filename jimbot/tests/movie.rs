@@ -0,0 +1,132 @@
+//! Records joypad inputs and plays them back
+
+use jimbot::boot::BootMode;
+use jimbot::cartridge::new_cartridge_with_storage;
+use jimbot::cartridge::save_storage::MemoryStorage;
+use jimbot::jimbot::Jimbot;
+use jimbot::mmu::joypad::Key;
+use jimbot::movie::Movie;
+
+const FRAMES: u64 = 65;
+
+/// MBC1 with battery backed ram, stores the action buttons at $A000 and adds them up at $A001
+fn rom() -> Vec<u8> {
+    let program = [
+        0x3E, 0x0A, 0xEA, 0x00, 0x00, // ram enable
+        0x3E, 0x10, 0xE0, 0x00, 0xF0, 0x00, 0x47, // b <- action buttons
+        0xFA, 0x01, 0xA0, 0x80, 0xEA, 0x01, 0xA0,
+        0x78, 0xEA, 0x00, 0xA0,
+        0x18, 0xEC,
+    ];
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x147] = 0x03;
+    rom[0x149] = 0x02;
+    rom[0x150..0x150 + program.len()].copy_from_slice(&program);
+    rom
+}
+
+fn new_jimbot(storage: &MemoryStorage) -> Jimbot {
+    Jimbot::new(new_cartridge_with_storage(rom(), Box::new(storage.clone())).unwrap(), BootMode::Skip)
+}
+
+/// MBC3+TIMER+RAM+BATTERY
+fn rtc_jimbot(saved_seconds_ago: u64) -> Jimbot {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x147] = 0x10;
+    rom[0x149] = 0x02;
+    rom[0x150..0x152].copy_from_slice(&[0x18, 0xFE]);
+    // ram, clock and latched registers, then the unix time of the save
+    let mut save = vec![0; 0x2000 + 40];
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    save.extend_from_slice(&(now - saved_seconds_ago).to_le_bytes());
+    let storage = MemoryStorage::new(Some(save));
+    Jimbot::new(new_cartridge_with_storage(rom, Box::new(storage)).unwrap(), BootMode::Skip)
+}
+
+/// A pressed from frame 10 to 20, B from frame 30 to 31
+fn record() -> (Movie, Vec<u8>) {
+    let mut jimbot = new_jimbot(&MemoryStorage::new(Some(vec![0x11; 0x2000])));
+    jimbot.start_movie_recording().unwrap();
+    while jimbot.frame_count() < FRAMES {
+        match jimbot.frame_count() {
+            10 => jimbot.joypad_press(Key::A),
+            20 => jimbot.joypad_release(Key::A),
+            30 => jimbot.joypad_press(Key::B),
+            31 => jimbot.joypad_release(Key::B),
+            _ => {}
+        }
+        jimbot.run_frame();
+    }
+    (jimbot.stop_movie_recording().unwrap(), jimbot.save_state())
+}
+
+fn play(movie: Movie, storage: &MemoryStorage) -> Jimbot {
+    let mut jimbot = new_jimbot(storage);
+    jimbot.play_movie(movie).unwrap();
+    while jimbot.is_movie_playing() {
+        // ignored during the playback
+        jimbot.joypad_press(Key::Start);
+        jimbot.run_frame();
+    }
+    jimbot
+}
+
+#[test]
+fn record_and_play() {
+    let (movie, state) = record();
+    assert_eq!(movie.inputs().len(), 4);
+    assert_eq!(movie.hashes().iter().map(|hash| hash.frame).collect::<Vec<_>>(), [60]);
+    assert_eq!((movie.frames(), movie.save_ram().map(|ram| ram[0])), (FRAMES, Some(0x11)));
+    let text = movie.to_text();
+    assert_eq!(Movie::from_text(&text), Ok(movie.clone()));
+
+    let storage = MemoryStorage::new(Some(vec![0x22; 0x2000]));
+    let mut jimbot = play(Movie::from_text(&text).unwrap(), &storage);
+    let playback = jimbot.movie_playback().unwrap();
+    assert_eq!(playback.desync(), None);
+    assert_eq!(jimbot.frame_count(), FRAMES);
+    assert_eq!(jimbot.save_state(), state);
+
+    // the movie ram is not written over the player's save
+    jimbot.flush_save();
+    drop(jimbot);
+    assert_eq!(storage.bytes(), Some(vec![0x22; 0x2000]));
+}
+
+#[test]
+fn desync() {
+    let (movie, _) = record();
+    // B is never pressed
+    let text: String = movie.to_text().lines()
+        .filter(|line| !line.ends_with("press B"))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    let jimbot = play(Movie::from_text(&text).unwrap(), &MemoryStorage::default());
+    let desync = jimbot.movie_playback().unwrap().desync().unwrap();
+    assert_eq!(desync.frame, 60);
+    assert_eq!(desync.expected, movie.hashes()[0].crc32);
+    assert_ne!(desync.actual, desync.expected);
+}
+
+#[test]
+fn clock_is_recorded() {
+    let mut recorder = rtc_jimbot(1000);
+    recorder.start_movie_recording().unwrap();
+    while recorder.frame_count() < FRAMES {
+        recorder.run_frame();
+    }
+    let movie = recorder.stop_movie_recording().unwrap();
+    assert!(movie.clock().is_some());
+    assert_eq!(Movie::from_text(&movie.to_text()), Ok(movie.clone()));
+
+    // the clock caught up on another host time
+    let mut jimbot = rtc_jimbot(50000);
+    jimbot.play_movie(movie.clone()).unwrap();
+    while jimbot.is_movie_playing() {
+        jimbot.run_frame();
+    }
+    assert_eq!(jimbot.movie_playback().unwrap().desync(), None);
+    assert_eq!(jimbot.save_state(), recorder.save_state());
+}
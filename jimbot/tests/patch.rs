@@ -0,0 +1,150 @@
+//! Applies hand built IPS, UPS and BPS patches
+
+use jimbot::patch::{apply, apply_all, PatchError};
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 })
+    })
+}
+
+fn number(patch: &mut Vec<u8>, mut val: usize) {
+    loop {
+        let bits = (val & 0x7F) as u8;
+        val >>= 7;
+        if val == 0 {
+            patch.push(0x80 | bits);
+            return;
+        }
+        patch.push(bits);
+        val -= 1;
+    }
+}
+
+/// appends the source, target and patch crc32
+fn checksums(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+    patch.extend_from_slice(&crc32(source).to_le_bytes());
+    patch.extend_from_slice(&crc32(target).to_le_bytes());
+    let crc = crc32(&patch);
+    patch.extend_from_slice(&crc.to_le_bytes());
+    patch
+}
+
+fn rom() -> Vec<u8> {
+    (0..=255).collect()
+}
+
+#[test]
+fn ips() {
+    let mut patch = b"PATCH".to_vec();
+    // record at $10, RLE record at $FE extending the rom, end and truncation to $101 bytes
+    patch.extend_from_slice(&[0x00, 0x00, 0x10, 0x00, 0x02, 0xAA, 0xBB]);
+    patch.extend_from_slice(&[0x00, 0x00, 0xFE, 0x00, 0x00, 0x00, 0x04, 0xCC]);
+    patch.extend_from_slice(b"EOF");
+    patch.extend_from_slice(&[0x00, 0x01, 0x01]);
+    let target = apply(&rom(), &patch).unwrap();
+    assert_eq!(target.len(), 0x101);
+    assert_eq!(target[0x0F..0x13], [0x0F, 0xAA, 0xBB, 0x12]);
+    assert_eq!(target[0xFD..], [0xFD, 0xCC, 0xCC, 0xCC]);
+    assert_eq!(apply(&rom(), &patch[..patch.len() - 6]), Err(PatchError::Truncated));
+}
+
+#[test]
+fn ups_both_ways() {
+    let source = rom();
+    let mut target = source.clone();
+    target[3] ^= 0x55;
+    target[4] ^= 0x01;
+    target.push(0x77);
+    let mut patch = b"UPS1".to_vec();
+    number(&mut patch, source.len());
+    number(&mut patch, target.len());
+    number(&mut patch, 3);
+    patch.extend_from_slice(&[0x55, 0x01, 0x00]);
+    number(&mut patch, 0x100 - 6);
+    patch.extend_from_slice(&[0x77, 0x00]);
+    let patch = checksums(patch, &source, &target);
+    assert_eq!(apply(&source, &patch).unwrap(), target);
+    assert_eq!(apply(&target, &patch).unwrap(), source);
+    assert!(matches!(apply(&source[1..], &patch), Err(PatchError::SourceChecksum { .. })));
+}
+
+#[test]
+fn bps_actions() {
+    let source = rom();
+    // source read 4, target read 2, target copy 6 (repeats the 2 bytes read), source copy 4 from $80
+    // then source read back at $10
+    let mut target = source[0..4].to_vec();
+    target.extend_from_slice(&[0xDE, 0xAD, 0xDE, 0xAD, 0xDE, 0xAD, 0xDE, 0xAD]);
+    target.extend_from_slice(&source[0x80..0x84]);
+    target.extend_from_slice(&source[0x10..0x12]);
+    let mut patch = b"BPS1".to_vec();
+    number(&mut patch, source.len());
+    number(&mut patch, target.len());
+    number(&mut patch, 0);
+    number(&mut patch, 3 << 2);
+    number(&mut patch, (1 << 2) | 1);
+    patch.extend_from_slice(&[0xDE, 0xAD]);
+    number(&mut patch, (5 << 2) | 3);
+    number(&mut patch, 4 << 1);
+    number(&mut patch, (3 << 2) | 2);
+    number(&mut patch, 0x80 << 1);
+    number(&mut patch, 1 << 2);
+    let patch = checksums(patch, &source, &target);
+    assert_eq!(apply(&source, &patch).unwrap(), target);
+
+    let mut corrupted = patch.clone();
+    corrupted[8] ^= 1;
+    assert!(matches!(apply(&source, &corrupted), Err(PatchError::PatchChecksum { .. })));
+    assert_eq!(apply(&source, b"NOT A PATCH"), Err(PatchError::UnknownFormat));
+}
+
+#[test]
+fn chained_patches() {
+    let first = [b"PATCH".as_slice(), &[0x00, 0x00, 0x00, 0x00, 0x01, 0x11], b"EOF"].concat();
+    let second = [b"PATCH".as_slice(), &[0x00, 0x00, 0x01, 0x00, 0x01, 0x22], b"EOF"].concat();
+    let target = apply_all(rom(), &[first, second]).unwrap();
+    assert_eq!(target[0..3], [0x11, 0x22, 0x02]);
+}
+
+#[test]
+fn crafted_sizes_are_rejected() {
+    let source = rom();
+    // a target of 2^61 bytes is not allocated
+    let mut patch = b"BPS1".to_vec();
+    number(&mut patch, source.len());
+    number(&mut patch, 1 << 61);
+    number(&mut patch, 0);
+    let patch = checksums(patch, &source, &source);
+    assert_eq!(apply(&source, &patch), Err(PatchError::OutOfBounds));
+
+    let mut patch = b"UPS1".to_vec();
+    number(&mut patch, source.len());
+    number(&mut patch, 1 << 61);
+    let patch = checksums(patch, &source, &source);
+    assert_eq!(apply(&source, &patch), Err(PatchError::OutOfBounds));
+
+    // skips past the end of the rom
+    let mut patch = b"UPS1".to_vec();
+    number(&mut patch, source.len());
+    number(&mut patch, source.len());
+    number(&mut patch, 0x10);
+    patch.extend_from_slice(&[0x01, 0x00]);
+    number(&mut patch, usize::MAX - 0x10);
+    patch.extend_from_slice(&[0x01, 0x00]);
+    let patch = checksums(patch, &source, &source);
+    assert_eq!(apply(&source, &patch), Err(PatchError::OutOfBounds));
+
+    // source copy far out of the rom
+    let mut patch = b"BPS1".to_vec();
+    number(&mut patch, source.len());
+    number(&mut patch, 4);
+    number(&mut patch, 0);
+    number(&mut patch, (3 << 2) | 2);
+    number(&mut patch, usize::MAX - 1);
+    let patch = checksums(patch, &source, &source);
+    assert_eq!(apply(&source, &patch), Err(PatchError::OutOfBounds));
+
+    let ips = [b"PATCH".as_slice(), &[0xFF, 0xFF, 0xFE, 0x00, 0x01, 0x11], b"EOF"].concat();
+    assert_eq!(apply(&source, &ips), Err(PatchError::OutOfBounds));
+}
@@ -0,0 +1,57 @@
+//! Feeds printer packets as a game would send them on the serial port
+
+use jimbot::serial::printer::GameBoyPrinter;
+use jimbot::serial::SerialDevice;
+
+/// sends a packet, returns the keep alive and status bytes
+fn send_packet(printer: &mut GameBoyPrinter, command: u8, compression: u8, data: &[u8]) -> (u8, u8) {
+    let mut packet = vec![0x88, 0x33, command, compression, data.len() as u8, (data.len() >> 8) as u8];
+    packet.extend_from_slice(data);
+    let checksum = packet[2..].iter().fold(0u16, |sum, byte| sum.wrapping_add(*byte as u16));
+    packet.extend_from_slice(&checksum.to_le_bytes());
+    packet.extend_from_slice(&[0, 0]);
+    let response: Vec<u8> = packet.into_iter().map(|byte| printer.exchange(byte)).collect();
+    (response[response.len() - 2], response[response.len() - 1])
+}
+
+#[test]
+fn print_job() {
+    let printer = GameBoyPrinter::default();
+    let mut device = printer.clone();
+    assert_eq!(send_packet(&mut device, 0x01, 0, &[]), (0x81, 0x00));
+
+    // 2 rows of tiles, every line of a tile uses the color (tile + line) % 4
+    let mut tiles = Vec::new();
+    for tile in 0..40 {
+        for line in 0..8 {
+            let color = (tile + line) % 4;
+            tiles.push(if color & 1 == 1 { 0xFF } else { 0x00 });
+            tiles.push(if color & 2 == 2 { 0xFF } else { 0x00 });
+        }
+    }
+    assert_eq!(send_packet(&mut device, 0x04, 0, &tiles), (0x81, 0x08));
+    // 640 bytes of $AA compressed in runs of 129 and 124
+    let compressed = [0xFF, 0xAA, 0xFF, 0xAA, 0xFF, 0xAA, 0xFF, 0xAA, 0xFA, 0xAA];
+    assert_eq!(send_packet(&mut device, 0x04, 1, &compressed), (0x81, 0x08));
+    assert_eq!(send_packet(&mut device, 0x04, 0, &[]), (0x81, 0x0C));
+    assert_eq!(send_packet(&mut device, 0x02, 0, &[1, 0x13, 0xE4, 0x40]), (0x81, 0x06));
+    while send_packet(&mut device, 0x0F, 0, &[]).1 & 0x02 != 0 {}
+
+    let images = printer.take_images();
+    assert_eq!(images.len(), 1);
+    let image = &images[0];
+    assert_eq!((image.width(), image.height()), (160, 32));
+    assert_eq!(image.shades()[0], 0);
+    assert_eq!(image.shades()[160], 1);
+    assert_eq!(image.shades()[160 * 3], 3);
+    assert_eq!(&image.shades()[160 * 16..160 * 16 + 2], &[3, 0]);
+    assert!(image.to_png().starts_with(b"\x89PNG\r\n\x1a\n"));
+}
+
+#[test]
+fn checksum_error() {
+    let mut printer = GameBoyPrinter::default();
+    let mut packet = vec![0x88, 0x33, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00];
+    let response: Vec<u8> = packet.drain(..).map(|byte| printer.exchange(byte)).collect();
+    assert_eq!(&response[8..], &[0x81, 0x01]);
+}
@@ -0,0 +1,86 @@
+//! Stores snapshots in the rewind buffer and goes back in time
+
+use jimbot::boot::BootMode;
+use jimbot::cartridge::new_cartridge_from_bytes;
+use jimbot::jimbot::Jimbot;
+use jimbot::rewind::Rewind;
+
+#[test]
+fn delta_round_trip() {
+    let mut rewind = Rewind::new(1, usize::MAX);
+    let first: Vec<u8> = (0..=255).collect();
+    let different: Vec<u8> = first.iter().map(|byte| !byte).collect();
+    let longer: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+    let shorter = vec![0x42; 10];
+    let snapshots = [first.clone(), first.clone(), different, longer, shorter.clone(), vec![], first];
+    for (frame, snapshot) in snapshots.iter().enumerate() {
+        rewind.push(frame as u64, snapshot.clone());
+    }
+    assert_eq!(rewind.len(), snapshots.len());
+    // an identical snapshot costs its header only
+    assert!(rewind.size() < 3 * 256 + 1000 + 10 + 32);
+    for (frame, snapshot) in snapshots.iter().enumerate().rev() {
+        assert_eq!(rewind.rewind_to(frame as u64).as_ref(), Some(snapshot), "frame {}", frame);
+    }
+    assert_eq!(rewind.len(), 1);
+}
+
+#[test]
+fn oldest_snapshots_are_evicted() {
+    let mut rewind = Rewind::new(1, 1000);
+    for frame in 0..100 {
+        rewind.push(frame, vec![frame as u8; 200]);
+    }
+    assert!(rewind.size() <= 1000);
+    assert!(rewind.len() < 100);
+    let oldest = rewind.oldest_frame().unwrap();
+    assert!(oldest > 0);
+    // the history stops at the oldest snapshot left
+    assert_eq!(rewind.rewind_to(0), Some(vec![oldest as u8; 200]));
+
+    // a snapshot older than the newest one clears the history
+    rewind.push(oldest + 1, vec![1; 200]);
+    rewind.push(0, vec![0; 200]);
+    assert_eq!((rewind.len(), rewind.oldest_frame()), (1, Some(0)));
+}
+
+/// counts the frames in $C000
+fn counter_rom() -> Vec<u8> {
+    let program = [
+        0xF0, 0x44, 0xFE, 0x90, 0x20, 0xFA, // wait for line 144
+        0xFA, 0x00, 0xC0, 0x3C, 0xEA, 0x00, 0xC0, // ($C000)++
+        0xF0, 0x44, 0xFE, 0x90, 0x28, 0xFA, // wait for the end of line 144
+        0x18, 0xED,
+    ];
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x150..0x150 + program.len()].copy_from_slice(&program);
+    rom
+}
+
+#[test]
+fn rewind_frames() {
+    let mut jimbot = Jimbot::new(new_cartridge_from_bytes(counter_rom()).unwrap(), BootMode::Skip);
+    // nothing to rewind yet
+    assert_eq!(jimbot.rewind_frames(10).unwrap().number(), 0);
+    let mut invalid = Rewind::default();
+    invalid.push(0, b"not a state".to_vec());
+    jimbot.enable_rewind(invalid);
+    assert!(jimbot.rewind_frames(1).is_err());
+    assert_eq!(jimbot.frame().number(), 0);
+    jimbot.enable_rewind(Rewind::new(2, 1024 * 1024));
+    let mut states = Vec::new();
+    for _ in 0..20 {
+        jimbot.run_frame();
+        states.push(jimbot.save_state());
+    }
+    assert_eq!(jimbot.mmu().wram()[0], 20);
+
+    // frame 15 is between two snapshots, it is run again from frame 14
+    assert_eq!(jimbot.rewind_frames(5).unwrap().number(), 15);
+    assert_eq!(jimbot.mmu().wram()[0], 15);
+    assert_eq!(jimbot.save_state(), states[14]);
+    assert_eq!(jimbot.rewind_frames(100).unwrap().number(), 1);
+    assert_eq!(jimbot.rewind().unwrap().len(), 1);
+    assert_eq!(jimbot.save_state(), states[0]);
+}
@@ -0,0 +1,150 @@
+//! Drives the MBC3 real time clock through the cartridge registers
+
+use jimbot::cartridge::{new_cartridge_with_storage, Cartridge};
+use jimbot::cartridge::save_storage::MemoryStorage;
+use common::{now, run_seconds};
+
+mod common;
+
+const RAM_SIZE: usize = 0x2000;
+
+/// MBC3+TIMER+RAM+BATTERY with 8 KiB of ram, loaded from `save`
+fn cartridge(save: Option<Vec<u8>>) -> (Box<dyn Cartridge>, MemoryStorage) {
+    let mut rom = vec![0; 0x8000];
+    rom[0x147] = 0x10;
+    rom[0x149] = 0x02;
+    let (mut cartridge, storage) = common::cartridge_with_save(rom, save);
+    cartridge.set(0x0000, 0x0A);
+    (cartridge, storage)
+}
+
+fn write(cartridge: &mut Box<dyn Cartridge>, register: u8, val: u8) {
+    cartridge.set(0x4000, register);
+    cartridge.set(0xA000, val);
+}
+
+fn latch(cartridge: &mut Box<dyn Cartridge>) {
+    cartridge.set(0x6000, 0x00);
+    cartridge.set(0x6000, 0x01);
+}
+
+/// latched seconds, minutes, hours, day low and day high
+fn read(cartridge: &mut Box<dyn Cartridge>) -> Vec<u8> {
+    (0x08..=0x0C).map(|register| {
+        cartridge.set(0x4000, register);
+        cartridge.get(0xA000)
+    }).collect()
+}
+
+/// save with the clock and latched registers, the time of the save as a u64 (48 bytes) or u32 (44 bytes)
+fn save(registers: [u8; 5], latched: [u8; 5], timestamp: u64, footer_size: usize) -> Vec<u8> {
+    let mut save = vec![0x5A; RAM_SIZE];
+    for register in registers.into_iter().chain(latched) {
+        save.extend_from_slice(&(register as u32).to_le_bytes());
+    }
+    save.extend_from_slice(&timestamp.to_le_bytes()[..footer_size - 40]);
+    save
+}
+
+#[test]
+fn seconds_carry_to_days() {
+    let (mut cartridge, _) = cartridge(None);
+    for (register, val) in [(0x08, 59), (0x09, 59), (0x0A, 23), (0x0B, 0x41), (0x0C, 0x00)] {
+        write(&mut cartridge, register, val);
+    }
+    run_seconds(&mut cartridge, 1);
+    latch(&mut cartridge);
+    assert_eq!(read(&mut cartridge), [0, 0, 0, 0x42, 0x00]);
+}
+
+#[test]
+fn day_counter_overflow_sets_the_carry() {
+    let (mut cartridge, _) = cartridge(None);
+    for (register, val) in [(0x08, 59), (0x09, 59), (0x0A, 23), (0x0B, 0xFF), (0x0C, 0x01)] {
+        write(&mut cartridge, register, val);
+    }
+    run_seconds(&mut cartridge, 1);
+    latch(&mut cartridge);
+    assert_eq!(read(&mut cartridge), [0, 0, 0, 0x00, 0x80]);
+    // the carry stays until the game clears it
+    run_seconds(&mut cartridge, 1);
+    latch(&mut cartridge);
+    assert_eq!(read(&mut cartridge), [1, 0, 0, 0x00, 0x80]);
+    write(&mut cartridge, 0x0C, 0x00);
+    latch(&mut cartridge);
+    assert_eq!(read(&mut cartridge)[4], 0x00);
+}
+
+#[test]
+fn halt_stops_the_clock() {
+    let (mut cartridge, _) = cartridge(None);
+    write(&mut cartridge, 0x08, 10);
+    write(&mut cartridge, 0x0C, 0x40);
+    run_seconds(&mut cartridge, 2);
+    latch(&mut cartridge);
+    assert_eq!(read(&mut cartridge), [10, 0, 0, 0, 0x40]);
+    write(&mut cartridge, 0x0C, 0x00);
+    run_seconds(&mut cartridge, 2);
+    latch(&mut cartridge);
+    assert_eq!(read(&mut cartridge), [12, 0, 0, 0, 0x00]);
+}
+
+#[test]
+fn latch_needs_0_then_1() {
+    let (mut cartridge, _) = cartridge(None);
+    write(&mut cartridge, 0x08, 30);
+    // reads the latched copy, not the clock
+    assert_eq!(read(&mut cartridge)[0], 0);
+    cartridge.set(0x6000, 0x01);
+    assert_eq!(read(&mut cartridge)[0], 0);
+    cartridge.set(0x6000, 0x00);
+    assert_eq!(read(&mut cartridge)[0], 0);
+    cartridge.set(0x6000, 0x01);
+    assert_eq!(read(&mut cartridge)[0], 30);
+    // the copy does not follow the clock
+    run_seconds(&mut cartridge, 1);
+    assert_eq!(read(&mut cartridge)[0], 30);
+    cartridge.set(0x6000, 0x01);
+    assert_eq!(read(&mut cartridge)[0], 30);
+    latch(&mut cartridge);
+    assert_eq!(read(&mut cartridge)[0], 31);
+}
+
+#[test]
+fn save_footer() {
+    // halted, the host time does not move the clock
+    let registers = [10, 20, 5, 0x34, 0x41];
+    let latched = [1, 2, 3, 4, 0x40];
+    for footer_size in [48, 44] {
+        let (mut cartridge, storage) = cartridge(Some(save(registers, latched, 1000, footer_size)));
+        assert_eq!(cartridge.save_data().unwrap(), &vec![0x5A; RAM_SIZE]);
+        assert_eq!(read(&mut cartridge), latched);
+        latch(&mut cartridge);
+        assert_eq!(read(&mut cartridge), registers);
+
+        cartridge.flush_save();
+        let saved = storage.bytes().unwrap();
+        // always written with a u64 time
+        assert_eq!(saved.len(), RAM_SIZE + 48);
+        assert_eq!(saved[..RAM_SIZE + 40], save(registers, registers, 0, 48)[..RAM_SIZE + 40]);
+        let timestamp = u64::from_le_bytes(saved[RAM_SIZE + 40..].try_into().unwrap());
+        assert!(timestamp.abs_diff(now()) < 60);
+    }
+    // wrong footer size
+    let mut bad = save(registers, latched, 1000, 48);
+    bad.pop();
+    let mut rom = vec![0; 0x8000];
+    rom[0x147] = 0x10;
+    rom[0x149] = 0x02;
+    assert!(new_cartridge_with_storage(rom, Box::new(MemoryStorage::new(Some(bad)))).is_err());
+}
+
+#[test]
+fn catches_up_on_host_time() {
+    // saved 2 days and 5 seconds ago
+    let (mut cartridge, _) = cartridge(Some(save([0; 5], [0; 5], now() - 2 * 86400 - 5, 48)));
+    latch(&mut cartridge);
+    let registers = read(&mut cartridge);
+    assert!((5..=6).contains(&registers[0]), "{:?}", registers);
+    assert_eq!(registers[1..], [0, 0, 2, 0]);
+}
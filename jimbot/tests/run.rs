@@ -0,0 +1,74 @@
+//! Steps the machine by frame, by m-cycles and until a condition holds
+
+use jimbot::boot::BootMode;
+use jimbot::cartridge::new_cartridge_from_bytes;
+use jimbot::jimbot::Jimbot;
+
+const FRAME: u64 = Jimbot::M_CYCLES_PER_FRAME as u64;
+
+/// counts the frames in $C000, with the lcd on or off
+fn counter_rom(lcd_on: bool) -> Vec<u8> {
+    let lcdc = if lcd_on { 0x91 } else { 0x11 };
+    let program = [
+        0x3E, lcdc, 0xE0, 0x40, // LDH (LCDC), lcdc
+        0xFA, 0x00, 0xC0, 0x3C, 0xEA, 0x00, 0xC0, // ($C000)++
+        0x18, 0xF7,
+    ];
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x150..0x150 + program.len()].copy_from_slice(&program);
+    rom
+}
+
+fn jimbot(lcd_on: bool) -> Jimbot {
+    Jimbot::new(new_cartridge_from_bytes(counter_rom(lcd_on)).unwrap(), BootMode::Skip)
+}
+
+#[test]
+fn run_frame() {
+    for lcd_on in [true, false] {
+        let mut jimbot = jimbot(lcd_on);
+        // the first frame ends wherever the skipped boot left the PPU
+        let first = jimbot.run_frame().cycles();
+        assert!(first > 0 && first <= FRAME, "lcd {}: first frame after {} m-cycles", lcd_on, first);
+        for frame in 2..10 {
+            let (number, cycles) = { let frame = jimbot.run_frame(); (frame.number(), frame.cycles()) };
+            assert_eq!(number, frame, "lcd {}", lcd_on);
+            assert_eq!(cycles, first + (frame - 1) * FRAME, "lcd {}: frame {}", lcd_on, frame);
+        }
+    }
+}
+
+#[test]
+fn run_cycles() {
+    let mut jimbot = jimbot(true);
+    for n in [0, 1, 7, FRAME - 1, FRAME, 3 * FRAME + 5] {
+        let before = jimbot.cycle_count();
+        assert_eq!(jimbot.run_cycles(n).cycles(), before + n);
+    }
+    // frames follow the first one every FRAME m-cycles
+    let first = self::jimbot(true).run_frame().cycles();
+    assert_eq!(jimbot.frame_count(), 1 + (jimbot.cycle_count() - first) / FRAME);
+}
+
+#[test]
+fn run_until() {
+    let mut jimbot = jimbot(true);
+    assert_eq!(jimbot.run_until(|jimbot| jimbot.cycle_count() == 1234).cycles(), 1234);
+    // already true: one m-cycle is run before checking
+    assert_eq!(jimbot.run_until(|_| true).cycles(), 1235);
+
+    let mut jimbot = self::jimbot(true);
+    let mut checks = 0;
+    let stop = jimbot.run_until(|jimbot| {
+        checks += 1;
+        jimbot.mmu().get(0xC000) == 3
+    }).cycles();
+    assert_eq!(checks, stop);
+    // the cycle before did not hold
+    let mut jimbot = self::jimbot(true);
+    jimbot.run_cycles(stop - 1);
+    assert_eq!(jimbot.mmu().get(0xC000), 2);
+    jimbot.run_cycles(1);
+    assert_eq!(jimbot.mmu().get(0xC000), 3);
+}
@@ -0,0 +1,105 @@
+//! Save state round trip and every load error, a rejected state leaves the machine untouched
+
+use jimbot::boot::BootMode;
+use jimbot::cartridge::new_cartridge_from_bytes;
+use jimbot::jimbot::Jimbot;
+use jimbot::save_state::{SaveStateError, MAGIC, VERSION};
+
+const TITLE: &[u8] = b"SAVESTATE";
+
+/// increments $C000 forever, `title` in the header
+fn jimbot(title: &[u8]) -> Jimbot {
+    let program = [0x21, 0x00, 0xC0, 0x34, 0x18, 0xFD];
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x134..0x134 + title.len()].copy_from_slice(title);
+    rom[0x150..0x150 + program.len()].copy_from_slice(&program);
+    Jimbot::new(new_cartridge_from_bytes(rom).unwrap(), BootMode::Skip)
+}
+
+/// a state made a few frames in
+fn state() -> Vec<u8> {
+    let mut jimbot = jimbot(TITLE);
+    for _ in 0..3 {
+        jimbot.run_frame();
+    }
+    jimbot.save_state()
+}
+
+/// `bytes` is rejected with `error` and the machine keeps its state
+fn assert_rejected(jimbot: &mut Jimbot, bytes: &[u8], error: SaveStateError) {
+    let before = jimbot.save_state();
+    assert_eq!(jimbot.load_state(bytes), Err(error));
+    assert_eq!(jimbot.save_state(), before);
+}
+
+/// offset of the "JBOT" section: magic, version, title and checksums
+fn first_section() -> usize {
+    4 + 2 + 4 + TITLE.len() + 3
+}
+
+#[test]
+fn round_trip() {
+    let state = state();
+    assert_eq!(&state[..4], MAGIC);
+    assert_eq!(&state[first_section()..first_section() + 4], b"JBOT");
+    let mut jimbot = jimbot(TITLE);
+    jimbot.run_frame();
+    jimbot.load_state(&state).unwrap();
+    assert_eq!(jimbot.save_state(), state);
+    assert_eq!(jimbot.frame().number(), 3);
+}
+
+#[test]
+fn bad_magic() {
+    let mut jimbot = jimbot(TITLE);
+    jimbot.run_frame();
+    let mut state = state();
+    state[0] = b'X';
+    assert_rejected(&mut jimbot, &state, SaveStateError::BadMagic);
+    assert_rejected(&mut jimbot, b"JB", SaveStateError::BadMagic);
+}
+
+#[test]
+fn unsupported_version() {
+    let mut jimbot = jimbot(TITLE);
+    jimbot.run_frame();
+    let mut state = state();
+    state[4..6].copy_from_slice(&(VERSION - 1).to_le_bytes());
+    assert_rejected(&mut jimbot, &state, SaveStateError::UnsupportedVersion(VERSION - 1));
+}
+
+#[test]
+fn rom_mismatch() {
+    let mut jimbot = self::jimbot(b"OTHER");
+    jimbot.run_frame();
+    assert_rejected(&mut jimbot, &state(), SaveStateError::RomMismatch);
+}
+
+#[test]
+fn truncated() {
+    let mut jimbot = jimbot(TITLE);
+    jimbot.run_frame();
+    let state = state();
+    for len in [5, first_section() + 2, state.len() / 2, state.len() - 1] {
+        assert_rejected(&mut jimbot, &state[..len], SaveStateError::UnexpectedEof);
+    }
+}
+
+#[test]
+fn bad_section() {
+    let mut jimbot = jimbot(TITLE);
+    jimbot.run_frame();
+    let mut state = state();
+    state[first_section()] = b'X';
+    assert_rejected(&mut jimbot, &state, SaveStateError::BadSection(*b"XBOT"));
+}
+
+#[test]
+fn invalid_value() {
+    let mut jimbot = jimbot(TITLE);
+    jimbot.run_frame();
+    let mut state = state();
+    state.push(0);
+    assert_rejected(&mut jimbot, &state, SaveStateError::InvalidValue("trailing data"));
+}
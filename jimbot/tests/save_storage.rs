@@ -0,0 +1,107 @@
+//! Battery backed ram flushed to the save storage
+
+use std::path::PathBuf;
+use jimbot::cartridge::{new_cartridge_with_storage, Cartridge};
+use jimbot::cartridge::save_storage::{FileStorage, MemoryStorage, SaveStorage};
+
+/// `Battery::IDLE_M_CYCLES`, about 1 second
+const IDLE: u32 = 1 << 20;
+/// `Battery::MAX_DELAY_M_CYCLES`, about 10 seconds
+const MAX_DELAY: u32 = 10 << 20;
+
+/// MBC1+RAM+BATTERY with 8 KiB of ram, enabled
+fn cartridge(storage: &MemoryStorage) -> Box<dyn Cartridge> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x147] = 0x03;
+    rom[0x149] = 0x02;
+    let mut cartridge = new_cartridge_with_storage(rom, Box::new(storage.clone())).unwrap();
+    cartridge.set(0x0000, 0x0A);
+    cartridge
+}
+
+fn run(cartridge: &mut Box<dyn Cartridge>, m_cycles: u32) {
+    for _ in 0..m_cycles {
+        cartridge.cycle();
+    }
+}
+
+fn saved_byte(storage: &MemoryStorage) -> Option<u8> {
+    storage.bytes().map(|bytes| bytes[0])
+}
+
+#[test]
+fn flushed_once_idle() {
+    let storage = MemoryStorage::default();
+    let mut cartridge = cartridge(&storage);
+    run(&mut cartridge, 2 * IDLE);
+    assert_eq!(saved_byte(&storage), None);
+    cartridge.set(0xA000, 0x42);
+    run(&mut cartridge, IDLE - 1);
+    assert_eq!(saved_byte(&storage), None);
+    run(&mut cartridge, 1);
+    assert_eq!(saved_byte(&storage), Some(0x42));
+    assert_eq!(storage.bytes().unwrap().len(), 0x2000);
+}
+
+#[test]
+fn flushed_after_max_delay_while_writing() {
+    let storage = MemoryStorage::default();
+    let mut cartridge = cartridge(&storage);
+    let mut elapsed = 0;
+    while elapsed < MAX_DELAY - 1 {
+        cartridge.set(0xA000, (elapsed / (IDLE / 2)) as u8);
+        let m_cycles = (IDLE / 2).min(MAX_DELAY - 1 - elapsed);
+        run(&mut cartridge, m_cycles);
+        elapsed += m_cycles;
+    }
+    assert_eq!(saved_byte(&storage), None);
+    run(&mut cartridge, 1);
+    assert_eq!(saved_byte(&storage), Some(19));
+}
+
+#[test]
+fn flushed_on_request_and_drop() {
+    let storage = MemoryStorage::default();
+    let mut cartridge = cartridge(&storage);
+    cartridge.set(0xA000, 0x01);
+    cartridge.flush_save();
+    assert_eq!(saved_byte(&storage), Some(0x01));
+    cartridge.set(0xA000, 0x02);
+    drop(cartridge);
+    assert_eq!(saved_byte(&storage), Some(0x02));
+
+    // loaded back
+    let cartridge = self::cartridge(&storage);
+    assert_eq!(cartridge.save_data().unwrap()[0], 0x02);
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("jimbot-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn file_storage_renames_and_backs_up() {
+    let dir = temp_dir("file-storage");
+    let path = dir.join("game.sav");
+    let read = |suffix: &str| std::fs::read(dir.join(format!("game.sav{}", suffix))).ok();
+    let mut storage = FileStorage::new(&path);
+    assert_eq!(storage.load().unwrap(), None);
+
+    storage.store(b"one").unwrap();
+    assert_eq!((read(""), read(".bak")), (Some(b"one".to_vec()), None));
+    storage.store(b"two").unwrap();
+    assert_eq!((read(""), read(".bak")), (Some(b"two".to_vec()), Some(b"one".to_vec())));
+    // the backup follows every write
+    storage.store(b"three").unwrap();
+    assert_eq!((read(""), read(".bak")), (Some(b"three".to_vec()), Some(b"two".to_vec())));
+    // written through a temporary file renamed over the save
+    assert_eq!(read(".tmp"), None);
+    assert_eq!(FileStorage::new(&path).load().unwrap(), Some(b"three".to_vec()));
+
+    let storage = FileStorage::next_to_rom(dir.join("game.gb").to_str().unwrap(), "TITLE");
+    assert_eq!(storage.path(), dir.join("TITLE.sav"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
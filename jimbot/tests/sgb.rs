@@ -0,0 +1,112 @@
+//! Sends SGB command packets through the joypad register
+
+use jimbot::boot::BootMode;
+use jimbot::cartridge::new_cartridge_from_bytes;
+use jimbot::jimbot::Jimbot;
+use jimbot::sgb::{LCD_X, LCD_Y, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// sends the packet at hl (16 bytes), hl points to the next packet after the call
+const SEND_PACKET: [u8; 42] = [
+    0xAF, 0xE0, 0x00, // reset pulse
+    0x3E, 0x30, 0xE0, 0x00,
+    0x16, 0x10, // ld d, 16
+    0x2A, 0x5F, 0x06, 0x08, // e <- (hl+), b <- 8
+    0x3E, 0x20, 0xCB, 0x3B, 0x30, 0x02, 0x3E, 0x10, // P14 low for a 0, P15 low for a 1
+    0xE0, 0x00, 0x3E, 0x30, 0xE0, 0x00,
+    0x05, 0x20, 0xEF, // next bit
+    0x15, 0x20, 0xE8, // next byte
+    0x3E, 0x20, 0xE0, 0x00, 0x3E, 0x30, 0xE0, 0x00, // stop bit
+    0xC9,
+];
+
+/// SGB rom running `program` at $0150, `packets` at $0200 and `SEND_PACKET` at $0180
+fn rom(program: &[u8], packets: &[[u8; 16]]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x146] = 0x03;
+    rom[0x14B] = 0x33;
+    rom[0x150..0x150 + program.len()].copy_from_slice(program);
+    rom[0x180..0x180 + SEND_PACKET.len()].copy_from_slice(&SEND_PACKET);
+    rom[0x200..0x200 + packets.len() * 16].copy_from_slice(&packets.concat());
+    rom
+}
+
+fn packet(command: u8, data: &[u8]) -> [u8; 16] {
+    let mut packet = [0; 16];
+    packet[0] = (command << 3) | 1;
+    packet[1..1 + data.len()].copy_from_slice(data);
+    packet
+}
+
+fn run(rom: Vec<u8>, frames: usize) -> Jimbot {
+    let mut jimbot = Jimbot::new(new_cartridge_from_bytes(rom).unwrap(), BootMode::Skip);
+    for _ in 0..frames {
+        jimbot.run_frame();
+    }
+    jimbot
+}
+
+#[test]
+fn palettes_attributes_and_multiplayer() {
+    let program = [
+        0x3E, 0xFF, 0xE0, 0x47, // BGP: every pixel in shade 3
+        0x21, 0x00, 0x02, 0x0E, 0x03, // 3 packets at $0200
+        0xCD, 0x80, 0x01, 0x0D, 0x20, 0xFA,
+        0xF0, 0x00, 0xEA, 0x00, 0xC0, // id of the first joypad
+        0x3E, 0x10, 0xE0, 0x00, 0x3E, 0x30, 0xE0, 0x00,
+        0xF0, 0x00, 0xEA, 0x01, 0xC0, // id of the second joypad
+        0x18, 0xFE,
+    ];
+    let packets = [
+        // PAL01: color 0 white, palette 0 red, green, blue, palette 1 darker
+        packet(0x00, &[0xFF, 0x7F, 0x1F, 0x00, 0xE0, 0x03, 0x00, 0x7C, 0x10, 0x00, 0x00, 0x02, 0x00, 0x40]),
+        // ATTR_DIV: palette 0 left of column 10, palette 1 on it and after
+        packet(0x06, &[0x11, 10]),
+        // MLT_REQ: 2 joypads
+        packet(0x11, &[0x01]),
+    ];
+    let jimbot = run(rom(&program, &packets), 3);
+    let sgb = jimbot.mmu().sgb().unwrap();
+    assert_eq!(sgb.palettes()[1], [0x7FFF, 0x0010, 0x0200, 0x4000]);
+    assert_eq!((sgb.attribute(9, 0), sgb.attribute(10, 17)), (0, 1));
+    assert_eq!(jimbot.mmu().wram()[0..2], [0xFF, 0xFE]);
+
+    let frame = jimbot.frame();
+    assert_eq!((frame.lcd()[79][0], frame.lcd()[80][143]), (0x7C00, 0x4000));
+    let screen = frame.sgb_screen().unwrap();
+    assert_eq!(screen[LCD_X + 80][LCD_Y], 0x4000);
+    // no border, the backdrop is color 0
+    assert_eq!(screen[0][0], 0x7FFF);
+    assert_eq!((frame.width(), frame.height()), (SCREEN_WIDTH, SCREEN_HEIGHT));
+    assert_eq!(frame.to_rgba().len(), SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+
+    let state = jimbot.save_state();
+    let mut other = run(rom(&program, &packets), 0);
+    other.load_state(&state).unwrap();
+    assert_eq!(other.save_state(), state);
+    assert_eq!(other.frame().sgb_screen().unwrap()[LCD_X + 80][LCD_Y], 0x4000);
+}
+
+#[test]
+fn border_transfer() {
+    let program = [
+        0xAF, 0xE0, 0x40, // lcd off
+        0x21, 0x00, 0x80, 0x06, 0x08, // tile 0: $FF, $00 rows
+        0x3E, 0xFF, 0x22, 0xAF, 0x22, 0x05, 0x20, 0xF8,
+        0x3E, 0x91, 0xE0, 0x40, // lcd on, the bg shows tile 0 everywhere
+        0x21, 0x00, 0x02,
+        0xCD, 0x80, 0x01, // CHR_TRN
+        0xF0, 0x44, 0xFE, 0x91, 0x20, 0xFA, // next frame
+        0xCD, 0x80, 0x01, // PCT_TRN
+        0xF0, 0x44, 0xFE, 0x91, 0x20, 0xFA,
+        0x18, 0xFE,
+    ];
+    // tiles $80-$FF then the map, both made of tile 0: SNES planes 0 and 2 set (color 5), map entries $00FF
+    // (tile $FF, palette 4) and palette 4 colors $00FF
+    let packets = [packet(0x13, &[0x01]), packet(0x14, &[])];
+    let jimbot = run(rom(&program, &packets), 5);
+    let screen = jimbot.frame().sgb_screen().unwrap();
+    assert_eq!(screen[0][0], 0x00FF);
+    // the border is drawn over the lcd
+    assert_eq!(screen[LCD_X][LCD_Y], 0x00FF);
+}
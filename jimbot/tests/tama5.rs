@@ -0,0 +1,169 @@
+//! Drives the TAMA5 memory and TC8521 clock through the $A000/$A001 register window
+
+use jimbot::cartridge::Cartridge;
+use jimbot::cartridge::save_storage::MemoryStorage;
+use common::{now, run_seconds};
+
+mod common;
+
+const WRITE_MEMORY: u8 = 0;
+const READ_MEMORY: u8 = 1;
+const WRITE_CLOCK: u8 = 2;
+const READ_CLOCK: u8 = 3;
+/// clock register of the mode, bit 2 alarm enable and bit 3 running
+const MODE: u8 = 0x0D;
+
+/// 512 KiB TAMA5 holding the bank number at the start of every bank, loaded from `save`
+fn cartridge(save: Option<Vec<u8>>) -> (Box<dyn Cartridge>, MemoryStorage) {
+    let mut rom = vec![0; 0x80000];
+    for (bank, data) in rom.chunks_mut(0x4000).enumerate() {
+        data[0] = bank as u8;
+    }
+    rom[0x147] = 0xFD;
+    rom[0x148] = 0x04;
+    common::cartridge_with_save(rom, save)
+}
+
+fn select(cartridge: &mut Box<dyn Cartridge>, register: u8) {
+    cartridge.set(0xA001, register);
+}
+
+fn write(cartridge: &mut Box<dyn Cartridge>, register: u8, val: u8) {
+    select(cartridge, register);
+    cartridge.set(0xA000, val);
+}
+
+fn read(cartridge: &mut Box<dyn Cartridge>, register: u8) -> u8 {
+    select(cartridge, register);
+    cartridge.get(0xA000)
+}
+
+/// value in $4/$5, command and address in $6/$7, then the result in $C/$D
+fn command(cartridge: &mut Box<dyn Cartridge>, command: u8, address: u8, value: u8) -> u8 {
+    write(cartridge, 0x04, value & 0x0F);
+    write(cartridge, 0x05, value >> 4);
+    write(cartridge, 0x06, (command << 1) | (address >> 4));
+    write(cartridge, 0x07, address & 0x0F);
+    (read(cartridge, 0x0D) & 0x0F) << 4 | (read(cartridge, 0x0C) & 0x0F)
+}
+
+fn set_clock(cartridge: &mut Box<dyn Cartridge>, registers: &[(u8, u8)]) {
+    for &(register, val) in registers {
+        command(cartridge, WRITE_CLOCK, register, val);
+    }
+}
+
+/// page 0 registers $00-$0D, one nibble each
+fn clock(cartridge: &mut Box<dyn Cartridge>) -> Vec<u8> {
+    (0x00..=0x0D).map(|register| command(cartridge, READ_CLOCK, register, 0) & 0x0F).collect()
+}
+
+/// 10:20:30 on 04/01 of year 05, a monday
+const TIME: [(u8, u8); 12] = [
+    (0x00, 0), (0x01, 3), (0x02, 0), (0x03, 2), (0x04, 0), (0x05, 1),
+    (0x06, 1), (0x07, 4), (0x08, 0), (0x09, 1), (0x0A, 0), (0x0B, 5),
+];
+
+#[test]
+fn rom_banks() {
+    let (mut cartridge, _) = cartridge(None);
+    assert_eq!(cartridge.get(0x4000), 0x00);
+    write(&mut cartridge, 0x00, 0x05);
+    assert_eq!(cartridge.get(0x4000), 0x05);
+    write(&mut cartridge, 0x01, 0x01);
+    assert_eq!(cartridge.get(0x4000), 0x15);
+    assert_eq!(cartridge.get(0x0000), 0x00);
+}
+
+#[test]
+fn memory_commands() {
+    let (mut cartridge, _) = cartridge(None);
+    command(&mut cartridge, WRITE_MEMORY, 0x1F, 0xA5);
+    assert_eq!(command(&mut cartridge, READ_MEMORY, 0x1F, 0x00), 0xA5);
+    assert_eq!(command(&mut cartridge, READ_MEMORY, 0x0F, 0x00), 0x00);
+    assert_eq!(cartridge.save_data().unwrap()[0x1F], 0xA5);
+
+    command(&mut cartridge, READ_MEMORY, 0x1F, 0x00);
+    assert_eq!(read(&mut cartridge, 0x0C), 0xF5);
+    assert_eq!(read(&mut cartridge, 0x0D), 0xFA);
+    assert_eq!(read(&mut cartridge, 0x0A), 0xF1);
+    assert_eq!(read(&mut cartridge, 0x04), 0xFF);
+}
+
+#[test]
+fn clock_rolls_over_the_century() {
+    let (mut cartridge, _) = cartridge(None);
+    // 23:59:59 on 31/12 of year 99, a saturday
+    set_clock(&mut cartridge, &[
+        (0x00, 9), (0x01, 5), (0x02, 9), (0x03, 5), (0x04, 3), (0x05, 2),
+        (0x06, 6), (0x07, 1), (0x08, 3), (0x09, 2), (0x0A, 1), (0x0B, 9), (0x0C, 9),
+    ]);
+    run_seconds(&mut cartridge, 1);
+    // 00:00:00 on 01/01 of year 00, a sunday
+    assert_eq!(clock(&mut cartridge), [0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0x08]);
+
+    // 28/02 of a leap year
+    set_clock(&mut cartridge, &[(0x07, 8), (0x08, 2), (0x09, 2), (0x0A, 0), (0x0B, 4), (0x04, 3), (0x05, 2), (0x02, 9), (0x03, 5), (0x01, 5), (0x00, 9)]);
+    run_seconds(&mut cartridge, 1);
+    assert_eq!(clock(&mut cartridge)[0x07..=0x0A], [9, 2, 2, 0]);
+}
+
+#[test]
+fn alarm_flag() {
+    let (mut cartridge, _) = cartridge(None);
+    // alarm at 12:00, the clock at 11:59:59
+    set_clock(&mut cartridge, &[(0x12, 0), (0x13, 0), (0x14, 2), (0x15, 1)]);
+    set_clock(&mut cartridge, &[(0x04, 1), (0x05, 1), (0x02, 9), (0x03, 5), (0x01, 5), (0x00, 9)]);
+    run_seconds(&mut cartridge, 1);
+    // disabled
+    assert_eq!(read(&mut cartridge, 0x0A), 0xF1);
+
+    set_clock(&mut cartridge, &[(MODE, 0x0C), (0x04, 1), (0x05, 1), (0x02, 9), (0x03, 5), (0x01, 5), (0x00, 9)]);
+    run_seconds(&mut cartridge, 1);
+    assert_eq!(read(&mut cartridge, 0x0A), 0xF3);
+    assert_eq!(cartridge.get(0xA000), 0xF3);
+    // cleared once another register is selected
+    select(&mut cartridge, 0x00);
+    assert_eq!(read(&mut cartridge, 0x0A), 0xF1);
+}
+
+#[test]
+fn footer_round_trip() {
+    let (mut cartridge, storage) = cartridge(None);
+    command(&mut cartridge, WRITE_MEMORY, 0x03, 0x5A);
+    set_clock(&mut cartridge, &TIME);
+    set_clock(&mut cartridge, &[(0x13, 0x07), (MODE, 0x00)]);
+    let time = clock(&mut cartridge);
+    drop(cartridge);
+
+    let save = storage.bytes().unwrap();
+    assert_eq!(save.len(), 32 + 40);
+    assert_eq!(save[0x03], 0x5A);
+    let timestamp = u64::from_le_bytes(save[32..40].try_into().unwrap());
+    assert!(now().abs_diff(timestamp) <= 1);
+    assert_eq!(save[40..40 + 14], time);
+    assert_eq!(save[40 + 0x13], 0x07);
+
+    // stopped, the clock did not move
+    let (mut cartridge, _) = self::cartridge(Some(save));
+    assert_eq!(command(&mut cartridge, READ_MEMORY, 0x03, 0x00), 0x5A);
+    assert_eq!(clock(&mut cartridge), time);
+    assert_eq!(command(&mut cartridge, READ_CLOCK, 0x13, 0x00) & 0x0F, 0x07);
+}
+
+#[test]
+fn catches_up_on_host_time() {
+    let (mut cartridge, storage) = cartridge(None);
+    set_clock(&mut cartridge, &TIME);
+    drop(cartridge);
+
+    // saved a day, an hour, a minute and a second ago
+    let mut save = storage.bytes().unwrap();
+    let timestamp = u64::from_le_bytes(save[32..40].try_into().unwrap()) - 90061;
+    save[32..40].copy_from_slice(&timestamp.to_le_bytes());
+    let (mut cartridge, _) = self::cartridge(Some(save));
+    let time = clock(&mut cartridge);
+    // 11:21:31 on 05/01, a tuesday, a second may pass while loading
+    assert!(time[0x00] == 1 || time[0x00] == 2);
+    assert_eq!(time[0x01..=0x0B], [3, 1, 2, 1, 1, 2, 5, 0, 1, 0, 5]);
+}